@@ -0,0 +1,140 @@
+// `#[test]` items are dropped without the test harness, leaving their imports unused
+#![allow(
+    dead_code,
+    unused_imports,
+    clippy::needless_return
+)]
+
+// the compiler is a binary crate, so the frontend modules are compiled in here
+#[path = "../src/ast.rs"]
+mod ast;
+#[path = "../src/definition.rs"]
+mod definition;
+#[path = "../src/diagnostic.rs"]
+mod diagnostic;
+#[path = "../src/grammar.rs"]
+mod grammar;
+#[path = "../src/parse_tree.rs"]
+mod parse_tree;
+#[path = "../src/parser.rs"]
+mod parser;
+#[path = "../src/span.rs"]
+mod span;
+#[cfg(test)]
+#[path = "../src/test_util.rs"]
+mod test_util;
+#[path = "../src/token.rs"]
+mod token;
+#[path = "../src/token_grammar.rs"]
+mod token_grammar;
+#[path = "../src/token_stream.rs"]
+mod token_stream;
+#[path = "../src/tokenizer.rs"]
+mod tokenizer;
+
+use std::convert::TryFrom;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const SIZES: &[(&str, usize)] = &[
+    ("small", 10),
+    ("medium", 100),
+    ("large", 1000)
+];
+
+// a deterministic program with the given number of functions, each one
+// calling the previous so the inputs stay the same across runs, names avoid
+// clashing with types such as `f32`
+fn generate(functions: usize) -> String {
+    let mut out = String::from("mem memory = (1; page; 2);\n\nlet mut counter <- 0;\n");
+
+    for i in 0..functions {
+        out.push_str(&format!("\n// function {}\n", i));
+        out.push_str(&format!("fn step{}(n: i32, m: i32) -> i32 {{\n", i));
+        out.push_str("    let total <- 0;\n");
+        out.push_str("    let i <- 1;\n\n");
+        out.push_str("    while (i <= n) {\n");
+
+        match i {
+            0 => out.push_str("        total <- total + i * m;\n"),
+            _ => out.push_str(&format!("        total <- total + step{}(i, m - 1);\n", i - 1))
+        }
+
+        out.push_str("        i <- i + 1;\n");
+        out.push_str("    }\n\n");
+        out.push_str("    if (total > 1000) {\n");
+        out.push_str("        ret (total >> 2) & 0xff;\n");
+        out.push_str("    }\n\n");
+        out.push_str("    counter <- counter + 1;\n");
+        out.push_str("    total\n");
+        out.push_str("}\n");
+    }
+
+    return out;
+}
+
+fn tokenize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tokenize");
+
+    for (name, functions) in SIZES.iter() {
+        let text = generate(*functions);
+
+        group.throughput(Throughput::Bytes(u64::try_from(text.len()).unwrap()));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &text, |b, text| {
+            b.iter(|| return tokenizer::tokenize(text).unwrap());
+        });
+    }
+
+    group.finish();
+}
+
+fn parse_syntax(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_syntax");
+
+    for (name, functions) in SIZES.iter() {
+        let text = generate(*functions);
+        let tokens = tokenizer::tokenize(&text).unwrap();
+
+        group.throughput(Throughput::Elements(u64::try_from(tokens.len()).unwrap()));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &tokens, |b, tokens| {
+            b.iter(|| return parser::parse_syntax(tokens, false).unwrap());
+        });
+    }
+
+    group.finish();
+}
+
+// every spelling the tokenizer classifies, along with identifiers that miss
+// all tables, compared against the linear scan the lookups replaced
+fn lookup(c: &mut Criterion) {
+    let words = definition::KEYWORD_TOKENS.iter().map(|x| return x.0)
+        .chain(definition::TYPE_TOKENS.iter().map(|x| return x.0))
+        .chain(definition::SYMBOL_TOKENS.iter().map(|x| return x.0))
+        .chain(["total", "counter", "step12", "n"])
+        .collect::<Vec<_>>();
+    let mut group = c.benchmark_group("lookup");
+
+    group.throughput(Throughput::Elements(u64::try_from(words.len()).unwrap()));
+    group.bench_function("linear", |b| {
+        b.iter(|| {
+            return words.iter().filter(|s| {
+                return definition::KEYWORD_TOKENS.iter().any(|x| return &x.0 == *s)
+                    || definition::TYPE_TOKENS.iter().any(|x| return &x.0 == *s)
+                    || definition::SYMBOL_TOKENS.iter().any(|x| return &x.0 == *s);
+            }).count();
+        });
+    });
+    group.bench_function("match", |b| {
+        b.iter(|| {
+            return words.iter().filter(|s| {
+                return token::Keyword::try_from(**s).is_ok()
+                    || token::Type::try_from(**s).is_ok()
+                    || token::Symbol::try_from(**s).is_ok();
+            }).count();
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, tokenize, parse_syntax, lookup);
+criterion_main!(benches);
@@ -0,0 +1,511 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::ast;
+use crate::definition;
+use crate::diagnostic;
+use crate::ir;
+use crate::resolver;
+use crate::span::Span;
+use crate::token;
+use crate::visitor;
+use crate::visitor::Visitor;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lint {
+    UnusedImports,
+    UnusedVariables,
+    ChainedComparisons,
+    OverAlignedAccess,
+    KeywordTypos,
+    UnknownAttributes
+}
+
+impl Lint {
+    pub const ALL: [Lint; 6] = [
+        Lint::UnusedImports,
+        Lint::UnusedVariables,
+        Lint::ChainedComparisons,
+        Lint::OverAlignedAccess,
+        Lint::KeywordTypos,
+        Lint::UnknownAttributes
+    ];
+
+    pub const fn name(&self) -> &'static str {
+        return match self {
+            Lint::UnusedImports => "unused-imports",
+            Lint::UnusedVariables => "unused-variables",
+            Lint::ChainedComparisons => "chained-comparisons",
+            Lint::OverAlignedAccess => "over-aligned-access",
+            Lint::KeywordTypos => "keyword-typos",
+            Lint::UnknownAttributes => "unknown-attributes"
+        };
+    }
+}
+
+impl FromStr for Lint {
+    type Err = Box<dyn Error>;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match Lint::ALL.iter().find(|x| return x.name() == s) {
+            Some(x) => Ok(x.clone()),
+            None => Err(format!("unknown lint: {}", s).into())
+        };
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return f.write_str(self.name());
+    }
+}
+
+// what a lint does when it fires
+#[derive(Debug, Clone, PartialEq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    lint: Lint,
+    message: String,
+    span: Span
+}
+
+impl Warning {
+    pub const fn lint(&self) -> &Lint {
+        return &self.lint;
+    }
+
+    // `path:ln:col: warning: message`
+    pub fn render(&self, path: &str, text: &str) -> diagnostic::Report {
+        return diagnostic::Report::new(diagnostic::Severity::Warning, &self.message).with_span(self.span.clone()).locate(path, text);
+    }
+
+    // denied lints are reported as `error`, naming the lint that denied them
+    pub fn render_denied(&self, path: &str, text: &str) -> diagnostic::Report {
+        let message = format!("{} [{}]", self.message, self.lint);
+
+        return diagnostic::Report::new(diagnostic::Severity::Error, message).with_span(self.span.clone()).locate(path, text);
+    }
+}
+
+#[cfg(test)]
+impl Warning {
+    pub fn message(&self) -> &str {
+        return &self.message;
+    }
+}
+
+// every lint of one file. imports count as used when any file of the module
+// refers to them
+pub fn lints(program: &ast::Program, symbols: &resolver::SymbolTable, module: &resolver::SymbolTable) -> Vec<Warning> {
+    let mut warnings = unused_imports(symbols, module);
+
+    warnings.extend(unused_variables(program));
+    warnings.extend(chained_comparisons(program));
+    warnings.extend(over_aligned_accesses(program));
+    warnings.extend(keyword_typos(program, module));
+    warnings.extend(unknown_attributes(program));
+
+    return warnings;
+}
+
+// imports that nothing refers to still have to be provided by the host, memories
+// are left out since loads and stores use them without naming them. imports of
+// one linked file count as used when any file of the module refers to them
+pub fn unused_imports(symbols: &resolver::SymbolTable, module: &resolver::SymbolTable) -> Vec<Warning> {
+    return symbols.symbols().iter()
+        .filter(|x| return x.is_imported() && !x.is_exported())
+        .filter(|x| return *x.kind() != resolver::SymbolKind::Memory)
+        .filter(|x| return !module.is_referenced(x.name()))
+        .map(|x| return Warning {
+            lint: Lint::UnusedImports,
+            message: format!("unused import: {} `{}`", x.kind(), x.name()),
+            span: x.span().clone()
+        })
+        .collect();
+}
+
+// local bindings that are never read. assigning to a binding does not count
+// as reading it, and names starting with `_` are left alone
+pub fn unused_variables(program: &ast::Program) -> Vec<Warning> {
+    let mut lint = UnusedVariables::default();
+
+    lint.visit_program(program);
+
+    return lint.warnings;
+}
+
+#[derive(Default)]
+struct UnusedVariables {
+    warnings: Vec<Warning>
+}
+
+impl Visitor for UnusedVariables {
+    fn visit_fn(&mut self, function: &ast::FunctionDeclaration) {
+        let mut locals = Locals::default();
+
+        locals.visit_block(&function.body);
+
+        for decl in locals.declared.iter() {
+            let names = match &decl.pattern {
+                ast::Pattern::Single(x) => std::slice::from_ref(x),
+                ast::Pattern::Tuple(list) => list.as_slice()
+            };
+
+            for binding in names.iter().filter(|x| return !x.name.starts_with('_') && !locals.read.contains(&x.name)) {
+                self.warnings.push(Warning {
+                    lint: Lint::UnusedVariables,
+                    message: format!("unused variable: `{}`", binding.name),
+                    span: decl.span.clone()
+                });
+            }
+        }
+
+        // nested functions are linted on their own
+        visitor::walk_fn(self, function);
+    }
+}
+
+// bindings of one function body and the names read in it, shadowing is not
+// told apart
+#[derive(Default)]
+struct Locals {
+    declared: Vec<ast::VariableDeclaration>,
+    read: HashSet<String>,
+    functions: HashSet<String>
+}
+
+impl Visitor for Locals {
+    fn visit_fn(&mut self, _: &ast::FunctionDeclaration) {}
+
+    fn visit_stmt(&mut self, stmt: &ast::Statement) {
+        match stmt {
+            ast::Statement::Variable(x) => self.declared.push(x.clone()),
+            ast::Statement::Function(x) => {
+                self.functions.insert(x.name.clone());
+            },
+            ast::Statement::Assignment(ast::Expression::Identifier(_), value) => {
+                self.visit_expr(value);
+
+                return;
+            },
+            _ => {}
+        }
+
+        visitor::walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &ast::Expression) {
+        match expr {
+            ast::Expression::Identifier(x) | ast::Expression::TypeOf(x) => {
+                self.read.insert(x.clone());
+            },
+            _ => {}
+        }
+
+        visitor::walk_expr(self, expr);
+    }
+}
+
+// `a < b < c` compares the result of `a < b` with `c`, which is rarely meant.
+// expressions carry no span, so the enclosing function is reported
+pub fn chained_comparisons(program: &ast::Program) -> Vec<Warning> {
+    let mut lint = ChainedComparisons::default();
+
+    lint.visit_program(program);
+
+    return lint.warnings;
+}
+
+#[derive(Default)]
+struct ChainedComparisons {
+    span: Span,
+    warnings: Vec<Warning>
+}
+
+impl Visitor for ChainedComparisons {
+    fn visit_fn(&mut self, function: &ast::FunctionDeclaration) {
+        self.span = function.span.clone();
+
+        visitor::walk_fn(self, function);
+    }
+
+    fn visit_expr(&mut self, expr: &ast::Expression) {
+        if let ast::Expression::Binary { op, lhs, .. } = expr {
+            if let ast::Expression::Binary { op: inner, .. } = lhs.as_ref() {
+                if is_comparison(op) && is_comparison(inner) {
+                    self.warnings.push(Warning {
+                        lint: Lint::ChainedComparisons,
+                        message: "comparison operators cannot be chained, the left side is already a comparison".to_string(),
+                        span: self.span.clone()
+                    });
+                }
+            }
+        }
+
+        visitor::walk_expr(self, expr);
+    }
+}
+
+// alignment hints above the size of the accessed number cannot be promised,
+// and are lowered to its natural alignment
+pub fn over_aligned_accesses(program: &ast::Program) -> Vec<Warning> {
+    let mut lint = OverAlignedAccesses::default();
+
+    lint.visit_program(program);
+
+    return lint.warnings;
+}
+
+#[derive(Default)]
+struct OverAlignedAccesses {
+    span: Span,
+    warnings: Vec<Warning>
+}
+
+impl Visitor for OverAlignedAccesses {
+    fn visit_fn(&mut self, function: &ast::FunctionDeclaration) {
+        self.span = function.span.clone();
+
+        visitor::walk_fn(self, function);
+    }
+
+    fn visit_expr(&mut self, expr: &ast::Expression) {
+        if let ast::Expression::Offset { signature: Some(ast::TypeExpression::Primitive(ty)), align: Some(align), .. } = expr {
+            let size = match ty {
+                token::Type::I32 | token::Type::F32 => 4,
+                token::Type::I64 | token::Type::F64 => 8,
+                _ => u32::MAX
+            };
+
+            if align.parse::<u32>().is_ok_and(|x| return x > size) {
+                self.warnings.push(Warning {
+                    lint: Lint::OverAlignedAccess,
+                    message: format!("alignment of {} bytes exceeds the size of {}, {} bytes are used instead", align, ty.name(), size),
+                    span: self.span.clone()
+                });
+            }
+        }
+
+        visitor::walk_expr(self, expr);
+    }
+}
+
+// a name standing alone at the start of a statement that is defined nowhere,
+// and is one typo away from a keyword, as `retrun;` or `brek;`. a name
+// followed by more, as `retrun 1;`, fails to parse before any lint runs
+pub fn keyword_typos(program: &ast::Program, module: &resolver::SymbolTable) -> Vec<Warning> {
+    let mut lint = KeywordTypos { module, names: HashSet::new(), warnings: vec![] };
+
+    lint.visit_program(program);
+
+    return lint.warnings;
+}
+
+struct KeywordTypos<'a> {
+    module: &'a resolver::SymbolTable,
+    // params, locals and nested functions of the function being linted
+    names: HashSet<String>,
+    warnings: Vec<Warning>
+}
+
+impl KeywordTypos<'_> {
+    // the name starts the statement at `start`
+    fn check(&mut self, name: &str, start: usize) {
+        if self.names.contains(name) || self.module.get(name).is_some() || ir::Intrinsic::from_name(name).is_some() {
+            return;
+        }
+
+        if let Some(keyword) = definition::keyword_typo(name) {
+            self.warnings.push(Warning {
+                lint: Lint::KeywordTypos,
+                message: format!("`{}` is not defined, did you mean the keyword `{}`?", name, keyword),
+                span: start..start + name.len()
+            });
+        }
+    }
+}
+
+impl Visitor for KeywordTypos<'_> {
+    fn visit_fn(&mut self, function: &ast::FunctionDeclaration) {
+        let mut locals = Locals::default();
+
+        locals.visit_block(&function.body);
+
+        let names = function.signature.params.iter().map(|x| return x.name.clone())
+            .chain(locals.declared.iter().flat_map(|x| return match &x.pattern {
+                ast::Pattern::Single(x) => vec![x.name.clone()],
+                ast::Pattern::Tuple(list) => list.iter().map(|x| return x.name.clone()).collect()
+            }))
+            .chain(locals.functions)
+            .collect();
+        let outer = std::mem::replace(&mut self.names, names);
+
+        visitor::walk_fn(self, function);
+
+        self.names = outer;
+    }
+
+    // statements are walked here rather than in `visit_stmt`, which sees
+    // them without their spans
+    fn visit_block(&mut self, block: &ast::Block) {
+        for stmt in block.statements.iter() {
+            if let ast::Statement::Expression(ast::Expression::Identifier(x) | ast::Expression::Call { callee: x, .. }) = &stmt.node {
+                self.check(x, stmt.span.start);
+            }
+
+            self.visit_stmt(stmt);
+        }
+
+        if let Some(tail) = &block.tail {
+            if let ast::Expression::Identifier(x) = &tail.node {
+                self.check(x, tail.span.start);
+            }
+
+            self.visit_expr(tail);
+        }
+    }
+}
+
+// attributes that neither the transpiler nor the optimizer knows, which are
+// kept in the ast but have no effect
+pub fn unknown_attributes(program: &ast::Program) -> Vec<Warning> {
+    return program.declarations.iter()
+        .filter_map(|x| return match x {
+            ast::Declaration::Function(x) => Some(x),
+            _ => None
+        })
+        .flat_map(|x| return x.attributes.iter().filter(|x| return !ast::ATTRIBUTES.contains(&x.name.as_str())))
+        .map(|x| return Warning {
+            lint: Lint::UnknownAttributes,
+            message: format!("unknown attribute: `@{}`", x.name),
+            span: x.span.clone()
+        })
+        .collect();
+}
+
+fn is_comparison(op: &token::Symbol) -> bool {
+    return ir::BinaryOp::from_symbol(op).is_some_and(ir::BinaryOp::is_comparison);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::tokenizer;
+
+    fn analyze(text: &str) -> Vec<Warning> {
+        let program = parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap();
+        let symbols = resolver::resolve(&program).unwrap();
+
+        return unused_imports(&symbols, &symbols);
+    }
+
+    #[test]
+    fn warn_unused_import() {
+        let text = "imp fn log(a: i32) from \"env\";\nimp let g = i32 from \"env\";\nfn f() { log(g); }\nimp fn trace() from \"env\";";
+        let warnings = analyze(text);
+
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].render("main.cwal", text).to_string(), "main.cwal:4:1: warning: unused import: function `trace`");
+    }
+
+    #[test]
+    fn warn_unused_variable() {
+        let text = "fn f(a: i32) -> i32 {\n    let b <- a;\n    let mut c <- 1;\n    c <- 2;\n    let _d <- 3;\n    a\n}";
+        let program = parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap();
+        let rendered = unused_variables(&program).iter().map(|x| return x.render("main.cwal", text).to_string()).collect::<Vec<_>>();
+
+        assert_eq!(rendered, vec![
+            "main.cwal:2:5: warning: unused variable: `b`",
+            "main.cwal:3:5: warning: unused variable: `c`"
+        ]);
+    }
+
+    #[test]
+    fn warn_chained_comparison() {
+        let warnings = |text| return chained_comparisons(&parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap());
+
+        assert_eq!(warnings("fn f(a: i32, b: i32, c: i32) -> i32 { a < b <u c }")[0].lint(), &Lint::ChainedComparisons);
+        assert!(warnings("fn f(a: i32, b: i32, c: i32) -> i32 { a < b + c }").is_empty());
+    }
+
+    #[test]
+    fn warn_over_aligned_access() {
+        let warnings = |text| return over_aligned_accesses(&parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap());
+
+        assert_eq!(warnings("fn f() -> i32 { @0<i32>(memory; 8) }")[0].message(), "alignment of 8 bytes exceeds the size of i32, 4 bytes are used instead");
+        assert!(warnings("fn f() -> i64 { @0<i64>(memory; 8) }").is_empty());
+    }
+
+    #[test]
+    fn warn_unknown_attribute() {
+        let text = "@inline fn f() {}\n@export(\"g\") @cold fn g() {}";
+        let program = parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap();
+        let rendered = unknown_attributes(&program).iter().map(|x| return x.render("main.cwal", text).to_string()).collect::<Vec<_>>();
+
+        assert_eq!(rendered, vec!["main.cwal:2:14: warning: unknown attribute: `@cold`"]);
+    }
+
+    #[test]
+    fn warn_keyword_typo() {
+        let warnings = |text| {
+            let program = parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap();
+
+            return keyword_typos(&program, &resolver::resolve(&program).unwrap()).iter().map(|x| return x.message().to_string()).collect::<Vec<_>>();
+        };
+
+        assert_eq!(warnings("fn f() { retrun; }"), vec!["`retrun` is not defined, did you mean the keyword `ret`?"]);
+        assert_eq!(warnings("fn f() { while (1) { brek; } conitnue(); }"), vec![
+            "`brek` is not defined, did you mean the keyword `brk`?",
+            "`conitnue` is not defined, did you mean the keyword `cont`?"
+        ]);
+        assert_eq!(warnings("fn f() -> i32 { let x <- 1; rett }"), vec!["`rett` is not defined, did you mean the keyword `ret`?"]);
+    }
+
+    #[test]
+    fn locate_keyword_typo() {
+        let text = "fn f(a: i32) {\n    a <- 1;\n    fn g() {\n        // brek;\n        retrun;\n    }\n    while (a) {\n        brek;\n    }\n    g();\n}";
+        let program = parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap();
+        let rendered = keyword_typos(&program, &resolver::resolve(&program).unwrap()).iter()
+            .map(|x| return x.render("main.cwal", text).to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(rendered, vec![
+            "main.cwal:5:9: warning: `retrun` is not defined, did you mean the keyword `ret`?",
+            "main.cwal:8:9: warning: `brek` is not defined, did you mean the keyword `brk`?"
+        ]);
+    }
+
+    #[test]
+    fn accept_names_close_to_keywords() {
+        let warnings = |text| {
+            let program = parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap();
+
+            return keyword_typos(&program, &resolver::resolve(&program).unwrap());
+        };
+
+        assert!(warnings("fn rep() {} fn f(get: i32) -> i32 { let net <- get; rep(); fn deferred() {} deferred(); net }").is_empty());
+        assert!(warnings("fn f() { fu(); x; }").is_empty());
+    }
+
+    #[test]
+    fn parse_lint_names() {
+        for lint in Lint::ALL.iter() {
+            assert_eq!(&lint.name().parse::<Lint>().unwrap(), lint);
+        }
+
+        assert!("unused".parse::<Lint>().is_err());
+    }
+
+    #[test]
+    fn accept_used_import() {
+        assert!(analyze("imp fn log(a: i32) from \"env\"; fn f() { log(1); }").is_empty());
+    }
+}
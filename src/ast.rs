@@ -0,0 +1,1470 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::definition;
+use crate::diagnostic;
+use crate::span::{Span, Spanned};
+use crate::token;
+use crate::parse_tree::{self, Tree};
+
+// enums
+#[derive(Debug, Clone, PartialEq)]
+pub enum Declaration {
+    Function(FunctionDeclaration),
+    Type(TypeDeclaration),
+    Table(TableDeclaration),
+    Memory(MemoryDeclaration),
+    Variable(VariableDeclaration),
+    Import(ImportDeclaration),
+    ImportModule(ImportModuleDeclaration),
+    Export(ExportDeclaration),
+    Include(IncludeDeclaration),
+    StaticAssert(StaticAssertion),
+    // tokens skipped by error recovery, only built by `Parser::into_ast`
+    Error(ErrorDeclaration)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportItem {
+    Function(String, Signature),
+    Table(String, TypeExpression),
+    Memory(String, TypeExpression),
+    Variable(Binding, TypeExpression)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportItem {
+    Function(FunctionDeclaration),
+    Table(TableDeclaration),
+    Memory(MemoryDeclaration),
+    Variable(VariableDeclaration),
+    Aliased(String, String)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeExpression {
+    Primitive(token::Type),
+    Named(String),
+    Function(Vec<token::Type>, Option<Box<TypeExpression>>),
+    Tuple(Vec<TypeExpression>),
+    Vector(token::Type, String),
+    Range(String, token::Type, String),
+    TypeOf(String)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Single(Binding),
+    Tuple(Vec<Binding>)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Variable(VariableDeclaration),
+    Expression(Expression),
+    Assignment(Expression, Expression),
+    If(IfStatement),
+    While(WhileStatement),
+    Repeat(RepeatStatement),
+    // `defer { ... }` runs its block on every exit of the function, the
+    // latest first
+    Defer(Block),
+    Return(Option<Expression>),
+    Break,
+    Continue,
+    StaticAssert(StaticAssertion),
+    // lifted to module scope by `lowering::lower`
+    Function(FunctionDeclaration),
+    Block(Block)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Literal(String),
+    Identifier(String),
+    Call {
+        callee: String,
+        args: Vec<Expression>
+    },
+    CallIndirect {
+        target: Box<Expression>,
+        signature: TypeExpression,
+        args: Vec<Expression>
+    },
+    // `name::<types>(args)` calls an instantiation of the generic function
+    // `name`, or else the function at entry `name` of the first table. told
+    // apart and replaced by `lowering::lower`
+    GenericCall {
+        callee: String,
+        types: Vec<TypeExpression>,
+        args: Vec<Expression>
+    },
+    Unary {
+        op: token::Symbol,
+        operand: Box<Expression>
+    },
+    Binary {
+        op: token::Symbol,
+        lhs: Box<Expression>,
+        rhs: Box<Expression>
+    },
+    Conditional {
+        condition: Box<Expression>,
+        consequent: Box<Expression>,
+        alternative: Box<Expression>
+    },
+    Tuple(Vec<Expression>),
+    TypeOf(String),
+    // `i64` as the argument of `size_of(i64)` or `align_of(i64)`, which
+    // `lowering::lower` folds into a literal
+    Type(token::Type),
+    // `u32(x)` reads an integer as another type, changing either its
+    // signedness or its width
+    Cast {
+        ty: token::Type,
+        operand: Box<Expression>
+    },
+    // `@offset<ty>(memory)` reads the `ty` at byte `offset` of a memory, and
+    // writes it when assigned to. `@offset(table)::<sig>(args)` instead calls
+    // the function at entry `offset` of a table
+    Offset {
+        offset: Box<Expression>,
+        signature: Option<TypeExpression>,
+        base: String,
+        // alignment hint in bytes, `@0<i64>(memory; 4)`
+        align: Option<String>
+    },
+    // the extra arguments of a call to a variadic import, each a `ty`, which
+    // are passed as a pointer to them in memory and their count. only built
+    // by `lowering::lower`
+    Variadic {
+        ty: token::Type,
+        args: Vec<Expression>
+    }
+}
+
+// structs
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub declarations: Vec<Declaration>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionDeclaration {
+    pub span: Span,
+    pub name: String,
+    pub is_public: bool,
+    // type parameters of a generic function, `fn max<T>(..)`, which is
+    // instantiated for each distinct list of types it is called with
+    pub generics: Vec<String>,
+    pub constraints: Vec<Constraint>,
+    pub signature: Signature,
+    pub body: Block,
+    pub attributes: Vec<Attribute>
+}
+
+// `@name` or `@name("argument")` before a function. the passes knowing a name
+// act on it, any other name is reported by the `unknown-attributes` lint
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub span: Span,
+    pub name: String,
+    // the contents of the string literal, still escaped
+    pub argument: Option<String>
+}
+
+// `T: i32 | i64` in a `where` clause, `T` may only be one of the types
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constraint {
+    pub param: String,
+    pub types: Vec<token::Type>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub params: Vec<Param>,
+    pub result: Option<TypeExpression>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub span: Span,
+    pub name: String,
+    pub ty: TypeExpression,
+    pub default: Option<Expression>,
+    // `...args: i32` takes any number of trailing arguments
+    pub is_variadic: bool
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeDeclaration {
+    pub span: Span,
+    pub name: String,
+    pub ty: TypeExpression
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableDeclaration {
+    pub span: Span,
+    pub name: String,
+    pub ty: TypeExpression
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryDeclaration {
+    pub span: Span,
+    pub name: String,
+    pub ty: TypeExpression
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableDeclaration {
+    pub span: Span,
+    pub pattern: Pattern,
+    // the type written after the name, which the value must have
+    pub ty: Option<TypeExpression>,
+    pub value: Expression
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    pub name: String,
+    pub is_mutable: bool
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportDeclaration {
+    pub span: Span,
+    pub module: String,
+    pub item: ImportItem
+}
+
+// `imp "path.wasm";` imports the exports of a prebuilt module, expanded into
+// one import declaration per export by `importer::expand`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportModuleDeclaration {
+    pub span: Span,
+    pub path: String
+}
+
+// `static_assert(cond, "msg");`, checked at compile time and emitting nothing
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaticAssertion {
+    pub span: Span,
+    pub condition: Expression,
+    pub message: String
+}
+
+// `incl "path";` shares the declarations of another file with this one,
+// `pub incl` also keeps its exports as exports of the module
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncludeDeclaration {
+    pub span: Span,
+    pub path: String,
+    pub is_public: bool
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorDeclaration {
+    pub span: Span
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportDeclaration {
+    pub alias: Option<String>,
+    pub item: ExportItem
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub statements: Vec<Spanned<Statement>>,
+    // trailing expression without `;`, which becomes the value of the block
+    pub tail: Option<Spanned<Expression>>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IfStatement {
+    pub condition: Expression,
+    pub block: Block,
+    pub else_ifs: Vec<(Expression, Block)>,
+    pub else_block: Option<Block>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WhileStatement {
+    pub condition: Expression,
+    pub block: Block
+}
+
+// `repeat N { ... }` runs its block a constant `N` times
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepeatStatement {
+    pub count: String,
+    pub block: Block
+}
+
+struct Builder<'t, 'a> {
+    tokens: &'t [Spanned<token::Token<'a>>]
+}
+
+impl Program {
+    pub fn build(tree: &Tree, tokens: &[Spanned<token::Token>]) -> Result<Self, Box<dyn Error>> {
+        let builder = Builder { tokens };
+
+        return Ok(Self {
+            declarations: tree.trees().map(|x| return builder.declaration(x)).collect::<Result<_, _>>()?
+        });
+    }
+}
+
+// attributes acted on by the transpiler or the optimizer. `inline` is only
+// a hint, kept for an inlining pass
+pub const ATTRIBUTES: &[&str] = &["export", "inline"];
+
+impl FunctionDeclaration {
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        return self.attributes.iter().find(|x| return x.name == name);
+    }
+}
+
+impl Expression {
+    pub fn build(tree: &Tree, tokens: &[Spanned<token::Token>]) -> Result<Self, Box<dyn Error>> {
+        return Builder { tokens }.expression(tree);
+    }
+
+    pub fn is_constant(&self) -> bool {
+        return match self {
+            Expression::Literal(_) => true,
+            Expression::Unary { operand, .. } => operand.is_constant(),
+            Expression::Binary { lhs, rhs, .. } => lhs.is_constant() && rhs.is_constant(),
+            Expression::Conditional { condition, consequent, alternative } => {
+                condition.is_constant() && consequent.is_constant() && alternative.is_constant()
+            },
+            Expression::Tuple(list) => list.iter().all(|x| return x.is_constant()),
+            _ => false
+        };
+    }
+
+    // evaluating the expression can neither call, write nor trap, so it may
+    // be evaluated when its value is not needed. integer division traps on
+    // zero, arithmetic on overflow when `traps_on_overflow` is set, and a
+    // memory or table read out of bounds
+    pub fn is_pure(&self, traps_on_overflow: bool) -> bool {
+        return match self {
+            Expression::Literal(_) | Expression::Identifier(_) | Expression::TypeOf(_) | Expression::Type(_) => true,
+            Expression::Unary { operand, .. } | Expression::Cast { operand, .. } => operand.is_pure(traps_on_overflow),
+            Expression::Binary { op: token::Symbol::Solidus | token::Symbol::Modulo | token::Symbol::SolidusUnsigned | token::Symbol::ModuloUnsigned, .. } => false,
+            Expression::Binary { op: token::Symbol::Plus | token::Symbol::Minus | token::Symbol::Asterisk | token::Symbol::PlusUnsigned | token::Symbol::MinusUnsigned | token::Symbol::AsteriskUnsigned, .. } if traps_on_overflow => false,
+            Expression::Binary { lhs, rhs, .. } => lhs.is_pure(traps_on_overflow) && rhs.is_pure(traps_on_overflow),
+            Expression::Conditional { condition, consequent, alternative } => {
+                condition.is_pure(traps_on_overflow) && consequent.is_pure(traps_on_overflow) && alternative.is_pure(traps_on_overflow)
+            },
+            Expression::Tuple(list) => list.iter().all(|x| return x.is_pure(traps_on_overflow)),
+            Expression::Call { .. } | Expression::CallIndirect { .. } | Expression::GenericCall { .. } | Expression::Offset { .. } | Expression::Variadic { .. } => false
+        };
+    }
+}
+
+// a node of the program that knows its span in the source
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Node<'a> {
+    Declaration(&'a Declaration),
+    Param(&'a Param),
+    Variable(&'a VariableDeclaration),
+    StaticAssert(&'a StaticAssertion),
+    Function(&'a FunctionDeclaration),
+    // any other statement, such as an assignment or an `if`
+    Statement(&'a Spanned<Statement>),
+    // the value of a `ret`, an expression statement or a block. only the
+    // value of a block has a span, the others are bounded by their statement
+    Expression(&'a Expression, Option<&'a Span>)
+}
+
+// the nodes covering an offset, from the declaration to the innermost one
+pub type NodePath<'a> = Vec<Node<'a>>;
+
+// where an editor highlights a node found by `find_at`
+#[allow(dead_code)]
+impl<'a> Node<'a> {
+    pub fn span(&self) -> Option<&'a Span> {
+        return match self {
+            Node::Declaration(x) => x.span(),
+            Node::Param(x) => Some(&x.span),
+            Node::Variable(x) => Some(&x.span),
+            Node::StaticAssert(x) => Some(&x.span),
+            Node::Function(x) => Some(&x.span),
+            Node::Statement(x) => Some(&x.span),
+            Node::Expression(_, span) => *span
+        };
+    }
+}
+
+impl Declaration {
+    // an aliased export names an item declared elsewhere, so it has none
+    pub fn span(&self) -> Option<&Span> {
+        return match self {
+            Declaration::Function(x) | Declaration::Export(ExportDeclaration { item: ExportItem::Function(x), .. }) => Some(&x.span),
+            Declaration::Table(x) | Declaration::Export(ExportDeclaration { item: ExportItem::Table(x), .. }) => Some(&x.span),
+            Declaration::Memory(x) | Declaration::Export(ExportDeclaration { item: ExportItem::Memory(x), .. }) => Some(&x.span),
+            Declaration::Variable(x) | Declaration::Export(ExportDeclaration { item: ExportItem::Variable(x), .. }) => Some(&x.span),
+            Declaration::Type(x) => Some(&x.span),
+            Declaration::Import(x) => Some(&x.span),
+            Declaration::ImportModule(x) => Some(&x.span),
+            Declaration::Include(x) => Some(&x.span),
+            Declaration::StaticAssert(x) => Some(&x.span),
+            Declaration::Error(x) => Some(&x.span),
+            Declaration::Export(ExportDeclaration { item: ExportItem::Aliased(..), .. }) => None
+        };
+    }
+}
+
+// the innermost nodes covering the byte offset, for editor queries. an
+// offset between the tokens of a node, or inside an expression, which has no
+// span of its own, gives the node around it. go to definition and hover ask
+// for it, the compiler only reads whole declarations
+#[allow(dead_code)]
+pub fn find_at(program: &Program, offset: usize) -> Option<NodePath<'_>> {
+    let declaration = program.declarations.iter().find(|x| return x.span().is_some_and(|x| return x.contains(&offset)))?;
+    let mut path = vec![Node::Declaration(declaration)];
+
+    if let Declaration::Function(x) | Declaration::Export(ExportDeclaration { item: ExportItem::Function(x), .. }) = declaration {
+        find_in_function(x, offset, &mut path);
+    }
+
+    return Some(path);
+}
+
+fn find_in_function<'a>(function: &'a FunctionDeclaration, offset: usize, path: &mut NodePath<'a>) {
+    match function.signature.params.iter().find(|x| return x.span.contains(&offset)) {
+        Some(x) => path.push(Node::Param(x)),
+        None => find_in_block(&function.body, offset, path)
+    }
+}
+
+fn find_in_block<'a>(block: &'a Block, offset: usize, path: &mut NodePath<'a>) {
+    if let Some(tail) = block.tail.as_ref().filter(|x| return x.span.contains(&offset)) {
+        return path.push(Node::Expression(&tail.node, Some(&tail.span)));
+    }
+
+    for statement in block.statements.iter().filter(|x| return x.span.contains(&offset)) {
+        let blocks = match &statement.node {
+            Statement::Variable(x) if x.span.contains(&offset) => return path.push(Node::Variable(x)),
+            Statement::StaticAssert(x) if x.span.contains(&offset) => return path.push(Node::StaticAssert(x)),
+            Statement::Function(x) if x.span.contains(&offset) => {
+                path.push(Node::Function(x));
+
+                return find_in_function(x, offset, path);
+            },
+            Statement::Expression(x) | Statement::Return(Some(x)) => {
+                path.push(Node::Statement(statement));
+
+                return path.push(Node::Expression(x, None));
+            },
+            Statement::If(x) => std::iter::once(&x.block).chain(x.else_ifs.iter().map(|x| return &x.1)).chain(x.else_block.iter()).collect(),
+            Statement::While(x) => vec![&x.block],
+            Statement::Repeat(x) => vec![&x.block],
+            Statement::Defer(x) | Statement::Block(x) => vec![x],
+            _ => vec![]
+        };
+
+        path.push(Node::Statement(statement));
+
+        // a nested block has no span of its own, the statement holding it bounds it
+        for block in blocks {
+            let len = path.len();
+
+            find_in_block(block, offset, path);
+
+            if path.len() > len {
+                return;
+            }
+        }
+    }
+}
+
+pub fn string_contents(literal: &str) -> Option<&str> {
+    return literal.strip_prefix('"').and_then(|x| return x.strip_suffix('"'));
+}
+
+// the value of a string literal's contents, `\xNN` escapes give single
+// bytes, so the result is only checked to be utf-8 once they are joined
+pub fn unescape(contents: &str) -> Result<String, Box<dyn Error>> {
+    let mut bytes = vec![];
+    let mut chars = contents.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('t') => bytes.push(b'\t'),
+            Some('r') => bytes.push(b'\r'),
+            Some('0') => bytes.push(0),
+            Some('"') => bytes.push(b'"'),
+            Some('\'') => bytes.push(b'\''),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let digits = chars.by_ref().take(2).collect::<String>();
+
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(x) if digits.len() == 2 => bytes.push(x),
+                    _ => return Err(format!("invalid escape `\\x{}` in string \"{}\"", digits, contents).into())
+                }
+            },
+            Some('u') => {
+                let invalid = || return format!("invalid unicode escape in string \"{}\"", contents);
+                let (digits, rest) = chars.as_str().strip_prefix('{').and_then(|x| return x.split_once('}')).ok_or_else(invalid)?;
+                let scalar = u32::from_str_radix(digits, 16).ok().and_then(char::from_u32).ok_or_else(invalid)?;
+
+                bytes.extend_from_slice(scalar.encode_utf8(&mut [0; 4]).as_bytes());
+                chars = rest.chars();
+            },
+            Some(x) => return Err(format!("unknown escape `\\{}` in string \"{}\"", x, contents).into()),
+            None => return Err(format!("string \"{}\" ends in an unfinished escape", contents).into())
+        }
+    }
+
+    return String::from_utf8(bytes).map_err(|_| return format!("string \"{}\" is not valid utf-8", contents).into());
+}
+
+pub fn type_name(ty: &token::Type) -> &'static str {
+    return definition::TYPE_TOKENS.iter()
+        .find(|x| return &x.1 == ty)
+        .map_or("?", |x| return x.0);
+}
+
+// the value of a local declared without initializer, which wasm would
+// otherwise leave at zero only on the first pass through a loop
+fn zero(name: &str, ty: &TypeExpression) -> Result<Expression, Box<dyn Error>> {
+    return match ty {
+        TypeExpression::Primitive(x @ (token::Type::I32 | token::Type::I64 | token::Type::U32 | token::Type::U64)) => Ok(Expression::Cast {
+            ty: x.clone(),
+            operand: Box::new(Expression::Literal("0".to_string()))
+        }),
+        TypeExpression::Primitive(x @ (token::Type::F32 | token::Type::F64)) => Ok(Expression::Literal(format!("0.0{}", type_name(x)))),
+        x => Err(format!("`let {}: {}` needs an initializer; only number types start at zero", name, x).into())
+    };
+}
+
+fn type_list(list: &[token::Type]) -> String {
+    return list.iter().map(type_name).collect::<Vec<_>>().join(", ");
+}
+
+// source form of types
+impl fmt::Display for TypeExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            TypeExpression::Primitive(x) => write!(f, "{}", type_name(x)),
+            TypeExpression::Named(x) => write!(f, "{}", x),
+            TypeExpression::Function(params, Some(result)) => write!(f, "fn({}) -> {}", type_list(params), result),
+            TypeExpression::Function(params, None) => write!(f, "fn({})", type_list(params)),
+            TypeExpression::Tuple(list) => write!(f, "({})", list.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")),
+            TypeExpression::Vector(ty, count) => write!(f, "({}; {})", type_name(ty), count),
+            TypeExpression::Range(min, ty, max) => write!(f, "({}; {}; {})", min, type_name(ty), max),
+            TypeExpression::TypeOf(x) => write!(f, "typeof {}", x)
+        };
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}: {}", self.param, self.types.iter().map(|x| return type_name(x)).collect::<Vec<_>>().join(" | "));
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params = self.params.iter().map(|x| return x.ty.to_string()).collect::<Vec<_>>();
+
+        return match &self.result {
+            Some(result) => write!(f, "fn({}) -> {}", params.join(", "), result),
+            None => write!(f, "fn({})", params.join(", "))
+        };
+    }
+}
+
+// binding power of binary operators, higher binds tighter
+fn precedence(op: &token::Symbol) -> u8 {
+    return match op {
+        token::Symbol::PipeForward => 1,
+        token::Symbol::LogicalOr => 2,
+        token::Symbol::LogicalAnd => 3,
+        token::Symbol::BitwiseOr => 4,
+        token::Symbol::BitwiseXor => 5,
+        token::Symbol::BitwiseAnd => 6,
+        token::Symbol::Equal
+        | token::Symbol::NotEqual => 7,
+        token::Symbol::LessThan
+        | token::Symbol::GreaterThan
+        | token::Symbol::LessThanOrEqual
+        | token::Symbol::GreaterThanOrEqual
+        | token::Symbol::LessThanUnsigned
+        | token::Symbol::GreaterThanUnsigned
+        | token::Symbol::LessThanOrEqualUnsigned
+        | token::Symbol::GreaterThanOrEqualUnsigned => 8,
+        token::Symbol::ShiftLeftLogical
+        | token::Symbol::ShiftRightArithmatic
+        | token::Symbol::ShiftRightLogical => 9,
+        token::Symbol::Plus
+        | token::Symbol::Minus
+        | token::Symbol::PlusUnsigned
+        | token::Symbol::MinusUnsigned => 10,
+        _ => 11
+    };
+}
+
+// the operands of an expression in order, with the operators between them
+struct ExpressionChain {
+    operands: Vec<Expression>,
+    operators: Vec<token::Symbol>,
+    conditional: Option<(Expression, Expression)>
+}
+
+impl ExpressionChain {
+    // left-associative precedence climbing over the collected operands
+    fn into_expression(self) -> Expression {
+        let ExpressionChain { operands, operators, conditional } = self;
+
+        let mut operands = operands.into_iter();
+        let mut operators = operators.into_iter().peekable();
+        let first = operands.next().expect("unexpected empty expression");
+
+        let expr = climb(first, 0, &mut operands, &mut operators);
+
+        if let Some((consequent, alternative)) = conditional {
+            return Expression::Conditional {
+                condition: Box::new(expr),
+                consequent: Box::new(consequent),
+                alternative: Box::new(alternative)
+            };
+        }
+
+        return expr;
+    }
+}
+
+fn climb(
+    mut lhs: Expression,
+    min: u8,
+    operands: &mut dyn Iterator<Item = Expression>,
+    operators: &mut std::iter::Peekable<std::vec::IntoIter<token::Symbol>>
+) -> Expression {
+    while let Some(op) = operators.next_if(|x| return precedence(x) > min) {
+        let mut rhs = operands.next().expect("unexpected missing operand");
+
+        while let Some(next) = operators.peek() {
+            if precedence(next) <= precedence(&op) {
+                break;
+            }
+
+            rhs = climb(rhs, precedence(&op), operands, operators);
+        }
+
+        lhs = Expression::Binary {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs)
+        };
+    }
+
+    return lhs;
+}
+
+impl<'t, 'a> Builder<'t, 'a> {
+    fn token(&self, index: usize) -> &'t token::Token<'a> {
+        return &self.tokens[index].node;
+    }
+
+    // byte range covered by the tokens of the tree
+    fn span(&self, tree: &Tree) -> Span {
+        return match tree.token_bounds() {
+            Some((first, last)) => self.tokens[first].span.start..self.tokens[last].span.end,
+            None => 0..0
+        };
+    }
+
+    fn first_token(&self, tree: &Tree) -> Result<&'t token::Token<'a>, Box<dyn Error>> {
+        return match tree.tokens().next() {
+            Some(index) => Ok(self.token(index)),
+            None => Err(format!("malformed {}: missing token", tree.name()).into())
+        };
+    }
+
+    fn identifier(&self, tree: &Tree) -> Result<String, Box<dyn Error>> {
+        for index in tree.tokens() {
+            if let token::Token::Identifier(x) = self.token(index) {
+                return Ok(x.value().to_string());
+            }
+        }
+
+        return Err(format!("malformed {}: missing identifier", tree.name()).into());
+    }
+
+    fn string_literal(&self, tree: &Tree) -> Result<String, Box<dyn Error>> {
+        for index in tree.tokens() {
+            if let token::Token::Literal(token::Literal::String(x)) = self.token(index) {
+                return Ok(x[1..x.len() - 1].to_string());
+            }
+        }
+
+        return Err(format!("malformed {}: missing string literal", tree.name()).into());
+    }
+
+    fn numeric_literals(&self, tree: &Tree) -> Vec<String> {
+        return tree.tokens().filter_map(|x| return match self.token(x) {
+            token::Token::Literal(token::Literal::Numeric(x)) => Some(x.to_string()),
+            _ => None
+        }).collect();
+    }
+
+    fn types(&self, tree: &Tree) -> Vec<token::Type> {
+        return tree.tokens().filter_map(|x| return match self.token(x) {
+            token::Token::Type(x) => Some(x.clone()),
+            _ => None
+        }).collect();
+    }
+
+    fn child<'n>(&self, tree: &'n Tree, name: &str) -> Result<&'n Tree, Box<dyn Error>> {
+        return tree.tree(name).ok_or_else(|| return format!("malformed {}: missing {}", tree.name(), name).into());
+    }
+
+    // declarations
+    fn declaration(&self, tree: &Tree) -> Result<Declaration, Box<dyn Error>> {
+        return match tree.name() {
+            "FunctionDeclaration" => Ok(Declaration::Function(self.function(tree)?)),
+            "TypeDeclaration" => Ok(Declaration::Type(TypeDeclaration {
+                span: self.span(tree),
+                name: self.identifier(tree)?,
+                ty: self.type_assignment(tree)?
+            })),
+            "TableDeclaration" => Ok(Declaration::Table(self.table(tree)?)),
+            "MemoryDeclaration" => Ok(Declaration::Memory(self.memory(tree)?)),
+            "VariableDeclaration" => Ok(Declaration::Variable(self.global(tree)?)),
+            "ImportDeclaration" => {
+                let inner = tree.trees().next().ok_or("malformed ImportDeclaration: missing item")?;
+
+                match inner.name() {
+                    "ImportedItemDeclaration" => Ok(Declaration::Import(ImportDeclaration { span: self.span(tree), ..self.import(inner)? })),
+                    "ImportedModuleDeclaration" => Ok(Declaration::ImportModule(ImportModuleDeclaration {
+                        span: self.span(tree),
+                        path: self.string_literal(inner)?
+                    })),
+                    _ => Err(format!("unexpected imported item: {}", inner.name()).into())
+                }
+            },
+            "ExportDeclaration" => Ok(Declaration::Export(self.export(tree)?)),
+            "AttributedDeclaration" => {
+                let attributes = tree.trees().filter(|x| return x.name() == "Attribute").map(|x| return self.attribute(x)).collect::<Result<_, _>>()?;
+
+                Ok(Declaration::Function(FunctionDeclaration { attributes, ..self.function(self.child(tree, "FunctionDeclaration")?)? }))
+            },
+            "PublicDeclaration" => {
+                let inner = tree.trees().next().ok_or("malformed PublicDeclaration: missing declaration")?;
+
+                match self.declaration(inner)? {
+                    Declaration::Function(x) => Ok(Declaration::Function(FunctionDeclaration { span: self.span(tree), is_public: true, ..x })),
+                    Declaration::Include(x) => Ok(Declaration::Include(IncludeDeclaration { span: self.span(tree), is_public: true, ..x })),
+                    _ => Err(format!("unexpected public declaration: {}", inner.name()).into())
+                }
+            },
+            "StaticAssertion" => Ok(Declaration::StaticAssert(self.static_assertion(tree)?)),
+            "ErrorDeclaration" => Ok(Declaration::Error(ErrorDeclaration { span: self.span(tree) })),
+            "IncludeDeclaration" => Ok(Declaration::Include(IncludeDeclaration {
+                span: self.span(tree),
+                path: self.string_literal(tree)?,
+                is_public: false
+            })),
+            _ => Err(format!("unexpected declaration: {}", tree.name()).into())
+        };
+    }
+
+    fn function(&self, tree: &Tree) -> Result<FunctionDeclaration, Box<dyn Error>> {
+        let is_public = tree.tokens().any(|x| return self.token(x) == &token::Token::Keyword(token::Keyword::Public));
+
+        return Ok(FunctionDeclaration {
+            span: self.span(tree),
+            name: self.identifier(tree)?,
+            is_public,
+            generics: match tree.tree("GenericParameter") {
+                Some(x) => self.generics(x)?,
+                None => vec![]
+            },
+            constraints: match tree.tree("WhereClause") {
+                Some(x) => self.constraints(x)?,
+                None => vec![]
+            },
+            signature: self.signature(self.child(tree, "Signature")?)?,
+            body: self.block(self.child(tree, "FunctionBlock")?)?,
+            attributes: vec![]
+        });
+    }
+
+    fn attribute(&self, tree: &Tree) -> Result<Attribute, Box<dyn Error>> {
+        return Ok(Attribute {
+            span: self.span(tree),
+            name: self.identifier(tree)?,
+            argument: match tree.tree("AttributeArgument") {
+                Some(x) => Some(self.string_literal(x)?),
+                None => None
+            }
+        });
+    }
+
+    fn generics(&self, tree: &Tree) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut list = vec![self.identifier(tree)?];
+
+        for con in tree.trees().filter(|x| return x.name() == "ConGenericParameter") {
+            list.push(self.identifier(con)?);
+        }
+
+        return Ok(list);
+    }
+
+    fn constraints(&self, tree: &Tree) -> Result<Vec<Constraint>, Box<dyn Error>> {
+        let constraint = |x| return Ok::<_, Box<dyn Error>>(Constraint { param: self.identifier(x)?, types: self.types_deep(x) });
+        let mut list = vec![constraint(self.child(tree, "TypeConstraint")?)?];
+
+        for con in tree.trees().filter(|x| return x.name() == "ConTypeConstraint") {
+            list.push(constraint(self.child(con, "TypeConstraint")?)?);
+        }
+
+        return Ok(list);
+    }
+
+    fn table(&self, tree: &Tree) -> Result<TableDeclaration, Box<dyn Error>> {
+        return Ok(TableDeclaration {
+            span: self.span(tree),
+            name: self.identifier(tree)?,
+            ty: self.type_assignment(tree)?
+        });
+    }
+
+    fn memory(&self, tree: &Tree) -> Result<MemoryDeclaration, Box<dyn Error>> {
+        return Ok(MemoryDeclaration {
+            span: self.span(tree),
+            name: self.identifier(tree)?,
+            ty: self.type_assignment(tree)?
+        });
+    }
+
+    fn import(&self, tree: &Tree) -> Result<ImportDeclaration, Box<dyn Error>> {
+        let module = self.string_literal(tree)?;
+        let item_tree = tree.trees().next().ok_or("malformed ImportedItemDeclaration: missing item")?;
+
+        let item = match item_tree.name() {
+            "ImportedFunctionDeclaration" => ImportItem::Function(
+                self.identifier(item_tree)?,
+                self.signature(self.child(item_tree, "Signature")?)?
+            ),
+            "ImportedTableDeclaration" => ImportItem::Table(self.identifier(item_tree)?, self.type_assignment(item_tree)?),
+            "ImportedMemoryDeclaration" => ImportItem::Memory(self.identifier(item_tree)?, self.type_assignment(item_tree)?),
+            "ImportedVariableDeclaration" => ImportItem::Variable(self.binding(item_tree)?, self.type_assignment(item_tree)?),
+            _ => return Err(format!("unexpected imported item: {}", item_tree.name()).into())
+        };
+
+        return Ok(ImportDeclaration {
+            span: self.span(tree),
+            module,
+            item
+        });
+    }
+
+    fn export(&self, tree: &Tree) -> Result<ExportDeclaration, Box<dyn Error>> {
+        let alias = self.string_literal(tree).ok();
+        let item_tree = tree.trees().next().ok_or("malformed ExportDeclaration: missing item")?;
+
+        let item = match item_tree.name() {
+            "FunctionDeclaration" => ExportItem::Function(self.function(item_tree)?),
+            "TableDeclaration" => ExportItem::Table(self.table(item_tree)?),
+            "MemoryDeclaration" => ExportItem::Memory(self.memory(item_tree)?),
+            "VariableDeclaration" => ExportItem::Variable(self.global(item_tree)?),
+            "AliasedExportDeclaration" => ExportItem::Aliased(self.identifier(item_tree)?, self.string_literal(item_tree)?),
+            _ => return Err(format!("unexpected exported item: {}", item_tree.name()).into())
+        };
+
+        return Ok(ExportDeclaration { alias, item });
+    }
+
+    // types
+    fn type_assignment(&self, tree: &Tree) -> Result<TypeExpression, Box<dyn Error>> {
+        let assignment = self.child(tree, "ConTypeAssignment")?;
+
+        return self.type_expression(self.child(assignment, "TypeExpression")?);
+    }
+
+    fn type_expression(&self, tree: &Tree) -> Result<TypeExpression, Box<dyn Error>> {
+        if let Some(x) = tree.trees().next() {
+            return match x.name() {
+                "TypeFunctionExpression" => {
+                    let signature = self.child(x, "TypeSignature")?;
+                    let params = match self.child(signature, "TypeParameter")?.tree("TypeParamSequence") {
+                        Some(sequence) => self.types_deep(sequence),
+                        None => vec![]
+                    };
+                    let result = match signature.tree("ResultType") {
+                        Some(result) => Some(Box::new(self.type_expression(self.child(result, "TypeExpression")?)?)),
+                        None => None
+                    };
+
+                    Ok(TypeExpression::Function(params, result))
+                },
+                "ParentheseTypeVariant" => {
+                    if let Some(range) = x.tree("ConRangeType") {
+                        let mut limits = self.numeric_literals(range).into_iter();
+                        let ty = self.types(range).pop().ok_or("malformed ConRangeType: missing type")?;
+
+                        return Ok(TypeExpression::Range(
+                            limits.next().ok_or("malformed ConRangeType: missing minimum")?,
+                            ty,
+                            limits.next().ok_or("malformed ConRangeType: missing maximum")?
+                        ));
+                    }
+
+                    let tuple = self.child(x, "ConTupleType")?;
+
+                    if let Some(vec) = tuple.tree("VecShorthandType") {
+                        let ty = self.types(tuple).pop().ok_or("vector elements must be a primitive type")?;
+                        let count = self.numeric_literals(vec).pop().ok_or("malformed VecShorthandType: missing length")?;
+
+                        return Ok(TypeExpression::Vector(ty, count));
+                    }
+
+                    Ok(TypeExpression::Tuple(self.tuple_elements(tuple)))
+                },
+                "TypeOfExpression" => Ok(TypeExpression::TypeOf(self.identifier(x)?)),
+                _ => Err(format!("unexpected type expression: {}", x.name()).into())
+            };
+        }
+
+        return match self.first_token(tree)? {
+            token::Token::Type(x) => Ok(TypeExpression::Primitive(x.clone())),
+            token::Token::Identifier(x) => Ok(TypeExpression::Named(x.value().to_string())),
+            x => Err(format!("unexpected type token: {:?}", x).into())
+        };
+    }
+
+    fn types_deep(&self, tree: &Tree) -> Vec<token::Type> {
+        let mut list = self.types(tree);
+
+        for child in tree.trees() {
+            list.extend(self.types_deep(child));
+        }
+
+        return list;
+    }
+
+    // elements are primitive or named, in source order
+    fn tuple_elements(&self, tree: &Tree) -> Vec<TypeExpression> {
+        let mut list = vec![];
+
+        for node in tree.children() {
+            match node {
+                parse_tree::Node::Token(x) => match self.token(*x) {
+                    token::Token::Type(x) => list.push(TypeExpression::Primitive(x.clone())),
+                    token::Token::Identifier(x) => list.push(TypeExpression::Named(x.value().to_string())),
+                    _ => {}
+                },
+                parse_tree::Node::Tree(x) => list.extend(self.tuple_elements(x))
+            }
+        }
+
+        return list;
+    }
+
+    fn signature(&self, tree: &Tree) -> Result<Signature, Box<dyn Error>> {
+        let mut params = vec![];
+
+        if let Some(sequence) = self.child(tree, "Parameter")?.tree("ParamSequence") {
+            params.push(self.param(self.child(sequence, "ParamType")?)?);
+
+            for con in sequence.trees().filter(|x| return x.name() == "ConParamType") {
+                params.push(self.param(self.child(con, "ParamType")?)?);
+            }
+        }
+
+        let result = match tree.tree("ResultType") {
+            Some(x) => Some(self.type_expression(self.child(x, "TypeExpression")?)?),
+            None => None
+        };
+
+        return Ok(Signature { params, result });
+    }
+
+    fn param(&self, tree: &Tree) -> Result<Param, Box<dyn Error>> {
+        let default = match tree.tree("ConDefaultAssignment") {
+            Some(x) => Some(self.expression(self.child(x, "Expression")?)?),
+            None => None
+        };
+
+        return Ok(Param {
+            span: self.span(tree),
+            name: self.identifier(tree)?,
+            ty: self.type_expression(self.child(tree, "TypeExpression")?)?,
+            default,
+            is_variadic: tree.tokens().next().is_some_and(|x| return self.token(x) == &token::Token::Symbol(token::Symbol::Ellipsis))
+        });
+    }
+
+    // statements
+    fn block(&self, tree: &Tree) -> Result<Block, Box<dyn Error>> {
+        let mut statements = vec![];
+        let mut tail = None;
+        let mut trees = tree.trees().peekable();
+
+        while let Some(x) = trees.next() {
+            let is_terminated = x.tokens().any(|x| return self.token(x) == &token::Token::Symbol(token::Symbol::SemiColon));
+
+            if x.name() != "ExpressionStatement" || is_terminated {
+                statements.push(Spanned::new(self.statement(x)?, self.span(x)));
+            }
+            else if trees.peek().is_none() && x.tree("ConAssignmentExpression").is_none() {
+                tail = Some(Spanned::new(self.expression(self.child(x, "Expression")?)?, self.span(x)));
+            }
+            else if x.tree("ConAssignmentExpression").is_some() {
+                return Err(diagnostic::UNEXPECTED_TOKEN.message("expected `;` after assignment").with_span(self.span(x)).into());
+            }
+            else if x.tree("ConIncrementExpression").is_some_and(|x| return self.first_token(x).ok() == Some(&token::Token::Symbol(token::Symbol::Decrement))) {
+                return Err(diagnostic::UNEXPECTED_TOKEN.message(format!("expected `;` after decrement; {}", definition::DECREMENT_HINT)).with_span(self.span(x)).into());
+            }
+            // a misspelled keyword reads as a name, so `retrun 1;` is a name
+            // missing its `;` followed by another statement, and `whiel (x)`
+            // a call
+            else if let Expression::Identifier(name) | Expression::Call { callee: name, .. } = self.expression(self.child(x, "Expression")?)? {
+                let message = match definition::keyword_typo(&name) {
+                    Some(keyword) => format!("expected `;` after expression; `{}` is not a keyword, did you mean `{}`?", name, keyword),
+                    None => "expected `;` after expression".to_string()
+                };
+
+                return Err(diagnostic::UNEXPECTED_TOKEN.message(message).with_span(self.span(x)).into());
+            }
+            else {
+                return Err(diagnostic::UNEXPECTED_TOKEN.message("expected `;` after expression").with_span(self.span(x)).into());
+            }
+        }
+
+        return Ok(Block {
+            statements,
+            tail
+        });
+    }
+
+    fn statement(&self, tree: &Tree) -> Result<Statement, Box<dyn Error>> {
+        return match tree.name() {
+            "VariableDeclaration" => Ok(Statement::Variable(self.variable(tree)?)),
+            "ExpressionStatement" => {
+                let expr = self.expression(self.child(tree, "Expression")?)?;
+
+                if let Some(x) = tree.tree("ConAssignmentExpression") {
+                    return Ok(Statement::Assignment(expr, self.expression(self.child(x, "Expression")?)?));
+                }
+
+                match tree.tree("ConIncrementExpression") {
+                    Some(x) => self.increment(expr, x),
+                    None => Ok(Statement::Expression(expr))
+                }
+            },
+            "IfStatement" => {
+                let mut else_ifs = vec![];
+
+                for x in tree.trees().filter(|x| return x.name() == "ElseIfStatement") {
+                    else_ifs.push((self.condition(x)?, self.block(self.child(x, "FunctionBlock")?)?));
+                }
+
+                let else_block = match tree.tree("ElseStatement") {
+                    Some(x) => Some(self.block(self.child(x, "FunctionBlock")?)?),
+                    None => None
+                };
+
+                Ok(Statement::If(IfStatement {
+                    condition: self.condition(tree)?,
+                    block: self.block(self.child(tree, "FunctionBlock")?)?,
+                    else_ifs,
+                    else_block
+                }))
+            },
+            "WhileStatement" => Ok(Statement::While(WhileStatement {
+                condition: self.condition(tree)?,
+                block: self.block(self.child(tree, "FunctionBlock")?)?
+            })),
+            "RepeatStatement" => Ok(Statement::Repeat(RepeatStatement {
+                count: self.numeric_literals(tree).into_iter().next().ok_or("malformed RepeatStatement: missing count")?,
+                block: self.block(self.child(tree, "FunctionBlock")?)?
+            })),
+            "DeferStatement" => Ok(Statement::Defer(self.block(self.child(tree, "FunctionBlock")?)?)),
+            "ReturnStatement" => match tree.tree("Expression") {
+                Some(x) => Ok(Statement::Return(Some(self.expression(x)?))),
+                None => Ok(Statement::Return(None))
+            },
+            "BreakStatement" => Ok(Statement::Break),
+            "ContinueStatement" => Ok(Statement::Continue),
+            "StaticAssertion" => Ok(Statement::StaticAssert(self.static_assertion(tree)?)),
+            "FunctionDeclaration" => Ok(Statement::Function(self.function(tree)?)),
+            "FunctionBlock" => Ok(Statement::Block(self.block(tree)?)),
+            _ => Err(format!("unexpected statement: {}", tree.name()).into())
+        };
+    }
+
+    fn static_assertion(&self, tree: &Tree) -> Result<StaticAssertion, Box<dyn Error>> {
+        return Ok(StaticAssertion {
+            span: self.span(tree),
+            condition: self.expression(self.child(tree, "Expression")?)?,
+            message: unescape(&self.string_literal(tree)?)?
+        });
+    }
+
+    // `i++` and `i--` are sugar for `i <- i + 1` and `i <- i - 1`
+    fn increment(&self, target: Expression, tree: &Tree) -> Result<Statement, Box<dyn Error>> {
+        let op = match self.first_token(tree)? {
+            token::Token::Symbol(token::Symbol::Increment) => token::Symbol::Plus,
+            token::Token::Symbol(token::Symbol::Decrement) => token::Symbol::Minus,
+            x => return Err(format!("unexpected postfix operator: {:?}", x).into())
+        };
+
+        let what = match &target {
+            Expression::Identifier(_) => None,
+            Expression::Literal(x) => Some(format!("the literal `{}`", x)),
+            Expression::Call { callee, .. } => Some(format!("the result of `{}`", callee)),
+            _ => Some("an expression".to_string())
+        };
+
+        if let Some(what) = what {
+            return Err(format!("cannot increment or decrement {}, only variables can be", what).into());
+        }
+
+        return Ok(Statement::Assignment(target.clone(), Expression::Binary {
+            op,
+            lhs: Box::new(target),
+            rhs: Box::new(Expression::Literal("1".to_string()))
+        }));
+    }
+
+    fn variable(&self, tree: &Tree) -> Result<VariableDeclaration, Box<dyn Error>> {
+        let pattern = match tree.tree("MultiIdDeclaration") {
+            Some(x) => Pattern::Tuple(self.bindings(x)?),
+            None => Pattern::Single(self.binding(self.child(tree, "MutableIdDeclaration")?)?)
+        };
+        let ty = tree.tree("ConVariableType").map(|x| return self.type_expression(self.child(x, "TypeExpression")?)).transpose()?;
+        let value = match (tree.tree("ConAssignmentExpression"), &ty, &pattern) {
+            (None, _, Pattern::Tuple(_)) => return Err("a destructuring `let` needs an initializer".into()),
+            (_, Some(_), Pattern::Tuple(_)) => return Err("a destructuring `let` cannot have a type; each name takes the type of its value".into()),
+            (Some(x), _, _) => self.expression(self.child(x, "Expression")?)?,
+            (None, Some(ty), Pattern::Single(binding)) => zero(&binding.name, ty)?,
+            (None, None, Pattern::Single(binding)) => {
+                return Err(format!("`let {}` needs a type when it has no initializer, as in `let {}: i32;`", binding.name, binding.name).into());
+            }
+        };
+
+        return Ok(VariableDeclaration {
+            span: self.span(tree),
+            pattern,
+            ty,
+            value
+        });
+    }
+
+    // a global is initialized by a constant, so it cannot be left to zero
+    fn global(&self, tree: &Tree) -> Result<VariableDeclaration, Box<dyn Error>> {
+        if tree.tree("ConVariableType").is_some() && tree.tree("ConAssignmentExpression").is_none() {
+            return Err(format!("global `{}` needs an initializer", self.identifier(self.child(tree, "MutableIdDeclaration")?)?).into());
+        }
+
+        return self.variable(tree);
+    }
+
+    fn binding(&self, tree: &Tree) -> Result<Binding, Box<dyn Error>> {
+        let is_mutable = tree.tokens().any(|x| return self.token(x) == &token::Token::Keyword(token::Keyword::Mutable));
+
+        return Ok(Binding {
+            name: self.identifier(tree)?,
+            is_mutable
+        });
+    }
+
+    fn bindings(&self, tree: &Tree) -> Result<Vec<Binding>, Box<dyn Error>> {
+        let mut list = vec![self.binding(self.child(tree, "MutableIdDeclaration")?)?];
+
+        for con in tree.trees().filter(|x| return x.name() == "ConMultiIdDeclaration") {
+            list.push(self.binding(self.child(con, "MutableIdDeclaration")?)?);
+        }
+
+        return Ok(list);
+    }
+
+    fn condition(&self, tree: &Tree) -> Result<Expression, Box<dyn Error>> {
+        return self.grouped(self.child(tree, "GroupedOrTupleExpression")?);
+    }
+
+    // expressions
+    pub fn expression(&self, tree: &Tree) -> Result<Expression, Box<dyn Error>> {
+        return Ok(self.chain(tree)?.into_expression());
+    }
+
+    fn chain(&self, tree: &Tree) -> Result<ExpressionChain, Box<dyn Error>> {
+        let mut chain = match tree.children().first() {
+            Some(parse_tree::Node::Tree(x)) if x.name() == "UnaryExpression" => {
+                let op = match self.first_token(x)? {
+                    token::Token::Symbol(op) => op.clone(),
+                    x => return Err(format!("unexpected unary operator: {:?}", x).into())
+                };
+                let mut chain = self.chain(self.child(x, "OperandExpression")?)?;
+
+                let operand = chain.operands.remove(0);
+                chain.operands.insert(0, Expression::Unary { op, operand: Box::new(operand) });
+
+                chain
+            },
+            Some(parse_tree::Node::Tree(x)) => ExpressionChain {
+                operands: vec![self.primary(x)?],
+                operators: vec![],
+                conditional: None
+            },
+            Some(parse_tree::Node::Token(index)) => match self.token(*index) {
+                // string literals keep their quotes
+                token::Token::Literal(token::Literal::Numeric(x) | token::Literal::String(x)) => ExpressionChain {
+                    operands: vec![Expression::Literal(x.to_string())],
+                    operators: vec![],
+                    conditional: None
+                },
+                x => return Err(format!("unexpected expression token: {:?}", x).into())
+            },
+            None => return Err("malformed Expression: missing operand".into())
+        };
+
+        for x in tree.trees().filter(|x| return x.name() == "ConBinaryExpression") {
+            let op = match self.first_token(x)? {
+                token::Token::Symbol(op) => op.clone(),
+                x => return Err(format!("unexpected binary operator: {:?}", x).into())
+            };
+            let rhs = self.chain(self.child(x, "OperandExpression")?)?;
+
+            chain.operators.push(op);
+            chain.operands.extend(rhs.operands);
+        }
+
+        if let Some(x) = tree.tree("ConConditionalExpression") {
+            let mut branches = x.trees().filter(|x| return x.name() == "Expression");
+
+            let consequent = self.expression(branches.next().ok_or("malformed ConConditionalExpression: missing consequent")?)?;
+            let alternative = self.expression(branches.next().ok_or("malformed ConConditionalExpression: missing alternative")?)?;
+
+            chain.conditional = Some((consequent, alternative));
+        }
+
+        return Ok(chain);
+    }
+
+    fn primary(&self, tree: &Tree) -> Result<Expression, Box<dyn Error>> {
+        return match tree.name() {
+            "WithIdExpression" => {
+                let mut name = self.identifier(tree)?;
+
+                for member in tree.trees().filter(|x| return x.name() == "ConMemberExpression") {
+                    name = format!("{}.{}", name, self.identifier(member)?);
+                }
+
+                if let Some(x) = tree.tree("FuncCallArg") {
+                    return Ok(Expression::Call {
+                        callee: name,
+                        args: self.args(x)?
+                    });
+                }
+
+                if let Some(x) = tree.tree("ConCallIndirectExpression") {
+                    return Ok(Expression::GenericCall {
+                        callee: name,
+                        types: self.generic_types(self.child(x, "GenericArgument")?)?,
+                        args: self.args(self.child(x, "FuncCallArg")?)?
+                    });
+                }
+
+                Ok(Expression::Identifier(name))
+            },
+            "TypeOfExpression" => Ok(Expression::TypeOf(self.identifier(tree)?)),
+            "CastExpression" => {
+                let ty = self.types(tree).into_iter().next().ok_or("malformed CastExpression: missing type")?;
+
+                match tree.tree("CastOperand") {
+                    Some(x) => Ok(Expression::Cast { ty, operand: Box::new(self.expression(self.child(x, "Expression")?)?) }),
+                    None => Ok(Expression::Type(ty))
+                }
+            },
+            "OffsetExpression" => {
+                let offset = match (tree.tree("GroupedOrTupleExpression"), tree.tokens().nth(1).map(|x| return self.token(x))) {
+                    (Some(x), _) => self.grouped(x)?,
+                    (None, Some(token::Token::Literal(token::Literal::Numeric(x)))) => Expression::Literal(x.to_string()),
+                    (None, Some(token::Token::Identifier(x))) => Expression::Identifier(x.value().to_string()),
+                    _ => return Err("malformed OffsetExpression: missing offset".into())
+                };
+                let signature = match tree.tree("GenericArgument") {
+                    Some(x) => Some(self.single_type(x)?),
+                    None => None
+                };
+                let base = match tree.tokens().filter_map(|x| return match self.token(x) {
+                    token::Token::Identifier(x) => Some(x.value().to_string()),
+                    _ => None
+                }).last() {
+                    Some(x) => x,
+                    None => return Err("malformed OffsetExpression: missing base".into())
+                };
+
+                let align = match tree.tree("OffsetAlignment") {
+                    Some(x) => Some(self.numeric_literals(x).pop().ok_or("malformed OffsetAlignment: missing alignment")?),
+                    None => None
+                };
+
+                let expr = Expression::Offset {
+                    offset: Box::new(offset),
+                    signature,
+                    base,
+                    align
+                };
+
+                match tree.tree("ConCallIndirectExpression") {
+                    Some(x) => self.call_indirect(expr, x),
+                    None => Ok(expr)
+                }
+            },
+            "GroupedOrTupleExpression" => self.grouped(tree),
+            _ => Err(format!("unexpected expression: {}", tree.name()).into())
+        };
+    }
+
+    fn grouped(&self, tree: &Tree) -> Result<Expression, Box<dyn Error>> {
+        let mut list = vec![self.expression(self.child(tree, "Expression")?)?];
+
+        for con in tree.trees().filter(|x| return x.name() == "ConExprSequence") {
+            list.push(self.expression(self.child(con, "Expression")?)?);
+        }
+
+        if list.len() == 1 {
+            return Ok(list.remove(0));
+        }
+
+        return Ok(Expression::Tuple(list));
+    }
+
+    fn call_indirect(&self, target: Expression, tree: &Tree) -> Result<Expression, Box<dyn Error>> {
+        return Ok(Expression::CallIndirect {
+            target: Box::new(target),
+            signature: self.single_type(self.child(tree, "GenericArgument")?)?,
+            args: self.args(self.child(tree, "FuncCallArg")?)?
+        });
+    }
+
+    fn generic_types(&self, tree: &Tree) -> Result<Vec<TypeExpression>, Box<dyn Error>> {
+        let mut list = vec![self.type_expression(self.child(tree, "TypeExpression")?)?];
+
+        for con in tree.trees().filter(|x| return x.name() == "ConGenericArgument") {
+            list.push(self.type_expression(self.child(con, "TypeExpression")?)?);
+        }
+
+        return Ok(list);
+    }
+
+    // only an instantiation of a generic function takes several types
+    fn single_type(&self, tree: &Tree) -> Result<TypeExpression, Box<dyn Error>> {
+        return match self.generic_types(tree)?.as_slice() {
+            [x] => Ok(x.clone()),
+            list => Err(format!("expected a single type in `<..>`, found {}", list.len()).into())
+        };
+    }
+
+    fn args(&self, tree: &Tree) -> Result<Vec<Expression>, Box<dyn Error>> {
+        let mut list = vec![];
+
+        if let Some(sequence) = tree.tree("FuncCallArgSequence") {
+            list.push(self.expression(self.child(sequence, "Expression")?)?);
+
+            for con in sequence.trees().filter(|x| return x.name() == "ConFuncCallArgSequence") {
+                list.push(self.expression(self.child(con, "Expression")?)?);
+            }
+        }
+
+        return Ok(list);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::assert_parses;
+
+    // the source text of the innermost node at the offset of the marker
+    fn innermost<'a>(text: &'a str, marker: &str) -> Option<&'a str> {
+        let offset = text.find(marker).unwrap();
+        let program = assert_parses(text);
+        let path = find_at(&program, offset)?;
+
+        return path.iter().rev().find_map(|x| return x.span()).map(|x| return &text[x.clone()]);
+    }
+
+    #[test]
+    fn tell_pure_expressions() {
+        let expr = |text: &str| {
+            let program = assert_parses(&format!("fn f(a: i32, b: i32) -> i32 {{ {} }}", text));
+
+            return match &program.declarations[0] {
+                Declaration::Function(x) => x.body.tail.clone().unwrap(),
+                _ => unreachable!()
+            };
+        };
+
+        assert!(expr("a + -b * 2").is_pure(false));
+        assert!(!expr("a + -b * 2").is_pure(true));
+        assert!(expr("a ? i64(b) : (b << 1)").is_pure(true));
+        assert!(!expr("a + f()").is_pure(false));
+        assert!(!expr("a ? 1 : a %u b").is_pure(false));
+        assert!(!expr("@a<i32>(memory)").is_pure(false));
+    }
+
+    #[test]
+    fn find_param() {
+        let text = "fn f(a: i32, bb: i64) -> i64 { bb }";
+
+        assert_eq!(innermost(text, "bb:"), Some("bb: i64"));
+        assert_eq!(innermost(text, "i64)"), Some("bb: i64"));
+    }
+
+    #[test]
+    fn find_enclosing_node_of_expression() {
+        let text = "fn f(a: i32) -> i32 { if (a > 0) { let b <- a * 2; } fn g() -> i32 { let c <- 1; c } a }";
+        let program = assert_parses(text);
+        let path = find_at(&program, text.find("* 2").unwrap()).unwrap();
+
+        assert_eq!(path.len(), 3);
+        assert!(matches!(path[0], Node::Declaration(Declaration::Function(x)) if x.name == "f"));
+        assert!(matches!(path[1], Node::Statement(Spanned { node: Statement::If(_), .. })));
+        assert_eq!(innermost(text, "* 2"), Some("let b <- a * 2;"));
+        assert_eq!(innermost(text, "<- 1"), Some("let c <- 1;"));
+        assert!(matches!(find_at(&program, text.find("<- 1").unwrap()).unwrap()[1], Node::Function(x) if x.name == "g"));
+    }
+
+    #[test]
+    fn find_statement_expression() {
+        let text = "fn f(a: i32) -> i32 { a <- a + 1; ret a * 2; }\nfn g(a: i32) -> i32 { a * 3 }";
+        let program = assert_parses(text);
+        let path = find_at(&program, text.find("* 2").unwrap()).unwrap();
+
+        assert_eq!(path.len(), 3);
+        assert!(matches!(path[1], Node::Statement(Spanned { node: Statement::Return(_), .. })));
+        assert!(matches!(path[2], Node::Expression(_, None)));
+        assert_eq!(innermost(text, "* 2"), Some("ret a * 2;"));
+        assert_eq!(innermost(text, "+ 1"), Some("a <- a + 1;"));
+        assert!(matches!(find_at(&program, text.find("* 3").unwrap()).unwrap()[1], Node::Expression(_, Some(_))));
+        assert_eq!(innermost(text, "* 3"), Some("a * 3"));
+    }
+
+    #[test]
+    fn find_between_tokens() {
+        let text = "fn f() -> i32 {\n    let a <- 1;\n\n    a\n}\nmem m = (1; page; 2);\n";
+
+        assert_eq!(innermost(text, "\n\n"), Some(&text[..text.find("\nmem").unwrap()]));
+        assert_eq!(innermost(text, "m ="), Some("mem m = (1; page; 2);"));
+        assert_eq!(innermost(text, "\nmem"), None);
+    }
+}
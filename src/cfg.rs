@@ -0,0 +1,222 @@
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::ir::Instruction;
+
+// the control-flow graph of a function, over runs of its lowered instructions.
+// a run starts at a loop, an `else`, the `end` a branch can reach, or after a
+// branch, and the last run holds the closing `end` of the function
+#[derive(Debug, Clone, PartialEq)]
+pub struct Graph {
+    pub blocks: Vec<Range<usize>>,
+    pub edges: Vec<(usize, usize)>
+}
+
+// where a structured instruction closes, and the `else` of each `if`
+struct Nesting {
+    ends: HashMap<usize, usize>,
+    elses: HashMap<usize, usize>,
+    // the `if` of each `else`
+    ifs: HashMap<usize, usize>
+}
+
+impl Nesting {
+    fn of(body: &[Instruction]) -> Self {
+        let mut nesting = Self { ends: HashMap::new(), elses: HashMap::new(), ifs: HashMap::new() };
+        let mut stack = vec![];
+
+        for (index, instruction) in body.iter().enumerate() {
+            match instruction {
+                Instruction::Block(_) | Instruction::Loop(_) | Instruction::If(_) => stack.push(index),
+                Instruction::Else => if let Some(x) = stack.last() {
+                    nesting.elses.insert(*x, index);
+                    nesting.ifs.insert(index, *x);
+                },
+                Instruction::End => if let Some(x) = stack.pop() {
+                    nesting.ends.insert(x, index);
+                },
+                _ => {}
+            }
+        }
+
+        return nesting;
+    }
+}
+
+pub fn build(body: &[Instruction]) -> Graph {
+    let nesting = Nesting::of(body);
+    let exit = body.len().saturating_sub(1);
+    let mut leaders = BTreeSet::new();
+
+    leaders.insert(0);
+    leaders.insert(exit);
+
+    for (index, instruction) in body.iter().enumerate() {
+        match instruction {
+            Instruction::Loop(_) | Instruction::Else => { leaders.insert(index); },
+            Instruction::Block(_) | Instruction::If(_) => if let Some(x) = nesting.ends.get(&index) {
+                leaders.insert(*x);
+            },
+            _ => {}
+        }
+
+        if matches!(instruction, Instruction::Br(_) | Instruction::BrIf(_) | Instruction::Return | Instruction::ReturnCall(_) | Instruction::ReturnCallIndirect(..) | Instruction::Unreachable | Instruction::If(_)) {
+            leaders.insert(index + 1);
+        }
+    }
+
+    let leaders = leaders.into_iter().filter(|x| return *x < body.len()).collect::<Vec<_>>();
+    let blocks = leaders.iter().zip(leaders.iter().skip(1).chain(std::iter::once(&body.len())))
+        .map(|(start, end)| return *start..*end)
+        .collect::<Vec<_>>();
+    let block_of = |index: usize| return blocks.iter().position(|x| return x.contains(&index));
+
+    // the openers enclosing each instruction, to resolve branch depths
+    let mut stack: Vec<usize> = vec![];
+    let mut targets: HashMap<usize, Vec<usize>> = HashMap::new();
+
+    for (index, instruction) in body.iter().enumerate() {
+        let label = |depth: u32| -> usize {
+            let opener = usize::try_from(depth).ok()
+                .and_then(|x| return stack.len().checked_sub(x + 1))
+                .map(|x| return stack[x]);
+
+            return match opener {
+                Some(x) if matches!(body[x], Instruction::Loop(_)) => x,
+                Some(x) => nesting.ends.get(&x).copied().unwrap_or(exit),
+                None => exit
+            };
+        };
+        let next = index + 1;
+        let successors = match instruction {
+            Instruction::Br(x) => vec![label(*x)],
+            Instruction::BrIf(x) => vec![label(*x), next],
+            Instruction::Return | Instruction::ReturnCall(_) | Instruction::ReturnCallIndirect(..) => vec![exit],
+            Instruction::Unreachable => vec![],
+            Instruction::If(_) => vec![next, nesting.elses.get(&index).or_else(|| return nesting.ends.get(&index)).copied().unwrap_or(exit)],
+            // the end of a `then` branch skips its `else`
+            _ if matches!(body.get(next), Some(Instruction::Else)) => {
+                vec![nesting.ifs.get(&next).and_then(|x| return nesting.ends.get(x)).copied().unwrap_or(exit)]
+            },
+            _ if next < body.len() => vec![next],
+            _ => vec![]
+        };
+
+        targets.insert(index, successors);
+
+        match instruction {
+            Instruction::Block(_) | Instruction::Loop(_) | Instruction::If(_) => stack.push(index),
+            Instruction::End => { stack.pop(); },
+            _ => {}
+        }
+    }
+
+    let mut edges = vec![];
+
+    for (from, block) in blocks.iter().enumerate() {
+        let last = block.end - 1;
+
+        for to in targets.get(&last).into_iter().flatten().filter_map(|x| return block_of(*x)) {
+            if !edges.contains(&(from, to)) {
+                edges.push((from, to));
+            }
+        }
+    }
+
+    return Graph { blocks, edges };
+}
+
+// a Graphviz `digraph` of the function, one box per run of instructions
+pub fn dump_function(name: &str, body: &[Instruction]) -> String {
+    let graph = build(body);
+    let mut out = format!("digraph \"{}\" {{\n    node [shape=box, fontname=monospace];\n", escape(name));
+
+    for (index, block) in graph.blocks.iter().enumerate() {
+        let label = block.clone()
+            .map(|x| return format!("{:04} {}\\l", x, escape(&body[x].to_wat())))
+            .collect::<String>();
+
+        writeln!(out, "    b{} [label=\"{}\"];", index, label).expect("unexpected write failure");
+    }
+
+    for (from, to) in graph.edges.iter() {
+        writeln!(out, "    b{} -> b{};", from, to).expect("unexpected write failure");
+    }
+
+    out.push_str("}\n");
+
+    return out;
+}
+
+pub fn escape(text: &str) -> String {
+    return text.replace('\\', "\\\\").replace('"', "\\\"");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{BlockType, ValType};
+
+    #[test]
+    fn split_if_into_blocks() {
+        // fn f(a: i32) -> i32 { if (a) { ret 1; } 0 }
+        let body = vec![
+            Instruction::LocalGet(0),
+            Instruction::If(BlockType::Empty),
+            Instruction::I32Const(1),
+            Instruction::Return,
+            Instruction::End,
+            Instruction::I32Const(0),
+            Instruction::End
+        ];
+
+        assert_eq!(build(&body), Graph {
+            blocks: vec![0..2, 2..4, 4..6, 6..7],
+            edges: vec![(0, 1), (0, 2), (1, 3), (2, 3)]
+        });
+    }
+
+    #[test]
+    fn link_loop_back_edges() {
+        // while (a) { a <- a - 1; }
+        let body = vec![
+            Instruction::Block(BlockType::Empty),
+            Instruction::Loop(BlockType::Empty),
+            Instruction::LocalGet(0),
+            Instruction::Eqz(ValType::I32),
+            Instruction::BrIf(1),
+            Instruction::LocalGet(0),
+            Instruction::I32Const(1),
+            Instruction::Binary(ValType::I32, crate::ir::BinaryOp::Sub),
+            Instruction::LocalSet(0),
+            Instruction::Br(0),
+            Instruction::End,
+            Instruction::End,
+            Instruction::End
+        ];
+
+        assert_eq!(build(&body), Graph {
+            blocks: vec![0..1, 1..5, 5..10, 10..11, 11..12, 12..13],
+            edges: vec![(0, 1), (1, 4), (1, 2), (2, 1), (3, 4), (4, 5)]
+        });
+    }
+
+    #[test]
+    fn skip_else_after_then_branch() {
+        let body = vec![
+            Instruction::LocalGet(0),
+            Instruction::If(BlockType::Empty),
+            Instruction::Call(0),
+            Instruction::Else,
+            Instruction::Call(1),
+            Instruction::End,
+            Instruction::End
+        ];
+
+        assert_eq!(build(&body).edges, vec![(0, 1), (0, 2), (1, 3), (2, 3), (3, 4)]);
+        assert!(dump_function("f", &body).contains("    b1 [label=\"0002 call 0\\l\"];\n"));
+    }
+}
@@ -0,0 +1,1977 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::num::IntErrorKind;
+
+use crate::ast;
+use crate::diagnostic;
+use crate::ir;
+use crate::optimizer;
+use crate::token;
+use crate::visitor;
+use crate::visitor::Visitor;
+
+// known value types of names in scope, names of unknown type are left out
+pub type Scope = HashMap<String, token::Type>;
+
+// signatures of every function that can be called by name
+pub type Signatures<'a> = HashMap<&'a str, &'a ast::Signature>;
+
+// engines limit a function to 1000 params and results, which a vector expands into
+const MAX_VECTOR_LENGTH: usize = 1000;
+
+// a page is 64 KiB, so a 32-bit memory holds at most 65536 of them
+pub const MAX_PAGE_COUNT: u32 = 65536;
+const MAX_REPEAT_COUNT: u32 = 2_147_483_647;
+
+// types of literals without a suffix, a float always takes the default while
+// an integer takes it only when its context does not decide
+#[derive(Debug, Clone, PartialEq)]
+pub struct Defaults {
+    pub int: token::Type,
+    pub float: token::Type
+}
+
+impl Default for Defaults {
+    fn default() -> Self {
+        return Self { int: token::Type::I32, float: token::Type::F64 };
+    }
+}
+
+// main program section
+pub fn check(program: &ast::Program) -> Result<(), Box<dyn Error>> {
+    let signatures = function_signatures(program);
+
+    check_recursive_types(program)?;
+    check_types(program)?;
+    check_strings(program)?;
+    check_static_assertions(program)?;
+    check_defaults(program, &signatures)?;
+
+    for decl in program.declarations.iter() {
+        let function = match decl {
+            ast::Declaration::Function(x) => x,
+            ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Function(x), .. }) => x,
+            _ => continue
+        };
+
+        check_attributes(function)?;
+        check_returns(function)?;
+        check_defers(function)?;
+        check_loop_exits(function)?;
+        check_body(function, &signatures)?;
+    }
+
+    return Ok(());
+}
+
+// unknown attributes are left to the `unknown-attributes` lint
+fn check_attributes(function: &ast::FunctionDeclaration) -> Result<(), Box<dyn Error>> {
+    for (i, attribute) in function.attributes.iter().enumerate().filter(|(_, x)| return ast::ATTRIBUTES.contains(&x.name.as_str())) {
+        if function.attributes[..i].iter().any(|x| return x.name == attribute.name) {
+            return Err(format!("attribute `{}` is given more than once to function `{}`", attribute.name, function.name).into());
+        }
+
+        if attribute.name == "inline" && attribute.argument.is_some() {
+            return Err(format!("attribute `inline` of function `{}` takes no argument", function.name).into());
+        }
+    }
+
+    return Ok(());
+}
+
+fn check_returns(function: &ast::FunctionDeclaration) -> Result<(), Box<dyn Error>> {
+    match &function.signature.result {
+        Some(_) if !is_returning(&function.body) => {
+            return Err(diagnostic::MISSING_RETURN.message(format!("function `{}` does not return a value on all paths", function.name)).into());
+        },
+        None if function.body.tail.is_some() => {
+            return Err(format!("function `{}` has no result type, but its body ends in a value", function.name).into());
+        },
+        _ => {}
+    }
+
+    return Ok(());
+}
+
+// unsuffixed float literals are given the default suffix, so later stages
+// read their type from the literal alone
+pub fn default_literals(program: &mut ast::Program, defaults: &Defaults) {
+    for decl in program.declarations.iter_mut() {
+        match decl {
+            ast::Declaration::Function(x) | ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Function(x), .. }) => {
+                default_function(x, defaults);
+            },
+            ast::Declaration::Variable(x) | ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Variable(x), .. }) => {
+                default_expression(&mut x.value, defaults);
+            },
+            ast::Declaration::StaticAssert(x) => default_expression(&mut x.condition, defaults),
+            _ => {}
+        }
+    }
+}
+
+fn default_function(function: &mut ast::FunctionDeclaration, defaults: &Defaults) {
+    for x in function.signature.params.iter_mut().filter_map(|x| return x.default.as_mut()) {
+        default_expression(x, defaults);
+    }
+
+    default_block(&mut function.body, defaults);
+}
+
+fn default_block(block: &mut ast::Block, defaults: &Defaults) {
+    for stmt in block.statements.iter_mut() {
+        match &mut stmt.node {
+            ast::Statement::Variable(x) => default_expression(&mut x.value, defaults),
+            ast::Statement::Expression(x) | ast::Statement::Return(Some(x)) => default_expression(x, defaults),
+            ast::Statement::Assignment(target, value) => {
+                default_expression(target, defaults);
+                default_expression(value, defaults);
+            },
+            ast::Statement::If(x) => {
+                default_expression(&mut x.condition, defaults);
+                default_block(&mut x.block, defaults);
+
+                for (condition, block) in x.else_ifs.iter_mut() {
+                    default_expression(condition, defaults);
+                    default_block(block, defaults);
+                }
+
+                if let Some(block) = &mut x.else_block {
+                    default_block(block, defaults);
+                }
+            },
+            ast::Statement::While(x) => {
+                default_expression(&mut x.condition, defaults);
+                default_block(&mut x.block, defaults);
+            },
+            ast::Statement::Repeat(x) => default_block(&mut x.block, defaults),
+            ast::Statement::Defer(x) => default_block(x, defaults),
+            ast::Statement::StaticAssert(x) => default_expression(&mut x.condition, defaults),
+            ast::Statement::Function(x) => default_function(x, defaults),
+            ast::Statement::Block(x) => default_block(x, defaults),
+            ast::Statement::Return(None) | ast::Statement::Break | ast::Statement::Continue => {}
+        }
+    }
+
+    if let Some(tail) = &mut block.tail {
+        default_expression(tail, defaults);
+    }
+}
+
+fn default_expression(expr: &mut ast::Expression, defaults: &Defaults) {
+    match expr {
+        ast::Expression::Literal(x) if literal_type(x) == Some(token::Type::F64) && !x.ends_with("f64") => {
+            x.push_str(ast::type_name(&defaults.float));
+        },
+        ast::Expression::Call { args, .. } | ast::Expression::Tuple(args) | ast::Expression::Variadic { args, .. } => {
+            args.iter_mut().for_each(|x| return default_expression(x, defaults));
+        },
+        ast::Expression::CallIndirect { target, args, .. } => {
+            default_expression(target, defaults);
+            args.iter_mut().for_each(|x| return default_expression(x, defaults));
+        },
+        ast::Expression::Unary { operand, .. } | ast::Expression::Cast { operand, .. } => default_expression(operand, defaults),
+        ast::Expression::Binary { lhs, rhs, .. } => {
+            default_expression(lhs, defaults);
+            default_expression(rhs, defaults);
+        },
+        ast::Expression::Conditional { condition, consequent, alternative } => {
+            default_expression(condition, defaults);
+            default_expression(consequent, defaults);
+            default_expression(alternative, defaults);
+        },
+        ast::Expression::Offset { offset, .. } => default_expression(offset, defaults),
+        _ => {}
+    }
+}
+
+// operators with an unsigned operand are replaced by their unsigned forms, so
+// later stages pick the instruction from the operator alone. operations on
+// untyped literals alone take the signedness their context expects, and a
+// decimal literal has to fit the range of the type it is expected to be
+pub fn sign_operators(program: &mut ast::Program, defaults: &Defaults) -> Result<(), Box<dyn Error>> {
+    let owned = owned_signatures(program);
+    let signatures = borrow_signatures(&owned);
+    let globals = global_scope(program, &defaults.int);
+
+    for decl in program.declarations.iter_mut() {
+        if let ast::Declaration::Function(x) | ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Function(x), .. }) = decl {
+            let typing = Typing::new(&globals, x, &signatures, &defaults.int);
+
+            walk_typed(&mut x.body, &typing, &mut sign_expression)?;
+        }
+    }
+
+    return Ok(());
+}
+
+fn sign_expression(expr: &mut ast::Expression, expected: Option<&token::Type>, typing: &Typing) -> Result<(), Box<dyn Error>> {
+    match expr {
+        ast::Expression::Binary { .. } => {
+            let ty = typing.operation(expr, expected);
+
+            if let ast::Expression::Binary { op, lhs, rhs } = expr {
+                let is_unsigned_operation = [lhs, rhs].iter()
+                    .filter_map(|x| return typing.type_of(x))
+                    .chain(ty)
+                    .any(|x| return is_unsigned(&x));
+
+                if is_unsigned_operation {
+                    if let Some(x) = unsigned_operator(op) {
+                        *op = x;
+                    }
+                }
+            }
+        },
+        ast::Expression::Literal(x) => check_range(x, x, expected)?,
+        ast::Expression::Unary { op: token::Symbol::Minus, operand } => {
+            if let ast::Expression::Literal(x) = operand.as_ref() {
+                check_range(&format!("-{}", x), x, expected)?;
+            }
+        },
+        _ => {}
+    }
+
+    return Ok(());
+}
+
+// a decimal literal is a number of the type, while one in another radix
+// gives the bits of the type and may take any value of its width
+fn check_range(text: &str, literal: &str, expected: Option<&token::Type>) -> Result<(), Box<dyn Error>> {
+    let ty = match expected {
+        Some(x @ (token::Type::I32 | token::Type::U32 | token::Type::I64 | token::Type::U64)) if is_untyped(&ast::Expression::Literal(literal.to_string())) => x,
+        _ => return Ok(())
+    };
+    let is_negative = text.starts_with('-');
+    let (digits, radix) = radix(literal);
+    let magnitude = match u128::from_str_radix(digits, radix) {
+        Ok(x) => x,
+        Err(_) => return Err(format!("invalid integer literal `{}`", text).into())
+    };
+    let (min, max) = match (ty, radix) {
+        (token::Type::I32, 10) => (1 << 31, u128::from(i32::MAX.unsigned_abs())),
+        (token::Type::I64, 10) => (1 << 63, u128::from(i64::MAX.unsigned_abs())),
+        (token::Type::U32, 10) => (0, u128::from(u32::MAX)),
+        (token::Type::U64, 10) => (0, u128::from(u64::MAX)),
+        (token::Type::I32 | token::Type::U32, _) => (1 << 31, u128::from(u32::MAX)),
+        _ => (1 << 63, u128::from(u64::MAX))
+    };
+
+    if (is_negative && magnitude > min) || (!is_negative && magnitude > max) {
+        return Err(format!("literal `{}` is out of range for {}", text, ast::type_name(ty)).into());
+    }
+
+    return Ok(());
+}
+
+type TypedVisit<'f> = dyn FnMut(&mut ast::Expression, Option<&token::Type>, &Typing) -> Result<(), Box<dyn Error>> + 'f;
+
+// calls `f` on every expression of a block, before its operands, with the
+// type the transpiler is expected to emit it at
+fn walk_typed(block: &mut ast::Block, typing: &Typing, f: &mut TypedVisit) -> Result<(), Box<dyn Error>> {
+    let mut typing = typing.clone();
+    let int = typing.int().clone();
+
+    for stmt in block.statements.iter_mut() {
+        match &mut stmt.node {
+            ast::Statement::Variable(x) => {
+                let expected = x.ty.as_ref().and_then(primitive).unwrap_or_else(|| return int.clone());
+
+                walk_typed_expression(&mut x.value, Some(&expected), &typing, f)?;
+                typing.bind(x);
+            },
+            ast::Statement::Expression(x) => walk_typed_expression(x, Some(&int), &typing, f)?,
+            ast::Statement::Return(Some(x)) => walk_typed_value(x, &typing, f)?,
+            ast::Statement::Assignment(target, value) => {
+                let expected = match target {
+                    ast::Expression::Identifier(x) => typing.variable(x),
+                    ast::Expression::Offset { signature: Some(ast::TypeExpression::Primitive(x)), .. } => Some(x.clone()),
+                    _ => None
+                };
+
+                if let ast::Expression::Offset { offset, .. } = target {
+                    walk_typed_expression(offset, Some(&token::Type::U32), &typing, f)?;
+                }
+
+                walk_typed_expression(value, expected.as_ref(), &typing, f)?;
+            },
+            ast::Statement::If(x) => {
+                walk_typed_expression(&mut x.condition, Some(&token::Type::I32), &typing, f)?;
+                walk_typed(&mut x.block, &typing, f)?;
+
+                for (condition, block) in x.else_ifs.iter_mut() {
+                    walk_typed_expression(condition, Some(&token::Type::I32), &typing, f)?;
+                    walk_typed(block, &typing, f)?;
+                }
+
+                if let Some(block) = &mut x.else_block {
+                    walk_typed(block, &typing, f)?;
+                }
+            },
+            ast::Statement::While(x) => {
+                walk_typed_expression(&mut x.condition, Some(&token::Type::I32), &typing, f)?;
+                walk_typed(&mut x.block, &typing, f)?;
+            },
+            ast::Statement::Repeat(ast::RepeatStatement { block, .. }) | ast::Statement::Defer(block) | ast::Statement::Block(block) => {
+                walk_typed(block, &typing, f)?;
+            },
+            ast::Statement::StaticAssert(_)
+            | ast::Statement::Function(_)
+            | ast::Statement::Return(None)
+            | ast::Statement::Break
+            | ast::Statement::Continue => {}
+        }
+    }
+
+    if let Some(x) = &mut block.tail {
+        walk_typed_value(x, &typing, f)?;
+    }
+
+    return Ok(());
+}
+
+// a returned value, whose elements a tuple result types one by one
+fn walk_typed_value(expr: &mut ast::Expression, typing: &Typing, f: &mut TypedVisit) -> Result<(), Box<dyn Error>> {
+    let expected = typing.results(expr);
+
+    if let ast::Expression::Tuple(list) = expr {
+        for (x, ty) in list.iter_mut().zip(expected.iter()) {
+            walk_typed_expression(x, ty.as_ref(), typing, f)?;
+        }
+
+        return Ok(());
+    }
+
+    return walk_typed_expression(expr, expected[0].as_ref(), typing, f);
+}
+
+// addresses and table entries are unsigned
+fn walk_typed_expression(expr: &mut ast::Expression, expected: Option<&token::Type>, typing: &Typing, f: &mut TypedVisit) -> Result<(), Box<dyn Error>> {
+    f(expr, expected, typing)?;
+
+    let ty = typing.operation(expr, expected);
+    let int = typing.int().clone();
+
+    match expr {
+        ast::Expression::Call { callee, args } => {
+            let types = typing.arguments(callee, args);
+
+            for (x, ty) in args.iter_mut().zip(types) {
+                walk_typed_expression(x, ty.as_ref(), typing, f)?;
+            }
+        },
+        ast::Expression::CallIndirect { target, args, .. } => {
+            walk_typed_expression(target, Some(&token::Type::U32), typing, f)?;
+
+            for x in args.iter_mut() {
+                walk_typed_expression(x, None, typing, f)?;
+            }
+        },
+        ast::Expression::Tuple(args) => {
+            for x in args.iter_mut() {
+                walk_typed_expression(x, Some(&int), typing, f)?;
+            }
+        },
+        ast::Expression::Variadic { ty, args } => {
+            for x in args.iter_mut() {
+                walk_typed_expression(x, Some(ty), typing, f)?;
+            }
+        },
+        // a negated literal is a single constant, so its digits are not visited on their own
+        ast::Expression::Unary { op: token::Symbol::Minus, operand } if matches!(operand.as_ref(), ast::Expression::Literal(_)) => {},
+        ast::Expression::Unary { operand, .. } | ast::Expression::Cast { operand, .. } => walk_typed_expression(operand, ty.as_ref(), typing, f)?,
+        ast::Expression::Binary { lhs, rhs, .. } => {
+            walk_typed_expression(lhs, ty.as_ref(), typing, f)?;
+            walk_typed_expression(rhs, ty.as_ref(), typing, f)?;
+        },
+        ast::Expression::Conditional { condition, consequent, alternative } => {
+            walk_typed_expression(condition, Some(&token::Type::I32), typing, f)?;
+            walk_typed_expression(consequent, ty.as_ref(), typing, f)?;
+            walk_typed_expression(alternative, ty.as_ref(), typing, f)?;
+        },
+        ast::Expression::Offset { offset, .. } => walk_typed_expression(offset, Some(&token::Type::U32), typing, f)?,
+        _ => {}
+    }
+
+    return Ok(());
+}
+
+const fn unsigned_operator(op: &token::Symbol) -> Option<token::Symbol> {
+    return match op {
+        token::Symbol::Plus => Some(token::Symbol::PlusUnsigned),
+        token::Symbol::Minus => Some(token::Symbol::MinusUnsigned),
+        token::Symbol::Asterisk => Some(token::Symbol::AsteriskUnsigned),
+        token::Symbol::Solidus => Some(token::Symbol::SolidusUnsigned),
+        token::Symbol::Modulo => Some(token::Symbol::ModuloUnsigned),
+        token::Symbol::LessThan => Some(token::Symbol::LessThanUnsigned),
+        token::Symbol::GreaterThan => Some(token::Symbol::GreaterThanUnsigned),
+        token::Symbol::LessThanOrEqual => Some(token::Symbol::LessThanOrEqualUnsigned),
+        token::Symbol::GreaterThanOrEqual => Some(token::Symbol::GreaterThanOrEqualUnsigned),
+        token::Symbol::ShiftRightArithmatic => Some(token::Symbol::ShiftRightLogical),
+        _ => None
+    };
+}
+
+// functions without a result type take the one their returns agree on,
+// earlier functions are inferred first so later ones can call them
+pub fn infer_results(program: &mut ast::Program, defaults: &Defaults) -> Result<(), Box<dyn Error>> {
+    for i in 0..program.declarations.len() {
+        let result = {
+            let signatures = function_signatures(program);
+            let function = match &program.declarations[i] {
+                ast::Declaration::Function(x) => x,
+                ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Function(x), .. }) => x,
+                _ => continue
+            };
+
+            if function.signature.result.is_some() {
+                continue;
+            }
+
+            infer_result(function, &signatures, defaults)?
+        };
+
+        match &mut program.declarations[i] {
+            ast::Declaration::Function(x) => x.signature.result = result,
+            ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Function(x), .. }) => x.signature.result = result,
+            _ => {}
+        }
+    }
+
+    return Ok(());
+}
+
+// a value returned by `ret` or a tail expression, `None` for a bare `ret;`
+// and `Some(None)` for a value whose type comes from the context
+type Returned = Option<Option<ast::TypeExpression>>;
+
+fn infer_result(function: &ast::FunctionDeclaration, signatures: &Signatures, defaults: &Defaults) -> Result<Option<ast::TypeExpression>, Box<dyn Error>> {
+    let scope = param_scope(function);
+    let mut returns = vec![];
+
+    collect_returns(&function.body, &scope, signatures, defaults, &mut returns);
+
+    if let Some(tail) = &function.body.tail {
+        returns.push(Some(value_type(tail, &scope, signatures, defaults)));
+    }
+
+    if returns.iter().all(Option::is_none) {
+        return Ok(None);
+    }
+
+    if returns.iter().any(Option::is_none) {
+        return Err(format!("function `{}` returns a value on some paths but not on others", function.name).into());
+    }
+
+    let mut result: Option<ast::TypeExpression> = None;
+
+    for ty in returns.into_iter().flatten().flatten() {
+        match &result {
+            Some(x) if x != &ty => {
+                return Err(format!("function `{}` returns both {} and {}; give it an explicit result type", function.name, x, ty).into());
+            },
+            Some(_) => {},
+            None => result = Some(ty)
+        }
+    }
+
+    // plain integers take the default when nothing else decides
+    return Ok(Some(result.unwrap_or_else(|| return ast::TypeExpression::Primitive(defaults.int.clone()))));
+}
+
+fn collect_returns(block: &ast::Block, scope: &Scope, signatures: &Signatures, defaults: &Defaults, returns: &mut Vec<Returned>) {
+    let mut scope = scope.clone();
+
+    for stmt in block.statements.iter() {
+        match &stmt.node {
+            ast::Statement::Variable(x) => bind(x, &mut scope, signatures),
+            ast::Statement::Return(x) => returns.push(x.as_ref().map(|x| return value_type(x, &scope, signatures, defaults))),
+            ast::Statement::If(x) => {
+                collect_returns(&x.block, &scope, signatures, defaults, returns);
+
+                for (_, block) in x.else_ifs.iter() {
+                    collect_returns(block, &scope, signatures, defaults, returns);
+                }
+
+                if let Some(block) = &x.else_block {
+                    collect_returns(block, &scope, signatures, defaults, returns);
+                }
+            },
+            ast::Statement::While(x) => collect_returns(&x.block, &scope, signatures, defaults, returns),
+            ast::Statement::Repeat(x) => collect_returns(&x.block, &scope, signatures, defaults, returns),
+            ast::Statement::Block(x) => collect_returns(x, &scope, signatures, defaults, returns),
+            _ => {}
+        }
+    }
+}
+
+// the full type of a returned value, tuples are typed element by element
+fn value_type(expr: &ast::Expression, scope: &Scope, signatures: &Signatures, defaults: &Defaults) -> Option<ast::TypeExpression> {
+    return match expr {
+        ast::Expression::Tuple(list) => Some(ast::TypeExpression::Tuple(list.iter()
+            .map(|x| return value_type(x, scope, signatures, defaults).unwrap_or_else(|| return ast::TypeExpression::Primitive(defaults.int.clone())))
+            .collect())),
+        ast::Expression::Call { callee, .. } if signatures.contains_key(callee.as_str()) => signatures[callee.as_str()].result.clone(),
+        _ => expression_type(expr, scope, signatures).map(ast::TypeExpression::Primitive)
+    };
+}
+
+// deferred blocks are copied before every exit of the function, so they may
+// only be declared at the top of its body and cannot exit it themselves
+fn check_defers(function: &ast::FunctionDeclaration) -> Result<(), Box<dyn Error>> {
+    for stmt in function.body.statements.iter() {
+        if let ast::Statement::Defer(x) = &stmt.node {
+            if x.tail.is_some() {
+                return Err(format!("deferred block in function `{}` cannot end in a value", function.name).into());
+            }
+
+            if let Some(keyword) = deferred_exit(x, false) {
+                return Err(format!("deferred block in function `{}` cannot use `{}`", function.name, keyword).into());
+            }
+        }
+
+        if inner_blocks(stmt).into_iter().any(has_defer) {
+            return Err(format!("`defer` is only allowed at the top of the body of function `{}`", function.name).into());
+        }
+    }
+
+    return Ok(());
+}
+
+// `brk` and `cont` branch to the innermost `while` or `repeat`, so they have
+// nothing to branch to outside of one. the language has no loop labels
+fn check_loop_exits(function: &ast::FunctionDeclaration) -> Result<(), Box<dyn Error>> {
+    if let Some(keyword) = loop_exit(&function.body) {
+        return Err(format!("`{}` outside of a loop in function `{}`", keyword, function.name).into());
+    }
+
+    return Ok(());
+}
+
+// the first `brk` or `cont` that no loop of the block encloses
+fn loop_exit(block: &ast::Block) -> Option<&'static str> {
+    return block.statements.iter().find_map(|x| {
+        return match &x.node {
+            ast::Statement::Break => Some("brk"),
+            ast::Statement::Continue => Some("cont"),
+            ast::Statement::While(_) | ast::Statement::Repeat(_) => None,
+            _ => inner_blocks(x).into_iter().find_map(loop_exit)
+        };
+    });
+}
+
+fn inner_blocks(stmt: &ast::Statement) -> Vec<&ast::Block> {
+    return match stmt {
+        ast::Statement::If(x) => std::iter::once(&x.block)
+            .chain(x.else_ifs.iter().map(|x| return &x.1))
+            .chain(x.else_block.iter())
+            .collect(),
+        ast::Statement::While(ast::WhileStatement { block, .. }) | ast::Statement::Repeat(ast::RepeatStatement { block, .. }) => vec![block],
+        ast::Statement::Block(x) | ast::Statement::Defer(x) => vec![x],
+        _ => vec![]
+    };
+}
+
+fn has_defer(block: &ast::Block) -> bool {
+    return block.statements.iter().any(|x| return matches!(&x.node, ast::Statement::Defer(_)) || inner_blocks(x).into_iter().any(has_defer));
+}
+
+// `ret` anywhere, or `brk` and `cont` outside of a loop of the block itself
+fn deferred_exit(block: &ast::Block, in_loop: bool) -> Option<&'static str> {
+    return block.statements.iter().find_map(|x| {
+        return match &x.node {
+            ast::Statement::Return(_) => Some("ret"),
+            ast::Statement::Break if !in_loop => Some("brk"),
+            ast::Statement::Continue if !in_loop => Some("cont"),
+            ast::Statement::While(_) | ast::Statement::Repeat(_) => inner_blocks(x).into_iter().find_map(|x| return deferred_exit(x, true)),
+            _ => inner_blocks(x).into_iter().find_map(|x| return deferred_exit(x, in_loop))
+        };
+    });
+}
+
+// a block returns when it ends in a value, or when its last statement
+// returns on every path
+fn is_returning(block: &ast::Block) -> bool {
+    if block.tail.is_some() {
+        return true;
+    }
+
+    return match block.statements.last().map(|x| return &x.node) {
+        Some(ast::Statement::Return(_)) => true,
+        Some(ast::Statement::Block(x)) => is_returning(x),
+        Some(ast::Statement::If(x)) => {
+            let else_block = match &x.else_block {
+                Some(x) => x,
+                None => return false
+            };
+
+            is_returning(&x.block)
+                && x.else_ifs.iter().all(|(_, block)| return is_returning(block))
+                && is_returning(else_block)
+        },
+        _ => false
+    };
+}
+
+// type section
+// a type may only refer to itself through a function type, which is held by
+// reference. any other cycle of aliases would have no finite layout
+fn check_recursive_types(program: &ast::Program) -> Result<(), Box<dyn Error>> {
+    let aliases = program.declarations.iter()
+        .filter_map(|x| return match x {
+            ast::Declaration::Type(x) => Some((x.name.as_str(), &x.ty)),
+            _ => None
+        })
+        .collect::<HashMap<_, _>>();
+
+    for decl in program.declarations.iter() {
+        if let ast::Declaration::Type(x) = decl {
+            check_cycle(&aliases, &x.name, &mut vec![])?;
+        }
+    }
+
+    return Ok(());
+}
+
+fn check_cycle<'a>(aliases: &HashMap<&'a str, &'a ast::TypeExpression>, name: &'a str, path: &mut Vec<&'a str>) -> Result<(), Box<dyn Error>> {
+    if let Some(start) = path.iter().position(|x| return *x == name) {
+        let cycle = path[start..].iter().chain([name].iter()).copied().collect::<Vec<_>>();
+
+        return Err(format!("type `{}` contains itself without indirection: {}", name, cycle.join(" -> ")).into());
+    }
+
+    // unknown names are reported where the type is used
+    let ty = match aliases.get(name) {
+        Some(x) => x,
+        None => return Ok(())
+    };
+
+    path.push(name);
+
+    for inner in contained_names(ty) {
+        check_cycle(aliases, inner, path)?;
+    }
+
+    path.pop();
+
+    return Ok(());
+}
+
+// names laid out inline by a type, function types only refer to theirs
+fn contained_names(ty: &ast::TypeExpression) -> Vec<&str> {
+    return match ty {
+        ast::TypeExpression::Named(x) => vec![x.as_str()],
+        ast::TypeExpression::Tuple(list) => list.iter().flat_map(contained_names).collect(),
+        _ => vec![]
+    };
+}
+
+fn check_types(program: &ast::Program) -> Result<(), Box<dyn Error>> {
+    for decl in program.declarations.iter() {
+        match decl {
+            ast::Declaration::Function(x) => check_signature(&x.signature)?,
+            ast::Declaration::Type(x) => check_type(&x.ty)?,
+            ast::Declaration::Table(x) => check_table(&x.ty)?,
+            ast::Declaration::Memory(x) => check_type(&x.ty)?,
+            ast::Declaration::Variable(_) => {},
+            ast::Declaration::Import(x) => match &x.item {
+                ast::ImportItem::Function(_, signature) => check_signature(signature)?,
+                ast::ImportItem::Table(_, ty) => check_table(ty)?,
+                ast::ImportItem::Memory(_, ty) | ast::ImportItem::Variable(_, ty) => check_type(ty)?
+            },
+            ast::Declaration::Export(x) => match &x.item {
+                ast::ExportItem::Function(x) => check_signature(&x.signature)?,
+                ast::ExportItem::Table(x) => check_table(&x.ty)?,
+                ast::ExportItem::Memory(x) => check_type(&x.ty)?,
+                ast::ExportItem::Variable(_) | ast::ExportItem::Aliased(..) => {}
+            },
+            ast::Declaration::Include(_) | ast::Declaration::ImportModule(_) | ast::Declaration::StaticAssert(_) | ast::Declaration::Error(_) => {}
+        }
+    }
+
+    return Ok(());
+}
+
+fn check_signature(signature: &ast::Signature) -> Result<(), Box<dyn Error>> {
+    for param in signature.params.iter() {
+        check_type(&param.ty)?;
+    }
+
+    return match &signature.result {
+        Some(x) => check_type(x),
+        None => Ok(())
+    };
+}
+
+// tables hold references, `fref` for functions and `xref` for host values
+fn check_table(ty: &ast::TypeExpression) -> Result<(), Box<dyn Error>> {
+    return match ty {
+        ast::TypeExpression::Range(_, token::Type::Fref | token::Type::Xref, _) => check_type(ty),
+        ast::TypeExpression::Range(_, x, _) => Err(format!("table elements must be fref or xref, found {}", ast::type_name(x)).into()),
+        x => Err(format!("expected a table type `(min; fref; max)`, found `{}`", x).into())
+    };
+}
+
+fn check_type(ty: &ast::TypeExpression) -> Result<(), Box<dyn Error>> {
+    return match ty {
+        ast::TypeExpression::Vector(_, count) => {
+            vector_length(count)?;
+
+            Ok(())
+        },
+        ast::TypeExpression::Range(min, token::Type::Page, max) => {
+            let min = page_count(min)?;
+
+            if !max.is_empty() && page_count(max)? < min {
+                return Err(diagnostic::INVALID_PAGE_COUNT.message(format!("memory maximum of {} pages is below its minimum of {}", max, min)).into());
+            }
+
+            Ok(())
+        },
+        ast::TypeExpression::Function(_, Some(result)) => check_type(result),
+        _ => Ok(())
+    };
+}
+
+// splits a radix prefix off an integer literal
+fn radix(literal: &str) -> (&str, u32) {
+    if let Some(x) = literal.strip_prefix("0x") {
+        return (x, 16);
+    }
+    else if let Some(x) = literal.strip_prefix("0b") {
+        return (x, 2);
+    }
+    else if let Some(x) = literal.strip_prefix("0o") {
+        return (x, 8);
+    }
+
+    return (literal, 10);
+}
+
+// the length of `(ty; N)`, which may be written in any integer radix
+pub fn vector_length(count: &str) -> Result<usize, Box<dyn Error>> {
+    if literal_type(count).is_some() {
+        return Err(diagnostic::INVALID_VECTOR_LENGTH.message(format!("vector length `{}` must be an integer", count)).into());
+    }
+
+    let (digits, radix) = radix(count);
+
+    return match usize::from_str_radix(digits, radix) {
+        Ok(0) => Err(diagnostic::INVALID_VECTOR_LENGTH.message("vector length must be at least 1").into()),
+        Ok(x) if x <= MAX_VECTOR_LENGTH => Ok(x),
+        Ok(_) => Err(diagnostic::INVALID_VECTOR_LENGTH.message(format!("vector length `{}` exceeds the maximum of {}", count, MAX_VECTOR_LENGTH)).into()),
+        Err(x) if x.kind() == &IntErrorKind::PosOverflow => {
+            Err(diagnostic::INVALID_VECTOR_LENGTH.message(format!("vector length `{}` exceeds the maximum of {}", count, MAX_VECTOR_LENGTH)).into())
+        },
+        Err(_) => Err(diagnostic::INVALID_VECTOR_LENGTH.message(format!("invalid vector length `{}`", count)).into())
+    };
+}
+
+// a `page` literal, an unsigned count of memory pages in any integer radix
+pub fn page_count(count: &str) -> Result<u32, Box<dyn Error>> {
+    if literal_type(count).is_some() {
+        return Err(diagnostic::INVALID_PAGE_COUNT.message(format!("page count `{}` must be an integer", count)).into());
+    }
+
+    let (digits, radix) = radix(count);
+
+    return match u32::from_str_radix(digits, radix) {
+        Ok(x) if x <= MAX_PAGE_COUNT => Ok(x),
+        Ok(_) => Err(diagnostic::INVALID_PAGE_COUNT.message(format!("page count `{}` exceeds the maximum of {}", count, MAX_PAGE_COUNT)).into()),
+        Err(x) if x.kind() == &IntErrorKind::PosOverflow => {
+            Err(diagnostic::INVALID_PAGE_COUNT.message(format!("page count `{}` exceeds the maximum of {}", count, MAX_PAGE_COUNT)).into())
+        },
+        Err(_) => Err(diagnostic::INVALID_PAGE_COUNT.message(format!("invalid page count `{}`", count)).into())
+    };
+}
+
+// the counter of a `repeat` loop is an i32 local, so the count must fit in one
+pub fn repeat_count(count: &str) -> Result<u32, Box<dyn Error>> {
+    if literal_type(count).is_some() {
+        return Err(diagnostic::INVALID_REPEAT_COUNT.message(format!("repeat count `{}` must be an integer", count)).into());
+    }
+
+    let (digits, radix) = radix(count);
+
+    return match u32::from_str_radix(digits, radix) {
+        Ok(x) if x <= MAX_REPEAT_COUNT => Ok(x),
+        Ok(_) => Err(diagnostic::INVALID_REPEAT_COUNT.message(format!("repeat count `{}` exceeds the maximum of {}", count, MAX_REPEAT_COUNT)).into()),
+        Err(x) if x.kind() == &IntErrorKind::PosOverflow => {
+            Err(diagnostic::INVALID_REPEAT_COUNT.message(format!("repeat count `{}` exceeds the maximum of {}", count, MAX_REPEAT_COUNT)).into())
+        },
+        Err(_) => Err(diagnostic::INVALID_REPEAT_COUNT.message(format!("invalid repeat count `{}`", count)).into())
+    };
+}
+
+// body section
+// the signatures apart from the program, for passes that change it
+pub fn owned_signatures(program: &ast::Program) -> HashMap<String, ast::Signature> {
+    return function_signatures(program).into_iter()
+        .map(|(name, signature)| return (name.to_string(), signature.clone()))
+        .collect();
+}
+
+pub fn borrow_signatures(owned: &HashMap<String, ast::Signature>) -> Signatures<'_> {
+    return owned.iter().map(|(name, signature)| return (name.as_str(), signature)).collect();
+}
+
+fn function_signatures(program: &ast::Program) -> Signatures<'_> {
+    let mut signatures = HashMap::new();
+
+    for decl in program.declarations.iter() {
+        let (name, signature) = match decl {
+            ast::Declaration::Function(x) => (&x.name, &x.signature),
+            ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Function(x), .. }) => (&x.name, &x.signature),
+            ast::Declaration::Import(ast::ImportDeclaration { item: ast::ImportItem::Function(name, signature), .. }) => (name, signature),
+            _ => continue
+        };
+
+        signatures.insert(name.as_str(), signature);
+    }
+
+    return signatures;
+}
+
+fn check_defaults(program: &ast::Program, signatures: &Signatures) -> Result<(), Box<dyn Error>> {
+    for decl in program.declarations.iter() {
+        let (name, signature) = match decl {
+            ast::Declaration::Function(x) => (&x.name, &x.signature),
+            ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Function(x), .. }) => (&x.name, &x.signature),
+            ast::Declaration::Import(ast::ImportDeclaration { item: ast::ImportItem::Function(name, signature), .. }) => (name, signature),
+            _ => continue
+        };
+
+        for param in signature.params.iter() {
+            if let Some(default) = &param.default {
+                check_value_type(primitive(&param.ty), default, &Scope::new(), signatures).map_err(|found| {
+                    return diagnostic::MISMATCHED_TYPE.message(format!("default value of parameter `{}` in function `{}` {}", param.name, name, found));
+                })?;
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+// `Null` has no type of its own, so a variable it initializes must declare one
+fn check_variable(variable: &ast::VariableDeclaration, function: &str, scope: &Scope, signatures: &Signatures) -> Result<(), Box<dyn Error>> {
+    let binding = match &variable.pattern {
+        ast::Pattern::Single(x) => x,
+        ast::Pattern::Tuple(_) => return Ok(())
+    };
+
+    if variable.ty.is_none() && variable.value == ast::Expression::Literal("Null".to_string()) {
+        return Err(format!(
+            "`let {}` in function `{}` cannot tell which reference `Null` is; declare its type, as in `let {}: xref <- Null;`",
+            binding.name, function, binding.name
+        ).into());
+    }
+
+    return check_value_type(variable.ty.as_ref().and_then(primitive), &variable.value, scope, signatures).map_err(|found| {
+        return diagnostic::MISMATCHED_TYPE.message(format!("value of `{}` in function `{}` {}", binding.name, function, found)).into();
+    });
+}
+
+// a value given for a parameter or assigned to a place must have its type,
+// untyped literals take it. the error tells what was expected and found instead
+fn check_value_type(expected: Option<token::Type>, value: &ast::Expression, scope: &Scope, signatures: &Signatures) -> Result<(), String> {
+    let (expected, found) = match (expected, expression_type(value, scope, signatures)) {
+        (Some(x), Some(y)) => (x, y),
+        _ => return Ok(())
+    };
+
+    if ir::ValType::from_token(&expected) == ir::ValType::from_token(&found) {
+        return Ok(());
+    }
+
+    return Err(format!("must be {}, found {}", ast::type_name(&expected), ast::type_name(&found)));
+}
+
+fn param_scope(function: &ast::FunctionDeclaration) -> Scope {
+    return function.signature.params.iter()
+        .filter_map(|x| return match &x.ty {
+            ast::TypeExpression::Primitive(ty) => Some((x.name.clone(), ty.clone())),
+            _ => None
+        })
+        .collect();
+}
+
+// a variable takes its declared type, or else the type of its value
+fn bind(variable: &ast::VariableDeclaration, scope: &mut Scope, signatures: &Signatures) {
+    match &variable.pattern {
+        ast::Pattern::Single(binding) => match variable.ty.as_ref().and_then(primitive).or_else(|| return expression_type(&variable.value, scope, signatures)) {
+            Some(ty) => { scope.insert(binding.name.clone(), ty); },
+            None => { scope.remove(&binding.name); }
+        },
+        ast::Pattern::Tuple(list) => for binding in list.iter() {
+            scope.remove(&binding.name);
+        }
+    }
+}
+
+fn check_body(function: &ast::FunctionDeclaration, signatures: &Signatures) -> Result<(), Box<dyn Error>> {
+    let scope = param_scope(function);
+
+    if let Some(tail) = &function.body.tail {
+        check_result(tail, function, &scope, signatures)?;
+    }
+
+    return check_block(&function.body, function, &scope, signatures);
+}
+
+fn check_block(block: &ast::Block, function: &ast::FunctionDeclaration, scope: &Scope, signatures: &Signatures) -> Result<(), Box<dyn Error>> {
+    let mut scope = scope.clone();
+
+    for stmt in block.statements.iter() {
+        match &stmt.node {
+            ast::Statement::Variable(x) => {
+                check_pages(&x.value, &scope, signatures)?;
+                check_variable(x, &function.name, &scope, signatures)?;
+                bind(x, &mut scope, signatures);
+            },
+            ast::Statement::Expression(x) => check_pages(x, &scope, signatures)?,
+            ast::Statement::Assignment(target, value) => {
+                check_pages(value, &scope, signatures)?;
+
+                if expression_type(target, &scope, signatures) == Some(token::Type::Page) {
+                    check_page_operand(value, &scope, signatures)?;
+                }
+
+                check_value_type(expression_type(target, &scope, signatures), value, &scope, signatures).map_err(|found| {
+                    return match target {
+                        ast::Expression::Identifier(name) => diagnostic::MISMATCHED_TYPE.message(format!("value assigned to `{}` in function `{}` {}", name, function.name, found)),
+                        _ => diagnostic::MISMATCHED_TYPE.message(format!("value stored in function `{}` {}", function.name, found))
+                    };
+                })?;
+            },
+            ast::Statement::Return(Some(x)) => {
+                check_pages(x, &scope, signatures)?;
+                check_result(x, function, &scope, signatures)?;
+            },
+            ast::Statement::If(x) => {
+                check_condition(&x.condition, "if", &function.name, &scope, signatures)?;
+                check_block(&x.block, function, &scope, signatures)?;
+
+                for (condition, block) in x.else_ifs.iter() {
+                    check_condition(condition, "elif", &function.name, &scope, signatures)?;
+                    check_block(block, function, &scope, signatures)?;
+                }
+
+                if let Some(block) = &x.else_block {
+                    check_block(block, function, &scope, signatures)?;
+                }
+            },
+            ast::Statement::While(x) => {
+                check_condition(&x.condition, "while", &function.name, &scope, signatures)?;
+                check_block(&x.block, function, &scope, signatures)?;
+            },
+            ast::Statement::Repeat(x) => {
+                repeat_count(&x.count)?;
+                check_block(&x.block, function, &scope, signatures)?;
+            },
+            ast::Statement::Defer(x) => check_block(x, function, &scope, signatures)?,
+            ast::Statement::Block(x) => check_block(x, function, &scope, signatures)?,
+            _ => {}
+        }
+    }
+
+    if let Some(tail) = &block.tail {
+        check_pages(tail, &scope, signatures)?;
+    }
+
+    return Ok(());
+}
+
+fn check_result(expr: &ast::Expression, function: &ast::FunctionDeclaration, scope: &Scope, signatures: &Signatures) -> Result<(), Box<dyn Error>> {
+    return match &function.signature.result {
+        Some(ast::TypeExpression::Primitive(token::Type::Page)) => check_page_operand(expr, scope, signatures),
+        _ => Ok(())
+    };
+}
+
+// `br_if` takes an i32, so floats are not truthy and must be compared explicitly
+fn check_condition(expr: &ast::Expression, keyword: &str, function: &str, scope: &Scope, signatures: &Signatures) -> Result<(), Box<dyn Error>> {
+    check_pages(expr, scope, signatures)?;
+
+    return match condition_mismatch(expr, scope, signatures) {
+        Some(found) => Err(diagnostic::INVALID_CONDITION.message(format!("`{}` condition in function `{}` must be a scalar integer, found {}", keyword, function, found)).into()),
+        None => Ok(())
+    };
+}
+
+// what a condition holds when it is not a single integer, with calls typed
+// by the result of the function called
+fn condition_mismatch(expr: &ast::Expression, scope: &Scope, signatures: &Signatures) -> Option<String> {
+    let ty = match expr {
+        ast::Expression::Tuple(list) => return Some(format!("a tuple of {} values", list.len())),
+        ast::Expression::Call { callee, .. } if signatures.contains_key(callee.as_str()) => match &signatures[callee.as_str()].result {
+            None => return Some(format!("no value, since `{}` returns nothing", callee)),
+            Some(ast::TypeExpression::Tuple(list)) => return Some(format!("a tuple of {} values", list.len())),
+            Some(ty @ ast::TypeExpression::Vector(..)) => return Some(format!("the vector {}", ty)),
+            Some(ty) => primitive(ty)
+        },
+        _ => expression_type(expr, scope, signatures)
+    };
+
+    return match ty {
+        Some(ty @ token::Type::F32) | Some(ty @ token::Type::F64) => Some(format!("{}; compare it explicitly instead", ast::type_name(&ty))),
+        Some(ty @ token::Type::Fref) | Some(ty @ token::Type::Xref) => Some(format!("{}; references are not truthy", ast::type_name(&ty))),
+        _ => None
+    };
+}
+
+// `page` values only combine with other page counts and plain integer literals,
+// unsigned integers only with integers of the same signedness, and operands
+// only with operands of the same width
+fn check_pages(expr: &ast::Expression, scope: &Scope, signatures: &Signatures) -> Result<(), Box<dyn Error>> {
+    match expr {
+        ast::Expression::Unary { op, operand } => {
+            check_pages(operand, scope, signatures)?;
+
+            if op == &token::Symbol::Minus && expression_type(operand, scope, signatures) == Some(token::Type::Page) {
+                return Err(diagnostic::INVALID_PAGE_COUNT.message("cannot negate a page count").into());
+            }
+        },
+        ast::Expression::Binary { op, lhs, rhs } => {
+            check_pages(lhs, scope, signatures)?;
+            check_pages(rhs, scope, signatures)?;
+
+            if expression_type(lhs, scope, signatures) == Some(token::Type::Page) {
+                check_page_operand(rhs, scope, signatures)?;
+            }
+            else if expression_type(rhs, scope, signatures) == Some(token::Type::Page) {
+                check_page_operand(lhs, scope, signatures)?;
+            }
+
+            if op != &token::Symbol::LogicalAnd && op != &token::Symbol::LogicalOr {
+                check_signedness(lhs, rhs, scope, signatures)?;
+                check_width(op, lhs, rhs, scope, signatures)?;
+                check_equality(op, lhs, rhs, scope, signatures)?;
+            }
+        },
+        ast::Expression::Cast { ty, operand } => {
+            check_pages(operand, scope, signatures)?;
+            check_cast(ty, operand, scope, signatures)?;
+        },
+        ast::Expression::Call { callee, args } => {
+            for arg in args.iter() {
+                check_pages(arg, scope, signatures)?;
+            }
+
+            if let Some(signature) = signatures.get(callee.as_str()) {
+                for (param, arg) in signature.params.iter().zip(args.iter()) {
+                    if param.ty == ast::TypeExpression::Primitive(token::Type::Page) {
+                        check_page_operand(arg, scope, signatures)?;
+                    }
+
+                    check_value_type(primitive(&param.ty), arg, scope, signatures).map_err(|found| {
+                        return diagnostic::MISMATCHED_TYPE.message(format!("argument for parameter `{}` of function `{}` {}", param.name, callee, found));
+                    })?;
+                }
+            }
+            else if let Some(intrinsic) = ir::Intrinsic::from_name(callee) {
+                check_intrinsic(callee, intrinsic, args, scope, signatures)?;
+            }
+        },
+        ast::Expression::CallIndirect { target, args, .. } => {
+            check_pages(target, scope, signatures)?;
+
+            for arg in args.iter() {
+                check_pages(arg, scope, signatures)?;
+            }
+        },
+        ast::Expression::Conditional { condition, consequent, alternative } => {
+            check_pages(condition, scope, signatures)?;
+            check_pages(consequent, scope, signatures)?;
+            check_pages(alternative, scope, signatures)?;
+
+            if let Some(found) = condition_mismatch(condition, scope, signatures) {
+                return Err(diagnostic::INVALID_CONDITION.message(format!("`?:` condition must be a scalar integer, found {}", found)).into());
+            }
+        },
+        ast::Expression::Tuple(list) | ast::Expression::Variadic { args: list, .. } => for x in list.iter() {
+            check_pages(x, scope, signatures)?;
+        },
+        _ => {}
+    }
+
+    return Ok(());
+}
+
+// an unsigned operand selects unsigned instructions for the whole operation,
+// so the other operand must not be a signed integer
+fn check_signedness(lhs: &ast::Expression, rhs: &ast::Expression, scope: &Scope, signatures: &Signatures) -> Result<(), Box<dyn Error>> {
+    return match (expression_type(lhs, scope, signatures), expression_type(rhs, scope, signatures)) {
+        (Some(x), Some(y)) if x != y && (is_unsigned(&x) || is_unsigned(&y)) => {
+            let unsigned = if is_unsigned(&x) { &x } else { &y };
+
+            Err(diagnostic::MIXED_SIGNEDNESS.message(format!(
+                "cannot mix {} with {}; cast one operand explicitly, as in `{}(x)`",
+                ast::type_name(&x), ast::type_name(&y), ast::type_name(unsigned)
+            )).into())
+        },
+        _ => Ok(())
+    };
+}
+
+// wasm never converts implicitly, so both operands have one value type. page
+// counts are left to the page rules
+fn check_width(op: &token::Symbol, lhs: &ast::Expression, rhs: &ast::Expression, scope: &Scope, signatures: &Signatures) -> Result<(), Box<dyn Error>> {
+    let (x, y) = match (expression_type(lhs, scope, signatures), expression_type(rhs, scope, signatures)) {
+        (Some(x), Some(y)) if x != token::Type::Page && y != token::Type::Page => (x, y),
+        _ => return Ok(())
+    };
+
+    if ir::ValType::from_token(&x) == ir::ValType::from_token(&y) {
+        return Ok(());
+    }
+
+    if is_integer(&x) && is_integer(&y) {
+        let wider = if ir::ValType::from_token(&x) == ir::ValType::I64 { &x } else { &y };
+
+        return Err(diagnostic::MIXED_WIDTH.message(format!(
+            "cannot mix {} with {} in `{}`; cast the narrower operand explicitly, as in `{}(x)`",
+            ast::type_name(&x), ast::type_name(&y), op.name(), ast::type_name(wider)
+        )).into());
+    }
+
+    return Err(diagnostic::MIXED_WIDTH.message(format!(
+        "cannot mix {} with {} in `{}`; both operands must have the same type",
+        ast::type_name(&x), ast::type_name(&y), op.name()
+    )).into());
+}
+
+// `ref.eq` only compares the references of the gc proposal, so a function
+// or host reference can only be tested against `Null`
+fn check_equality(op: &token::Symbol, lhs: &ast::Expression, rhs: &ast::Expression, scope: &Scope, signatures: &Signatures) -> Result<(), Box<dyn Error>> {
+    if op != &token::Symbol::Equal && op != &token::Symbol::NotEqual {
+        return Ok(());
+    }
+
+    return match [expression_type(lhs, scope, signatures), expression_type(rhs, scope, signatures)] {
+        [Some(x @ (token::Type::Fref | token::Type::Xref)), _] | [_, Some(x @ (token::Type::Fref | token::Type::Xref))] => Err(format!(
+            "cannot compare {} values with `{}`; test a reference against `Null` with `is_null(x)` instead",
+            ast::type_name(&x), op.name()
+        ).into()),
+        _ => Ok(())
+    };
+}
+
+// a cast changes the width or the signedness of an integer, but not both at
+// once, so widening never has to guess how to extend
+fn check_cast(ty: &token::Type, operand: &ast::Expression, scope: &Scope, signatures: &Signatures) -> Result<(), Box<dyn Error>> {
+    if !is_integer(ty) {
+        return Err(diagnostic::MIXED_SIGNEDNESS.message(format!("cannot cast to {}; only integer types can be cast", ast::type_name(ty))).into());
+    }
+
+    return match expression_type(operand, scope, signatures) {
+        Some(x) if !is_integer(&x) => {
+            Err(diagnostic::MIXED_SIGNEDNESS.message(format!("cannot cast {} to {}; only integer types can be cast", ast::type_name(&x), ast::type_name(ty))).into())
+        },
+        Some(x) if ir::ValType::from_token(&x) != ir::ValType::from_token(ty) && is_unsigned(&x) != is_unsigned(ty) => {
+            // the width of the target with the signedness of the operand
+            let step = match (ir::ValType::from_token(ty), is_unsigned(&x)) {
+                (ir::ValType::I32, true) => token::Type::U32,
+                (ir::ValType::I32, false) => token::Type::I32,
+                (_, true) => token::Type::U64,
+                (_, false) => token::Type::I64
+            };
+
+            Err(diagnostic::MIXED_SIGNEDNESS.message(format!(
+                "cannot cast {} to {} in one step; change the width and the signedness separately, as in `{}({}(x))`",
+                ast::type_name(&x), ast::type_name(ty), ast::type_name(ty), ast::type_name(&step)
+            )).into())
+        },
+        _ => Ok(())
+    };
+}
+
+const fn is_unsigned(ty: &token::Type) -> bool {
+    return matches!(ty, token::Type::U32 | token::Type::U64);
+}
+
+const fn is_integer(ty: &token::Type) -> bool {
+    return matches!(ty, token::Type::I32 | token::Type::I64 | token::Type::U32 | token::Type::U64 | token::Type::Page);
+}
+
+// an expression used where a page count is expected
+fn check_page_operand(expr: &ast::Expression, scope: &Scope, signatures: &Signatures) -> Result<(), Box<dyn Error>> {
+    return match expr {
+        ast::Expression::Literal(x) => {
+            page_count(x)?;
+
+            Ok(())
+        },
+        ast::Expression::Unary { op: token::Symbol::Minus, operand } => match operand.as_ref() {
+            ast::Expression::Literal(x) => Err(diagnostic::INVALID_PAGE_COUNT.message(format!("page count `-{}` must not be negative", x)).into()),
+            _ => check_page_operand(operand, scope, signatures)
+        },
+        _ => match expression_type(expr, scope, signatures) {
+            Some(token::Type::Page) | None => Ok(()),
+            Some(ty) => Err(diagnostic::INVALID_PAGE_COUNT.message(format!("cannot mix page with {}; convert the page count explicitly", ast::type_name(&ty))).into())
+        }
+    };
+}
+
+// intrinsics take operands of a single type they are defined for, page
+// counts are not integers to them
+fn check_intrinsic(name: &str, intrinsic: ir::Intrinsic, args: &[ast::Expression], scope: &Scope, signatures: &Signatures) -> Result<(), Box<dyn Error>> {
+    if args.len() != intrinsic.arity() {
+        return Err(format!("intrinsic `{}` takes {} arguments, but {} were given", name, intrinsic.arity(), args.len()).into());
+    }
+
+    let types = args.iter().filter_map(|x| return expression_type(x, scope, signatures)).collect::<Vec<_>>();
+
+    if let Some(ty) = types.iter().find(|x| return **x == token::Type::Page || !intrinsic.accepts(ir::ValType::from_token(x))) {
+        return Err(format!("intrinsic `{}` takes {} operands, found {}", name, intrinsic.operands_name(), ast::type_name(ty)).into());
+    }
+
+    if let [a, b] = types.as_slice() {
+        if a != b {
+            return Err(format!("operands of intrinsic `{}` must have the same type, found {} and {}", name, ast::type_name(a), ast::type_name(b)).into());
+        }
+    }
+
+    return Ok(());
+}
+
+fn expression_type(expr: &ast::Expression, scope: &Scope, signatures: &Signatures) -> Option<token::Type> {
+    return match expr {
+        ast::Expression::Literal(x) => literal_type(x),
+        ast::Expression::Identifier(x) => scope.get(x).cloned(),
+        ast::Expression::Call { callee, args } if !signatures.contains_key(callee.as_str()) => match ir::Intrinsic::from_name(callee) {
+            Some(ir::Intrinsic::IsNull) => Some(token::Type::I32),
+            Some(_) => args.iter().find_map(|x| return expression_type(x, scope, signatures)),
+            None => None
+        },
+        ast::Expression::Call { callee, .. } => match signatures.get(callee.as_str()).and_then(|x| return x.result.as_ref()) {
+            Some(ast::TypeExpression::Primitive(ty)) => Some(ty.clone()),
+            _ => None
+        },
+        ast::Expression::Unary { op: token::Symbol::LogicalNegation, .. } => Some(token::Type::I32),
+        ast::Expression::Unary { operand, .. } => expression_type(operand, scope, signatures),
+        ast::Expression::Binary { op, lhs, rhs } => match op {
+            token::Symbol::Equal
+            | token::Symbol::NotEqual
+            | token::Symbol::LessThan
+            | token::Symbol::GreaterThan
+            | token::Symbol::LessThanOrEqual
+            | token::Symbol::GreaterThanOrEqual
+            | token::Symbol::LessThanUnsigned
+            | token::Symbol::GreaterThanUnsigned
+            | token::Symbol::LessThanOrEqualUnsigned
+            | token::Symbol::GreaterThanOrEqualUnsigned
+            | token::Symbol::LogicalAnd
+            | token::Symbol::LogicalOr => Some(token::Type::I32),
+            _ => expression_type(lhs, scope, signatures).or_else(|| return expression_type(rhs, scope, signatures))
+        },
+        ast::Expression::Conditional { consequent, alternative, .. } => {
+            expression_type(consequent, scope, signatures).or_else(|| return expression_type(alternative, scope, signatures))
+        },
+        ast::Expression::Offset { signature: Some(ast::TypeExpression::Primitive(ty)), .. } => Some(ty.clone()),
+        ast::Expression::Cast { ty, .. } => Some(ty.clone()),
+        _ => None
+    };
+}
+
+// untyped integer literals, and operations on nothing else, which take the
+// type their context expects
+fn is_untyped(expr: &ast::Expression) -> bool {
+    return match expr {
+        ast::Expression::Literal(x) => literal_type(x).is_none() && ast::string_contents(x).is_none() && x != "Null",
+        ast::Expression::Unary { op, operand } => *op != token::Symbol::LogicalNegation && is_untyped(operand),
+        ast::Expression::Binary { lhs, rhs, .. } => is_untyped(lhs) && is_untyped(rhs),
+        ast::Expression::Conditional { consequent, alternative, .. } => is_untyped(consequent) && is_untyped(alternative),
+        _ => false
+    };
+}
+
+// the types a function sees, to tell the type an operation is emitted at the
+// way the transpiler does: from an operand that has one, or else from the
+// type its context expects. the expected type passed in is `None` where it
+// cannot be told, and `int` where the context leaves it to the default
+#[derive(Clone)]
+pub struct Typing<'a> {
+    scope: Scope,
+    signatures: &'a Signatures<'a>,
+    int: token::Type,
+    results: Vec<Option<token::Type>>
+}
+
+impl<'a> Typing<'a> {
+    pub fn new(globals: &Scope, function: &ast::FunctionDeclaration, signatures: &'a Signatures<'a>, int: &token::Type) -> Self {
+        let mut scope = globals.clone();
+
+        scope.extend(param_scope(function));
+
+        return Self {
+            scope,
+            signatures,
+            int: int.clone(),
+            results: match &function.signature.result {
+                Some(ast::TypeExpression::Tuple(list)) => list.iter().map(primitive).collect(),
+                Some(x) => vec![primitive(x)],
+                None => vec![]
+            }
+        };
+    }
+
+    pub const fn int(&self) -> &token::Type {
+        return &self.int;
+    }
+
+    pub fn bind(&mut self, variable: &ast::VariableDeclaration) {
+        bind(variable, &mut self.scope, self.signatures);
+    }
+
+    pub fn variable(&self, name: &str) -> Option<token::Type> {
+        return self.scope.get(name).cloned();
+    }
+
+    // the type of an expression on its own, without its context
+    pub fn type_of(&self, expr: &ast::Expression) -> Option<token::Type> {
+        return expression_type(expr, &self.scope, self.signatures);
+    }
+
+    // the type the operands of an operation are emitted at, `None` when one
+    // of them has a type that cannot be told
+    pub fn operation(&self, expr: &ast::Expression, expected: Option<&token::Type>) -> Option<token::Type> {
+        return match expr {
+            ast::Expression::Binary { op, lhs, rhs } => {
+                let is_comparison = ir::BinaryOp::from_symbol(op).is_some_and(ir::BinaryOp::is_comparison);
+
+                self.operands(&[lhs, rhs], if is_comparison { Some(&self.int) } else { expected })
+            },
+            ast::Expression::Unary { op: token::Symbol::LogicalNegation, operand } => self.operands(&[operand], Some(&self.int)),
+            ast::Expression::Unary { operand, .. } => self.operands(&[operand], expected),
+            ast::Expression::Conditional { consequent, alternative, .. } => self.operands(&[consequent, alternative], expected),
+            ast::Expression::Cast { ty, operand } => self.operands(&[operand], Some(ty)),
+            _ => None
+        };
+    }
+
+    fn operands(&self, list: &[&ast::Expression], expected: Option<&token::Type>) -> Option<token::Type> {
+        if let Some(ty) = list.iter().find_map(|x| return expression_type(x, &self.scope, self.signatures)) {
+            return Some(ty);
+        }
+
+        return if list.iter().all(|x| return is_untyped(x)) { expected.cloned() } else { None };
+    }
+
+    // the type each argument of a call is expected to be. the operands of an
+    // intrinsic share a type, which untyped ones alone leave undecided here
+    pub fn arguments(&self, callee: &str, args: &[ast::Expression]) -> Vec<Option<token::Type>> {
+        let signature = match self.signatures.get(callee) {
+            Some(x) => x,
+            None => {
+                let shared = args.iter().find_map(|x| return expression_type(x, &self.scope, self.signatures));
+
+                return vec![shared.filter(|_| return ir::Intrinsic::from_name(callee).is_some()); args.len()];
+            }
+        };
+        let mut params = signature.params.iter();
+
+        return args.iter()
+            .map(|arg| {
+                let param = params.next();
+
+                // the extra arguments of a variadic call fill two parameters
+                if let ast::Expression::Variadic { .. } = arg {
+                    params.next();
+                }
+
+                return param.and_then(|x| return primitive(&x.ty));
+            })
+            .collect();
+    }
+
+    // the type a returned value is expected to be, and each of its elements
+    // when a tuple fills a tuple result
+    pub fn results(&self, value: &ast::Expression) -> Vec<Option<token::Type>> {
+        return match value {
+            ast::Expression::Tuple(list) if list.len() == self.results.len() => self.results.clone(),
+            ast::Expression::Tuple(list) => vec![Some(self.int.clone()); list.len()],
+            _ => vec![self.results.first().cloned().unwrap_or_else(|| return Some(self.int.clone()))]
+        };
+    }
+}
+
+fn primitive(ty: &ast::TypeExpression) -> Option<token::Type> {
+    return match ty {
+        ast::TypeExpression::Primitive(x) => Some(x.clone()),
+        _ => None
+    };
+}
+
+// the type of each global, integers are always emitted at the default
+pub fn global_scope(program: &ast::Program, int: &token::Type) -> Scope {
+    let mut scope = Scope::new();
+
+    for decl in program.declarations.iter() {
+        let variable = match decl {
+            ast::Declaration::Variable(x) => x,
+            ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Variable(x), .. }) => x,
+            _ => continue
+        };
+
+        if let ast::Pattern::Single(binding) = &variable.pattern {
+            let ty = match &variable.value {
+                ast::Expression::Literal(x) => literal_type(x).unwrap_or_else(|| return int.clone()),
+                ast::Expression::Unary { operand, .. } => match operand.as_ref() {
+                    ast::Expression::Literal(x) => literal_type(x).unwrap_or_else(|| return int.clone()),
+                    _ => continue
+                },
+                _ => continue
+            };
+
+            scope.insert(binding.name.clone(), ty);
+        }
+    }
+
+    return scope;
+}
+
+// immutable globals usable in constant expressions, by name
+type Constants<'a> = HashMap<&'a str, &'a ast::Expression>;
+
+fn check_static_assertions(program: &ast::Program) -> Result<(), Box<dyn Error>> {
+    let mut constants = Constants::new();
+
+    for decl in program.declarations.iter() {
+        let variable = match decl {
+            ast::Declaration::Variable(x) => x,
+            ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Variable(x), .. }) => x,
+            _ => continue
+        };
+
+        if let ast::Pattern::Single(binding) = &variable.pattern {
+            if !binding.is_mutable {
+                constants.insert(binding.name.as_str(), &variable.value);
+            }
+        }
+    }
+
+    for decl in program.declarations.iter() {
+        match decl {
+            ast::Declaration::StaticAssert(x) => check_static_assertion(x, &constants)?,
+            ast::Declaration::Function(x) => check_function_assertions(x, &constants)?,
+            ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Function(x), .. }) => check_function_assertions(x, &constants)?,
+            _ => {}
+        }
+    }
+
+    return Ok(());
+}
+
+// params and locals shadow the globals of the same name
+fn check_function_assertions(function: &ast::FunctionDeclaration, constants: &Constants) -> Result<(), Box<dyn Error>> {
+    let mut constants = constants.clone();
+
+    for param in function.signature.params.iter() {
+        constants.remove(param.name.as_str());
+    }
+
+    return check_block_assertions(&function.body, &constants);
+}
+
+fn check_block_assertions(block: &ast::Block, constants: &Constants) -> Result<(), Box<dyn Error>> {
+    let mut constants = constants.clone();
+
+    for stmt in block.statements.iter() {
+        match &stmt.node {
+            ast::Statement::Variable(x) => match &x.pattern {
+                ast::Pattern::Single(binding) => { constants.remove(binding.name.as_str()); },
+                ast::Pattern::Tuple(list) => for binding in list.iter() {
+                    constants.remove(binding.name.as_str());
+                }
+            },
+            ast::Statement::StaticAssert(x) => check_static_assertion(x, &constants)?,
+            ast::Statement::If(x) => {
+                check_block_assertions(&x.block, &constants)?;
+
+                for (_, block) in x.else_ifs.iter() {
+                    check_block_assertions(block, &constants)?;
+                }
+
+                if let Some(block) = &x.else_block {
+                    check_block_assertions(block, &constants)?;
+                }
+            },
+            ast::Statement::While(x) => check_block_assertions(&x.block, &constants)?,
+            ast::Statement::Repeat(x) => check_block_assertions(&x.block, &constants)?,
+            ast::Statement::Defer(x) => check_block_assertions(x, &constants)?,
+            ast::Statement::Block(x) => check_block_assertions(x, &constants)?,
+            _ => {}
+        }
+    }
+
+    return Ok(());
+}
+
+fn check_static_assertion(assertion: &ast::StaticAssertion, constants: &Constants) -> Result<(), Box<dyn Error>> {
+    return match constant_value(&assertion.condition, constants, constants.len()) {
+        Some(0) => Err(diagnostic::STATIC_ASSERTION.message(format!("static assertion failed: {}", assertion.message)).into()),
+        Some(_) => Ok(()),
+        None => Err(diagnostic::STATIC_ASSERTION.message("static assertion condition must be a constant integer expression").into())
+    };
+}
+
+// the i32 value of an expression made of integer literals, immutable globals
+// and operators on them, comparisons give 1 or 0. `depth` bounds how many
+// globals are followed, so globals defined by each other end
+fn constant_value(expr: &ast::Expression, constants: &Constants, depth: usize) -> Option<i32> {
+    let value = |x| return constant_value(x, constants, depth);
+
+    return match expr {
+        ast::Expression::Literal(x) if literal_type(x).is_none() && ast::string_contents(x).is_none() => {
+            let (digits, radix) = radix(x);
+
+            i32::from_str_radix(digits, radix).ok()
+                .or_else(|| return u32::from_str_radix(digits, radix).ok().map(|x| return i32::from_ne_bytes(x.to_ne_bytes())))
+        },
+        ast::Expression::Identifier(x) if depth > 0 => constants.get(x.as_str()).and_then(|x| return constant_value(x, constants, depth - 1)),
+        ast::Expression::Unary { op: token::Symbol::LogicalNegation, operand } => value(operand).map(|x| return i32::from(x == 0)),
+        ast::Expression::Unary { op, operand } => value(operand).and_then(|x| return optimizer::fold_unary(op, x)),
+        ast::Expression::Binary { op, lhs, rhs } => {
+            let (lhs, rhs) = (value(lhs)?, value(rhs)?);
+            let (lhs_unsigned, rhs_unsigned) = (u32::from_ne_bytes(lhs.to_ne_bytes()), u32::from_ne_bytes(rhs.to_ne_bytes()));
+
+            match op {
+                token::Symbol::Equal => Some(i32::from(lhs == rhs)),
+                token::Symbol::NotEqual => Some(i32::from(lhs != rhs)),
+                token::Symbol::LessThan => Some(i32::from(lhs < rhs)),
+                token::Symbol::GreaterThan => Some(i32::from(lhs > rhs)),
+                token::Symbol::LessThanOrEqual => Some(i32::from(lhs <= rhs)),
+                token::Symbol::GreaterThanOrEqual => Some(i32::from(lhs >= rhs)),
+                token::Symbol::LessThanUnsigned => Some(i32::from(lhs_unsigned < rhs_unsigned)),
+                token::Symbol::GreaterThanUnsigned => Some(i32::from(lhs_unsigned > rhs_unsigned)),
+                token::Symbol::LessThanOrEqualUnsigned => Some(i32::from(lhs_unsigned <= rhs_unsigned)),
+                token::Symbol::GreaterThanOrEqualUnsigned => Some(i32::from(lhs_unsigned >= rhs_unsigned)),
+                token::Symbol::LogicalAnd => Some(i32::from(lhs != 0 && rhs != 0)),
+                token::Symbol::LogicalOr => Some(i32::from(lhs != 0 || rhs != 0)),
+                _ => optimizer::fold_binary(op, lhs, rhs)
+            }
+        },
+        ast::Expression::Conditional { condition, consequent, alternative } => match value(condition)? {
+            0 => value(alternative),
+            _ => value(consequent)
+        },
+        _ => None
+    };
+}
+
+// string literals only exist at compile time, where `+` concatenates them
+fn check_strings(program: &ast::Program) -> Result<(), Box<dyn Error>> {
+    let mut operands = StringOperands::default();
+
+    operands.visit_program(program);
+
+    return match operands.error {
+        Some(x) => Err(x.into()),
+        None => Ok(())
+    };
+}
+
+#[derive(Default)]
+struct StringOperands {
+    error: Option<String>
+}
+
+impl Visitor for StringOperands {
+    fn visit_expr(&mut self, expr: &ast::Expression) {
+        if self.error.is_some() {
+            return;
+        }
+
+        match expr {
+            ast::Expression::Unary { op, operand } if is_string(operand) => {
+                self.error = Some(format!("operator {} is not defined for strings", op.name()));
+            },
+            ast::Expression::Binary { op, lhs, rhs } if is_string(lhs) || is_string(rhs) => {
+                if *op != token::Symbol::Plus {
+                    self.error = Some(format!("operator {} is not defined for strings", op.name()));
+                }
+                else if !is_string(lhs) || !is_string(rhs) {
+                    self.error = Some("cannot concatenate a string with a non-string value".to_string());
+                }
+            },
+            _ => {}
+        }
+
+        visitor::walk_expr(self, expr);
+    }
+}
+
+// a string literal, or a concatenation of them
+fn is_string(expr: &ast::Expression) -> bool {
+    return match expr {
+        ast::Expression::Literal(x) => ast::string_contents(x).is_some(),
+        ast::Expression::Binary { op: token::Symbol::Plus, lhs, rhs } => is_string(lhs) && is_string(rhs),
+        _ => false
+    };
+}
+
+// plain integers take their type from the context
+fn literal_type(literal: &str) -> Option<token::Type> {
+    if literal.starts_with("0x") || ast::string_contents(literal).is_some() {
+        return None;
+    }
+
+    if literal.ends_with("f32") {
+        return Some(token::Type::F32);
+    }
+
+    if literal.ends_with("f64") || literal.contains('.') || literal == "NaN" || literal == "Inf" {
+        return Some(token::Type::F64);
+    }
+
+    return None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::tokenizer;
+
+    fn check_source(text: &str) -> Result<(), Box<dyn Error>> {
+        let mut program = parser::parse_syntax(&tokenizer::tokenize(text)?, false)?;
+
+        infer_results(&mut program, &Defaults::default())?;
+
+        return check(&program);
+    }
+
+    fn inferred_result(text: &str, name: &str) -> Result<Option<String>, Box<dyn Error>> {
+        return inferred_result_with(text, name, &Defaults::default());
+    }
+
+    fn inferred_result_with(text: &str, name: &str, defaults: &Defaults) -> Result<Option<String>, Box<dyn Error>> {
+        let mut program = parser::parse_syntax(&tokenizer::tokenize(text)?, false)?;
+
+        default_literals(&mut program, defaults);
+        infer_results(&mut program, defaults)?;
+
+        return Ok(program.declarations.iter().find_map(|x| return match x {
+            ast::Declaration::Function(x) if x.name == name => Some(x.signature.result.as_ref().map(ToString::to_string)),
+            _ => None
+        }).flatten());
+    }
+
+    #[test]
+    fn accept_tail_expression() {
+        assert!(check_source("fn f(a: i32) -> i32 { let b <- a + 1; b * 2 }").is_ok());
+    }
+
+    #[test]
+    fn accept_tail_expression_after_return() {
+        assert!(check_source("fn f(a: i32) -> i32 { if (a) { ret 0; } a + 1 }").is_ok());
+    }
+
+    #[test]
+    fn reject_missing_return() {
+        let err = check_source("fn f(a: i32) -> i32 { if (a) { ret 0; } }").unwrap_err();
+
+        assert!(err.to_string().contains("does not return a value on all paths"));
+    }
+
+    #[test]
+    fn reject_tail_expression_without_result() {
+        let program = parser::parse_syntax(&tokenizer::tokenize("fn f(a: i32) { a }").unwrap(), false).unwrap();
+
+        assert!(check(&program).is_err());
+    }
+
+    #[test]
+    fn infer_result_type() {
+        assert_eq!(inferred_result("fn f(a: i32) { if (a) { ret 0; } a + 1 }", "f").unwrap().as_deref(), Some("i32"));
+        assert_eq!(inferred_result("fn f() { ret 1; }", "f").unwrap().as_deref(), Some("i32"));
+        assert_eq!(inferred_result("fn f(a: f64) { (a, 2) }", "f").unwrap().as_deref(), Some("(f64, i32)"));
+        assert_eq!(inferred_result("fn f(a: i64) { a }\nfn g() { f(1) }", "g").unwrap().as_deref(), Some("i64"));
+        assert_eq!(inferred_result("fn f(a: i32) { if (a) { ret; } }", "f").unwrap(), None);
+    }
+
+    #[test]
+    fn infer_with_default_types() {
+        let defaults = Defaults { int: token::Type::I64, float: token::Type::F32 };
+        let inferred = |text| return inferred_result_with(text, "f", &defaults).unwrap();
+
+        assert_eq!(inferred("fn f() { ret 1; }").as_deref(), Some("i64"));
+        assert_eq!(inferred("fn f() { (1, -2.5) }").as_deref(), Some("(i64, f32)"));
+        assert_eq!(inferred("fn f() { 2.5f64 }").as_deref(), Some("f64"));
+        assert_eq!(inferred("fn f(a: i32) { a + 1 }").as_deref(), Some("i32"));
+    }
+
+    #[test]
+    fn reject_conflicting_returns() {
+        let message = |text| return check_source(text).unwrap_err().to_string();
+
+        assert_eq!(message("fn f(a: i32) { if (a) { ret 1.5; } a }"), "function `f` returns both f64 and i32; give it an explicit result type");
+        assert_eq!(message("fn f(a: i32) { if (a) { ret; } a }"), "function `f` returns a value on some paths but not on others");
+        assert!(message("fn f(a: i32) { if (a) { ret 1; } }").contains("does not return a value on all paths"));
+    }
+
+    #[test]
+    fn accept_integer_condition() {
+        assert!(check_source("fn f(a: i32) { while (a > 0) { a <- a - 1; } if (a) { ret; } }").is_ok());
+    }
+
+    #[test]
+    fn reject_tuple_condition() {
+        let err = check_source("fn f(a: i32) { while (a, a) { ret; } }").unwrap_err();
+
+        assert!(err.to_string().contains("found a tuple of 2 values"));
+    }
+
+    #[test]
+    fn reject_float_condition() {
+        let err = check_source("fn f(a: f32) { if (a) { ret; } }").unwrap_err();
+
+        assert!(err.to_string().contains("found f32"));
+        assert!(check_source("fn f(a: i32) { let b <- 1.5; if (a) {} elif (b * 2.0) {} }").is_err());
+        assert!(check_source("fn f(a: f64) { if (a > 0.0) { ret; } }").is_ok());
+    }
+
+    #[test]
+    fn reject_condition_by_call_result() {
+        let message = |text| return check_source(text).unwrap_err().to_string();
+
+        assert!(message("fn g() {} fn f() { if (g()) { ret; } }").contains("found no value, since `g` returns nothing"));
+        assert!(message("fn g() -> (i32, i32) { (1, 2) } fn f() { while (g()) { ret; } }").contains("found a tuple of 2 values"));
+        assert!(message("fn g() -> f32 { 1.5f32 } fn f() { if (g()) { ret; } }").contains("found f32"));
+        assert!(check_source("fn g(a: i64) -> i64 { a } fn f(a: i64) { if (g(a)) { ret; } }").is_ok());
+    }
+
+    #[test]
+    fn reject_mismatched_parameter_values() {
+        let message = |text| return check_source(text).unwrap_err().to_string();
+
+        assert_eq!(message("fn f(a: f32 = 1.5) -> f32 { a }"), "error[E0015]: default value of parameter `a` in function `f` must be f32, found f64");
+        assert_eq!(message("fn g(a: i64) -> i64 { a } fn f(b: i32) -> i64 { g(b) }"), "error[E0015]: argument for parameter `a` of function `g` must be i64, found i32");
+        assert!(check_source("fn f(a: f32 = 1.5f32, b: i64 = 2) -> f32 { a }").is_ok());
+        assert!(check_source("fn g(a: i64, b: f64) -> i64 { a } fn f(b: i32) -> i64 { g(i64(b), 1) }").is_ok());
+    }
+
+    #[test]
+    fn check_declared_variable_type() {
+        let message = |text| return check_source(text).unwrap_err().to_string();
+
+        assert!(check_source("fn f() -> i32 { let x: xref <- Null; is_null(x) }").is_ok());
+        assert_eq!(message("fn f() -> i32 { let x <- Null; is_null(x) }"), "`let x` in function `f` cannot tell which reference `Null` is; declare its type, as in `let x: xref <- Null;`");
+        assert_eq!(message("fn f(b: i32) { let x: i64 <- b; }"), "error[E0015]: value of `x` in function `f` must be i64, found i32");
+        assert!(message("fn f(b: i32) { let x: i64 <- 5; x <- b; }").contains("value assigned to `x`"));
+    }
+
+    #[test]
+    fn reject_mismatched_assignment() {
+        let message = |text| return check_source(text).unwrap_err().to_string();
+
+        assert_eq!(message("fn f(b: i32) { let x: i64; x <- b; }"), "error[E0015]: value assigned to `x` in function `f` must be i64, found i32");
+        assert!(message("fn f(a: i32) { a <- 1.5; }").contains("must be i32, found f64"));
+        assert!(check_source("fn f(b: i32) { let x: i64; x <- i64(b); x <- 2; }").is_ok());
+    }
+
+    #[test]
+    fn reject_float_conditional_condition() {
+        let err = check_source("fn f(a: f64) -> i32 { a ? 1 : 2 }").unwrap_err();
+
+        assert_eq!(err.to_string(), "error[E0005]: `?:` condition must be a scalar integer, found f64; compare it explicitly instead");
+        assert!(check_source("fn g() {} fn f() -> i32 { g() ? 1 : 2 }").is_err());
+        assert!(check_source("fn f(a: i32) -> i32 { a ? 1 : 2 }").is_ok());
+    }
+
+    #[test]
+    fn accept_vector_length() {
+        assert!(check_source("fn f() -> (i32; 4) { (1, 2, 3, 4) }").is_ok());
+    }
+
+    #[test]
+    fn reject_float_vector_length() {
+        let err = check_source("type v = (i32; 2.5);").unwrap_err();
+
+        assert_eq!(err.to_string(), "error[E0006]: vector length `2.5` must be an integer");
+    }
+
+    #[test]
+    fn parse_radix_vector_length() {
+        assert!(check_source("fn f(a: (f64; 0x10)) {}").is_ok());
+        assert_eq!(vector_length("0x10").unwrap(), 16);
+        assert_eq!(vector_length("0b10").unwrap(), 2);
+    }
+
+    #[test]
+    fn reject_vector_length_out_of_range() {
+        assert!(vector_length("0").is_err());
+        assert!(vector_length("1001").is_err());
+        assert!(vector_length("0xffffffffffffffffffff").unwrap_err().to_string().contains("exceeds the maximum"));
+    }
+    #[test]
+    fn accept_page_count() {
+        assert!(check_source("mem memory = (1; page; 0x10);\nfn grow(p: page) -> page { p + 1 }").is_ok());
+        assert_eq!(page_count("65536").unwrap(), 65536);
+    }
+
+    #[test]
+    fn reject_negative_page_count() {
+        let err = check_source("fn grow(p: page) -> page { p + -1 }").unwrap_err();
+
+        assert_eq!(err.to_string(), "error[E0007]: page count `-1` must not be negative");
+        assert!(check_source("fn f(p: page) -> page { ret -2; }").is_err());
+        assert!(page_count("65537").unwrap_err().to_string().contains("exceeds the maximum"));
+        assert!(check_source("mem memory = (2; page; 1);").is_err());
+    }
+
+    #[test]
+    fn reject_page_mixed_with_integer() {
+        let err = check_source("fn f(p: page, a: i32) -> page { p * a }").unwrap_err();
+
+        assert!(err.to_string().contains("cannot mix page with i32"));
+    }
+
+    #[test]
+    fn accept_reference_tables() {
+        assert!(check_source("tab table = (1; fref; 2); imp tab hosts = (1; xref; 2) from \"env\";").is_ok());
+
+        let err = check_source("tab table = (1; i32; 2);").unwrap_err();
+
+        assert!(err.to_string().contains("table elements must be fref or xref, found i32"));
+    }
+
+    #[test]
+    fn reject_string_mixed_with_integer() {
+        let err = check_source("fn f() { let s <- \"a\" + 1; }").unwrap_err();
+
+        assert!(err.to_string().contains("cannot concatenate a string with a non-string value"));
+        assert_eq!(check_source("fn f() { let s <- \"a\" * 2; }").unwrap_err().to_string(), "operator * is not defined for strings");
+        assert_eq!(check_source("fn f() { let s <- -\"a\"; }").unwrap_err().to_string(), "operator - is not defined for strings");
+        assert!(check_source("fn f() { let s <- \"a\" - \"b\"; }").is_err());
+        assert!(check_source("fn f() { let s <- \"a\" + \"b\" + \"c\"; }").is_ok());
+    }
+
+    #[test]
+    fn reject_recursive_type() {
+        let message = |text| return check_source(text).unwrap_err().to_string();
+
+        assert_eq!(message("type List = (i32, List);"), "type `List` contains itself without indirection: List -> List");
+        assert_eq!(message("type A = (i32, B);\ntype B = A;"), "type `A` contains itself without indirection: A -> B -> A");
+    }
+
+    #[test]
+    fn accept_type_recursive_through_function() {
+        assert!(check_source("type Next = fn (i32) -> Next;").is_ok());
+        assert!(check_source("type Pair = (i32, i64);\ntype Nested = (Pair, f32);").is_ok());
+    }
+
+    #[test]
+    fn accept_static_assertion() {
+        assert!(check_source("static_assert(1 + 1 == 2, \"math\");").is_ok());
+        assert!(check_source("let pages <- 0x4;\nlet limit <- pages * 2;\nstatic_assert(limit >= pages && pages <u 16, \"limit\");").is_ok());
+        assert!(check_source("fn f() { static_assert(!0, \"not\"); }").is_ok());
+    }
+
+    #[test]
+    fn reject_failed_static_assertion() {
+        let message = |text| return check_source(text).unwrap_err().to_string();
+
+        assert_eq!(message("let pages <- 1;\nstatic_assert(pages >= 2, \"at least two pages are needed\");"), "error[E0010]: static assertion failed: at least two pages are needed");
+        assert!(message("fn f() { if (1) { static_assert(2 < 1, \"nested\"); } }").contains("static assertion failed: nested"));
+    }
+
+    #[test]
+    fn reject_non_constant_static_assertion() {
+        let message = |text| return check_source(text).unwrap_err().to_string();
+        let expected = "error[E0010]: static assertion condition must be a constant integer expression";
+
+        assert_eq!(message("let mut pages <- 1;\nstatic_assert(pages == 1, \"pages\");"), expected);
+        assert_eq!(message("let pages <- 1;\nfn f(pages: i32) { static_assert(pages == 1, \"pages\"); }"), expected);
+        assert_eq!(message("static_assert(1.5 > 1.0, \"float\");"), expected);
+        assert_eq!(message("let a <- b;\nlet b <- a;\nstatic_assert(a, \"cycle\");"), expected);
+    }
+
+    #[test]
+    fn reject_invalid_repeat_count() {
+        let message = |text| return check_source(text).unwrap_err().to_string();
+
+        assert!(check_source("fn f() { repeat 0 {} repeat 0x10 { repeat 2147483647 {} } }").is_ok());
+        assert_eq!(message("fn f() { repeat 2.5 {} }"), "error[E0011]: repeat count `2.5` must be an integer");
+        assert_eq!(message("fn f() { repeat 2147483648 {} }"), "error[E0011]: repeat count `2147483648` exceeds the maximum of 2147483647");
+        assert!(message("fn f() { if (1) { repeat 1.0 {} } }").contains("repeat count `1.0` must be an integer"));
+    }
+
+    #[test]
+    fn reject_loop_exit_outside_loop() {
+        let message = |text| return check_source(text).unwrap_err().to_string();
+
+        assert_eq!(message("fn f() { brk; }"), "`brk` outside of a loop in function `f`");
+        assert_eq!(message("fn f(a: i32) { while (a) { brk; } if (a) { { cont; } } }"), "`cont` outside of a loop in function `f`");
+        assert!(check_source("fn f(a: i32) { while (a) { if (a) { brk; } cont; } repeat 3 { { brk; } } }").is_ok());
+    }
+
+    #[test]
+    fn reject_misused_attributes() {
+        let message = |text| return check_source(text).unwrap_err().to_string();
+
+        assert_eq!(message("@inline(\"always\") fn f() {}"), "attribute `inline` of function `f` takes no argument");
+        assert_eq!(message("@export @export(\"g\") fn f() {}"), "attribute `export` is given more than once to function `f`");
+        assert!(check_source("@export(\"g\") @inline @cold @cold fn f() {}").is_ok());
+    }
+
+    #[test]
+    fn reject_misplaced_defer() {
+        let message = |text| return check_source(text).unwrap_err().to_string();
+
+        assert!(check_source("fn f() { defer { while (1) { brk; } } defer {} }").is_ok());
+        assert_eq!(message("fn f(a: i32) { if (a) { defer {} } }"), "`defer` is only allowed at the top of the body of function `f`");
+        assert_eq!(message("fn f() { defer { defer {} } }"), "`defer` is only allowed at the top of the body of function `f`");
+        assert_eq!(message("fn f() -> i32 { defer { if (1) { ret 1; } } 0 }"), "deferred block in function `f` cannot use `ret`");
+        assert_eq!(message("fn f() { while (1) { brk; } defer { cont; } }"), "deferred block in function `f` cannot use `cont`");
+        assert_eq!(message("fn f() { defer { 1 } }"), "deferred block in function `f` cannot end in a value");
+    }
+
+    #[test]
+    fn reject_mixed_signedness() {
+        let message = |text| return check_source(text).unwrap_err().to_string();
+
+        assert!(check_source("fn f(a: u32, b: i32) -> u32 { a / u32(b) + 1 }").is_ok());
+        assert!(check_source("fn f(a: u64, b: i64) -> i64 { i64(a) * b }").is_ok());
+        assert_eq!(message("fn f(a: u32, b: i32) -> u32 { a / b }"), "error[E0012]: cannot mix u32 with i32; cast one operand explicitly, as in `u32(x)`");
+        assert!(message("fn f(a: i64, b: u64) -> i32 { a < b }").contains("cannot mix i64 with u64"));
+        assert_eq!(message("fn f(a: i64) -> u32 { u32(a) }"), "error[E0012]: cannot cast i64 to u32 in one step; change the width and the signedness separately, as in `u32(i32(x))`");
+        assert!(message("fn f(a: u32) -> f32 { f32(a) }").contains("cannot cast to f32; only integer types can be cast"));
+    }
+
+    #[test]
+    fn reject_mixed_width() {
+        let message = |text| return check_source(text).unwrap_err().to_string();
+
+        assert!(check_source("fn f(a: i64, b: i64) -> i64 { a % b + a / 2 }").is_ok());
+        assert!(check_source("fn f(a: i32, b: i64) -> i64 { i64(a) % b }").is_ok());
+        assert!(check_source("fn f(a: u32, b: u64) -> i64 { i64(b / u64(a)) }").is_ok());
+        assert_eq!(message("fn f(a: i32, b: i64) -> i64 { a + b }"), "error[E0013]: cannot mix i32 with i64 in `+`; cast the narrower operand explicitly, as in `i64(x)`");
+        assert!(message("fn f(a: i64, b: i32) -> i64 { a % b }").contains("cannot mix i64 with i32 in `%`"));
+        assert!(message("fn f(a: i32, b: i64) -> i32 { a < b }").contains("cannot mix i32 with i64 in `<`"));
+        assert!(message("fn f(a: f32, b: f64) -> f64 { a * b }").contains("cannot mix f32 with f64 in `*`; both operands must have the same type"));
+        assert!(message("fn f(a: u32) -> i64 { i64(a) }").contains("cannot cast u32 to i64 in one step"));
+    }
+
+    #[test]
+    fn reject_incompatible_equality() {
+        let message = |text| return check_source(text).unwrap_err().to_string();
+
+        assert!(check_source("fn g() -> i64 { 5 } fn f(a: f32) -> i32 { (g() == 1) + (1 != g()) + (a == 0.5f32) }").is_ok());
+        assert_eq!(message("fn f(a: i32, b: f64) -> i32 { a == b }"), "error[E0013]: cannot mix i32 with f64 in `==`; both operands must have the same type");
+        assert!(message("fn f(a: fref) -> i32 { a == a }").contains("cannot compare fref values with `==`; test a reference against `Null`"));
+        assert!(message("fn f(a: xref) -> i32 { Null != a }").contains("cannot compare xref values with `!=`"));
+    }
+
+    #[test]
+    fn check_intrinsic_operands() {
+        assert!(check_source("fn f(a: f32, b: f32) -> f32 { max(sqrt(a), b) }").is_ok());
+        assert!(check_source("fn f(a: i32) -> f64 { sqrt(a) }").unwrap_err().to_string().contains("intrinsic `sqrt` takes f32 or f64 operands, found i32"));
+        assert!(check_source("fn f(a: f32, b: f64) -> f64 { min(a, b) }").unwrap_err().to_string().contains("found f32 and f64"));
+        assert!(check_source("fn f(a: f64) -> f64 { copysign(a) }").unwrap_err().to_string().contains("takes 2 arguments, but 1 were given"));
+        assert!(check_source("fn f(a: i64) -> i64 { popcnt(a) }").is_ok());
+        assert!(check_source("fn f(a: f64) -> i32 { clz(a) }").unwrap_err().to_string().contains("intrinsic `clz` takes i32 or i64 operands, found f64"));
+        assert!(check_source("fn f(a: i32) -> i32 { abs(a) }").is_err());
+    }
+}
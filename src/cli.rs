@@ -1,22 +1,127 @@
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::error::Error;
+use std::fmt;
+use std::io::IsTerminal;
 use structopt::StructOpt;
+use structopt::clap::AppSettings;
+
+use crate::analysis;
+use crate::checker;
+use crate::feature;
+use crate::optimizer;
+use crate::parser;
+use crate::token;
+use crate::tokenizer;
 
 #[derive(Debug, StructOpt)]
+#[structopt(setting = AppSettings::SubcommandsNegateReqs, setting = AppSettings::ArgsNegateSubcommands)]
 pub struct Opt {
-    /// Input file to be compiled
-    file: String,
-    /// Number of lines to read
+    /// Input files to be compiled and linked into one module
+    #[structopt(required_unless_one = &["explain", "list-features"])]
+    files: Vec<String>,
+    /// Output file, artifacts of `--emit` are named after it
     #[structopt(short = "o")]
     outfile: Option<String>,
     /// Optimization level
     #[structopt(short = "O")]
     opt_level: Option<OptLevel>,
+    /// Validate the emitted wasm before writing it (default)
+    #[structopt(long = "validate", overrides_with = "no-validate")]
+    validate: bool,
+    /// Skip validation of the emitted wasm
+    #[structopt(long = "no-validate", overrides_with = "validate")]
+    no_validate: bool,
+    /// Report each transformation made by the optimizer
+    #[structopt(long = "opt-remarks")]
+    opt_remarks: bool,
+    /// Print the parser stack for every processed token
+    #[structopt(long = "trace")]
+    trace: bool,
+    /// Print the resolved symbol table and exit
+    #[structopt(long = "dump-symbols")]
+    dump_symbols: bool,
+    /// Print the control-flow graph of every function in Graphviz DOT format and exit
+    #[structopt(long = "dump-cfg")]
+    dump_cfg: bool,
+    /// Print the formatted source and exit
+    #[structopt(long = "format")]
+    format: bool,
+    /// Treat warnings as errors
+    #[structopt(long = "deny-warnings")]
+    deny_warnings: bool,
+    /// Report every lint as an error, unless kept as a warning by `-W` or allowed by `-A`
+    #[structopt(long = "strict")]
+    strict: bool,
+    /// Keep a lint a warning: unused-imports, unused-variables, chained-comparisons, over-aligned-access, keyword-typos, unknown-attributes
+    #[structopt(short = "W", number_of_values = 1)]
+    warn: Vec<analysis::Lint>,
+    /// Silence a lint
+    #[structopt(short = "A", number_of_values = 1)]
+    allow: Vec<analysis::Lint>,
+    /// Comma-separated artifacts to write: wasm, wat, ir, tokens, metadata, dot-ast
+    #[structopt(long = "emit", use_delimiter = true)]
+    emit: Vec<EmitKind>,
+    /// Maximum nesting depth of the parser, counted in expressions and blocks
+    #[structopt(long = "recursion-limit")]
+    recursion_limit: Option<usize>,
+    /// Maximum length in bytes of an identifier or literal
+    #[structopt(long = "max-token-length")]
+    max_token_length: Option<usize>,
+    /// Define a name that enables its `incl NAME { ... }` sections
+    #[structopt(short = "D", number_of_values = 1)]
+    defines: Vec<String>,
+    /// Type of integer literals that no context decides: i32, i64
+    #[structopt(long = "default-int", default_value = "i32")]
+    default_int: IntType,
+    /// Type of float literals without a suffix: f32, f64
+    #[structopt(long = "default-float", default_value = "f64")]
+    default_float: FloatType,
+    /// Color diagnostics: auto, always, never
+    #[structopt(long = "color", default_value = "auto")]
+    color: ColorChoice,
+    /// Print a detailed explanation of an error code, e.g. E0001
+    #[structopt(long = "explain")]
+    explain: Option<String>,
+    /// Print the wasm proposals the transpiler knows of and exit
+    #[structopt(long = "list-features")]
+    list_features: bool,
+    /// Enable an opt-in wasm feature: tail-call
+    #[structopt(long = "enable", number_of_values = 1)]
+    features: Vec<String>,
+    /// Integer overflow of `+`, `-` and `*`: wrap, or trap on signed overflow
+    #[structopt(long = "overflow", default_value = "wrap")]
+    overflow: feature::Overflow,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Write markdown documentation of the exported functions
+    Doc {
+        /// Input file to be documented
+        file: String,
+        /// Output file, the documentation is printed when omitted
+        #[structopt(short = "o")]
+        outfile: Option<String>
+    },
+    /// Print the tokens of a fragment of source and what each stands for
+    ExplainToken {
+        /// Source fragment, as `ret` or `a |> f()`
+        text: String
+    }
 }
 
 impl Opt {
+    // always present unless `--explain` is given, artifacts are named after it
     pub fn file(&self) -> &str {
-        return &self.file;
+        return self.files.first().map_or("", |x| return x.as_str());
+    }
+
+    pub fn files(&self) -> &[String] {
+        return &self.files;
     }
 
     pub fn opt_level(&self) -> OptLevel {
@@ -27,6 +132,218 @@ impl Opt {
             return OptLevel::O3
         }
     }
+
+    pub const fn validate(&self) -> bool {
+        return self.validate || !self.no_validate;
+    }
+
+    pub const fn opt_remarks(&self) -> bool {
+        return self.opt_remarks;
+    }
+
+    pub const fn trace(&self) -> bool {
+        return self.trace;
+    }
+
+    pub const fn dump_symbols(&self) -> bool {
+        return self.dump_symbols;
+    }
+
+    pub const fn dump_cfg(&self) -> bool {
+        return self.dump_cfg;
+    }
+
+    pub const fn format(&self) -> bool {
+        return self.format;
+    }
+
+    pub const fn deny_warnings(&self) -> bool {
+        return self.deny_warnings;
+    }
+
+    // `-A` takes precedence over `-W`, which takes precedence over `--strict`
+    pub fn lint_level(&self, lint: &analysis::Lint) -> analysis::Level {
+        if self.allow.contains(lint) {
+            return analysis::Level::Allow;
+        }
+        else if self.warn.contains(lint) || !self.strict {
+            return analysis::Level::Warn;
+        }
+
+        return analysis::Level::Deny;
+    }
+
+    pub fn recursion_limit(&self) -> usize {
+        return self.recursion_limit.unwrap_or(parser::DEFAULT_MAX_DEPTH);
+    }
+
+    pub const fn overflow(&self) -> feature::Overflow {
+        return self.overflow;
+    }
+
+    pub fn max_token_length(&self) -> usize {
+        return self.max_token_length.unwrap_or(tokenizer::DEFAULT_MAX_TOKEN_LENGTH);
+    }
+
+    pub fn defines(&self) -> &[String] {
+        return &self.defines;
+    }
+
+    pub fn defaults(&self) -> checker::Defaults {
+        return checker::Defaults {
+            int: match self.default_int {
+                IntType::I32 => token::Type::I32,
+                IntType::I64 => token::Type::I64
+            },
+            float: match self.default_float {
+                FloatType::F32 => token::Type::F32,
+                FloatType::F64 => token::Type::F64
+            }
+        };
+    }
+
+    // `auto` colors only when diagnostics are written to a terminal
+    pub fn color(&self) -> bool {
+        return match self.color {
+            ColorChoice::Auto => std::io::stderr().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false
+        };
+    }
+
+    pub fn explain(&self) -> Option<&str> {
+        return self.explain.as_deref();
+    }
+
+    pub const fn list_features(&self) -> bool {
+        return self.list_features;
+    }
+
+    pub fn features(&self) -> &[String] {
+        return &self.features;
+    }
+
+    pub const fn command(&self) -> Option<&Command> {
+        return self.command.as_ref();
+    }
+
+    // `-o` alone keeps its meaning of writing wasm to exactly that path
+    pub fn emits(&self) -> Vec<EmitKind> {
+        if self.emit.is_empty() && self.outfile.is_some() {
+            return vec![EmitKind::Wasm];
+        }
+
+        return self.emit.clone();
+    }
+
+    // artifacts share the stem of `-o`, or of the first input file
+    pub fn output_path(&self, kind: &EmitKind) -> PathBuf {
+        if let Some(outfile) = &self.outfile {
+            if self.emit.is_empty() {
+                return PathBuf::from(outfile);
+            }
+
+            return Path::new(outfile).with_extension(kind.extension());
+        }
+
+        return Path::new(self.file()).with_extension(kind.extension());
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum EmitKind {
+    Wasm,
+    Wat,
+    Ir,
+    Tokens,
+    Metadata,
+    DotAst
+}
+
+impl EmitKind {
+    pub const fn extension(&self) -> &'static str {
+        return match self {
+            EmitKind::Wasm => "wasm",
+            EmitKind::Wat => "wat",
+            EmitKind::Ir => "ir",
+            EmitKind::Tokens => "tokens",
+            EmitKind::Metadata => "json",
+            EmitKind::DotAst => "dot"
+        };
+    }
+
+    // tokens and the ast are written before the module is built
+    pub const fn needs_module(&self) -> bool {
+        return !matches!(self, EmitKind::Tokens | EmitKind::DotAst);
+    }
+}
+
+impl FromStr for EmitKind {
+    type Err = Box<dyn Error>;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "wasm" => Ok(EmitKind::Wasm),
+            "wat" => Ok(EmitKind::Wat),
+            "ir" => Ok(EmitKind::Ir),
+            "tokens" => Ok(EmitKind::Tokens),
+            "metadata" => Ok(EmitKind::Metadata),
+            "dot-ast" => Ok(EmitKind::DotAst),
+            _ => Err(format!("cannot parse emit kind of: {}", s).into()),
+        };
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never
+}
+
+impl FromStr for ColorChoice {
+    type Err = Box<dyn Error>;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("cannot parse color choice of: {}", s).into()),
+        };
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum IntType {
+    I32,
+    I64
+}
+
+impl FromStr for IntType {
+    type Err = Box<dyn Error>;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "i32" => Ok(IntType::I32),
+            "i64" => Ok(IntType::I64),
+            _ => Err(format!("cannot parse integer type of: {}", s).into()),
+        };
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum FloatType {
+    F32,
+    F64
+}
+
+impl FromStr for FloatType {
+    type Err = Box<dyn Error>;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "f32" => Ok(FloatType::F32),
+            "f64" => Ok(FloatType::F64),
+            _ => Err(format!("cannot parse float type of: {}", s).into()),
+        };
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -39,6 +356,39 @@ pub enum OptLevel {
     OZ
 }
 
+impl OptLevel {
+    // (speed, size) priority, `Os` and `Oz` are `O2` with size taking precedence
+    const fn scale(&self) -> (u8, u8) {
+        return match self {
+            OptLevel::O0 => (0, 0),
+            OptLevel::O1 => (1, 0),
+            OptLevel::O2 => (2, 0),
+            OptLevel::O3 => (3, 0),
+            OptLevel::OS => (2, 1),
+            OptLevel::OZ => (2, 2)
+        };
+    }
+
+    pub fn enables(&self, pass: &optimizer::Pass) -> bool {
+        return self >= &pass.min_level();
+    }
+}
+
+// levels are ordered when one is at least as high as the other on both scales,
+// so `O3` and `Os` are not comparable
+impl PartialOrd for OptLevel {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let (speed, size) = self.scale();
+        let (other_speed, other_size) = other.scale();
+
+        return match (speed.cmp(&other_speed), size.cmp(&other_size)) {
+            (x, y) if x == y => Some(x),
+            (x, Ordering::Equal) | (Ordering::Equal, x) => Some(x),
+            _ => None
+        };
+    }
+}
+
 impl FromStr for OptLevel {
     type Err = Box<dyn Error>;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -49,7 +399,52 @@ impl FromStr for OptLevel {
             "3" => Ok(OptLevel::O3),
             "s" => Ok(OptLevel::OS),
             "z" => Ok(OptLevel::OZ),
-            _ => Err(format!("cannot parse optimizatoin level of: {}", s).into()),
+            _ => Err(format!("cannot parse optimization level of: {}", s).into()),
         };
     }
-}
\ No newline at end of file
+}
+
+// prints the same strings `FromStr` accepts
+impl fmt::Display for OptLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return f.write_str(match self {
+            OptLevel::O0 => "0",
+            OptLevel::O1 => "1",
+            OptLevel::O2 => "2",
+            OptLevel::O3 => "3",
+            OptLevel::OS => "s",
+            OptLevel::OZ => "z"
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn order_levels() {
+        assert!(OptLevel::O0 < OptLevel::O1);
+        assert!(OptLevel::O1 < OptLevel::O2);
+        assert!(OptLevel::O2 < OptLevel::O3);
+        assert!(OptLevel::O2 < OptLevel::OS);
+        assert!(OptLevel::OS < OptLevel::OZ);
+        assert_eq!(OptLevel::O3.partial_cmp(&OptLevel::OS), None);
+    }
+
+    #[test]
+    fn gate_passes() {
+        assert!(!OptLevel::O0.enables(&optimizer::Pass::ConstantFolding));
+        assert!(OptLevel::O1.enables(&optimizer::Pass::ConstantFolding));
+        assert!(!OptLevel::O1.enables(&optimizer::Pass::DeadFunctionElimination));
+        assert!(OptLevel::O2.enables(&optimizer::Pass::DeadFunctionElimination));
+        assert!(OptLevel::OZ.enables(&optimizer::Pass::DeadFunctionElimination));
+    }
+
+    #[test]
+    fn round_trip_levels() {
+        for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2, OptLevel::O3, OptLevel::OS, OptLevel::OZ].iter() {
+            assert_eq!(&OptLevel::from_str(&level.to_string()).unwrap(), level);
+        }
+    }
+}
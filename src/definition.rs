@@ -1,72 +1,298 @@
 use crate::token;
 
-pub const TYPE_TOKENS: &[(&str, token::Type)] = &[
-    ("i32",     token::Type::I32),
-    ("i64",     token::Type::I64),
-    ("f32",     token::Type::F32),
-    ("f64",     token::Type::F64),
-    ("fref",    token::Type::Fref),
-    ("xref",    token::Type::Xref),
-    ("page",    token::Type::Page)
-];
+// a table is written once as `"spelling" => value` pairs, which gives both a
+// slice of the pairs and a function matching a spelling to its value. the
+// compiler turns the `match` into a dispatch on length and bytes, which beats
+// scanning the slice
+macro_rules! token_table {
+    ($table:ident, $lookup:ident: $ty:ty { $($spelling:literal => $value:expr),* }) => {
+        pub const $table: &[(&str, $ty)] = &[$(($spelling, $value)),*];
+
+        pub fn $lookup(s: &str) -> Option<$ty> {
+            return match s {
+                $($spelling => Some($value),)*
+                _ => None
+            };
+        }
+    };
+}
+
+token_table! {
+    TYPE_TOKENS, type_of: token::Type {
+        "i32"     => token::Type::I32,
+        "i64"     => token::Type::I64,
+        "u32"     => token::Type::U32,
+        "u64"     => token::Type::U64,
+        "f32"     => token::Type::F32,
+        "f64"     => token::Type::F64,
+        "fref"    => token::Type::Fref,
+        "xref"    => token::Type::Xref,
+        "page"    => token::Type::Page
+    }
+}
+
+token_table! {
+    SYMBOL_TOKENS, symbol_of: token::Symbol {
+        "."       => token::Symbol::Dot,
+        "..."     => token::Symbol::Ellipsis,
+        ","       => token::Symbol::Comma,
+        ":"       => token::Symbol::Colon,
+        ";"       => token::Symbol::SemiColon,
+        "+"       => token::Symbol::Plus,
+        "-"       => token::Symbol::Minus,
+        "++"      => token::Symbol::Increment,
+        "--"      => token::Symbol::Decrement,
+        "*"       => token::Symbol::Asterisk,
+        "/"       => token::Symbol::Solidus,
+        "%"       => token::Symbol::Modulo,
+        "/u"      => token::Symbol::SolidusUnsigned,
+        "%u"      => token::Symbol::ModuloUnsigned,
+        "="       => token::Symbol::Assignment,
+        "=="      => token::Symbol::Equal,
+        "!="      => token::Symbol::NotEqual,
+        "<"       => token::Symbol::LessThan,
+        ">"       => token::Symbol::GreaterThan,
+        "<="      => token::Symbol::LessThanOrEqual,
+        ">="      => token::Symbol::GreaterThanOrEqual,
+        "<u"      => token::Symbol::LessThanUnsigned,
+        ">u"      => token::Symbol::GreaterThanUnsigned,
+        "<=u"     => token::Symbol::LessThanOrEqualUnsigned,
+        ">=u"     => token::Symbol::GreaterThanOrEqualUnsigned,
+        "<-"      => token::Symbol::LeftArrow,
+        "->"      => token::Symbol::RightArrow,
+        "&"       => token::Symbol::BitwiseAnd,
+        "|"       => token::Symbol::BitwiseOr,
+        "^"       => token::Symbol::BitwiseXor,
+        "~"       => token::Symbol::BitwiseNot,
+        "<<"      => token::Symbol::ShiftLeftLogical,
+        ">>"      => token::Symbol::ShiftRightArithmatic,
+        ">>>"     => token::Symbol::ShiftRightLogical,
+        "!"       => token::Symbol::LogicalNegation,
+        "&&"      => token::Symbol::LogicalAnd,
+        "||"      => token::Symbol::LogicalOr,
+        "|>"      => token::Symbol::PipeForward,
+        "?"       => token::Symbol::Query,
+        "::"      => token::Symbol::DoubleColon,
+        "@"       => token::Symbol::At,
+        "{"       => token::Symbol::LeftBrace,
+        "}"       => token::Symbol::RightBrace,
+        "("       => token::Symbol::LeftParenthese,
+        ")"       => token::Symbol::RightParenthese
+    }
+}
 
-// TODO: >>> (shift right logical) is currently not support
-pub const SYMBOL_TOKENS: &[(&str, token::Symbol)] = &[
-    (".",       token::Symbol::Dot),
-    (",",       token::Symbol::Comma),
-    (":",       token::Symbol::Colon),
-    (";",       token::Symbol::SemiColon),
-    ("+",       token::Symbol::Plus),
-    ("-",       token::Symbol::Minus),
-    ("*",       token::Symbol::Asterisk),
-    ("/",       token::Symbol::Solidus),
-    ("%",       token::Symbol::Modulo),
-    ("=",       token::Symbol::Assignment),
-    ("==",      token::Symbol::Equal),
-    ("!=",      token::Symbol::NotEqual),
-    ("<",       token::Symbol::LessThan),
-    (">",       token::Symbol::GreaterThan),
-    ("<=",      token::Symbol::LessThanOrEqual),
-    (">=",      token::Symbol::GreaterThanOrEqual),
-    ("<-",      token::Symbol::LeftArrow),
-    ("->",      token::Symbol::RightArrow),
-    ("&",       token::Symbol::BitwiseAnd),
-    ("|",       token::Symbol::BitwiseOr),
-    ("^",       token::Symbol::BitwiseXor),
-    ("~",       token::Symbol::BitwiseNot),
-    ("<<",      token::Symbol::ShiftLeftLogical),
-    (">>",      token::Symbol::ShiftRightLogical),
-    (">>>",     token::Symbol::ShiftRightArithmatic),
-    ("!",       token::Symbol::LogicalNegation),
-    ("&&",      token::Symbol::LogicalAnd),
-    ("||",      token::Symbol::LogicalOr),
-    ("|>",      token::Symbol::PipeForward),
-    ("?",       token::Symbol::Query),
-    ("::",      token::Symbol::DoubleColon),
-    ("{",       token::Symbol::LeftBrace),
-    ("}",       token::Symbol::RightBrace),
-    ("(",       token::Symbol::LeftParenthese),
-    (")",       token::Symbol::RightParenthese)
+token_table! {
+    KEYWORD_TOKENS, keyword_of: token::Keyword {
+        "fn"      => token::Keyword::Function,
+        "mut"     => token::Keyword::Mutable,
+        "let"     => token::Keyword::Let,
+        "mem"     => token::Keyword::Memory,
+        "tab"     => token::Keyword::Table,
+        "type"    => token::Keyword::Type,
+        "ret"     => token::Keyword::Return,
+        "if"      => token::Keyword::If,
+        "else"    => token::Keyword::Else,
+        "elif"    => token::Keyword::ElseIf,
+        "while"   => token::Keyword::While,
+        "repeat"  => token::Keyword::Repeat,
+        "defer"   => token::Keyword::Defer,
+        "brk"     => token::Keyword::Break,
+        "cont"    => token::Keyword::Cont,
+        "typeof"  => token::Keyword::TypeOf,
+        "exp"     => token::Keyword::Export,
+        "imp"     => token::Keyword::Import,
+        "as"      => token::Keyword::As,
+        "from"    => token::Keyword::From,
+        "incl"    => token::Keyword::Include,
+        "pub"     => token::Keyword::Public,
+        "where"   => token::Keyword::Where,
+        "static_assert" => token::Keyword::StaticAssert
+    }
+}
+
+// what a token stands for, in the words of a newcomer, for `explain-token`
+pub const fn description(token: &token::Token) -> &'static str {
+    return match token {
+        token::Token::Comment(_) => "comment, ignored by the compiler",
+        token::Token::Identifier(_) => "name of a function, variable, type, table or memory",
+        token::Token::Literal(token::Literal::Numeric(_)) => "number, a float may end in its type as in `0.5f32`",
+        token::Token::Literal(token::Literal::String(_)) => "string, stored in memory or naming an import or export",
+        token::Token::Keyword(x) => match x {
+            token::Keyword::Function => "function declaration",
+            token::Keyword::Let => "variable declaration",
+            token::Keyword::Mutable => "mutable variable, which can be assigned again",
+            token::Keyword::Memory => "memory declaration",
+            token::Keyword::Table => "table declaration",
+            token::Keyword::Type => "type alias declaration",
+            token::Keyword::Return => "return statement",
+            token::Keyword::If => "conditional statement",
+            token::Keyword::Else => "branch taken when no condition holds",
+            token::Keyword::ElseIf => "branch with a condition of its own, `else if`",
+            token::Keyword::While => "loop running while its condition holds",
+            token::Keyword::Repeat => "loop running a fixed number of times",
+            token::Keyword::Defer => "block run on every exit of the function",
+            token::Keyword::Break => "break statement, leaving the innermost loop",
+            token::Keyword::Cont => "continue statement, starting the next iteration",
+            token::Keyword::TypeOf => "type of a variable",
+            token::Keyword::Export => "export of a declaration to the host",
+            token::Keyword::Import => "import of a declaration from the host or a file",
+            token::Keyword::As => "name given to an import or export",
+            token::Keyword::From => "module an import is taken from",
+            token::Keyword::Include => "inclusion of a file, or of a section under `-D`",
+            token::Keyword::Public => "declaration visible to including files",
+            token::Keyword::Where => "constraint on the type parameters of a function",
+            token::Keyword::StaticAssert => "condition checked while compiling"
+        },
+        token::Token::Type(x) => match x {
+            token::Type::I32 => "32-bit signed integer",
+            token::Type::I64 => "64-bit signed integer",
+            token::Type::U32 => "32-bit unsigned integer",
+            token::Type::U64 => "64-bit unsigned integer",
+            token::Type::F32 => "32-bit float",
+            token::Type::F64 => "64-bit float",
+            token::Type::Fref => "reference to a function",
+            token::Type::Xref => "reference to a host value",
+            token::Type::Page => "count of 64 KiB memory pages"
+        },
+        token::Token::Symbol(x) => match x {
+            token::Symbol::Dot => "member access",
+            token::Symbol::Ellipsis => "variadic parameters",
+            token::Symbol::Comma => "separator of items",
+            token::Symbol::Colon => "type annotation",
+            token::Symbol::SemiColon => "end of a statement",
+            token::Symbol::Plus => "addition",
+            token::Symbol::Minus => "subtraction or negation",
+            token::Symbol::Increment => "increment by one",
+            token::Symbol::Decrement => "decrement by one",
+            token::Symbol::Asterisk => "multiplication",
+            token::Symbol::Solidus => "signed division",
+            token::Symbol::Modulo => "signed remainder",
+            token::Symbol::SolidusUnsigned => "unsigned division",
+            token::Symbol::ModuloUnsigned => "unsigned remainder",
+            token::Symbol::PlusUnsigned => "unsigned addition",
+            token::Symbol::MinusUnsigned => "unsigned subtraction",
+            token::Symbol::AsteriskUnsigned => "unsigned multiplication",
+            token::Symbol::Assignment => "value of a type alias, memory or table, or default of a parameter",
+            token::Symbol::Equal => "equality",
+            token::Symbol::NotEqual => "inequality",
+            token::Symbol::LessThan => "signed less than",
+            token::Symbol::GreaterThan => "signed greater than",
+            token::Symbol::LessThanOrEqual => "signed less than or equal",
+            token::Symbol::GreaterThanOrEqual => "signed greater than or equal",
+            token::Symbol::LessThanUnsigned => "unsigned less than",
+            token::Symbol::GreaterThanUnsigned => "unsigned greater than",
+            token::Symbol::LessThanOrEqualUnsigned => "unsigned less than or equal",
+            token::Symbol::GreaterThanOrEqualUnsigned => "unsigned greater than or equal",
+            token::Symbol::LeftArrow => "assignment",
+            token::Symbol::RightArrow => "result type of a function",
+            token::Symbol::BitwiseAnd => "bitwise and",
+            token::Symbol::BitwiseOr => "bitwise or",
+            token::Symbol::BitwiseXor => "bitwise exclusive or",
+            token::Symbol::BitwiseNot => "bitwise not",
+            token::Symbol::ShiftLeftLogical => "shift left",
+            token::Symbol::ShiftRightArithmatic => "arithmetic shift right, keeping the sign",
+            token::Symbol::ShiftRightLogical => "logical shift right, filling with zeros",
+            token::Symbol::LogicalNegation => "logical not",
+            token::Symbol::LogicalAnd => "logical and, short-circuiting",
+            token::Symbol::LogicalOr => "logical or, short-circuiting",
+            token::Symbol::Query => "conditional expression, `c ? a : b`",
+            token::Symbol::PipeForward => "pipe forward",
+            token::Symbol::DoubleColon => "type arguments of a call",
+            token::Symbol::At => "memory or table access at an offset",
+            token::Symbol::LeftBrace => "start of a block",
+            token::Symbol::RightBrace => "end of a block",
+            token::Symbol::LeftParenthese => "start of a group or argument list",
+            token::Symbol::RightParenthese => "end of a group or argument list"
+        }
+    };
+}
+
+// keywords that open a statement, with the spellings other languages give
+// them. two letter keywords are only matched by those, as too many short
+// names are one edit away from them
+const STATEMENT_KEYWORDS: &[(&str, &[&str])] = &[
+    ("ret", &["return"]),
+    ("brk", &["break"]),
+    ("cont", &["continue"]),
+    ("fn", &["func", "function"]),
+    ("let", &[]),
+    ("while", &[]),
+    ("repeat", &[]),
+    ("defer", &[]),
+    ("static_assert", &[])
 ];
 
-pub const KEYWORD_TOKENS: &[(&str, token::Keyword)] = &[
-    ("fn",      token::Keyword::Function),
-    ("mut",     token::Keyword::Mutable),
-    ("let",     token::Keyword::Let),
-    ("mem",     token::Keyword::Memory),
-    ("tab",     token::Keyword::Table),
-    ("type",    token::Keyword::Type),
-    ("ret",     token::Keyword::Return),
-    ("if",      token::Keyword::If),
-    ("else",    token::Keyword::Else),
-    ("elif",    token::Keyword::ElseIf),
-    ("while",   token::Keyword::While),
-    ("brk",     token::Keyword::Break),
-    ("cont",    token::Keyword::Cont),
-    ("typeof",  token::Keyword::TypeOf),
-    ("exp",     token::Keyword::Export),
-    ("imp",     token::Keyword::Import),
-    ("as",      token::Keyword::As),
-    ("from",    token::Keyword::From),
-    ("incl",    token::Keyword::Include)
-];
\ No newline at end of file
+// `--` is read as one symbol even before an operand, so `a--b` is a
+// decrement of `a` followed by `b` rather than a subtraction
+pub const DECREMENT_HINT: &str = "`--` only decrements a variable, as in `i--`, so write `a - -b` to subtract a negation";
+
+// the statement keyword a name is most likely a misspelling of, as `ret`
+// for `retrun` or `brk` for `break`
+pub fn keyword_typo(name: &str) -> Option<&'static str> {
+    return STATEMENT_KEYWORDS.iter()
+        .find(|(keyword, others)| return (keyword.len() > 2 && is_one_edit(name, keyword)) || others.iter().any(|x| return *x == name || is_one_edit(name, x)))
+        .map(|x| return x.0);
+}
+
+// one character inserted, removed, replaced, or swapped with its neighbour
+fn is_one_edit(a: &str, b: &str) -> bool {
+    let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+    let (shorter, longer) = if a.len() <= b.len() { (&a, &b) } else { (&b, &a) };
+    let prefix = shorter.iter().zip(longer.iter()).take_while(|(x, y)| return x == y).count();
+
+    return match longer.len() - shorter.len() {
+        0 if prefix == a.len() => false,
+        0 => a[prefix + 1..] == b[prefix + 1..] || (prefix + 1 < a.len() && a[prefix] == b[prefix + 1] && a[prefix + 1] == b[prefix] && a[prefix + 2..] == b[prefix + 2..]),
+        1 => shorter[prefix..] == longer[prefix + 1..],
+        _ => false
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a repeated spelling would leave its later entries unreachable
+    fn assert_resolves<T: Clone + PartialEq + std::fmt::Debug>(table: &[(&str, T)], lookup: fn(&str) -> Option<T>) {
+        for (spelling, value) in table.iter() {
+            assert_eq!(lookup(spelling).as_ref(), Some(value), "`{}`", spelling);
+        }
+    }
+
+    #[test]
+    fn look_up_every_spelling() {
+        assert_resolves(TYPE_TOKENS, type_of);
+        assert_resolves(SYMBOL_TOKENS, symbol_of);
+        assert_resolves(KEYWORD_TOKENS, keyword_of);
+    }
+
+    #[test]
+    fn miss_unknown_spelling() {
+        assert_eq!(type_of("i16"), None);
+        assert_eq!(symbol_of("<=>"), None);
+        assert_eq!(keyword_of("fun"), None);
+        assert_eq!(keyword_of(""), None);
+    }
+
+    #[test]
+    fn count_single_edits() {
+        assert!(is_one_edit("retrun", "return"));
+        assert!(is_one_edit("brek", "break"));
+        assert!(is_one_edit("lett", "let"));
+        assert!(is_one_edit("whle", "while"));
+        assert!(is_one_edit("rat", "ret"));
+        assert!(!is_one_edit("ret", "ret"));
+        assert!(!is_one_edit("retrun", "ret"));
+        assert!(!is_one_edit("tre", "ret"));
+    }
+
+    #[test]
+    fn suggest_statement_keywords() {
+        assert_eq!(keyword_typo("retrun"), Some("ret"));
+        assert_eq!(keyword_typo("return"), Some("ret"));
+        assert_eq!(keyword_typo("break"), Some("brk"));
+        assert_eq!(keyword_typo("whlie"), Some("while"));
+        assert_eq!(keyword_typo("fu"), None);
+        assert_eq!(keyword_typo("total"), None);
+    }
+}
@@ -0,0 +1,470 @@
+use std::error::Error;
+use std::fmt;
+use owo_colors::OwoColorize;
+
+use crate::span::Span;
+use crate::tokenizer;
+
+// an error kind with a stable code, explained by `--explain`
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub title: &'static str,
+    pub explanation: &'static str
+}
+
+// codes are never reused or renumbered, new kinds are appended at the end
+pub const UNEXPECTED_TOKEN: Diagnostic = Diagnostic {
+    code: "E0001",
+    title: "unexpected token",
+    explanation: "\
+The parser found a token that cannot appear at this point of the program.
+
+This usually means a missing `;`, an unbalanced bracket, or a keyword used
+in the wrong place.
+
+    fn add(a: i32, b: i32) -> i32 {
+        ret a + b   // missing `;`
+    }
+
+Check the tokens right before the reported position."
+};
+
+pub const UNKNOWN_TOKEN: Diagnostic = Diagnostic {
+    code: "E0002",
+    title: "unknown start of token",
+    explanation: "\
+The source contains a character that does not start any token.
+
+    let a <- 1 # 2;   // `#` is not an operator
+
+Identifiers may only contain letters, digits, `_` and `$`."
+};
+
+pub const UNCLOSED_STRING: Diagnostic = Diagnostic {
+    code: "E0003",
+    title: "unclosed string literal",
+    explanation: "\
+A string literal was opened with `\"` but the file ended before it was closed.
+
+    imp \"env   // missing closing `\"`
+
+Add the closing quote, escaping any `\"` inside the string as `\\\"`."
+};
+
+pub const MISSING_RETURN: Diagnostic = Diagnostic {
+    code: "E0004",
+    title: "missing return value",
+    explanation: "\
+A function with a result type must produce a value on every path, either by
+ending its body in an expression or by returning with `ret`.
+
+    fn sign(a: i32) -> i32 {
+        if (a) { ret 1; }   // nothing is returned when `a` is zero
+    }
+
+Add an `else` branch, a final `ret`, or a trailing expression."
+};
+
+pub const INVALID_CONDITION: Diagnostic = Diagnostic {
+    code: "E0005",
+    title: "invalid condition",
+    explanation: "\
+Conditions of `if`, `elif`, `while` and `?:` must be a single integer. wasm
+branches on an i32, so an i64 condition is compared with zero. Floats, tuples
+and calls that return nothing are not truthy.
+
+    fn f(a: f32) { if (a) { ret; } }   // f32 condition
+
+Compare the value explicitly instead, as in `if (a > 0.0)`."
+};
+
+pub const INVALID_VECTOR_LENGTH: Diagnostic = Diagnostic {
+    code: "E0006",
+    title: "invalid vector length",
+    explanation: "\
+The length of a vector type `(ty; N)` must be an integer from 1 to 1000,
+written in any radix.
+
+    type v = (i32; 2.5);   // not an integer
+
+Engines limit a function to 1000 params and results, which a vector expands into."
+};
+
+pub const INVALID_PAGE_COUNT: Diagnostic = Diagnostic {
+    code: "E0007",
+    title: "invalid page count",
+    explanation: "\
+A `page` is an unsigned count of 64 KiB memory pages. Page counts cannot be
+negative, cannot exceed 65536, and only combine with other page counts and
+plain integer literals.
+
+    fn shrink(p: page) -> page { p + -1 }   // negative page count
+
+Convert other integers explicitly before mixing them with page counts."
+};
+
+pub const RESERVED_KEYWORD: Diagnostic = Diagnostic {
+    code: "E0008",
+    title: "reserved keyword used as a name",
+    explanation: "\
+Keywords such as `if`, `fn` or `ret` are reserved and cannot name a function,
+parameter, variable or type.
+
+    fn if() {}   // `if` is a keyword
+
+Choose a different name, for example `if_` or `cond`."
+};
+
+pub const RECURSION_LIMIT: Diagnostic = Diagnostic {
+    code: "E0009",
+    title: "recursion limit reached",
+    explanation: "\
+The program nests expressions, statements or blocks deeper than the parser
+allows. Deep nesting would otherwise overflow the stack of the compiler.
+
+    fn f() -> i32 { ((((((((((1)))))))))) }   // times a few dozen
+
+Split the expression using local variables, or raise the limit with
+`--recursion-limit`."
+};
+
+pub const STATIC_ASSERTION: Diagnostic = Diagnostic {
+    code: "E0010",
+    title: "static assertion failed",
+    explanation: "\
+The condition of a `static_assert` is evaluated while compiling, and the
+program is rejected with its message when the condition is zero.
+
+    let pages <- 1;
+    static_assert(pages >= 2, \"at least two pages are needed\");
+
+The condition may only use integer literals, immutable globals and operators
+on them, since it cannot depend on values only known at run time."
+};
+
+pub const INVALID_REPEAT_COUNT: Diagnostic = Diagnostic {
+    code: "E0011",
+    title: "invalid repeat count",
+    explanation: "\
+The count of a `repeat` loop is fixed while compiling, so it must be a
+non-negative integer literal no greater than 2147483647.
+
+    fn f(n: i32) { repeat 2.5 {} }   // not an integer
+
+Use a `while` loop when the number of iterations is only known at run time."
+};
+
+pub const MIXED_SIGNEDNESS: Diagnostic = Diagnostic {
+    code: "E0012",
+    title: "mixed signedness",
+    explanation: "\
+Unsigned integers select the unsigned division, remainder, comparison and
+shift instructions, so they do not combine with signed integers.
+
+    fn half(a: u32, b: i32) -> u32 { a / b }   // u32 with i32
+
+Cast one of the operands, as in `a / u32(b)`. A cast changes either the
+signedness or the width of an integer, so `u32` to `i64` takes two casts."
+};
+
+pub const MIXED_WIDTH: Diagnostic = Diagnostic {
+    code: "E0013",
+    title: "mixed operand types",
+    explanation: "\
+Both operands of an arithmetic, bitwise, shift or comparison operator must
+have the same type, since a WebAssembly instruction takes operands of a
+single type and is never widened or narrowed implicitly.
+
+    fn f(a: i32, b: i64) -> i64 { a + b }   // i32 with i64
+
+Cast the narrower integer, as in `i64(a) + b`. Widening an integer keeps its
+signedness, so a `u32` becomes a `u64` and changes sign in a separate cast."
+};
+
+pub const TOKEN_TOO_LONG: Diagnostic = Diagnostic {
+    code: "E0014",
+    title: "token too long",
+    explanation: "\
+An identifier or literal is longer than the compiler accepts. Names end up in
+the export and name sections, where engines reject overly long ones, so the
+module would compile but fail to load.
+
+    exp \"aaaa...\" fn f() {}   // an alias of a hundred thousand bytes
+
+Shorten the name, or raise the limit with `--max-token-length`."
+};
+
+pub const MISMATCHED_TYPE: Diagnostic = Diagnostic {
+    code: "E0015",
+    title: "mismatched type",
+    explanation: "\
+A value given for a parameter must have the type of the parameter, whether it
+is passed in a call or is the default of the parameter, and a value assigned
+to a variable must have the type of the variable. WebAssembly never converts a
+value implicitly, so the module would fail to validate.
+
+    fn scale(a: f32 = 1.5) -> f32 { a }   // f64 default of an f32 parameter
+    fn f(b: i32) { let x: i64; x <- b; }  // i32 assigned to an i64
+
+Give the literal a suffix, as in `1.5f32`, or cast an integer, as in `i64(a)`."
+};
+
+pub const DIAGNOSTICS: &[Diagnostic] = &[
+    UNEXPECTED_TOKEN,
+    UNKNOWN_TOKEN,
+    UNCLOSED_STRING,
+    MISSING_RETURN,
+    INVALID_CONDITION,
+    INVALID_VECTOR_LENGTH,
+    INVALID_PAGE_COUNT,
+    RESERVED_KEYWORD,
+    RECURSION_LIMIT,
+    STATIC_ASSERTION,
+    INVALID_REPEAT_COUNT,
+    MIXED_SIGNEDNESS,
+    MIXED_WIDTH,
+    TOKEN_TOO_LONG,
+    MISMATCHED_TYPE
+];
+
+// the process exits with 1 when the program does not compile, and with 2
+// when the compiler was invoked wrongly or could not read or write a file
+pub const EXIT_COMPILE_ERROR: i32 = 1;
+pub const EXIT_USAGE_ERROR: i32 = 2;
+
+// errors that callers can tell apart by downcasting
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompileError {
+    // nesting went past the given depth
+    RecursionLimit(usize)
+}
+
+// how a diagnostic is labelled and colored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Remark
+}
+
+// a diagnostic as it is printed, `path:ln:col: error[E0001]: message`. an
+// error is often found with only the span of its tokens, which `locate`
+// turns into the location once the file is known
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub severity: Severity,
+    pub code: Option<&'static str>,
+    pub span: Option<Span>,
+    pub location: Option<String>,
+    pub message: String
+}
+
+// an invalid combination of options or arguments
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageError(pub String);
+
+impl Diagnostic {
+    // an error of this kind, displayed as `error[E0001]: ...`
+    pub fn message(&self, message: impl fmt::Display) -> Report {
+        return Report { code: Some(self.code), ..Report::new(Severity::Error, message) };
+    }
+}
+
+// the longer explanation printed by `--explain`
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}: {}\n\n{}\n", self.code, self.title, self.explanation);
+    }
+}
+
+impl CompileError {
+    pub fn report(&self) -> Report {
+        return match self {
+            CompileError::RecursionLimit(x) => RECURSION_LIMIT.message(format!("nesting exceeds the recursion limit of {}", x))
+        };
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return self.report().fmt(f);
+    }
+}
+
+impl Error for CompileError {}
+
+impl Severity {
+    pub const fn name(&self) -> &'static str {
+        return match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Remark => "remark"
+        };
+    }
+}
+
+impl Report {
+    pub fn new(severity: Severity, message: impl fmt::Display) -> Self {
+        return Self {
+            severity,
+            code: None,
+            span: None,
+            location: None,
+            message: message.to_string()
+        };
+    }
+
+    pub fn with_span(self, span: Span) -> Self {
+        return Self { span: Some(span), ..self };
+    }
+
+    pub fn at(self, location: String) -> Self {
+        return Self { location: Some(location), ..self };
+    }
+
+    // the span as `path:ln:col` of the file it was found in, unless the
+    // report is placed already
+    pub fn locate(self, path: &str, text: &str) -> Self {
+        return match (&self.location, &self.span) {
+            (None, Some(span)) => {
+                let (ln, col) = tokenizer::line_col(text, span.start.min(text.len()));
+
+                self.at(format!("{}:{}:{}", path, ln, col))
+            },
+            _ => self
+        };
+    }
+
+    // `error[E0001]`, or the severity alone without a code
+    fn label(&self) -> String {
+        return match self.code {
+            Some(code) => format!("{}[{}]", self.severity.name(), code),
+            None => self.severity.name().to_string()
+        };
+    }
+
+    // colors the location cyan and the label by severity, red for errors,
+    // yellow for warnings and green for remarks
+    pub fn paint(&self) -> String {
+        let label = match self.severity {
+            Severity::Error => self.label().red().bold().to_string(),
+            Severity::Warning => self.label().yellow().bold().to_string(),
+            Severity::Remark => self.label().green().bold().to_string()
+        };
+
+        return match &self.location {
+            Some(location) => format!("{}: {}: {}", location.cyan(), label, self.message),
+            None => format!("{}: {}", label, self.message)
+        };
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(location) = &self.location {
+            write!(f, "{}: ", location)?;
+        }
+
+        return write!(f, "{}: {}", self.label(), self.message);
+    }
+}
+
+impl Error for Report {}
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return f.write_str(&self.0);
+    }
+}
+
+impl Error for UsageError {}
+
+// any error as a report, errors without a kind of their own are plain errors
+pub fn render(err: &(dyn Error + 'static)) -> Report {
+    if let Some(x) = err.downcast_ref::<Report>() {
+        return x.clone();
+    }
+
+    if let Some(x) = err.downcast_ref::<CompileError>() {
+        return x.report();
+    }
+
+    return Report::new(Severity::Error, err);
+}
+
+// places an error found at a span of `path`, keeping errors without one as they are
+pub fn locate(err: Box<dyn Error>, path: &str, text: &str) -> Box<dyn Error> {
+    return match err.downcast::<Report>() {
+        Ok(x) => Box::new(x.locate(path, text)),
+        Err(err) => err
+    };
+}
+
+pub fn exit_code(err: &(dyn Error + 'static)) -> i32 {
+    if err.is::<UsageError>() || err.is::<std::io::Error>() {
+        return EXIT_USAGE_ERROR;
+    }
+
+    return EXIT_COMPILE_ERROR;
+}
+
+pub fn explain(code: &str) -> Result<&'static Diagnostic, Box<dyn Error>> {
+    return DIAGNOSTICS.iter()
+        .find(|x| return x.code.eq_ignore_ascii_case(code))
+        .ok_or_else(|| return UsageError(format!("no explanation for unknown error code `{}`", code)).into());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique_codes() {
+        for (i, diagnostic) in DIAGNOSTICS.iter().enumerate() {
+            assert_eq!(diagnostic.code, format!("E{:04}", i + 1));
+        }
+    }
+
+    #[test]
+    fn explain_code() {
+        assert_eq!(explain("e0002").unwrap().title, "unknown start of token");
+        assert!(explain("E9999").is_err());
+        assert_eq!(UNEXPECTED_TOKEN.message("x").to_string(), "error[E0001]: x");
+        assert_eq!(UNEXPECTED_TOKEN.message("x").code, Some("E0001"));
+    }
+
+    #[test]
+    fn categorize_errors() {
+        let usage = explain("E9999").err().unwrap();
+        let compile: Box<dyn Error> = "main.cwal:1:1: expected `;`".into();
+        let coded: Box<dyn Error> = Box::new(CompileError::RecursionLimit(8));
+
+        assert_eq!(exit_code(usage.as_ref()), EXIT_USAGE_ERROR);
+        assert_eq!(exit_code(compile.as_ref()), EXIT_COMPILE_ERROR);
+        assert_eq!(render(compile.as_ref()).to_string(), "error: main.cwal:1:1: expected `;`");
+        assert_eq!(render(coded.as_ref()).to_string(), "error[E0009]: nesting exceeds the recursion limit of 8");
+        assert_eq!(render(coded.as_ref()).code, Some("E0009"));
+    }
+
+    #[test]
+    fn locate_spans() {
+        let text = "fn f() {\n  let a: <- 1;\n}";
+        let report = UNEXPECTED_TOKEN.message("unexpected token `:`").with_span(16..17);
+        let located: Box<dyn Error> = locate(Box::new(report.clone()), "main.cwal", text);
+
+        assert_eq!(report.clone().locate("main.cwal", text).to_string(), "main.cwal:2:8: error[E0001]: unexpected token `:`");
+        assert_eq!(located.to_string(), "main.cwal:2:8: error[E0001]: unexpected token `:`");
+        assert_eq!(report.to_string(), "error[E0001]: unexpected token `:`");
+        assert_eq!(locate("plain".into(), "main.cwal", text).to_string(), "plain");
+    }
+
+    #[test]
+    fn paint_severity_and_span() {
+        let coded = UNCLOSED_STRING.message("unexpected unclosed string").at("a.cwal:1:5".to_string());
+
+        assert_eq!(coded.paint(), format!("{}: {}: unexpected unclosed string", "a.cwal:1:5".cyan(), "error[E0003]".red().bold()));
+        assert_eq!(Report::new(Severity::Error, "expected `;`").paint(), format!("{}: expected `;`", "error".red().bold()));
+        assert!(Report::new(Severity::Warning, "unused import").at("a.cwal:2:1".to_string()).paint().contains(&"warning".yellow().bold().to_string()));
+        assert!(Report::new(Severity::Remark, "folded").paint().contains(&"remark".green().bold().to_string()));
+    }
+}
@@ -0,0 +1,157 @@
+use std::error::Error;
+
+use crate::ast;
+
+// main program section
+// markdown listing every exported function with its signature and the `///`
+// comment lines right above it
+pub fn markdown(title: &str, program: &ast::Program, text: &str) -> Result<String, Box<dyn Error>> {
+    let mut out = format!("# {}\n", title);
+
+    for (name, function) in exported_functions(program)? {
+        out.push_str(&format!("\n## `{}`\n\n```\n{}\n```\n", name, signature(function, text)));
+
+        // the comment goes above the attributes of the function
+        let start = function.attributes.iter().map(|x| return x.span.start).fold(function.span.start, usize::min);
+        let doc = doc_comment(text, start);
+
+        if !doc.is_empty() {
+            out.push_str(&format!("\n{}\n", doc));
+        }
+    }
+
+    return Ok(out);
+}
+
+// exported name and declaration, in declaration order, whether exported by
+// `exp` or by an `@export` attribute. an alias is the value of its string
+// literal, as the module exports it
+fn exported_functions(program: &ast::Program) -> Result<Vec<(String, &ast::FunctionDeclaration)>, Box<dyn Error>> {
+    let mut res = vec![];
+
+    for decl in program.declarations.iter() {
+        if let ast::Declaration::Function(function) = decl {
+            if let Some(attribute) = function.attribute("export") {
+                let name = match &attribute.argument {
+                    Some(alias) => ast::unescape(alias)?,
+                    None => function.name.clone()
+                };
+
+                res.push((name, function));
+            }
+        }
+        else if let ast::Declaration::Export(x) = decl {
+            match &x.item {
+                ast::ExportItem::Function(function) => {
+                    let name = match &x.alias {
+                        Some(alias) => ast::unescape(alias)?,
+                        None => function.name.clone()
+                    };
+
+                    res.push((name, function));
+                },
+                ast::ExportItem::Aliased(name, alias) => {
+                    if let Some(function) = function(program, name) {
+                        res.push((ast::unescape(alias)?, function));
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+
+    return Ok(res);
+}
+
+fn function<'a>(program: &'a ast::Program, name: &str) -> Option<&'a ast::FunctionDeclaration> {
+    return program.declarations.iter().find_map(|x| return match x {
+        ast::Declaration::Function(x) if x.name == name => Some(x),
+        _ => None
+    });
+}
+
+fn signature(function: &ast::FunctionDeclaration, text: &str) -> String {
+    let params = function.signature.params.iter()
+        .map(|x| return param(x, text))
+        .collect::<Vec<_>>();
+
+    return match &function.signature.result {
+        Some(result) => format!("fn {}({}) -> {}", function.name, params.join(", "), result),
+        None => format!("fn {}({})", function.name, params.join(", "))
+    };
+}
+
+// a default is shown as written, the tree keeps no source form of expressions
+fn param(param: &ast::Param, text: &str) -> String {
+    let ellipsis = if param.is_variadic { "..." } else { "" };
+    let default = text.get(param.span.clone())
+        .and_then(|x| return x.split_once('='))
+        .filter(|_| return param.default.is_some())
+        .map(|x| return x.1.split_whitespace().collect::<Vec<_>>().join(" "));
+
+    return match default {
+        Some(x) => format!("{}{}: {} = {}", ellipsis, param.name, param.ty, x),
+        None => format!("{}{}: {}", ellipsis, param.name, param.ty)
+    };
+}
+
+// the `///` lines directly above the line holding the given offset
+fn doc_comment(text: &str, offset: usize) -> String {
+    let line_start = text[..offset].rfind('\n').map_or(0, |x| return x + 1);
+    let mut lines = text[..line_start].lines().rev()
+        .map(str::trim)
+        .take_while(|x| return x.starts_with("///"))
+        .map(|x| return x.trim_start_matches('/').trim())
+        .collect::<Vec<_>>();
+
+    lines.reverse();
+
+    return lines.join("\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::tokenizer;
+
+    #[test]
+    fn document_exported_function() {
+        let text = "/// Adds two numbers.\n/// Wraps on overflow.\nexp \"add\" fn add(a: i32, b: i32) -> i32 { a + b }\n\n// not a doc comment\nfn hidden() {}";
+        let program = parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap();
+        let doc = markdown("math", &program, text).unwrap();
+
+        assert!(doc.starts_with("# math\n"));
+        assert!(doc.contains("## `add`\n\n```\nfn add(a: i32, b: i32) -> i32\n```\n\nAdds two numbers.\nWraps on overflow.\n"));
+        assert!(!doc.contains("hidden"));
+    }
+
+    #[test]
+    fn document_default_params() {
+        let text = "exp \"add\" fn add(a: i32, b: i32 = 2, c: i32 =  -(1 + 2)) -> i32 { a + b + c }";
+        let program = parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap();
+
+        assert!(markdown("math", &program, text).unwrap().contains("```\nfn add(a: i32, b: i32 = 2, c: i32 = -(1 + 2)) -> i32\n```\n"));
+    }
+
+    #[test]
+    fn document_escaped_export_names() {
+        let text = "exp \"al\\\"x\" fn f() {}\nfn g() {}\nexp g as \"tab\\u{9}\";";
+        let program = parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap();
+        let doc = markdown("names", &program, text).unwrap();
+
+        assert!(doc.contains("## `al\"x`\n"));
+        assert!(doc.contains("## `tab\t`\n"));
+    }
+
+    #[test]
+    fn document_attribute_exports() {
+        let text = "/// Doubles a number.\n@export(\"twice\")\nfn double(a: i32) -> i32 { a * 2 }\n@export fn id(a: i32) -> i32 { a }\nfn hidden() {}";
+        let program = parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap();
+        let doc = markdown("attributes", &program, text).unwrap();
+
+        assert!(doc.contains("## `twice`\n\n```\nfn double(a: i32) -> i32\n```\n\nDoubles a number.\n"));
+        assert!(doc.contains("## `id`\n\n```\nfn id(a: i32) -> i32\n```\n"));
+        assert!(!doc.contains("hidden"));
+    }
+}
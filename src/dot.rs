@@ -0,0 +1,158 @@
+use std::fmt::Write;
+
+use crate::ast;
+use crate::cfg;
+use crate::visitor::{self, Visitor};
+
+// main program section
+// a Graphviz `digraph` of the ast, one box per node labeled with its kind,
+// and an edge from every node to each of its children
+pub fn ast(name: &str, program: &ast::Program) -> String {
+    let mut graph = Graph { out: String::new(), count: 0, parents: vec![] };
+
+    graph.out.push_str(&format!("digraph \"{}\" {{\n    node [shape=box, fontname=monospace];\n", cfg::escape(name)));
+    graph.visit_program(program);
+    graph.out.push_str("}\n");
+
+    return graph.out;
+}
+
+struct Graph {
+    out: String,
+    count: usize,
+    // the nodes enclosing the one being visited
+    parents: Vec<usize>
+}
+
+impl Graph {
+    fn node(&mut self, label: &str) -> usize {
+        let id = self.count;
+
+        self.count += 1;
+        writeln!(self.out, "    n{} [label=\"{}\"];", id, cfg::escape(label)).expect("unexpected write failure");
+
+        if let Some(parent) = self.parents.last() {
+            writeln!(self.out, "    n{} -> n{};", parent, id).expect("unexpected write failure");
+        }
+
+        return id;
+    }
+
+    // a node whose children are the nodes visited by `walk`
+    fn enter(&mut self, label: &str, walk: impl FnOnce(&mut Self)) {
+        let id = self.node(label);
+
+        self.parents.push(id);
+        walk(self);
+        self.parents.pop();
+    }
+}
+
+impl Visitor for Graph {
+    fn visit_program(&mut self, program: &ast::Program) {
+        self.enter("Program", |x| return visitor::walk_program(x, program));
+    }
+
+    // functions and variables are labeled by their own visit
+    fn visit_decl(&mut self, decl: &ast::Declaration) {
+        let label = match decl {
+            ast::Declaration::Function(_) | ast::Declaration::Variable(_) => return visitor::walk_decl(self, decl),
+            ast::Declaration::Type(_) => "Type",
+            ast::Declaration::Table(_) => "Table",
+            ast::Declaration::Memory(_) => "Memory",
+            ast::Declaration::Import(_) => "Import",
+            ast::Declaration::ImportModule(_) => "ImportModule",
+            ast::Declaration::Export(_) => "Export",
+            ast::Declaration::Include(_) => "Include",
+            ast::Declaration::StaticAssert(_) => "StaticAssert",
+            ast::Declaration::Error(_) => "Error"
+        };
+
+        self.enter(label, |x| return visitor::walk_decl(x, decl));
+    }
+
+    fn visit_fn(&mut self, function: &ast::FunctionDeclaration) {
+        self.enter(&format!("Function {}", function.name), |x| return visitor::walk_fn(x, function));
+    }
+
+    fn visit_variable(&mut self, variable: &ast::VariableDeclaration) {
+        let names = match &variable.pattern {
+            ast::Pattern::Single(x) => x.name.clone(),
+            ast::Pattern::Tuple(list) => format!("({})", list.iter().map(|x| return x.name.as_str()).collect::<Vec<_>>().join(", "))
+        };
+
+        self.enter(&format!("Let {}", names), |x| return visitor::walk_variable(x, variable));
+    }
+
+    fn visit_block(&mut self, block: &ast::Block) {
+        self.enter("Block", |x| return visitor::walk_block(x, block));
+    }
+
+    // statements holding a single node are labeled by that node alone
+    fn visit_stmt(&mut self, stmt: &ast::Statement) {
+        let label = match stmt {
+            ast::Statement::Variable(_) | ast::Statement::Expression(_) | ast::Statement::Function(_) | ast::Statement::Block(_) => {
+                return visitor::walk_stmt(self, stmt);
+            },
+            ast::Statement::Assignment(..) => "Assignment".to_string(),
+            ast::Statement::If(_) => "If".to_string(),
+            ast::Statement::While(_) => "While".to_string(),
+            ast::Statement::Repeat(x) => format!("Repeat {}", x.count),
+            ast::Statement::Defer(_) => "Defer".to_string(),
+            ast::Statement::Return(_) => "Return".to_string(),
+            ast::Statement::Break => "Break".to_string(),
+            ast::Statement::Continue => "Continue".to_string(),
+            ast::Statement::StaticAssert(_) => "StaticAssert".to_string()
+        };
+
+        self.enter(&label, |x| return visitor::walk_stmt(x, stmt));
+    }
+
+    fn visit_expr(&mut self, expr: &ast::Expression) {
+        let label = match expr {
+            ast::Expression::Literal(x) => format!("Literal {}", x),
+            ast::Expression::Identifier(x) => format!("Identifier {}", x),
+            ast::Expression::Call { callee, .. } => format!("Call {}", callee),
+            ast::Expression::CallIndirect { .. } => "CallIndirect".to_string(),
+            ast::Expression::GenericCall { callee, .. } => format!("GenericCall {}", callee),
+            ast::Expression::Unary { op, .. } => format!("Unary {}", op.name()),
+            ast::Expression::Binary { op, .. } => format!("Binary {}", op.name()),
+            ast::Expression::Conditional { .. } => "Conditional".to_string(),
+            ast::Expression::Tuple(_) => "Tuple".to_string(),
+            ast::Expression::TypeOf(x) => format!("TypeOf {}", x),
+            ast::Expression::Type(x) => format!("Type {}", x.name()),
+            ast::Expression::Cast { ty, .. } => format!("Cast {}", ty.name()),
+            ast::Expression::Offset { base, .. } => format!("Offset {}", base),
+            ast::Expression::Variadic { ty, .. } => format!("Variadic {}", ty.name())
+        };
+
+        self.enter(&label, |x| return visitor::walk_expr(x, expr));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::assert_parses;
+
+    #[test]
+    fn draw_expression_tree() {
+        let dot = ast("f.cwal", &assert_parses("fn f(a: i32) -> i32 { a * 2 + 1 }"));
+
+        // Program, Function, Block, two Binary, Identifier and two Literal
+        assert_eq!(dot.matches("[label=").count(), 8);
+        assert_eq!(dot.matches(" -> ").count(), 7);
+        assert!(dot.starts_with("digraph \"f.cwal\" {\n"));
+        assert!(dot.contains("    n3 [label=\"Binary +\"];\n    n2 -> n3;\n"));
+        assert!(dot.contains("    n4 [label=\"Binary *\"];\n    n3 -> n4;\n"));
+    }
+
+    #[test]
+    fn label_declarations_and_statements() {
+        let dot = ast("f.cwal", &assert_parses("exp \"f\" fn f() { repeat 2 { brk; } }"));
+
+        assert!(dot.contains("[label=\"Export\"]"));
+        assert!(dot.contains("[label=\"Repeat 2\"]"));
+        assert!(dot.contains("[label=\"Break\"]"));
+    }
+}
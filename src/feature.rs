@@ -0,0 +1,152 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::diagnostic::UsageError;
+
+// a wasm proposal, the version of the spec that first includes it, and
+// whether the transpiler emits it yet, printed by `--list-features`
+pub struct Feature {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub summary: &'static str,
+    pub status: Status
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Implemented,
+    // emitted only when named by `--enable`, as not every engine runs it
+    OptIn,
+    Missing
+}
+
+// the opt-in features named by `--enable`, and what `--overflow` makes of
+// integer arithmetic
+#[derive(Debug, Clone, Default)]
+pub struct Features {
+    pub tail_call: bool,
+    pub overflow: Overflow
+}
+
+// wasm wraps integers on overflow, `trap` checks the signed result of every
+// `+`, `-` and `*` instead, and reaches `unreachable` when it overflows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    Wrap,
+    Trap
+}
+
+impl Default for Overflow {
+    fn default() -> Self {
+        return Overflow::Wrap;
+    }
+}
+
+impl FromStr for Overflow {
+    type Err = Box<dyn Error>;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "wrap" => Ok(Overflow::Wrap),
+            "trap" => Ok(Overflow::Trap),
+            _ => Err(format!("cannot parse overflow behavior of: {}", s).into()),
+        };
+    }
+}
+
+pub const FEATURES: &[Feature] = &[
+    Feature {
+        name: "multi-value",
+        version: "2.0",
+        summary: "functions returning several values, destructured by `let (a, b)`",
+        status: Status::Implemented
+    },
+    Feature {
+        name: "reference-types",
+        version: "2.0",
+        summary: "`fref` and `xref` values, `Null` and tables of references",
+        status: Status::Implemented
+    },
+    Feature {
+        name: "bulk-memory",
+        version: "2.0",
+        summary: "`memory.copy` and `memory.fill`",
+        status: Status::Missing
+    },
+    Feature {
+        name: "simd",
+        version: "2.0",
+        summary: "128-bit vector values and instructions",
+        status: Status::Missing
+    },
+    Feature {
+        name: "tail-call",
+        version: "3.0",
+        summary: "`ret f(..)` reusing the frame of the caller, as `return_call`",
+        status: Status::OptIn
+    }
+];
+
+// always on features are accepted too, so naming one does no harm
+pub fn enable(names: &[String]) -> Result<Features, Box<dyn Error>> {
+    let mut features = Features::default();
+
+    for name in names {
+        match FEATURES.iter().find(|x| return x.name == name) {
+            Some(Feature { name: "tail-call", .. }) => features.tail_call = true,
+            Some(x) if x.status == Status::Implemented => {},
+            Some(x) => return Err(UsageError(format!("feature `{}` is not implemented yet", x.name)).into()),
+            None => return Err(UsageError(format!("unknown feature `{}`, see `--list-features`", name)).into())
+        }
+    }
+
+    return Ok(features);
+}
+
+// one feature per line, with its columns aligned
+pub fn listing() -> String {
+    let width = FEATURES.iter().map(|x| return x.name.len()).max().unwrap_or(0);
+    let mut out = format!("{:<width$}  {:<8}  {:<15}  description\n", "feature", "wasm", "status", width = width);
+
+    for feature in FEATURES {
+        out.push_str(&format!("{:<width$}  {}\n", feature.name, feature, width = width));
+    }
+
+    return out;
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self.status {
+            Status::Implemented => "implemented",
+            Status::OptIn => "opt-in",
+            Status::Missing => "not yet"
+        };
+
+        return write!(f, "{:<8}  {:<15}  {}", self.version, status, self.summary);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_every_feature() {
+        let listing = listing();
+        let lines = listing.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), FEATURES.len() + 1);
+        assert!(lines[1].starts_with("multi-value      2.0       implemented"));
+        assert!(lines[4].starts_with("simd             2.0       not yet"));
+        assert!(lines[5].starts_with("tail-call        3.0       opt-in"));
+    }
+
+    #[test]
+    fn enable_opt_in_features() {
+        assert!(enable(&["tail-call".to_string()]).unwrap().tail_call);
+        assert!(!enable(&["multi-value".to_string()]).unwrap().tail_call);
+        assert!(enable(&["simd".to_string()]).unwrap_err().to_string().contains("not implemented yet"));
+        assert!(enable(&["threads".to_string()]).unwrap_err().to_string().contains("unknown feature `threads`"));
+    }
+}
@@ -0,0 +1,411 @@
+use std::error::Error;
+
+use crate::token;
+use crate::tokenizer;
+
+const INDENT: &str = "    ";
+
+// the formatter works on the token stream rather than the syntax tree, since
+// the parser drops comments but the tokenizer keeps them in place
+struct Formatter<'a> {
+    lines: Vec<String>,
+    current: String,
+    prev: Option<&'a token::Token<'a>>,
+    depth: usize,
+    paren_depth: usize,
+    generic_depth: usize,
+    is_prefix: bool,
+    is_generic_closed: bool,
+    // the name right after `fn`, which type parameters may follow
+    is_function_name: bool,
+    // the index of `@index<ty>(memory)`, which a type argument may follow
+    is_offset_index: bool,
+    // paren depths at which a parenthesized offset index opened
+    offset_groups: Vec<usize>,
+    is_break_pending: bool,
+    is_continued: bool,
+    // inside an `@name("argument")` before a declaration, which has a line of its own
+    is_attribute: bool
+}
+
+impl<'a> Formatter<'a> {
+    fn new() -> Self {
+        return Self {
+            lines: vec![],
+            current: String::new(),
+            prev: None,
+            depth: 0,
+            paren_depth: 0,
+            generic_depth: 0,
+            is_prefix: false,
+            is_generic_closed: false,
+            is_function_name: false,
+            is_offset_index: false,
+            offset_groups: vec![],
+            is_break_pending: false,
+            is_continued: false,
+            is_attribute: false
+        };
+    }
+
+    fn flush(&mut self) {
+        if !self.current.is_empty() {
+            self.lines.push(std::mem::take(&mut self.current));
+        }
+    }
+
+    fn blank_line(&mut self) {
+        if self.lines.last().is_some_and(|x| return !x.is_empty() && !x.ends_with('{')) {
+            self.lines.push(String::new());
+        }
+    }
+
+    fn push(&mut self, text: &str, is_spaced: bool) {
+        if self.current.is_empty() {
+            let level = self.depth + usize::from(self.is_continued);
+
+            self.current.push_str(&INDENT.repeat(level));
+        }
+        else if is_spaced {
+            self.current.push(' ');
+        }
+
+        self.current.push_str(text);
+    }
+
+    fn comment(&mut self, text: &str, is_same_line: bool, newlines: usize) {
+        let is_line_comment = text.starts_with("//");
+
+        if is_same_line {
+            self.push(text, true);
+
+            // a line comment ends the line, so the rest of the statement continues below it
+            if is_line_comment {
+                self.flush();
+                self.is_continued |= !self.is_break_pending && self.prev.is_some();
+            }
+
+            return;
+        }
+
+        // a comment on its own line leads whatever follows it
+        let is_inside = !self.current.is_empty() && !self.is_break_pending;
+
+        self.flush();
+        self.is_break_pending = false;
+        self.is_continued = is_inside;
+
+        if newlines > 1 {
+            self.blank_line();
+        }
+
+        self.push(text, false);
+        self.flush();
+    }
+
+    fn token(&mut self, token: &'a token::Token<'a>, text: &str, newlines: usize) {
+        let is_closing_brace = token == &token::Token::Symbol(token::Symbol::RightBrace);
+        let is_empty_block = is_closing_brace && self.prev == Some(&token::Token::Symbol(token::Symbol::LeftBrace));
+
+        if self.is_attribute && self.paren_depth == 0 && self.ends_attribute(token) {
+            self.flush();
+            self.is_attribute = false;
+        }
+
+        if self.is_break_pending && !is_empty_block {
+            self.flush();
+            self.is_break_pending = false;
+            self.is_continued = false;
+
+            if newlines > 1 && !is_closing_brace {
+                self.blank_line();
+            }
+        }
+
+        match token {
+            token::Token::Symbol(token::Symbol::LeftBrace) => {
+                self.push(text, true);
+                self.depth += 1;
+                self.is_break_pending = true;
+            },
+            token::Token::Symbol(token::Symbol::RightBrace) => {
+                self.depth = self.depth.saturating_sub(1);
+
+                if is_empty_block {
+                    self.push(text, false);
+                }
+                else {
+                    self.flush();
+                    self.is_continued = false;
+                    self.push(text, false);
+                }
+
+                self.is_break_pending = true;
+            },
+            token::Token::Symbol(token::Symbol::SemiColon) => {
+                self.push(text, false);
+                self.is_break_pending = self.paren_depth == 0;
+            },
+            _ => {
+                let is_spaced = self.is_spaced(token);
+
+                match token {
+                    token::Token::Symbol(token::Symbol::LeftParenthese) => self.paren_depth += 1,
+                    token::Token::Symbol(token::Symbol::RightParenthese) => self.paren_depth = self.paren_depth.saturating_sub(1),
+                    token::Token::Symbol(token::Symbol::LessThan) if self.opens_generic() => {
+                        self.generic_depth += 1;
+                    },
+                    _ => {}
+                }
+
+                self.push(text, is_spaced);
+            }
+        }
+
+        let is_generic_end = self.generic_depth > 0 && token == &token::Token::Symbol(token::Symbol::GreaterThan);
+
+        if is_generic_end {
+            self.generic_depth -= 1;
+        }
+
+        self.is_generic_closed = is_generic_end;
+        self.is_function_name = matches!(token, token::Token::Identifier(_)) && self.prev == Some(&token::Token::Keyword(token::Keyword::Function));
+        self.update_offset(token);
+        let is_attribute_start = token == &token::Token::Symbol(token::Symbol::At) && self.depth == 0 && self.paren_depth == 0 && self.current.len() == text.len();
+
+        self.is_attribute |= is_attribute_start;
+        self.is_prefix = (is_operator(token) && !is_operand(self.prev)) || is_attribute_start;
+        self.prev = Some(token);
+    }
+
+    // the token after the name of an attribute, or after its argument
+    fn ends_attribute(&self, token: &token::Token) -> bool {
+        return match self.prev {
+            Some(token::Token::Symbol(token::Symbol::At)) => false,
+            Some(token::Token::Identifier(_)) => token != &token::Token::Symbol(token::Symbol::LeftParenthese),
+            _ => true
+        };
+    }
+
+    // `::<` of an instantiation or a call indirect, `<` of type parameters, or `<` of an offset
+    fn opens_generic(&self) -> bool {
+        return self.prev == Some(&token::Token::Symbol(token::Symbol::DoubleColon)) || self.is_function_name || self.is_offset_index;
+    }
+
+    // tracks the index after an `@` or `*` prefix, before `is_prefix` and `prev` move on
+    fn update_offset(&mut self, token: &token::Token) {
+        let is_after_prefix = self.is_prefix && matches!(self.prev, Some(token::Token::Symbol(token::Symbol::At | token::Symbol::Asterisk)));
+
+        self.is_offset_index = match token {
+            token::Token::Literal(_) | token::Token::Identifier(_) => is_after_prefix,
+            token::Token::Symbol(token::Symbol::LeftParenthese) => {
+                if is_after_prefix {
+                    self.offset_groups.push(self.paren_depth);
+                }
+
+                false
+            },
+            token::Token::Symbol(token::Symbol::RightParenthese) if self.offset_groups.last() == Some(&(self.paren_depth + 1)) => {
+                self.offset_groups.pop();
+
+                true
+            },
+            _ => false
+        };
+    }
+
+    // whether a space goes between the previous token and the given one
+    fn is_spaced(&self, token: &token::Token) -> bool {
+        let prev = match self.prev {
+            Some(x) => x,
+            None => return false
+        };
+
+        if let token::Token::Symbol(x) = token {
+            match x {
+                token::Symbol::Comma
+                | token::Symbol::SemiColon
+                | token::Symbol::Colon
+                | token::Symbol::Dot
+                | token::Symbol::DoubleColon
+                | token::Symbol::Increment
+                | token::Symbol::Decrement
+                | token::Symbol::RightParenthese => return false,
+                token::Symbol::LessThan if self.opens_generic() => return false,
+                token::Symbol::GreaterThan if self.generic_depth > 0 => return false,
+                token::Symbol::LeftParenthese => return !matches!(prev,
+                    token::Token::Identifier(_)
+                    | token::Token::Literal(_)
+                    | token::Token::Keyword(token::Keyword::Function | token::Keyword::StaticAssert)
+                    | token::Token::Symbol(token::Symbol::RightParenthese)
+                ) && !self.is_generic_closed && !self.is_prefix,
+                _ => {}
+            }
+        }
+
+        return match prev {
+            token::Token::Symbol(x) => match x {
+                token::Symbol::LeftParenthese
+                | token::Symbol::Dot
+                | token::Symbol::Ellipsis
+                | token::Symbol::DoubleColon => false,
+                token::Symbol::LessThan => self.generic_depth == 0,
+                _ => !self.is_prefix
+            },
+            _ => true
+        };
+    }
+}
+
+fn is_operator(token: &token::Token) -> bool {
+    return matches!(token, token::Token::Symbol(
+        token::Symbol::Minus
+        | token::Symbol::Plus
+        | token::Symbol::Asterisk
+        | token::Symbol::LogicalNegation
+        | token::Symbol::BitwiseNot
+        | token::Symbol::At
+    ));
+}
+
+// an operator is a prefix one when it does not follow an operand
+fn is_operand(token: Option<&token::Token>) -> bool {
+    return matches!(token, Some(
+        token::Token::Identifier(_)
+        | token::Token::Literal(_)
+        | token::Token::Type(_)
+        | token::Token::Symbol(token::Symbol::RightParenthese)
+    ));
+}
+
+// main program section
+pub fn format(text: &str) -> Result<String, Box<dyn Error>> {
+    let tokens = tokenizer::tokenize(text)?;
+    let mut formatter = Formatter::new();
+    let mut prev_end = 0;
+
+    for token in tokens.iter() {
+        let newlines = text[prev_end..token.span.start].matches('\n').count();
+        let slice = &text[token.span.clone()];
+
+        match &token.node {
+            token::Token::Comment(_) => {
+                let is_same_line = formatter.prev.is_some() && newlines == 0;
+
+                formatter.comment(slice.trim_end(), is_same_line, newlines);
+            },
+            _ => formatter.token(token, slice, newlines)
+        }
+
+        prev_end = token.span.end;
+    }
+
+    formatter.flush();
+
+    let mut out = formatter.lines.join("\n");
+    out.push('\n');
+
+    return Ok(out);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_statements() {
+        assert_eq!(format("fn f(a:i32)->i32{static_assert (1<2,\"ok\");let b<-a+1;ret (-b);}").unwrap(), [
+            "fn f(a: i32) -> i32 {",
+            "    static_assert(1 < 2, \"ok\");",
+            "    let b <- a + 1;",
+            "    ret (-b);",
+            "}",
+            ""
+        ].join("\n"));
+    }
+
+    #[test]
+    fn keep_leading_comment() {
+        assert_eq!(format("fn f() {\n  // first\n  let a <- 1;\n\n    /* second */\n let b <- 2; }").unwrap(), [
+            "fn f() {",
+            "    // first",
+            "    let a <- 1;",
+            "",
+            "    /* second */",
+            "    let b <- 2;",
+            "}",
+            ""
+        ].join("\n"));
+    }
+
+    #[test]
+    fn keep_trailing_comment() {
+        assert_eq!(format("fn f() { let a <- 1; // one\nlet b <- 2;   // two\n}").unwrap(), [
+            "fn f() {",
+            "    let a <- 1; // one",
+            "    let b <- 2; // two",
+            "}",
+            ""
+        ].join("\n"));
+    }
+
+    #[test]
+    fn keep_inline_comment() {
+        assert_eq!(format("fn f() { let a <- 1 + /* two */ 2; let b <- a + // three\n3; }").unwrap(), [
+            "fn f() {",
+            "    let a <- 1 + /* two */ 2;",
+            "    let b <- a + // three",
+            "        3;",
+            "}",
+            ""
+        ].join("\n"));
+    }
+
+    #[test]
+    fn format_generic_function() {
+        assert_eq!(format("fn max < T > (a:T,b:T)->T where T:i32|i64{a} fn f()->i64{max :: < i64 > (1,2)}").unwrap(), [
+            "fn max<T>(a: T, b: T) -> T where T: i32 | i64 {",
+            "    a",
+            "}",
+            "fn f() -> i64 {",
+            "    max::<i64>(1, 2)",
+            "}",
+            ""
+        ].join("\n"));
+    }
+
+    #[test]
+    fn format_attributes() {
+        assert_eq!(format("fn f(){} @export ( \"g\" ) @ inline fn g(){ @0<i32>(m); }").unwrap(), [
+            "fn f() {}",
+            "@export(\"g\")",
+            "@inline",
+            "fn g() {",
+            "    @0<i32>(m);",
+            "}",
+            ""
+        ].join("\n"));
+    }
+
+    #[test]
+    fn format_offsets() {
+        let formatted = format("fn f(a:i32){ @a < i64 > (m; 8) <- 1; *(a + 4)<f32>(m) <- 2; a <- @0<i32>(m) < a * 2; }").unwrap();
+
+        assert_eq!(formatted, [
+            "fn f(a: i32) {",
+            "    @a<i64>(m; 8) <- 1;",
+            "    *(a + 4)<f32>(m) <- 2;",
+            "    a <- @0<i32>(m) < a * 2;",
+            "}",
+            ""
+        ].join("\n"));
+        assert_eq!(format(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn format_is_stable() {
+        let once = format(&std::fs::read_to_string("tests/samples/simple.cwal").unwrap()).unwrap();
+
+        assert_eq!(format(&once).unwrap(), once);
+    }
+}
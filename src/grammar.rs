@@ -1,13 +1,42 @@
 use std::collections::VecDeque;
 use std::error::Error;
+use std::fmt;
 
+use crate::parser;
 use crate::token;
 use crate::token_grammar;
+use crate::parse_tree;
 
 pub trait Grammar {
     fn process(&mut self, token: &token::Token) -> Result;
+    fn finish(&mut self) -> Result;
     fn is_done(&self) -> bool;
     fn info(&self) -> String;
+    // the name the grammar is traced and labelled by
+    fn name(&self) -> &'static str;
+    fn attach(&mut self, node: parse_tree::Node);
+    fn into_node(self: Box<Self>) -> parse_tree::Node;
+    // a copy of the grammar in its current state
+    fn clone_box(&self) -> Box<dyn Grammar>;
+    // the kinds of token accepted next, and whether the grammar may also end here
+    fn expected(&self) -> (Vec<TokenKind>, bool);
+}
+
+// a token grammar matches a kind of token rather than a single token
+pub type TokenKind = token_grammar::TokenGrammar;
+
+// the first set of the grammar in its current state, for completion in an
+// editor. the parser only asks for it through `expected`
+#[allow(dead_code)]
+pub fn expected_tokens(grammar: &dyn Grammar) -> Vec<TokenKind> {
+    return grammar.expected().0;
+}
+
+// lets a parser be cloned for a trial parse
+impl Clone for Box<dyn Grammar> {
+    fn clone(&self) -> Self {
+        return self.clone_box();
+    }
 }
 
 pub enum Result {
@@ -22,17 +51,19 @@ pub enum GrammarQuantifier<'a> {
     OptionalMany(&'a [fn() -> Box<dyn Grammar>])
 }
 
+#[derive(Debug)]
 pub enum GrammarError {
-    TypeExpected,
-    SymbolExpected,
-    IdentifierExpected,
-    KeywordExpected,
-    ExpressionExpected,
+    // a keyword found where a name is required
+    ReservedKeyword(token::Keyword)
 }
 
+#[derive(Clone)]
 pub struct GrammarPattern<'a> {
     pattern: &'a [GrammarQuantifier<'a>],
+    children: Vec<parse_tree::Node>,
     is_done: bool,
+    // an optional name was skipped for the token being processed
+    is_name_skipped: bool,
     state: u8
 }
 
@@ -40,7 +71,9 @@ impl<'a> GrammarPattern<'a> {
     pub const fn new(pattern: &'a [GrammarQuantifier]) -> Self {
         return Self {
             pattern,
+            children: vec![],
             is_done: false,
+            is_name_skipped: false,
             state: 0
         };
     }
@@ -50,59 +83,91 @@ impl<'a> GrammarPattern<'a> {
             return Result::Passed;
         }
 
-        match self.current() {
+        match self.pattern.get(usize::from(self.state)).expect("Something went wrong") {
             GrammarQuantifier::One(prototypes) => {
-                for proto in prototypes.iter() {
-                    let mut dupl = proto();
-
-                    if let Result::Consumed(mut list) = dupl.process(token) {
-                        if !dupl.is_done() {
-                            list.push_front(dupl);
-                        }
-                        
-                        self.next();
-                        
-                        return Result::Consumed(list);
+                if let Some(list) = self.try_prototypes(prototypes, token) {
+                    self.next();
+
+                    return Result::Consumed(list);
+                }
+
+                if let token::Token::Keyword(x) = token {
+                    if self.is_name_skipped || accepts_identifier(prototypes) {
+                        return Result::Unexpected(Box::new(GrammarError::ReservedKeyword(x.clone())));
                     }
                 }
 
-                return Result::Unexpected("Err!".into());
+                return Result::Unexpected("no pattern matches".into());
             },
             GrammarQuantifier::OptionalOne(prototypes) => {
-                for proto in prototypes.iter() {
-                    let mut dupl = proto();
-
-                    if let Result::Consumed(mut list) = dupl.process(token) {
-                        if !dupl.is_done() {
-                            list.push_front(dupl);
-                        }
-                            
-                        self.next();
-                            
-                        return Result::Consumed(list);
-                    }
+                if let Some(list) = self.try_prototypes(prototypes, token) {
+                    self.next();
+
+                    return Result::Consumed(list);
                 }
 
+                self.skip(prototypes, token);
+
                 return self.execute_next(token);
             },
             GrammarQuantifier::OptionalMany(prototypes) => {
-                for proto in prototypes.iter() {
-                    let mut dupl = proto();
-
-                    if let Result::Consumed(mut list) = dupl.process(token) {
-                        if !dupl.is_done() {
-                            list.push_front(dupl);
-                        }
-                            
-                        return Result::Consumed(list);
-                    }
+                if let Some(list) = self.try_prototypes(prototypes, token) {
+                    return Result::Consumed(list);
                 }
 
+                self.skip(prototypes, token);
+
                 return self.execute_next(token);
             }
         };
     }
 
+    // skip the remaining optional quantifiers when the input has ended
+    pub fn finish(&mut self) -> Result {
+        while !self.is_done {
+            if let GrammarQuantifier::One(_) = self.current() {
+                return Result::Unexpected("unexpected end of input".into());
+            }
+
+            self.next();
+        }
+
+        return Result::Passed;
+    }
+
+    pub fn attach(&mut self, node: parse_tree::Node) {
+        self.children.push(node);
+    }
+
+    fn try_prototypes(&mut self, prototypes: &[fn() -> Box<dyn Grammar>], token: &token::Token) -> Option<VecDeque<Box<dyn Grammar>>> {
+        for proto in prototypes.iter() {
+            let mut dupl = proto();
+
+            if let Result::Consumed(mut list) = dupl.process(token) {
+                // finished grammars are attached right away, unfinished ones
+                // are handed to the parser and attached once they are popped
+                if dupl.is_done() && list.is_empty() {
+                    self.children.push(dupl.into_node());
+                }
+                else {
+                    list.push_front(dupl);
+                }
+
+                self.is_name_skipped = false;
+
+                return Some(list);
+            }
+        }
+
+        return None;
+    }
+
+    fn skip(&mut self, prototypes: &[fn() -> Box<dyn Grammar>], token: &token::Token) {
+        if let token::Token::Keyword(_) = token {
+            self.is_name_skipped = self.is_name_skipped || accepts_identifier(prototypes);
+        }
+    }
+
     fn execute_next(&mut self, token: &token::Token) -> Result {
         self.next();
         return self.execute(token);
@@ -116,11 +181,192 @@ impl<'a> GrammarPattern<'a> {
         }
     }
 
-    pub fn current(&self) -> &GrammarQuantifier {
+    // the alternatives of each quantifier are instantiated to look at their
+    // first matchers, going on past the quantifiers that can be skipped
+    pub fn expected(&self) -> (Vec<TokenKind>, bool) {
+        let mut kinds = vec![];
+
+        for quantifier in self.pattern.iter().skip(usize::from(self.state)) {
+            let (prototypes, mut is_optional) = match quantifier {
+                GrammarQuantifier::One(x) => (x, false),
+                GrammarQuantifier::OptionalOne(x) | GrammarQuantifier::OptionalMany(x) => (x, true)
+            };
+
+            for proto in prototypes.iter() {
+                let (first, is_empty) = proto().expected();
+
+                extend_unique(&mut kinds, first);
+                is_optional = is_optional || is_empty;
+            }
+
+            if !is_optional {
+                return (kinds, false);
+            }
+        }
+
+        return (kinds, true);
+    }
+
+    pub fn current(&self) -> &GrammarQuantifier<'_> {
         return self.pattern.get(usize::from(self.state)).expect("Something went wrong");
     }
 }
 
+pub fn extend_unique(kinds: &mut Vec<TokenKind>, more: Vec<TokenKind>) {
+    for x in more {
+        if !kinds.contains(&x) {
+            kinds.push(x);
+        }
+    }
+}
+
+// whether any of the prototypes would start with a name
+fn accepts_identifier(prototypes: &[fn() -> Box<dyn Grammar>]) -> bool {
+    let name = token::Token::Identifier(token::Identifier::new("_"));
+
+    return prototypes.iter().any(|x| return matches!(x().process(&name), Result::Consumed(_)));
+}
+
+impl fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            GrammarError::ReservedKeyword(x) => write!(f, "`{}` is a reserved keyword and cannot be used as a name", x.name())
+        };
+    }
+}
+
+impl Error for GrammarError {}
+
+// runs every alternative side by side and keeps the one matching the most
+// tokens, instead of committing to the first alternative that accepts a token.
+// the parser cannot give tokens back, so a candidate that is outlived by
+// another one is dropped, and the longest candidates are the last to finish.
+// no grammar of the language is ambiguous enough to need it yet
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct LongestMatch {
+    candidates: Vec<parser::Parser>,
+    winner: Option<parse_tree::Tree>,
+    is_done: bool
+}
+
+#[allow(dead_code)]
+impl LongestMatch {
+    pub fn new(prototypes: &[fn() -> Box<dyn Grammar>]) -> Self {
+        return Self {
+            candidates: prototypes.iter().map(|x| return parser::Parser::with_root(x(), false)).collect(),
+            winner: None,
+            is_done: false
+        };
+    }
+
+    fn choose(&mut self, mut finished: Vec<parse_tree::Tree>, error: Option<Box<dyn Error>>) -> Result {
+        if finished.len() > 1 {
+            let names = finished.iter().map(|x| return x.name()).collect::<Vec<_>>();
+
+            return Result::Unexpected(format!("ambiguous input: matches {}", names.join(", ")).into());
+        }
+
+        return match finished.pop() {
+            Some(tree) => {
+                self.winner = Some(tree);
+                self.is_done = true;
+
+                Result::Passed
+            },
+            None => Result::Unexpected(error.unwrap_or_else(|| return "no alternative matches".into()))
+        };
+    }
+}
+
+impl Grammar for LongestMatch {
+    fn process(&mut self, token: &token::Token) -> Result {
+        if self.is_done {
+            return Result::Passed;
+        }
+
+        let mut consumed = vec![];
+        let mut finished = vec![];
+        let mut error = None;
+
+        for mut candidate in self.candidates.drain(..) {
+            match candidate.feed(token) {
+                Ok(true) => consumed.push(candidate),
+                Ok(false) => match candidate.finish() {
+                    Ok(tree) => finished.push(tree),
+                    Err(err) => error = Some(err)
+                },
+                Err(err) => error = Some(err)
+            }
+        }
+
+        if !consumed.is_empty() {
+            self.candidates = consumed;
+
+            return Result::Consumed(VecDeque::new());
+        }
+
+        // nothing takes the token, so the candidates stopping here are the longest
+        return self.choose(finished, error);
+    }
+
+    fn finish(&mut self) -> Result {
+        if self.is_done {
+            return Result::Passed;
+        }
+
+        let mut finished = vec![];
+        let mut error = None;
+
+        for candidate in self.candidates.drain(..) {
+            match candidate.finish() {
+                Ok(tree) => finished.push(tree),
+                Err(err) => error = Some(err)
+            }
+        }
+
+        return self.choose(finished, error);
+    }
+
+    fn is_done(&self) -> bool {
+        return self.is_done;
+    }
+
+    fn info(&self) -> String {
+        return format!("LongestMatch:[{}]", self.candidates.len());
+    }
+
+    fn name(&self) -> &'static str {
+        return "LongestMatch";
+    }
+
+    fn attach(&mut self, _: parse_tree::Node) {
+        unreachable!("candidates are parsed on their own stacks");
+    }
+
+    fn into_node(self: Box<Self>) -> parse_tree::Node {
+        return parse_tree::Node::Tree(self.winner.expect("unexpected unfinished longest match"));
+    }
+
+    fn clone_box(&self) -> Box<dyn Grammar> {
+        return Box::new(self.clone());
+    }
+
+    fn expected(&self) -> (Vec<TokenKind>, bool) {
+        let mut kinds = vec![];
+        let mut is_empty = false;
+
+        for candidate in self.candidates.iter() {
+            let (first, can_finish) = candidate.expected();
+
+            extend_unique(&mut kinds, first);
+            is_empty = is_empty || can_finish;
+        }
+
+        return (kinds, is_empty);
+    }
+}
+
 // construction rules
 // 1. the first step cannot be self, it will cause infinite recusive calls.
 // 2. first grammar of each return argument must not collide with sibling members.
@@ -136,6 +382,8 @@ impl Program {
         return Self {
             pattern: GrammarPattern::new(&[
                 GrammarQuantifier::OptionalMany(&[
+                    || return Box::new(AttributedDeclaration::new()),
+                    || return Box::new(PublicDeclaration::new()),
                     || return Box::new(FunctionDeclaration::new()),
                     || return Box::new(TypeDeclaration::new()),
                     || return Box::new(TableDeclaration::new()),
@@ -143,6 +391,8 @@ impl Program {
                     || return Box::new(VariableDeclaration::new()),
                     || return Box::new(ImportDeclaration::new()),
                     || return Box::new(ExportDeclaration::new()),
+                    || return Box::new(IncludeDeclaration::new()),
+                    || return Box::new(StaticAssertion::new()),
                     || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::SemiColon))
                 ])
             ])
@@ -254,7 +504,8 @@ impl ConTupleType {
         return Self {
             pattern: GrammarPattern::new(&[
                 GrammarQuantifier::One(&[
-                    || return Box::new(token_grammar::TokenGrammar::any_type())
+                    || return Box::new(token_grammar::TokenGrammar::any_type()),
+                    || return Box::new(token_grammar::TokenGrammar::any_identifier())
                 ]),
                 GrammarQuantifier::OptionalOne(&[
                     || return Box::new(TupleTypeRecursiveSequence::new()),
@@ -295,19 +546,14 @@ impl TupleTypeSequence {
                     || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::Comma))
                 ]),
                 GrammarQuantifier::One(&[
-                    || return Box::new(token_grammar::TokenGrammar::any_type())
+                    || return Box::new(token_grammar::TokenGrammar::any_type()),
+                    || return Box::new(token_grammar::TokenGrammar::any_identifier())
                 ])
             ])
         };
     }
 }
 
-// global declaration
-#[derive(c_webassembly::Grammar)]
-pub struct GlobalDeclaration {
-    pattern: GrammarPattern<'static>
-}
-
 #[derive(c_webassembly::Grammar)]
 pub struct ImportedVariableDeclaration {
     pattern: GrammarPattern<'static>
@@ -461,7 +707,7 @@ impl ImportedMemoryDeclaration {
     }
 }
 
-// import declaration
+// import declaration, a string after `imp` imports a prebuilt wasm module
 #[derive(c_webassembly::Grammar)]
 pub struct ImportDeclaration {
     pattern: GrammarPattern<'static>
@@ -474,6 +720,25 @@ impl ImportDeclaration {
                 GrammarQuantifier::One(&[
                     || return Box::new(token_grammar::TokenGrammar::from_keyword(token::Keyword::Import))
                 ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(ImportedItemDeclaration::new()),
+                    || return Box::new(ImportedModuleDeclaration::new())
+                ])
+            ])
+        };
+    }
+}
+
+// imported item
+#[derive(c_webassembly::Grammar)]
+pub struct ImportedItemDeclaration {
+    pattern: GrammarPattern<'static>
+}
+
+impl ImportedItemDeclaration {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
                 GrammarQuantifier::One(&[
                     || return Box::new(ImportedFunctionDeclaration::new()),
                     || return Box::new(ImportedTableDeclaration::new()),
@@ -494,6 +759,179 @@ impl ImportDeclaration {
     }
 }
 
+// imported wasm module
+#[derive(c_webassembly::Grammar)]
+pub struct ImportedModuleDeclaration {
+    pattern: GrammarPattern<'static>
+}
+
+impl ImportedModuleDeclaration {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::any_string_literal())
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::SemiColon))
+                ])
+            ])
+        };
+    }
+}
+
+// public declaration, `pub` decides between a function and an include
+#[derive(c_webassembly::Grammar)]
+pub struct PublicDeclaration {
+    pattern: GrammarPattern<'static>
+}
+
+impl PublicDeclaration {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_keyword(token::Keyword::Public))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(FunctionDeclaration::new()),
+                    || return Box::new(IncludeDeclaration::new())
+                ])
+            ])
+        };
+    }
+}
+
+// `@inline` or `@export("name")` before a function
+#[derive(c_webassembly::Grammar)]
+pub struct AttributedDeclaration {
+    pattern: GrammarPattern<'static>
+}
+
+impl AttributedDeclaration {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(Attribute::new())
+                ]),
+                GrammarQuantifier::OptionalMany(&[
+                    || return Box::new(Attribute::new())
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(FunctionDeclaration::new())
+                ])
+            ])
+        };
+    }
+}
+
+#[derive(c_webassembly::Grammar)]
+pub struct Attribute {
+    pattern: GrammarPattern<'static>
+}
+
+impl Attribute {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::At))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::any_identifier())
+                ]),
+                GrammarQuantifier::OptionalOne(&[
+                    || return Box::new(AttributeArgument::new())
+                ])
+            ])
+        };
+    }
+}
+
+#[derive(c_webassembly::Grammar)]
+pub struct AttributeArgument {
+    pattern: GrammarPattern<'static>
+}
+
+impl AttributeArgument {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::LeftParenthese))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::any_string_literal())
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::RightParenthese))
+                ])
+            ])
+        };
+    }
+}
+
+// include declaration
+#[derive(c_webassembly::Grammar)]
+pub struct IncludeDeclaration {
+    pattern: GrammarPattern<'static>
+}
+
+impl IncludeDeclaration {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_keyword(token::Keyword::Include))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::any_string_literal())
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::SemiColon))
+                ])
+            ])
+        };
+    }
+}
+
+// static assertion, both a declaration and a statement
+#[derive(c_webassembly::Grammar)]
+pub struct StaticAssertion {
+    pattern: GrammarPattern<'static>
+}
+
+impl StaticAssertion {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_keyword(token::Keyword::StaticAssert))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::LeftParenthese))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(Expression::new())
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::Comma))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::any_string_literal())
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::RightParenthese))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::SemiColon))
+                ])
+            ])
+        };
+    }
+}
+
 // export declaration
 #[derive(c_webassembly::Grammar)]
 pub struct ExportDeclaration {
@@ -527,7 +965,160 @@ pub struct AliasedExportDeclaration {
     pattern: GrammarPattern<'static>
 }
 
-impl AliasedExportDeclaration {
+impl AliasedExportDeclaration {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::any_identifier())
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_keyword(token::Keyword::As))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::any_string_literal()),
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::SemiColon))
+                ])
+            ])
+        };
+    }
+}
+
+// function declaration and its components
+#[derive(c_webassembly::Grammar)]
+pub struct FunctionDeclaration {
+    pattern: GrammarPattern<'static>
+}
+
+impl FunctionDeclaration {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::OptionalOne(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_keyword(token::Keyword::Public))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_keyword(token::Keyword::Function))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::any_identifier())
+                ]),
+                GrammarQuantifier::OptionalOne(&[
+                    || return Box::new(GenericParameter::new())
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(Signature::new())
+                ]),
+                GrammarQuantifier::OptionalOne(&[
+                    || return Box::new(WhereClause::new())
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(FunctionBlock::new())
+                ])
+            ])
+        };
+    }
+}
+
+// `<T, U>` after the name of a generic function
+#[derive(c_webassembly::Grammar)]
+pub struct GenericParameter {
+    pattern: GrammarPattern<'static>
+}
+
+impl GenericParameter {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::LessThan))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::any_identifier())
+                ]),
+                GrammarQuantifier::OptionalMany(&[
+                    || return Box::new(ConGenericParameter::new())
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::GreaterThan))
+                ])
+            ])
+        };
+    }
+}
+
+#[derive(c_webassembly::Grammar)]
+pub struct ConGenericParameter {
+    pattern: GrammarPattern<'static>
+}
+
+impl ConGenericParameter {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::Comma))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::any_identifier())
+                ])
+            ])
+        };
+    }
+}
+
+// `where T: i32 | i64, U: f64` limits the types a parameter is instantiated with
+#[derive(c_webassembly::Grammar)]
+pub struct WhereClause {
+    pattern: GrammarPattern<'static>
+}
+
+impl WhereClause {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_keyword(token::Keyword::Where))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(TypeConstraint::new())
+                ]),
+                GrammarQuantifier::OptionalMany(&[
+                    || return Box::new(ConTypeConstraint::new())
+                ])
+            ])
+        };
+    }
+}
+
+#[derive(c_webassembly::Grammar)]
+pub struct ConTypeConstraint {
+    pattern: GrammarPattern<'static>
+}
+
+impl ConTypeConstraint {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::Comma))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(TypeConstraint::new())
+                ])
+            ])
+        };
+    }
+}
+
+#[derive(c_webassembly::Grammar)]
+pub struct TypeConstraint {
+    pattern: GrammarPattern<'static>
+}
+
+impl TypeConstraint {
     pub fn new() -> Self {
         return Self {
             pattern: GrammarPattern::new(&[
@@ -535,40 +1126,33 @@ impl AliasedExportDeclaration {
                     || return Box::new(token_grammar::TokenGrammar::any_identifier())
                 ]),
                 GrammarQuantifier::One(&[
-                    || return Box::new(token_grammar::TokenGrammar::from_keyword(token::Keyword::As))
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::Colon))
                 ]),
                 GrammarQuantifier::One(&[
-                    || return Box::new(token_grammar::TokenGrammar::any_string_literal()),
+                    || return Box::new(token_grammar::TokenGrammar::any_type())
                 ]),
-                GrammarQuantifier::One(&[
-                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::SemiColon))
+                GrammarQuantifier::OptionalMany(&[
+                    || return Box::new(ConConstraintType::new())
                 ])
             ])
         };
     }
 }
 
-// function declaration and its components
 #[derive(c_webassembly::Grammar)]
-pub struct FunctionDeclaration {
+pub struct ConConstraintType {
     pattern: GrammarPattern<'static>
 }
 
-impl FunctionDeclaration {
+impl ConConstraintType {
     pub fn new() -> Self {
         return Self {
             pattern: GrammarPattern::new(&[
                 GrammarQuantifier::One(&[
-                    || return Box::new(token_grammar::TokenGrammar::from_keyword(token::Keyword::Function))
-                ]),
-                GrammarQuantifier::One(&[
-                    || return Box::new(token_grammar::TokenGrammar::any_identifier())
-                ]),
-                GrammarQuantifier::One(&[
-                    || return Box::new(Signature::new())
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::BitwiseOr))
                 ]),
                 GrammarQuantifier::One(&[
-                    || return Box::new(FunctionBlock::new())
+                    || return Box::new(token_grammar::TokenGrammar::any_type())
                 ])
             ])
         };
@@ -761,6 +1345,9 @@ impl ParamType {
     pub fn new() -> Self {
         return Self {
             pattern: GrammarPattern::new(&[
+                GrammarQuantifier::OptionalOne(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::Ellipsis))
+                ]),
                 GrammarQuantifier::One(&[
                     || return Box::new(token_grammar::TokenGrammar::any_identifier())
                 ]),
@@ -769,6 +1356,30 @@ impl ParamType {
                 ]),
                 GrammarQuantifier::One(&[
                     || return Box::new(TypeExpression::new())
+                ]),
+                GrammarQuantifier::OptionalOne(&[
+                    || return Box::new(ConDefaultAssignment::new())
+                ])
+            ])
+        };
+    }
+}
+
+// -> default parameter value
+#[derive(c_webassembly::Grammar)]
+pub struct ConDefaultAssignment {
+    pattern: GrammarPattern<'static>
+}
+
+impl ConDefaultAssignment {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::Assignment))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(Expression::new())
                 ])
             ])
         };
@@ -834,9 +1445,13 @@ impl FunctionBlock {
                     || return Box::new(ExpressionStatement::new()),
                     || return Box::new(IfStatement::new()),
                     || return Box::new(WhileStatement::new()),
+                    || return Box::new(RepeatStatement::new()),
+                    || return Box::new(DeferStatement::new()),
                     || return Box::new(ReturnStatement::new()),
                     || return Box::new(BreakStatement::new()),
                     || return Box::new(ContinueStatement::new()),
+                    || return Box::new(StaticAssertion::new()),
+                    || return Box::new(FunctionDeclaration::new()),
                     || return Box::new(FunctionBlock::new()),
                     || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::SemiColon))
                 ]),
@@ -865,7 +1480,11 @@ impl VariableDeclaration {
                     || return Box::new(MutableIdDeclaration::new()),
                     || return Box::new(MultiIdDeclaration::new())
                 ]),
-                GrammarQuantifier::One(&[
+                // a missing initializer is reported when building the ast
+                GrammarQuantifier::OptionalOne(&[
+                    || return Box::new(ConVariableType::new())
+                ]),
+                GrammarQuantifier::OptionalOne(&[
                     || return Box::new(ConAssignmentExpression::new())
                 ]),
                 GrammarQuantifier::One(&[
@@ -876,6 +1495,27 @@ impl VariableDeclaration {
     }
 }
 
+// -> declared type of a variable
+#[derive(c_webassembly::Grammar)]
+pub struct ConVariableType {
+    pattern: GrammarPattern<'static>
+}
+
+impl ConVariableType {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::Colon))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(TypeExpression::new())
+                ])
+            ])
+        };
+    }
+}
+
 #[derive(c_webassembly::Grammar)]
 pub struct MutableIdDeclaration {
     pattern: GrammarPattern<'static>
@@ -1039,6 +1679,51 @@ impl WhileStatement {
     }
 }
 
+// -> repeat, the count is a literal so the number of iterations is known
+#[derive(c_webassembly::Grammar)]
+pub struct RepeatStatement {
+    pattern: GrammarPattern<'static>
+}
+
+impl RepeatStatement {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_keyword(token::Keyword::Repeat))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::any_numeric_literal())
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(FunctionBlock::new())
+                ])
+            ])
+        };
+    }
+}
+
+// -> defer, the block runs on every exit of the function
+#[derive(c_webassembly::Grammar)]
+pub struct DeferStatement {
+    pattern: GrammarPattern<'static>
+}
+
+impl DeferStatement {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_keyword(token::Keyword::Defer))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(FunctionBlock::new())
+                ])
+            ])
+        };
+    }
+}
+
 #[derive(c_webassembly::Grammar)]
 pub struct BreakStatement {
     pattern: GrammarPattern<'static>
@@ -1117,9 +1802,11 @@ impl ExpressionStatement {
                     || return Box::new(Expression::new())
                 ]),
                 GrammarQuantifier::OptionalOne(&[
-                    || return Box::new(ConAssignmentExpression::new())
+                    || return Box::new(ConAssignmentExpression::new()),
+                    || return Box::new(ConIncrementExpression::new())
                 ]),
-                GrammarQuantifier::One(&[
+                // omitted only by the tail expression of a block
+                GrammarQuantifier::OptionalOne(&[
                     || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::SemiColon))
                 ])
             ])
@@ -1148,23 +1835,46 @@ impl ConAssignmentExpression {
     }
 }
 
+// -> postfix increment/decrement
+#[derive(c_webassembly::Grammar)]
+pub struct ConIncrementExpression {
+    pattern: GrammarPattern<'static>
+}
+
+impl ConIncrementExpression {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbols(&[token::Symbol::Increment, token::Symbol::Decrement]))
+                ])
+            ])
+        };
+    }
+}
+
 #[derive(c_webassembly::Grammar)]
 pub struct Expression {
     pattern: GrammarPattern<'static>
 }
 
+// the alternatives of a single operand, which an operator chain repeats
+const OPERANDS: &[fn() -> Box<dyn Grammar>] = &[
+    || return Box::new(token_grammar::TokenGrammar::any_numeric_literal()),
+    || return Box::new(token_grammar::TokenGrammar::any_string_literal()),
+    || return Box::new(WithIdExpression::new()),
+    || return Box::new(TypeOfExpression::new()),
+    || return Box::new(CastExpression::new()),
+    || return Box::new(OffsetExpression::new()),
+    || return Box::new(GroupedOrTupleExpression::new()),
+    || return Box::new(UnaryExpression::new())
+];
+
 impl Expression {
     pub fn new() -> Self {
         return Self {
             pattern: GrammarPattern::new(&[
-                GrammarQuantifier::One(&[
-                    || return Box::new(token_grammar::TokenGrammar::any_numeric_literal()),
-                    || return Box::new(WithIdExpression::new()),
-                    || return Box::new(TypeOfExpression::new()),
-                    || return Box::new(OffsetExpression::new()),
-                    || return Box::new(GroupedOrTupleExpression::new()),
-                    || return Box::new(UnaryExpression::new())
-                ]),
+                GrammarQuantifier::One(OPERANDS),
                 GrammarQuantifier::OptionalMany(&[
                     || return Box::new(ConBinaryExpression::new())
                 ]),
@@ -1323,7 +2033,7 @@ impl UnaryExpression {
                     || return Box::new(token_grammar::TokenGrammar::any_unary_symbol())
                 ]),
                 GrammarQuantifier::One(&[
-                    || return Box::new(Expression::new())
+                    || return Box::new(OperandExpression::new())
                 ])
             ])
         };
@@ -1344,13 +2054,32 @@ impl ConBinaryExpression {
                     || return Box::new(token_grammar::TokenGrammar::any_binary_symbol())
                 ]),
                 GrammarQuantifier::One(&[
-                    || return Box::new(Expression::new())
+                    || return Box::new(OperandExpression::new())
                 ])
             ])
         };
     }
 }
 
+// -> operand
+// the operand of a unary or binary operator. it stops before the next
+// operator, so a chain is read by the `Expression` holding it rather than by
+// one nested `Expression` per operator
+#[derive(c_webassembly::Grammar)]
+pub struct OperandExpression {
+    pattern: GrammarPattern<'static>
+}
+
+impl OperandExpression {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(OPERANDS)
+            ])
+        };
+    }
+}
+
 // -> conditional (ternary)
 #[derive(c_webassembly::Grammar)]
 pub struct ConConditionalExpression {
@@ -1447,6 +2176,51 @@ impl TypeFunctionExpression {
     }
 }
 
+// -> cast
+// a type on its own is the argument of `size_of(i64)` and `align_of(i64)`
+#[derive(c_webassembly::Grammar)]
+pub struct CastExpression {
+    pattern: GrammarPattern<'static>
+}
+
+impl CastExpression {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::any_type())
+                ]),
+                GrammarQuantifier::OptionalOne(&[
+                    || return Box::new(CastOperand::new())
+                ])
+            ])
+        };
+    }
+}
+
+#[derive(c_webassembly::Grammar)]
+pub struct CastOperand {
+    pattern: GrammarPattern<'static>
+}
+
+impl CastOperand {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::LeftParenthese))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(Expression::new())
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::RightParenthese))
+                ])
+            ])
+        };
+    }
+}
+
 // -> typeof
 #[derive(c_webassembly::Grammar)]
 pub struct TypeOfExpression {
@@ -1469,6 +2243,7 @@ impl TypeOfExpression {
 }
 
 // -> offset
+// `@offset<ty>(base)`, `*` is the older spelling of `@`
 #[derive(c_webassembly::Grammar)]
 pub struct OffsetExpression {
     pattern: GrammarPattern<'static>
@@ -1479,11 +2254,12 @@ impl OffsetExpression {
         return Self {
             pattern: GrammarPattern::new(&[
                 GrammarQuantifier::One(&[
-                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::Asterisk))
+                    || return Box::new(token_grammar::TokenGrammar::from_symbols(&[token::Symbol::At, token::Symbol::Asterisk]))
                 ]),
                 GrammarQuantifier::One(&[
                     || return Box::new(token_grammar::TokenGrammar::any_numeric_literal()),
-                    || return Box::new(token_grammar::TokenGrammar::any_identifier())
+                    || return Box::new(token_grammar::TokenGrammar::any_identifier()),
+                    || return Box::new(GroupedOrTupleExpression::new())
                 ]),
                 GrammarQuantifier::OptionalOne(&[
                     || return Box::new(GenericArgument::new())
@@ -1494,6 +2270,9 @@ impl OffsetExpression {
                 GrammarQuantifier::One(&[
                     || return Box::new(token_grammar::TokenGrammar::any_identifier())
                 ]),
+                GrammarQuantifier::OptionalOne(&[
+                    || return Box::new(OffsetAlignment::new())
+                ]),
                 GrammarQuantifier::One(&[
                     || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::RightParenthese))
                 ]),
@@ -1505,7 +2284,29 @@ impl OffsetExpression {
     }
 }
 
+// `; 4` after the memory name, the alignment in bytes
+#[derive(c_webassembly::Grammar)]
+pub struct OffsetAlignment {
+    pattern: GrammarPattern<'static>
+}
+
+impl OffsetAlignment {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::SemiColon))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::any_numeric_literal())
+                ])
+            ])
+        };
+    }
+}
+
 // -> ganeric
+// several types only instantiate a generic function, as in `pair::<i32, f64>`
 #[derive(c_webassembly::Grammar)]
 pub struct GenericArgument {
     pattern: GrammarPattern<'static>
@@ -1521,6 +2322,9 @@ impl GenericArgument {
                 GrammarQuantifier::One(&[
                     || return Box::new(TypeExpression::new())
                 ]),
+                GrammarQuantifier::OptionalMany(&[
+                    || return Box::new(ConGenericArgument::new())
+                ]),
                 GrammarQuantifier::One(&[
                     || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::GreaterThan))
                 ])
@@ -1529,6 +2333,26 @@ impl GenericArgument {
     }
 }
 
+#[derive(c_webassembly::Grammar)]
+pub struct ConGenericArgument {
+    pattern: GrammarPattern<'static>
+}
+
+impl ConGenericArgument {
+    pub fn new() -> Self {
+        return Self {
+            pattern: GrammarPattern::new(&[
+                GrammarQuantifier::One(&[
+                    || return Box::new(token_grammar::TokenGrammar::from_symbol(token::Symbol::Comma))
+                ]),
+                GrammarQuantifier::One(&[
+                    || return Box::new(TypeExpression::new())
+                ])
+            ])
+        };
+    }
+}
+
 #[derive(c_webassembly::Grammar)]
 pub struct TypeExpression {
     pattern: GrammarPattern<'static>
@@ -1549,3 +2373,116 @@ impl TypeExpression {
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer;
+
+    #[derive(c_webassembly::Grammar)]
+    struct Name {
+        pattern: GrammarPattern<'static>
+    }
+
+    impl Name {
+        fn new() -> Self {
+            return Self {
+                pattern: GrammarPattern::new(&[
+                    GrammarQuantifier::One(&[
+                        || return Box::new(token_grammar::TokenGrammar::any_identifier())
+                    ])
+                ])
+            };
+        }
+    }
+
+    #[derive(c_webassembly::Grammar)]
+    struct OtherName {
+        pattern: GrammarPattern<'static>
+    }
+
+    impl OtherName {
+        fn new() -> Self {
+            return Self {
+                pattern: GrammarPattern::new(&[
+                    GrammarQuantifier::One(&[
+                        || return Box::new(token_grammar::TokenGrammar::any_identifier())
+                    ])
+                ])
+            };
+        }
+    }
+
+    #[derive(c_webassembly::Grammar)]
+    struct NameCall {
+        pattern: GrammarPattern<'static>
+    }
+
+    impl NameCall {
+        fn new() -> Self {
+            return Self {
+                pattern: GrammarPattern::new(&[
+                    GrammarQuantifier::One(&[
+                        || return Box::new(token_grammar::TokenGrammar::any_identifier())
+                    ]),
+                    GrammarQuantifier::One(&[
+                        || return Box::new(FuncCallArg::new())
+                    ])
+                ])
+            };
+        }
+    }
+
+    fn run(grammar: &mut LongestMatch, text: &str) -> Result {
+        for token in tokenizer::tokenize(text).unwrap().iter() {
+            match grammar.process(token) {
+                Result::Consumed(_) => continue,
+                result => return result
+            }
+        }
+
+        return grammar.finish();
+    }
+
+    #[test]
+    fn pick_longest_alternative() {
+        let mut grammar = LongestMatch::new(&[
+            || return Box::new(Name::new()),
+            || return Box::new(NameCall::new())
+        ]);
+
+        assert!(matches!(run(&mut grammar, "f(1);"), Result::Passed));
+
+        match Box::new(grammar).into_node() {
+            parse_tree::Node::Tree(tree) => assert_eq!(tree.name(), "NameCall"),
+            parse_tree::Node::Token(_) => panic!("unexpected token node")
+        }
+    }
+
+    #[test]
+    fn expect_first_tokens_of_alternatives() {
+        let grammar = LongestMatch::new(&[
+            || return Box::new(Name::new()),
+            || return Box::new(NameCall::new())
+        ]);
+
+        assert_eq!(expected_tokens(&grammar), vec![token_grammar::TokenGrammar::any_identifier()]);
+        assert_eq!(expected_tokens(&FunctionDeclaration::new()), vec![
+            token_grammar::TokenGrammar::from_keyword(token::Keyword::Public),
+            token_grammar::TokenGrammar::from_keyword(token::Keyword::Function)
+        ]);
+    }
+
+    #[test]
+    fn reject_tied_alternatives() {
+        let mut grammar = LongestMatch::new(&[
+            || return Box::new(Name::new()),
+            || return Box::new(OtherName::new())
+        ]);
+
+        match run(&mut grammar, "f;") {
+            Result::Unexpected(err) => assert!(err.to_string().contains("ambiguous")),
+            _ => panic!("expected an ambiguity error")
+        }
+    }
+}
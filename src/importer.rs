@@ -0,0 +1,291 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::path::Path;
+use wasmparser::{ExternalKind, FuncType, GlobalType, MemoryType, Parser, Payload, RefType, TableType, TypeRef, ValType};
+
+use crate::ast;
+use crate::io;
+use crate::resolver;
+use crate::token;
+use crate::visitor::Visitor;
+
+// main program section, replaces every `imp "path.wasm";` of a file with an
+// import of each export the file refers to, the module is named after the stem
+pub fn expand(program: &mut ast::Program, file: &str) -> Result<(), Box<dyn Error>> {
+    if !program.declarations.iter().any(|x| return matches!(x, ast::Declaration::ImportModule(_))) {
+        return Ok(());
+    }
+
+    let referenced = references(program);
+    let mut declarations = vec![];
+
+    for decl in program.declarations.drain(..) {
+        match decl {
+            ast::Declaration::ImportModule(x) => {
+                let buf = io::read_bytes(&io::relative_to(file, &x.path))?;
+
+                declarations.extend(imports(&x, &buf, &referenced)?.into_iter().map(ast::Declaration::Import));
+            },
+            x => declarations.push(x)
+        }
+    }
+
+    program.declarations = declarations;
+
+    return Ok(());
+}
+
+// names used anywhere in the program, unused exports are not imported
+fn references(program: &ast::Program) -> HashSet<String> {
+    let mut references = resolver::References::default();
+
+    references.visit_program(program);
+
+    let aliased = program.declarations.iter().filter_map(|x| {
+        return match x {
+            ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Aliased(name, _), .. }) => Some(name.clone()),
+            _ => None
+        };
+    });
+
+    return references.names.into_iter().chain(aliased).collect();
+}
+
+// the index spaces of a module, imports first as in the binary
+#[derive(Default)]
+struct Spaces {
+    types: Vec<FuncType>,
+    functions: Vec<u32>,
+    tables: Vec<TableType>,
+    memories: Vec<MemoryType>,
+    globals: Vec<GlobalType>,
+    exports: Vec<(String, ExternalKind, u32)>
+}
+
+impl Spaces {
+    fn read(buf: &[u8]) -> Result<Self, wasmparser::BinaryReaderError> {
+        let mut spaces = Self::default();
+
+        for payload in Parser::new(0).parse_all(buf) {
+            match payload? {
+                Payload::TypeSection(reader) => {
+                    for ty in reader.into_iter_err_on_gc_types() {
+                        spaces.types.push(ty?);
+                    }
+                },
+                Payload::ImportSection(reader) => {
+                    for import in reader {
+                        match import?.ty {
+                            TypeRef::Func(x) => spaces.functions.push(x),
+                            TypeRef::Table(x) => spaces.tables.push(x),
+                            TypeRef::Memory(x) => spaces.memories.push(x),
+                            TypeRef::Global(x) => spaces.globals.push(x),
+                            TypeRef::Tag(_) => {}
+                        }
+                    }
+                },
+                Payload::FunctionSection(reader) => {
+                    for x in reader {
+                        spaces.functions.push(x?);
+                    }
+                },
+                Payload::TableSection(reader) => {
+                    for x in reader {
+                        spaces.tables.push(x?.ty);
+                    }
+                },
+                Payload::MemorySection(reader) => {
+                    for x in reader {
+                        spaces.memories.push(x?);
+                    }
+                },
+                Payload::GlobalSection(reader) => {
+                    for x in reader {
+                        spaces.globals.push(x?.ty);
+                    }
+                },
+                Payload::ExportSection(reader) => {
+                    for x in reader {
+                        let export = x?;
+
+                        spaces.exports.push((export.name.to_string(), export.kind, export.index));
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        return Ok(spaces);
+    }
+
+    fn item(&self, name: &str, kind: &ExternalKind, index: u32) -> Result<ast::ImportItem, String> {
+        let missing = || return format!("{:?} index {} is out of bounds", kind, index);
+        let index = usize::try_from(index).map_err(|_| return missing())?;
+
+        return match kind {
+            ExternalKind::Func => {
+                let ty = self.functions.get(index).and_then(|x| return self.types.get(usize::try_from(*x).ok()?)).ok_or_else(missing)?;
+
+                Ok(ast::ImportItem::Function(name.to_string(), signature(ty)?))
+            },
+            ExternalKind::Table => {
+                let ty = self.tables.get(index).ok_or_else(missing)?;
+
+                Ok(ast::ImportItem::Table(name.to_string(), range(u64::from(ty.initial), ty.maximum.map(u64::from), ref_type(&ty.element_type)?)))
+            },
+            ExternalKind::Memory => {
+                let ty = self.memories.get(index).ok_or_else(missing)?;
+
+                if ty.memory64 || ty.shared {
+                    return Err("only unshared 32-bit memories are supported".to_string());
+                }
+
+                Ok(ast::ImportItem::Memory(name.to_string(), range(ty.initial, ty.maximum, token::Type::Page)))
+            },
+            ExternalKind::Global => {
+                let ty = self.globals.get(index).ok_or_else(missing)?;
+                let binding = ast::Binding { name: name.to_string(), is_mutable: ty.mutable };
+
+                Ok(ast::ImportItem::Variable(binding, ast::TypeExpression::Primitive(value_type(&ty.content_type)?)))
+            },
+            ExternalKind::Tag => Err("tags are not supported".to_string())
+        };
+    }
+}
+
+// an import declaration for each referenced export, in export order
+fn imports(decl: &ast::ImportModuleDeclaration, buf: &[u8], referenced: &HashSet<String>) -> Result<Vec<ast::ImportDeclaration>, Box<dyn Error>> {
+    let spaces = Spaces::read(buf).map_err(|x| return format!("cannot read wasm module `{}`: {} (at offset 0x{:x})", decl.path, x.message(), x.offset()))?;
+    let module = Path::new(&decl.path).file_stem().map_or_else(|| return decl.path.clone(), |x| return x.to_string_lossy().to_string());
+    let mut imports = vec![];
+
+    for (name, kind, index) in spaces.exports.iter() {
+        if !referenced.contains(name) {
+            continue;
+        }
+
+        let item = spaces.item(name, kind, *index).map_err(|x| return format!("cannot import `{}` from `{}`: {}", name, decl.path, x))?;
+
+        imports.push(ast::ImportDeclaration { span: decl.span.clone(), module: module.clone(), item });
+    }
+
+    return Ok(imports);
+}
+
+// parameters are unnamed in the binary, so they are numbered
+fn signature(ty: &FuncType) -> Result<ast::Signature, String> {
+    let params = ty.params().iter().enumerate().map(|(i, x)| {
+        return Ok(ast::Param { span: 0..0, name: format!("_{}", i), ty: ast::TypeExpression::Primitive(value_type(x)?), default: None, is_variadic: false });
+    }).collect::<Result<Vec<_>, String>>()?;
+
+    let results = ty.results().iter().map(|x| return Ok(ast::TypeExpression::Primitive(value_type(x)?))).collect::<Result<Vec<_>, String>>()?;
+
+    let result = match results.len() {
+        0 => None,
+        1 => results.into_iter().next(),
+        _ => Some(ast::TypeExpression::Tuple(results))
+    };
+
+    return Ok(ast::Signature { params, result });
+}
+
+fn range(min: u64, max: Option<u64>, ty: token::Type) -> ast::TypeExpression {
+    return ast::TypeExpression::Range(min.to_string(), ty, max.map_or_else(String::new, |x| return x.to_string()));
+}
+
+fn value_type(ty: &ValType) -> Result<token::Type, String> {
+    return match ty {
+        ValType::I32 => Ok(token::Type::I32),
+        ValType::I64 => Ok(token::Type::I64),
+        ValType::F32 => Ok(token::Type::F32),
+        ValType::F64 => Ok(token::Type::F64),
+        ValType::V128 => Err("v128 is not supported".to_string()),
+        ValType::Ref(x) => ref_type(x)
+    };
+}
+
+fn ref_type(ty: &RefType) -> Result<token::Type, String> {
+    if ty == &RefType::FUNCREF {
+        return Ok(token::Type::Fref);
+    }
+    else if ty == &RefType::EXTERNREF {
+        return Ok(token::Type::Xref);
+    }
+
+    return Err(format!("{:?} is not supported", ty));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker;
+    use crate::parser;
+    use crate::tokenizer;
+    use crate::transpiler;
+
+    fn parse(text: &str) -> ast::Program {
+        return parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap();
+    }
+
+    fn import_from(library: &str, text: &str) -> Result<Vec<ast::ImportDeclaration>, Box<dyn Error>> {
+        let buf = transpiler::Module::build(&parse(library), &checker::Defaults::default())?.to_wasm();
+        let program = parse(text);
+        let decl = match &program.declarations[0] {
+            ast::Declaration::ImportModule(x) => x,
+            x => panic!("expected an imported module, found {:?}", x)
+        };
+
+        return imports(decl, &buf, &references(&program));
+    }
+
+    #[test]
+    fn import_referenced_exports() {
+        let library = "
+            exp fn add(a: i32, b: i32) -> i32 { a + b }
+            exp fn pair(a: f64) -> (f64, i64) { (a, 1) }
+            exp mem memory = (1; page; 4);
+            exp tab table = (2; fref; 8);
+            exp let mut count <- 0;
+            exp fn unused() {}
+        ";
+        let text = "
+            imp \"lib/math.wasm\";
+            fn f() -> i32 { count <- count + 1; let (a, b) <- pair(0.5); add(1, 2) }
+            exp table as \"t\";
+            exp memory as \"m\";
+        ";
+        let imports = import_from(library, text).unwrap();
+        let i32_param = |name: &str| return ast::Param { span: 0..0, name: name.to_string(), ty: ast::TypeExpression::Primitive(token::Type::I32), default: None, is_variadic: false };
+
+        assert!(imports.iter().all(|x| return x.module == "math"));
+        assert_eq!(imports.iter().map(|x| return x.item.clone()).collect::<Vec<_>>(), vec![
+            ast::ImportItem::Function("add".to_string(), ast::Signature {
+                params: vec![i32_param("_0"), i32_param("_1")],
+                result: Some(ast::TypeExpression::Primitive(token::Type::I32))
+            }),
+            ast::ImportItem::Function("pair".to_string(), ast::Signature {
+                params: vec![ast::Param { span: 0..0, name: "_0".to_string(), ty: ast::TypeExpression::Primitive(token::Type::F64), default: None, is_variadic: false }],
+                result: Some(ast::TypeExpression::Tuple(vec![
+                    ast::TypeExpression::Primitive(token::Type::F64),
+                    ast::TypeExpression::Primitive(token::Type::I64)
+                ]))
+            }),
+            ast::ImportItem::Table("table".to_string(), ast::TypeExpression::Range("2".to_string(), token::Type::Fref, "8".to_string())),
+            ast::ImportItem::Memory("memory".to_string(), ast::TypeExpression::Range("1".to_string(), token::Type::Page, "4".to_string())),
+            ast::ImportItem::Variable(ast::Binding { name: "count".to_string(), is_mutable: true }, ast::TypeExpression::Primitive(token::Type::I32))
+        ]);
+    }
+
+    #[test]
+    fn reject_malformed_module() {
+        let program = parse("imp \"broken.wasm\";");
+        let decl = match &program.declarations[0] {
+            ast::Declaration::ImportModule(x) => x,
+            x => panic!("expected an imported module, found {:?}", x)
+        };
+        let err = imports(decl, b"not wasm", &HashSet::new()).unwrap_err();
+
+        assert!(err.to_string().starts_with("cannot read wasm module `broken.wasm`: "), "{}", err);
+    }
+}
@@ -1,19 +1,50 @@
 use std::fs;
-use std::io::Write;
+use std::io::{BufWriter, Write};
 use std::path::Path;
 
+// a path written in `file`, which is relative to the directory of `file`
+pub fn relative_to(file: &str, path: &str) -> String {
+    return Path::new(file).parent().map_or_else(|| return path.to_string(), |x| return x.join(path).to_string_lossy().to_string());
+}
+
+// whether two paths name the same file, falling back to comparing them as written
+pub fn same_file(a: &str, b: &str) -> bool {
+    return match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b
+    };
+}
+
+// errors name the file, keeping their kind
 pub fn read_file(path: &str) -> std::io::Result<String> {
-    return fs::read_to_string(path);
+    return fs::read_to_string(path).map_err(|x| return std::io::Error::new(x.kind(), format!("cannot read `{}`: {}", path, x)));
+}
+
+pub fn read_bytes(path: &str) -> std::io::Result<Vec<u8>> {
+    return fs::read(path).map_err(|x| return std::io::Error::new(x.kind(), format!("cannot read `{}`: {}", path, x)));
 }
 
 pub fn write_file(path: &str, buf: &[u8]) -> std::io::Result<()> {
+    return write_stream(path, |out| return out.write_all(buf));
+}
+
+// hands `f` a buffered writer over `path`, for output produced a piece at a
+// time. the file is removed again when `f` fails, rather than left partial
+pub fn write_stream(path: &str, f: impl FnOnce(&mut BufWriter<fs::File>) -> std::io::Result<()>) -> std::io::Result<()> {
     let path = Path::new(path);
 
     if let Some(parent_path) = path.parent() {
         fs::create_dir_all(parent_path)?;
     }
 
-    let mut out = fs::File::create(path)?;
+    let mut out = BufWriter::new(fs::File::create(path).map_err(|x| return std::io::Error::new(x.kind(), format!("cannot write `{}`: {}", path.display(), x)))?);
+
+    if let Err(err) = f(&mut out).and_then(|_| return out.flush()) {
+        drop(out);
+        fs::remove_file(path)?;
+
+        return Err(err);
+    }
 
-    return out.write_all(buf);
+    return Ok(());
 }
@@ -0,0 +1,705 @@
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+use crate::token;
+
+// type section
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValType {
+    I32,
+    I64,
+    F32,
+    F64,
+    FuncRef,
+    ExternRef
+}
+
+// params and results in order are the whole identity of a function type,
+// so the struct itself is the interning key
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FuncType {
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockType {
+    Empty,
+    Value(ValType)
+}
+
+impl ValType {
+    pub const fn from_token(ty: &token::Type) -> Self {
+        return match ty {
+            // page counts are unsigned, but `memory.size` and `memory.grow` work in i32
+            token::Type::I32 | token::Type::U32 | token::Type::Page => ValType::I32,
+            token::Type::I64 | token::Type::U64 => ValType::I64,
+            token::Type::F32 => ValType::F32,
+            token::Type::F64 => ValType::F64,
+            token::Type::Fref => ValType::FuncRef,
+            token::Type::Xref => ValType::ExternRef
+        };
+    }
+
+    pub const fn to_token(self) -> token::Type {
+        return match self {
+            ValType::I32 => token::Type::I32,
+            ValType::I64 => token::Type::I64,
+            ValType::F32 => token::Type::F32,
+            ValType::F64 => token::Type::F64,
+            ValType::FuncRef => token::Type::Fref,
+            ValType::ExternRef => token::Type::Xref
+        };
+    }
+
+    pub const fn code(self) -> u8 {
+        return match self {
+            ValType::I32 => 0x7f,
+            ValType::I64 => 0x7e,
+            ValType::F32 => 0x7d,
+            ValType::F64 => 0x7c,
+            ValType::FuncRef => 0x70,
+            ValType::ExternRef => 0x6f
+        };
+    }
+
+    pub const fn name(self) -> &'static str {
+        return match self {
+            ValType::I32 => "i32",
+            ValType::I64 => "i64",
+            ValType::F32 => "f32",
+            ValType::F64 => "f64",
+            ValType::FuncRef => "funcref",
+            ValType::ExternRef => "externref"
+        };
+    }
+
+    pub const fn is_float(self) -> bool {
+        return matches!(self, ValType::F32 | ValType::F64);
+    }
+
+    pub const fn is_reference(self) -> bool {
+        return matches!(self, ValType::FuncRef | ValType::ExternRef);
+    }
+
+    // loads and stores of each type follow one another in this order,
+    // references live in tables and never reach memory
+    const fn opcode_offset(self) -> u8 {
+        return match self {
+            ValType::I32 => 0,
+            ValType::I64 => 1,
+            ValType::F32 => 2,
+            ValType::F64 => 3,
+            ValType::FuncRef | ValType::ExternRef => panic!("unexpected reference in memory")
+        };
+    }
+
+    // log2 of the size in bytes, the alignment loads and stores promise
+    pub const fn natural_alignment(self) -> u32 {
+        return match self {
+            ValType::I32 | ValType::F32 => 2,
+            ValType::I64 | ValType::F64 => 3,
+            ValType::FuncRef | ValType::ExternRef => panic!("unexpected reference in memory")
+        };
+    }
+}
+
+// instruction section
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    DivU,
+    RemS,
+    RemU,
+    And,
+    Or,
+    Xor,
+    Shl,
+    ShrS,
+    ShrU,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    LtU,
+    GtU,
+    LeU,
+    GeU,
+    // floats only
+    Min,
+    Max,
+    Copysign
+}
+
+// instructions taking one operand, besides `neg` and `eqz`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    // integers only
+    Clz,
+    Ctz,
+    Popcnt,
+    // floats only
+    Abs,
+    Ceil,
+    Floor,
+    Trunc,
+    Nearest,
+    Sqrt
+}
+
+// operations called like a function, e.g. `sqrt(x)`, and lowered to a single
+// instruction. a function declared with the same name takes precedence
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Intrinsic {
+    Unary(UnaryOp),
+    Binary(BinaryOp),
+    IsNull
+}
+
+// alignment as a power of two, then a constant byte offset added to the address
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemArg {
+    pub align: u32,
+    pub offset: u32
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    // control
+    Unreachable,
+    Block(BlockType),
+    Loop(BlockType),
+    If(BlockType),
+    Else,
+    End,
+    Br(u32),
+    BrIf(u32),
+    Return,
+    Call(u32),
+    // type index, then table index
+    CallIndirect(u32, u32),
+    // calls that leave the function, replacing its frame
+    ReturnCall(u32),
+    ReturnCallIndirect(u32, u32),
+    Drop,
+    // the first of two numbers when the i32 on top is nonzero, else the second
+    Select,
+
+    // references and tables
+    RefNull(ValType),
+    RefIsNull,
+    RefFunc(u32),
+    TableGet(u32),
+    TableSet(u32),
+
+    // variables
+    LocalGet(u32),
+    LocalSet(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+
+    // memory
+    Load(ValType, MemArg),
+    Store(ValType, MemArg),
+
+    // numeric
+    I32Const(i32),
+    I64Const(i64),
+    // floats are kept as raw bits, so NaN payloads and signs survive
+    F32Const(u32),
+    F64Const(u64),
+    Eqz(ValType),
+    Neg(ValType),
+    Unary(ValType, UnaryOp),
+    Binary(ValType, BinaryOp),
+    I32WrapI64,
+    I64ExtendI32S,
+    I64ExtendI32U
+}
+
+impl BinaryOp {
+    pub fn from_symbol(symbol: &token::Symbol) -> Option<Self> {
+        return match symbol {
+            token::Symbol::Plus | token::Symbol::PlusUnsigned => Some(BinaryOp::Add),
+            token::Symbol::Minus | token::Symbol::MinusUnsigned => Some(BinaryOp::Sub),
+            token::Symbol::Asterisk | token::Symbol::AsteriskUnsigned => Some(BinaryOp::Mul),
+            token::Symbol::Solidus => Some(BinaryOp::Div),
+            token::Symbol::Modulo => Some(BinaryOp::RemS),
+            token::Symbol::SolidusUnsigned => Some(BinaryOp::DivU),
+            token::Symbol::ModuloUnsigned => Some(BinaryOp::RemU),
+            token::Symbol::BitwiseAnd => Some(BinaryOp::And),
+            token::Symbol::BitwiseOr => Some(BinaryOp::Or),
+            token::Symbol::BitwiseXor => Some(BinaryOp::Xor),
+            token::Symbol::ShiftLeftLogical => Some(BinaryOp::Shl),
+            token::Symbol::ShiftRightArithmatic => Some(BinaryOp::ShrS),
+            token::Symbol::ShiftRightLogical => Some(BinaryOp::ShrU),
+            token::Symbol::Equal => Some(BinaryOp::Eq),
+            token::Symbol::NotEqual => Some(BinaryOp::Ne),
+            token::Symbol::LessThan => Some(BinaryOp::Lt),
+            token::Symbol::GreaterThan => Some(BinaryOp::Gt),
+            token::Symbol::LessThanOrEqual => Some(BinaryOp::Le),
+            token::Symbol::GreaterThanOrEqual => Some(BinaryOp::Ge),
+            token::Symbol::LessThanUnsigned => Some(BinaryOp::LtU),
+            token::Symbol::GreaterThanUnsigned => Some(BinaryOp::GtU),
+            token::Symbol::LessThanOrEqualUnsigned => Some(BinaryOp::LeU),
+            token::Symbol::GreaterThanOrEqualUnsigned => Some(BinaryOp::GeU),
+            _ => None
+        };
+    }
+
+    pub const fn is_comparison(self) -> bool {
+        return matches!(self, BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Le | BinaryOp::Ge | BinaryOp::LtU | BinaryOp::GtU | BinaryOp::LeU | BinaryOp::GeU);
+    }
+
+    // opcode and mnemonic, integer division and comparisons are signed unless
+    // written with a `u` suffix, which floats do not have
+    pub fn encoding(self, ty: ValType) -> Option<(u8, &'static str)> {
+        return match ty {
+            ValType::I32 | ValType::I64 => {
+                let (compare, arith) = if ty == ValType::I32 { (0x46, 0x67) } else { (0x51, 0x79) };
+
+                match self {
+                    BinaryOp::Eq => Some((compare, "eq")),
+                    BinaryOp::Ne => Some((compare + 1, "ne")),
+                    BinaryOp::Lt => Some((compare + 2, "lt_s")),
+                    BinaryOp::Gt => Some((compare + 4, "gt_s")),
+                    BinaryOp::Le => Some((compare + 6, "le_s")),
+                    BinaryOp::Ge => Some((compare + 8, "ge_s")),
+                    BinaryOp::LtU => Some((compare + 3, "lt_u")),
+                    BinaryOp::GtU => Some((compare + 5, "gt_u")),
+                    BinaryOp::LeU => Some((compare + 7, "le_u")),
+                    BinaryOp::GeU => Some((compare + 9, "ge_u")),
+                    BinaryOp::Add => Some((arith + 3, "add")),
+                    BinaryOp::Sub => Some((arith + 4, "sub")),
+                    BinaryOp::Mul => Some((arith + 5, "mul")),
+                    BinaryOp::Div => Some((arith + 6, "div_s")),
+                    BinaryOp::DivU => Some((arith + 7, "div_u")),
+                    BinaryOp::RemS => Some((arith + 8, "rem_s")),
+                    BinaryOp::RemU => Some((arith + 9, "rem_u")),
+                    BinaryOp::And => Some((arith + 10, "and")),
+                    BinaryOp::Or => Some((arith + 11, "or")),
+                    BinaryOp::Xor => Some((arith + 12, "xor")),
+                    BinaryOp::Shl => Some((arith + 13, "shl")),
+                    BinaryOp::ShrS => Some((arith + 14, "shr_s")),
+                    BinaryOp::ShrU => Some((arith + 15, "shr_u")),
+                    BinaryOp::Min | BinaryOp::Max | BinaryOp::Copysign => None
+                }
+            },
+            ValType::F32 | ValType::F64 => {
+                let (compare, arith) = if ty == ValType::F32 { (0x5b, 0x8b) } else { (0x61, 0x99) };
+
+                match self {
+                    BinaryOp::Eq => Some((compare, "eq")),
+                    BinaryOp::Ne => Some((compare + 1, "ne")),
+                    BinaryOp::Lt => Some((compare + 2, "lt")),
+                    BinaryOp::Gt => Some((compare + 3, "gt")),
+                    BinaryOp::Le => Some((compare + 4, "le")),
+                    BinaryOp::Ge => Some((compare + 5, "ge")),
+                    BinaryOp::Add => Some((arith + 7, "add")),
+                    BinaryOp::Sub => Some((arith + 8, "sub")),
+                    BinaryOp::Mul => Some((arith + 9, "mul")),
+                    BinaryOp::Div => Some((arith + 10, "div")),
+                    BinaryOp::Min => Some((arith + 11, "min")),
+                    BinaryOp::Max => Some((arith + 12, "max")),
+                    BinaryOp::Copysign => Some((arith + 13, "copysign")),
+                    _ => None
+                }
+            },
+            ValType::FuncRef | ValType::ExternRef => None
+        };
+    }
+}
+
+impl UnaryOp {
+    // opcode and mnemonic, these lead the arithmetic of each integer group,
+    // and follow `abs` and `neg` in each float group
+    pub const fn encoding(self, ty: ValType) -> Option<(u8, &'static str)> {
+        return match ty {
+            ValType::I32 | ValType::I64 => {
+                let clz = if matches!(ty, ValType::I32) { 0x67 } else { 0x79 };
+
+                match self {
+                    UnaryOp::Clz => Some((clz, "clz")),
+                    UnaryOp::Ctz => Some((clz + 1, "ctz")),
+                    UnaryOp::Popcnt => Some((clz + 2, "popcnt")),
+                    _ => None
+                }
+            },
+            ValType::F32 | ValType::F64 => {
+                let abs = if matches!(ty, ValType::F32) { 0x8b } else { 0x99 };
+
+                match self {
+                    UnaryOp::Abs => Some((abs, "abs")),
+                    UnaryOp::Ceil => Some((abs + 2, "ceil")),
+                    UnaryOp::Floor => Some((abs + 3, "floor")),
+                    UnaryOp::Trunc => Some((abs + 4, "trunc")),
+                    UnaryOp::Nearest => Some((abs + 5, "nearest")),
+                    UnaryOp::Sqrt => Some((abs + 6, "sqrt")),
+                    _ => None
+                }
+            },
+            ValType::FuncRef | ValType::ExternRef => None
+        };
+    }
+}
+
+impl Intrinsic {
+    pub fn from_name(name: &str) -> Option<Self> {
+        return match name {
+            "clz" => Some(Intrinsic::Unary(UnaryOp::Clz)),
+            "ctz" => Some(Intrinsic::Unary(UnaryOp::Ctz)),
+            "popcnt" => Some(Intrinsic::Unary(UnaryOp::Popcnt)),
+            "abs" => Some(Intrinsic::Unary(UnaryOp::Abs)),
+            "ceil" => Some(Intrinsic::Unary(UnaryOp::Ceil)),
+            "floor" => Some(Intrinsic::Unary(UnaryOp::Floor)),
+            "trunc" => Some(Intrinsic::Unary(UnaryOp::Trunc)),
+            "nearest" => Some(Intrinsic::Unary(UnaryOp::Nearest)),
+            "sqrt" => Some(Intrinsic::Unary(UnaryOp::Sqrt)),
+            "min" => Some(Intrinsic::Binary(BinaryOp::Min)),
+            "max" => Some(Intrinsic::Binary(BinaryOp::Max)),
+            "copysign" => Some(Intrinsic::Binary(BinaryOp::Copysign)),
+            "is_null" => Some(Intrinsic::IsNull),
+            _ => None
+        };
+    }
+
+    pub const fn arity(self) -> usize {
+        return match self {
+            Intrinsic::Unary(_) | Intrinsic::IsNull => 1,
+            Intrinsic::Binary(_) => 2
+        };
+    }
+
+    // the two types an intrinsic is defined for, the first one is assumed
+    // when no operand tells
+    pub const fn operand_types(self) -> [ValType; 2] {
+        return match self {
+            Intrinsic::Unary(UnaryOp::Clz | UnaryOp::Ctz | UnaryOp::Popcnt) => [ValType::I32, ValType::I64],
+            Intrinsic::IsNull => [ValType::FuncRef, ValType::ExternRef],
+            _ => [ValType::F64, ValType::F32]
+        };
+    }
+
+    // the result type for operands of `ty`, tests result in an i32
+    pub const fn result_type(self, ty: ValType) -> ValType {
+        return match self {
+            Intrinsic::IsNull => ValType::I32,
+            _ => ty
+        };
+    }
+
+    pub fn accepts(self, ty: ValType) -> bool {
+        return self.operand_types().contains(&ty);
+    }
+
+    // e.g. "i32 or i64", spelled as in the source
+    pub fn operands_name(self) -> String {
+        let mut names = self.operand_types().map(|x| return x.to_token().name());
+
+        names.sort_unstable();
+
+        return names.join(" or ");
+    }
+
+    pub const fn instruction(self, ty: ValType) -> Instruction {
+        return match self {
+            Intrinsic::Unary(x) => Instruction::Unary(ty, x),
+            Intrinsic::Binary(x) => Instruction::Binary(ty, x),
+            Intrinsic::IsNull => Instruction::RefIsNull
+        };
+    }
+}
+
+impl Instruction {
+    pub fn to_wasm(&self, buf: &mut Vec<u8>) {
+        match self {
+            Instruction::Unreachable => buf.push(0x00),
+            Instruction::Block(x) => {
+                buf.push(0x02);
+                write_block_type(buf, x);
+            },
+            Instruction::Loop(x) => {
+                buf.push(0x03);
+                write_block_type(buf, x);
+            },
+            Instruction::If(x) => {
+                buf.push(0x04);
+                write_block_type(buf, x);
+            },
+            Instruction::Else => buf.push(0x05),
+            Instruction::End => buf.push(0x0b),
+            Instruction::Br(x) => {
+                buf.push(0x0c);
+                write_unsigned(buf, u64::from(*x));
+            },
+            Instruction::BrIf(x) => {
+                buf.push(0x0d);
+                write_unsigned(buf, u64::from(*x));
+            },
+            Instruction::Return => buf.push(0x0f),
+            Instruction::Call(x) => {
+                buf.push(0x10);
+                write_unsigned(buf, u64::from(*x));
+            },
+            Instruction::CallIndirect(ty, table) => {
+                buf.push(0x11);
+                write_unsigned(buf, u64::from(*ty));
+                write_unsigned(buf, u64::from(*table));
+            },
+            Instruction::ReturnCall(x) => {
+                buf.push(0x12);
+                write_unsigned(buf, u64::from(*x));
+            },
+            Instruction::ReturnCallIndirect(ty, table) => {
+                buf.push(0x13);
+                write_unsigned(buf, u64::from(*ty));
+                write_unsigned(buf, u64::from(*table));
+            },
+            Instruction::Drop => buf.push(0x1a),
+            Instruction::Select => buf.push(0x1b),
+            Instruction::RefNull(ty) => {
+                buf.push(0xd0);
+                buf.push(ty.code());
+            },
+            Instruction::RefIsNull => buf.push(0xd1),
+            Instruction::RefFunc(x) => {
+                buf.push(0xd2);
+                write_unsigned(buf, u64::from(*x));
+            },
+            Instruction::TableGet(x) => {
+                buf.push(0x25);
+                write_unsigned(buf, u64::from(*x));
+            },
+            Instruction::TableSet(x) => {
+                buf.push(0x26);
+                write_unsigned(buf, u64::from(*x));
+            },
+            Instruction::LocalGet(x) => {
+                buf.push(0x20);
+                write_unsigned(buf, u64::from(*x));
+            },
+            Instruction::LocalSet(x) => {
+                buf.push(0x21);
+                write_unsigned(buf, u64::from(*x));
+            },
+            Instruction::GlobalGet(x) => {
+                buf.push(0x23);
+                write_unsigned(buf, u64::from(*x));
+            },
+            Instruction::GlobalSet(x) => {
+                buf.push(0x24);
+                write_unsigned(buf, u64::from(*x));
+            },
+            Instruction::Load(ty, memarg) => {
+                buf.push(0x28 + ty.opcode_offset());
+                write_memarg(buf, memarg);
+            },
+            Instruction::Store(ty, memarg) => {
+                buf.push(0x36 + ty.opcode_offset());
+                write_memarg(buf, memarg);
+            },
+            Instruction::I32Const(x) => {
+                buf.push(0x41);
+                write_signed(buf, i64::from(*x));
+            },
+            Instruction::I64Const(x) => {
+                buf.push(0x42);
+                write_signed(buf, *x);
+            },
+            Instruction::F32Const(x) => {
+                buf.push(0x43);
+                buf.extend_from_slice(&x.to_le_bytes());
+            },
+            Instruction::F64Const(x) => {
+                buf.push(0x44);
+                buf.extend_from_slice(&x.to_le_bytes());
+            },
+            Instruction::Eqz(ty) => buf.push(if *ty == ValType::I32 { 0x45 } else { 0x50 }),
+            Instruction::Neg(ty) => buf.push(if *ty == ValType::F32 { 0x8c } else { 0x9a }),
+            Instruction::Unary(ty, op) => {
+                let (code, _) = op.encoding(*ty).expect("unexpected unsupported unary instruction");
+
+                buf.push(code);
+            },
+            Instruction::Binary(ty, op) => {
+                let (code, _) = op.encoding(*ty).expect("unexpected unsupported binary instruction");
+
+                buf.push(code);
+            },
+            Instruction::I32WrapI64 => buf.push(0xa7),
+            Instruction::I64ExtendI32S => buf.push(0xac),
+            Instruction::I64ExtendI32U => buf.push(0xad)
+        }
+    }
+
+    pub fn to_wat(&self) -> String {
+        return match self {
+            Instruction::Unreachable => "unreachable".to_string(),
+            Instruction::Block(x) => format!("block{}", block_type_wat(x)),
+            Instruction::Loop(x) => format!("loop{}", block_type_wat(x)),
+            Instruction::If(x) => format!("if{}", block_type_wat(x)),
+            Instruction::Else => "else".to_string(),
+            Instruction::End => "end".to_string(),
+            Instruction::Br(x) => format!("br {}", x),
+            Instruction::BrIf(x) => format!("br_if {}", x),
+            Instruction::Return => "return".to_string(),
+            Instruction::Call(x) => format!("call {}", x),
+            Instruction::CallIndirect(ty, table) => format!("call_indirect {} (type {})", table, ty),
+            Instruction::ReturnCall(x) => format!("return_call {}", x),
+            Instruction::ReturnCallIndirect(ty, table) => format!("return_call_indirect {} (type {})", table, ty),
+            Instruction::Drop => "drop".to_string(),
+            Instruction::Select => "select".to_string(),
+            Instruction::RefNull(ty) => format!("ref.null {}", ty.name().trim_end_matches("ref")),
+            Instruction::RefIsNull => "ref.is_null".to_string(),
+            Instruction::RefFunc(x) => format!("ref.func {}", x),
+            Instruction::TableGet(x) => format!("table.get {}", x),
+            Instruction::TableSet(x) => format!("table.set {}", x),
+            Instruction::LocalGet(x) => format!("local.get {}", x),
+            Instruction::LocalSet(x) => format!("local.set {}", x),
+            Instruction::GlobalGet(x) => format!("global.get {}", x),
+            Instruction::GlobalSet(x) => format!("global.set {}", x),
+            Instruction::Load(ty, memarg) => format!("{}.load{}", ty.name(), memarg_wat(*ty, memarg)),
+            Instruction::Store(ty, memarg) => format!("{}.store{}", ty.name(), memarg_wat(*ty, memarg)),
+            Instruction::I32Const(x) => format!("i32.const {}", x),
+            Instruction::I64Const(x) => format!("i64.const {}", x),
+            Instruction::F32Const(x) => format!("f32.const {}", float_wat(f64::from(f32::from_bits(*x)), *x == F32_CANONICAL_NAN)),
+            Instruction::F64Const(x) => format!("f64.const {}", float_wat(f64::from_bits(*x), *x == F64_CANONICAL_NAN)),
+            Instruction::Eqz(ty) => format!("{}.eqz", ty.name()),
+            Instruction::Neg(ty) => format!("{}.neg", ty.name()),
+            Instruction::Unary(ty, op) => {
+                let (_, name) = op.encoding(*ty).expect("unexpected unsupported unary instruction");
+
+                format!("{}.{}", ty.name(), name)
+            },
+            Instruction::Binary(ty, op) => {
+                let (_, name) = op.encoding(*ty).expect("unexpected unsupported binary instruction");
+
+                format!("{}.{}", ty.name(), name)
+            },
+            Instruction::I32WrapI64 => "i32.wrap_i64".to_string(),
+            Instruction::I64ExtendI32S => "i64.extend_i32_s".to_string(),
+            Instruction::I64ExtendI32U => "i64.extend_i32_u".to_string()
+        };
+    }
+}
+
+fn write_memarg(buf: &mut Vec<u8>, memarg: &MemArg) {
+    write_unsigned(buf, u64::from(memarg.align));
+    write_unsigned(buf, u64::from(memarg.offset));
+}
+
+// defaults are left out, as wat does
+fn memarg_wat(ty: ValType, memarg: &MemArg) -> String {
+    let mut res = String::new();
+
+    if memarg.offset != 0 {
+        res.push_str(&format!(" offset={}", memarg.offset));
+    }
+
+    if memarg.align != ty.natural_alignment() {
+        res.push_str(&format!(" align={}", 1_u32 << memarg.align));
+    }
+
+    return res;
+}
+
+fn write_block_type(buf: &mut Vec<u8>, ty: &BlockType) {
+    match ty {
+        BlockType::Empty => buf.push(0x40),
+        BlockType::Value(x) => buf.push(x.code())
+    }
+}
+
+fn block_type_wat(ty: &BlockType) -> String {
+    return match ty {
+        BlockType::Empty => String::new(),
+        BlockType::Value(x) => format!(" (result {})", x.name())
+    };
+}
+
+fn float_wat(value: f64, is_canonical_nan: bool) -> String {
+    return match value {
+        x if x.is_nan() && is_canonical_nan => "nan".to_string(),
+        x if x.is_nan() => "nan:arithmetic".to_string(),
+        x if x.is_infinite() => (if x < 0.0 { "-inf" } else { "inf" }).to_string(),
+        x => format!("{:?}", x)
+    };
+}
+
+// listing section
+// one numbered instruction per line, indented by block nesting, under the
+// function signature and the locals declared after its params
+pub fn dump_function(name: &str, ty: &FuncType, locals: &[ValType], body: &[Instruction]) -> String {
+    let types = |x: &[ValType]| return x.iter().map(|x| return x.name()).collect::<Vec<_>>().join(", ");
+    let mut out = format!("fn {}({})", name, types(&ty.params));
+
+    match ty.results.as_slice() {
+        [] => {},
+        [x] => write!(out, " -> {}", x.name()).expect("unexpected write failure"),
+        x => write!(out, " -> ({})", types(x)).expect("unexpected write failure")
+    }
+
+    out.push_str(" {\n");
+
+    if !locals.is_empty() {
+        writeln!(out, "    locals {}", types(locals)).expect("unexpected write failure");
+    }
+
+    let mut depth: usize = 0;
+
+    for (index, instruction) in body.iter().enumerate() {
+        if matches!(instruction, Instruction::End | Instruction::Else) {
+            depth = depth.saturating_sub(1);
+        }
+
+        writeln!(out, "    {:04} {}{}", index, "  ".repeat(depth), instruction.to_wat()).expect("unexpected write failure");
+
+        if matches!(instruction, Instruction::Block(_) | Instruction::Loop(_) | Instruction::If(_) | Instruction::Else) {
+            depth += 1;
+        }
+    }
+
+    out.push_str("}\n");
+
+    return out;
+}
+
+// constant section
+pub const F32_CANONICAL_NAN: u32 = 0x7fc0_0000;
+pub const F64_CANONICAL_NAN: u64 = 0x7ff8_0000_0000_0000;
+
+// leb128
+pub fn write_signed(buf: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = u8::try_from(value & 0x7f).expect("unexpected overflow");
+        value >>= 7;
+
+        let is_done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+
+        if is_done {
+            buf.push(byte);
+            return;
+        }
+
+        buf.push(byte | 0x80);
+    }
+}
+
+pub fn write_unsigned(buf: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = u8::try_from(value & 0x7f).expect("unexpected overflow");
+        value >>= 7;
+
+        if value == 0 {
+            buf.push(byte);
+            return;
+        }
+
+        buf.push(byte | 0x80);
+    }
+}
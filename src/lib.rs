@@ -1,3 +1,7 @@
+#![allow(
+    clippy::needless_return
+)]
+
 extern crate proc_macro;
 
 use quote::quote;
@@ -7,24 +11,40 @@ use syn::{parse_macro_input, DeriveInput};
 pub fn my_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
 
+    return proc_macro::TokenStream::from(impl_grammar(input));
+}
+
+fn impl_grammar(input: DeriveInput) -> proc_macro2::TokenStream {
     let struct_name = input.ident;
     let struct_val_name = struct_name.to_string();
 
-    let output = quote!{
+    return quote!{
         impl Grammar for #struct_name {
             fn process(&mut self, token: &token::Token) -> Result { return self.pattern.execute(token); }
+            fn finish(&mut self) -> Result { return self.pattern.finish(); }
             fn is_done(&self) -> bool { return self.pattern.is_done; }
             fn info(&self) -> String { return format!("{}:[{}]", #struct_val_name, self.pattern.state); }
+            fn name(&self) -> &'static str { return #struct_val_name; }
+            fn attach(&mut self, node: parse_tree::Node) { self.pattern.attach(node); }
+            fn into_node(self: Box<Self>) -> parse_tree::Node { return parse_tree::Node::Tree(parse_tree::Tree::new(#struct_val_name, self.pattern.children)); }
+            fn clone_box(&self) -> Box<dyn Grammar> { return Box::new(Self { pattern: self.pattern.clone() }); }
+            fn expected(&self) -> (Vec<TokenKind>, bool) { return self.pattern.expected(); }
         }
     };
-
-    return proc_macro::TokenStream::from(output);
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn name() {
-        unimplemented!();
+        let input: DeriveInput = syn::parse_str("pub struct Program { pattern: GrammarPattern<'static> }").unwrap();
+        let output = impl_grammar(input).to_string();
+
+        assert!(output.starts_with("impl Grammar for Program"));
+        assert!(output.contains("\"Program\""));
+        assert!(output.contains("fn clone_box"));
+        assert!(output.contains("fn expected"));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,981 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+use crate::ast;
+use crate::resolver;
+use crate::span::Spanned;
+use crate::token;
+use crate::visitor::{self, Visitor};
+
+// main program section
+pub fn lower(program: &mut ast::Program) -> Result<(), Box<dyn Error>> {
+    lift_functions(program)?;
+    monomorphize(program)?;
+
+    let mut signatures = HashMap::new();
+
+    for decl in program.declarations.iter() {
+        if let Some((name, signature)) = signature_of(decl) {
+            validate_variadic(name, signature, matches!(decl, ast::Declaration::Import(_)))?;
+            validate_defaults(name, signature)?;
+            signatures.insert(name.to_string(), signature.clone());
+        }
+    }
+
+    let globals = resolver::resolve(program)?.symbols().iter().map(|x| return x.name().to_string()).collect::<HashSet<_>>();
+    let constants = visible_constants(&globals);
+
+    for decl in program.declarations.iter_mut() {
+        match decl {
+            ast::Declaration::Function(function) | ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Function(function), .. }) => {
+                let mut names = Names::default();
+
+                names.visit_block(&function.body);
+                names.bound.extend(function.signature.params.iter().map(|x| return x.name.clone()));
+
+                lower_block(&mut function.body, &signatures, &visible_constants(&names.bound.union(&globals).cloned().collect()))?;
+            },
+            ast::Declaration::Variable(x) | ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Variable(x), .. }) => {
+                lower_expression(&mut x.value, &signatures, &constants)?;
+            },
+            ast::Declaration::StaticAssert(x) => lower_expression(&mut x.condition, &signatures, &constants)?,
+            _ => {}
+        }
+    }
+
+    return Ok(());
+}
+
+// built-in constants, folded into literals unless a name declared in the
+// program or the function shadows them
+const CONSTANTS: &[(&str, &str)] = &[
+    // bytes in a page of memory
+    ("PAGE_SIZE", "65536")
+];
+
+type Constants = HashMap<&'static str, &'static str>;
+
+fn visible_constants(shadowed: &HashSet<String>) -> Constants {
+    return CONSTANTS.iter().filter(|(name, _)| return !shadowed.contains(*name)).copied().collect();
+}
+
+// wasm has no nested functions, so a function declared in a block is moved to
+// module scope as `outer$inner`, and calls to it in that block are renamed.
+// it is not a closure, and only sees names declared at module scope
+fn lift_functions(program: &mut ast::Program) -> Result<(), Box<dyn Error>> {
+    let mut lifter = Lifter {
+        taken: resolver::resolve(program)?.symbols().iter().map(|x| return x.name().to_string()).collect(),
+        lifted: vec![]
+    };
+
+    for decl in program.declarations.iter_mut() {
+        let function = match decl {
+            ast::Declaration::Function(x) => x,
+            ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Function(x), .. }) => x,
+            _ => continue
+        };
+        let locals = function.signature.params.iter().map(|x| return x.name.clone()).collect();
+
+        lifter.block(&mut function.body, &function.name, &HashMap::new(), &locals)?;
+    }
+
+    program.declarations.extend(lifter.lifted.into_iter().map(ast::Declaration::Function));
+
+    return Ok(());
+}
+
+struct Lifter {
+    // names declared at module scope, and given to lifted functions so far
+    taken: HashSet<String>,
+    lifted: Vec<ast::FunctionDeclaration>
+}
+
+impl Lifter {
+    // `names` maps the nested functions in scope to their lifted names, and
+    // `locals` are the names of the enclosing functions a lifted one cannot use
+    fn block(&mut self, block: &mut ast::Block, enclosing: &str, names: &HashMap<String, String>, locals: &HashSet<String>) -> Result<(), Box<dyn Error>> {
+        let mut names = names.clone();
+        let mut locals = locals.clone();
+
+        // a nested function can be called anywhere in its block, even before it
+        for stmt in block.statements.iter() {
+            if let ast::Statement::Function(x) = &stmt.node {
+                names.insert(x.name.clone(), self.mangle(enclosing, &x.name));
+            }
+        }
+
+        for stmt in block.statements.iter_mut() {
+            match &mut stmt.node {
+                ast::Statement::Function(x) => {
+                    if x.is_public {
+                        return Err(format!("nested function `{}` in function `{}` cannot be public", x.name, enclosing).into());
+                    }
+
+                    check_captures(x, enclosing, &locals)?;
+
+                    let name = names[&x.name].clone();
+                    let params = locals.iter().cloned().chain(x.signature.params.iter().map(|x| return x.name.clone())).collect();
+
+                    self.block(&mut x.body, &name, &names, &params)?;
+                    x.name = name;
+                },
+                ast::Statement::Variable(x) => {
+                    rename_calls(&mut x.value, &names);
+
+                    match &x.pattern {
+                        ast::Pattern::Single(binding) => { locals.insert(binding.name.clone()); },
+                        ast::Pattern::Tuple(list) => locals.extend(list.iter().map(|x| return x.name.clone()))
+                    }
+                },
+                ast::Statement::Expression(x) | ast::Statement::Return(Some(x)) => rename_calls(x, &names),
+                ast::Statement::Assignment(target, value) => {
+                    rename_calls(target, &names);
+                    rename_calls(value, &names);
+                },
+                ast::Statement::If(x) => {
+                    rename_calls(&mut x.condition, &names);
+                    self.block(&mut x.block, enclosing, &names, &locals)?;
+
+                    for (condition, block) in x.else_ifs.iter_mut() {
+                        rename_calls(condition, &names);
+                        self.block(block, enclosing, &names, &locals)?;
+                    }
+
+                    if let Some(block) = &mut x.else_block {
+                        self.block(block, enclosing, &names, &locals)?;
+                    }
+                },
+                ast::Statement::While(x) => {
+                    rename_calls(&mut x.condition, &names);
+                    self.block(&mut x.block, enclosing, &names, &locals)?;
+                },
+                ast::Statement::Repeat(x) => self.block(&mut x.block, enclosing, &names, &locals)?,
+                ast::Statement::Defer(x) => self.block(x, enclosing, &names, &locals)?,
+                ast::Statement::Block(x) => self.block(x, enclosing, &names, &locals)?,
+                _ => {}
+            }
+        }
+
+        if let Some(x) = &mut block.tail {
+            rename_calls(x, &names);
+        }
+
+        for stmt in std::mem::take(&mut block.statements) {
+            match stmt {
+                Spanned { node: ast::Statement::Function(x), .. } => self.lifted.push(x),
+                x => block.statements.push(x)
+            }
+        }
+
+        return Ok(());
+    }
+
+    // functions of the same name in different blocks are told apart by a count
+    fn mangle(&mut self, enclosing: &str, name: &str) -> String {
+        let mut mangled = format!("{}${}", enclosing, name);
+        let mut count = 1;
+
+        while self.taken.contains(&mangled) {
+            mangled = format!("{}${}${}", enclosing, name, count);
+            count += 1;
+        }
+
+        self.taken.insert(mangled.clone());
+
+        return mangled;
+    }
+}
+
+// wasm has no generics, so a generic function is instantiated as a function of
+// its own, as `max$i64`, for every distinct list of types it is called with,
+// and the generic one is dropped along with any it was never called with
+fn monomorphize(program: &mut ast::Program) -> Result<(), Box<dyn Error>> {
+    let mut instantiator = Instantiator {
+        taken: resolver::resolve(program)?.symbols().iter().map(|x| return x.name().to_string()).collect(),
+        generics: HashMap::new(),
+        names: HashMap::new(),
+        instances: vec![]
+    };
+
+    for decl in std::mem::take(&mut program.declarations) {
+        match decl {
+            ast::Declaration::Function(x) if !x.generics.is_empty() && x.attribute("export").is_some() => {
+                return Err(format!("generic function `{}` cannot be exported; export a function calling one of its instantiations instead", x.name).into());
+            },
+            ast::Declaration::Function(x) if !x.generics.is_empty() => {
+                validate_generics(&x)?;
+                instantiator.generics.insert(x.name.clone(), x);
+            },
+            ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Function(x), .. }) if !x.generics.is_empty() => {
+                return Err(format!("generic function `{}` cannot be exported; export a function calling one of its instantiations instead", x.name).into());
+            },
+            x => program.declarations.push(x)
+        }
+    }
+
+    for decl in program.declarations.iter_mut() {
+        match decl {
+            ast::Declaration::Function(x) | ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Function(x), .. }) => {
+                for_each_expression(&mut x.body, &mut |x| return instantiator.expression(x))?;
+            },
+            ast::Declaration::Variable(x) | ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Variable(x), .. }) => {
+                for_each_subexpression(&mut x.value, &mut |x| return instantiator.expression(x))?;
+            },
+            ast::Declaration::StaticAssert(x) => for_each_subexpression(&mut x.condition, &mut |x| return instantiator.expression(x))?,
+            _ => {}
+        }
+    }
+
+    // an instantiation may call other generic functions in turn
+    let mut index = 0;
+
+    while index < instantiator.instances.len() {
+        let mut body = std::mem::replace(&mut instantiator.instances[index].body, ast::Block { statements: vec![], tail: None });
+
+        for_each_expression(&mut body, &mut |x| return instantiator.expression(x))?;
+        instantiator.instances[index].body = body;
+        index += 1;
+    }
+
+    program.declarations.extend(instantiator.instances.into_iter().map(ast::Declaration::Function));
+
+    return Ok(());
+}
+
+// every type parameter must be used by the signature, since it can only be
+// told from there, and has to leave some type its constraints all allow
+fn validate_generics(function: &ast::FunctionDeclaration) -> Result<(), Box<dyn Error>> {
+    for (index, param) in function.generics.iter().enumerate() {
+        if function.generics[..index].contains(param) {
+            return Err(format!("type parameter `{}` of function `{}` is declared twice", param, function.name).into());
+        }
+
+        let signature = &function.signature;
+
+        if !signature.params.iter().map(|x| return &x.ty).chain(signature.result.iter()).any(|x| return uses_type(x, param)) {
+            return Err(format!("type parameter `{}` of function `{}` is not used by its parameters or result", param, function.name).into());
+        }
+
+        let mut allowed = None::<Vec<&token::Type>>;
+
+        for constraint in function.constraints.iter().filter(|x| return &x.param == param) {
+            allowed = Some(match allowed {
+                Some(list) => list.into_iter().filter(|x| return constraint.types.contains(x)).collect(),
+                None => constraint.types.iter().collect()
+            });
+        }
+
+        if allowed.is_some_and(|x| return x.is_empty()) {
+            return Err(format!("no type satisfies all the constraints on `{}` of function `{}`", param, function.name).into());
+        }
+    }
+
+    if let Some(x) = function.constraints.iter().find(|x| return !function.generics.contains(&x.param)) {
+        return Err(format!("`where` clause of function `{}` constrains `{}`, which is not one of its type parameters", function.name, x.param).into());
+    }
+
+    return Ok(());
+}
+
+fn uses_type(ty: &ast::TypeExpression, name: &str) -> bool {
+    return match ty {
+        ast::TypeExpression::Named(x) => x == name,
+        ast::TypeExpression::Tuple(list) => list.iter().any(|x| return uses_type(x, name)),
+        ast::TypeExpression::Function(_, Some(result)) => uses_type(result, name),
+        _ => false
+    };
+}
+
+struct Instantiator {
+    // names declared at module scope, and given to instantiations so far
+    taken: HashSet<String>,
+    generics: HashMap<String, ast::FunctionDeclaration>,
+    // the name of the instantiation for a generic function and its types
+    names: HashMap<(String, Vec<&'static str>), String>,
+    instances: Vec<ast::FunctionDeclaration>
+}
+
+impl Instantiator {
+    // a `name::<ty>(args)` that is not a generic function is an indirect call
+    fn expression(&mut self, expr: &mut ast::Expression) -> Result<(), Box<dyn Error>> {
+        let replacement = match expr {
+            ast::Expression::Call { callee, .. } if self.generics.contains_key(callee.as_str()) => {
+                let count = self.generics[callee.as_str()].generics.len();
+
+                return Err(format!("generic function `{}` needs its type arguments, as in `{}::<{}>(..)`", callee, callee, vec!["i32"; count].join(", ")).into());
+            },
+            ast::Expression::GenericCall { callee, types, args } if self.generics.contains_key(callee.as_str()) => ast::Expression::Call {
+                callee: self.instantiate(callee, types)?,
+                args: std::mem::take(args)
+            },
+            ast::Expression::GenericCall { callee, types, args } => match types.as_slice() {
+                [signature] => ast::Expression::CallIndirect {
+                    target: Box::new(ast::Expression::Identifier(callee.clone())),
+                    signature: signature.clone(),
+                    args: std::mem::take(args)
+                },
+                _ => return Err(format!("`{}` is not a generic function, and an indirect call takes a single signature", callee).into())
+            },
+            _ => return Ok(())
+        };
+
+        *expr = replacement;
+
+        return Ok(());
+    }
+
+    fn instantiate(&mut self, name: &str, types: &[ast::TypeExpression]) -> Result<String, Box<dyn Error>> {
+        let generic = &self.generics[name];
+
+        if types.len() != generic.generics.len() {
+            return Err(format!("generic function `{}` takes {} type arguments, but {} were given", name, generic.generics.len(), types.len()).into());
+        }
+
+        let types = types.iter().map(|x| return match x {
+            ast::TypeExpression::Primitive(x) => Ok(x.clone()),
+            x => Err(format!("type arguments of generic function `{}` must be primitive types, found `{}`", name, x))
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        for (param, ty) in generic.generics.iter().zip(types.iter()) {
+            if let Some(x) = generic.constraints.iter().find(|x| return &x.param == param && !x.types.contains(ty)) {
+                return Err(format!("type {} does not satisfy the constraint `{}` of function `{}`", ast::type_name(ty), x, name).into());
+            }
+        }
+
+        let key = (name.to_string(), types.iter().map(ast::type_name).collect::<Vec<_>>());
+
+        if let Some(x) = self.names.get(&key) {
+            return Ok(x.clone());
+        }
+
+        let base = std::iter::once(name).chain(key.1.iter().copied()).collect::<Vec<_>>().join("$");
+        let mut mangled = base.clone();
+        let mut count = 1;
+
+        while self.taken.contains(&mangled) {
+            mangled = format!("{}${}", base, count);
+            count += 1;
+        }
+
+        let mut instance = generic.clone();
+        let substitutions = generic.generics.iter().cloned().zip(types).collect::<HashMap<_, _>>();
+
+        instance.name = mangled.clone();
+        instance.generics = vec![];
+        instance.constraints = vec![];
+        substitute(&mut instance, &substitutions)?;
+
+        self.taken.insert(mangled.clone());
+        self.names.insert(key, mangled.clone());
+        self.instances.push(instance);
+
+        return Ok(mangled);
+    }
+}
+
+// replaces the type parameters in a generic function by the types given, a
+// `T(x)` becomes a cast and a `T` in `size_of(T)` the type itself
+fn substitute(function: &mut ast::FunctionDeclaration, types: &HashMap<String, token::Type>) -> Result<(), Box<dyn Error>> {
+    let mut expression = |expr: &mut ast::Expression| {
+        let replacement = match expr {
+            ast::Expression::Identifier(x) => types.get(x.as_str()).map(|x| return ast::Expression::Type(x.clone())),
+            ast::Expression::Call { callee, args } => match (types.get(callee.as_str()), args.as_mut_slice()) {
+                (Some(ty), [operand]) => Some(ast::Expression::Cast { ty: ty.clone(), operand: Box::new(std::mem::replace(operand, ast::Expression::Tuple(vec![]))) }),
+                (Some(ty), _) => return Err(format!("a cast to {} takes a single value", ast::type_name(ty)).into()),
+                (None, _) => None
+            },
+            ast::Expression::GenericCall { types: list, .. } => {
+                list.iter_mut().for_each(|x| return substitute_type(x, types));
+                None
+            },
+            ast::Expression::CallIndirect { signature, .. } | ast::Expression::Offset { signature: Some(signature), .. } => {
+                substitute_type(signature, types);
+                None
+            },
+            _ => None
+        };
+
+        if let Some(x) = replacement {
+            *expr = x;
+        }
+
+        return Ok(());
+    };
+
+    for param in function.signature.params.iter_mut() {
+        substitute_type(&mut param.ty, types);
+
+        if let Some(x) = &mut param.default {
+            for_each_subexpression(x, &mut expression)?;
+        }
+    }
+
+    if let Some(x) = &mut function.signature.result {
+        substitute_type(x, types);
+    }
+
+    return for_each_expression(&mut function.body, &mut expression);
+}
+
+fn substitute_type(ty: &mut ast::TypeExpression, types: &HashMap<String, token::Type>) {
+    match ty {
+        ast::TypeExpression::Named(x) => if let Some(x) = types.get(x.as_str()) {
+            *ty = ast::TypeExpression::Primitive(x.clone());
+        },
+        ast::TypeExpression::Tuple(list) => list.iter_mut().for_each(|x| return substitute_type(x, types)),
+        ast::TypeExpression::Function(_, Some(result)) => substitute_type(result, types),
+        _ => {}
+    }
+}
+
+type Rewrite<'a> = dyn FnMut(&mut ast::Expression) -> Result<(), Box<dyn Error>> + 'a;
+
+// calls `f` on every expression in the block, inner ones before outer ones
+fn for_each_expression(block: &mut ast::Block, f: &mut Rewrite) -> Result<(), Box<dyn Error>> {
+    for statement in block.statements.iter_mut() {
+        match &mut statement.node {
+            ast::Statement::Variable(x) => for_each_subexpression(&mut x.value, f)?,
+            ast::Statement::Expression(x) | ast::Statement::Return(Some(x)) => for_each_subexpression(x, f)?,
+            ast::Statement::Assignment(target, value) => {
+                for_each_subexpression(target, f)?;
+                for_each_subexpression(value, f)?;
+            },
+            ast::Statement::If(x) => {
+                for_each_subexpression(&mut x.condition, f)?;
+                for_each_expression(&mut x.block, f)?;
+
+                for (condition, block) in x.else_ifs.iter_mut() {
+                    for_each_subexpression(condition, f)?;
+                    for_each_expression(block, f)?;
+                }
+
+                if let Some(block) = &mut x.else_block {
+                    for_each_expression(block, f)?;
+                }
+            },
+            ast::Statement::While(x) => {
+                for_each_subexpression(&mut x.condition, f)?;
+                for_each_expression(&mut x.block, f)?;
+            },
+            ast::Statement::Repeat(x) => for_each_expression(&mut x.block, f)?,
+            ast::Statement::Defer(x) | ast::Statement::Block(x) => for_each_expression(x, f)?,
+            ast::Statement::StaticAssert(x) => for_each_subexpression(&mut x.condition, f)?,
+            ast::Statement::Function(x) => for_each_expression(&mut x.body, f)?,
+            ast::Statement::Return(None) | ast::Statement::Break | ast::Statement::Continue => {}
+        }
+    }
+
+    if let Some(x) = &mut block.tail {
+        for_each_subexpression(x, f)?;
+    }
+
+    return Ok(());
+}
+
+fn for_each_subexpression(expr: &mut ast::Expression, f: &mut Rewrite) -> Result<(), Box<dyn Error>> {
+    match expr {
+        ast::Expression::Call { args, .. }
+        | ast::Expression::GenericCall { args, .. }
+        | ast::Expression::Variadic { args, .. }
+        | ast::Expression::Tuple(args) => {
+            for x in args.iter_mut() {
+                for_each_subexpression(x, f)?;
+            }
+        },
+        ast::Expression::CallIndirect { target, args, .. } => {
+            for_each_subexpression(target, f)?;
+
+            for x in args.iter_mut() {
+                for_each_subexpression(x, f)?;
+            }
+        },
+        ast::Expression::Unary { operand, .. } | ast::Expression::Cast { operand, .. } => for_each_subexpression(operand, f)?,
+        ast::Expression::Binary { lhs, rhs, .. } => {
+            for_each_subexpression(lhs, f)?;
+            for_each_subexpression(rhs, f)?;
+        },
+        ast::Expression::Conditional { condition, consequent, alternative } => {
+            for_each_subexpression(condition, f)?;
+            for_each_subexpression(consequent, f)?;
+            for_each_subexpression(alternative, f)?;
+        },
+        ast::Expression::Offset { offset, .. } => for_each_subexpression(offset, f)?,
+        ast::Expression::Literal(_) | ast::Expression::Identifier(_) | ast::Expression::TypeOf(_) | ast::Expression::Type(_) => {}
+    }
+
+    return f(expr);
+}
+
+// a nested function may only use locals of its own
+fn check_captures(function: &ast::FunctionDeclaration, enclosing: &str, locals: &HashSet<String>) -> Result<(), Box<dyn Error>> {
+    let mut names = Names::default();
+
+    names.visit_block(&function.body);
+
+    let captured = names.used.iter().find(|x| {
+        return locals.contains(*x)
+            && !names.bound.contains(*x)
+            && !function.signature.params.iter().any(|param| return &&param.name == x);
+    });
+
+    return match captured {
+        Some(x) => Err(format!("function `{}` cannot capture `{}` from the enclosing function `{}`; pass it as a parameter instead", function.name, x, enclosing).into()),
+        None => Ok(())
+    };
+}
+
+// names bound and used in a function body, leaving out nested functions
+#[derive(Default)]
+struct Names {
+    bound: HashSet<String>,
+    used: Vec<String>
+}
+
+impl Visitor for Names {
+    fn visit_fn(&mut self, _: &ast::FunctionDeclaration) {}
+
+    fn visit_variable(&mut self, variable: &ast::VariableDeclaration) {
+        match &variable.pattern {
+            ast::Pattern::Single(x) => { self.bound.insert(x.name.clone()); },
+            ast::Pattern::Tuple(list) => self.bound.extend(list.iter().map(|x| return x.name.clone()))
+        }
+
+        visitor::walk_variable(self, variable);
+    }
+
+    fn visit_expr(&mut self, expr: &ast::Expression) {
+        if let ast::Expression::Identifier(x) | ast::Expression::TypeOf(x) = expr {
+            self.used.push(x.clone());
+        }
+
+        visitor::walk_expr(self, expr);
+    }
+}
+
+fn rename_calls(expr: &mut ast::Expression, names: &HashMap<String, String>) {
+    match expr {
+        ast::Expression::Call { callee, args } | ast::Expression::GenericCall { callee, args, .. } => {
+            if let Some(x) = names.get(callee.as_str()) {
+                *callee = x.clone();
+            }
+
+            args.iter_mut().for_each(|x| return rename_calls(x, names));
+        },
+        ast::Expression::CallIndirect { target, args, .. } => {
+            rename_calls(target, names);
+            args.iter_mut().for_each(|x| return rename_calls(x, names));
+        },
+        ast::Expression::Unary { operand, .. } | ast::Expression::Cast { operand, .. } => rename_calls(operand, names),
+        ast::Expression::Binary { lhs, rhs, .. } => {
+            rename_calls(lhs, names);
+            rename_calls(rhs, names);
+        },
+        ast::Expression::Conditional { condition, consequent, alternative } => {
+            rename_calls(condition, names);
+            rename_calls(consequent, names);
+            rename_calls(alternative, names);
+        },
+        ast::Expression::Tuple(list) => list.iter_mut().for_each(|x| return rename_calls(x, names)),
+        ast::Expression::Offset { offset, .. } => rename_calls(offset, names),
+        _ => {}
+    }
+}
+
+fn signature_of(decl: &ast::Declaration) -> Option<(&str, &ast::Signature)> {
+    return match decl {
+        ast::Declaration::Function(x) => Some((&x.name, &x.signature)),
+        ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Function(x), .. }) => Some((&x.name, &x.signature)),
+        ast::Declaration::Import(ast::ImportDeclaration { item: ast::ImportItem::Function(name, signature), .. }) => Some((name, signature)),
+        _ => None
+    };
+}
+
+// defaults must be constant, and only trailing parameters may have one
+fn validate_defaults(name: &str, signature: &ast::Signature) -> Result<(), Box<dyn Error>> {
+    let mut is_defaulted = false;
+
+    for param in signature.params.iter() {
+        match &param.default {
+            Some(default) if !default.is_constant() => {
+                return Err(format!("default value of parameter `{}` in function `{}` must be a constant expression", param.name, name).into());
+            },
+            Some(_) => is_defaulted = true,
+            None if is_defaulted => {
+                return Err(format!("parameter `{}` in function `{}` must have a default value, since it follows a defaulted parameter", param.name, name).into());
+            },
+            None => {}
+        }
+    }
+
+    return Ok(());
+}
+
+// only an import can be variadic, since the host is the one reading the extra
+// arguments, and the spread is its last parameter, of a single number type
+fn validate_variadic(name: &str, signature: &ast::Signature, is_imported: bool) -> Result<(), Box<dyn Error>> {
+    for (index, param) in signature.params.iter().enumerate().filter(|(_, x)| return x.is_variadic) {
+        if !is_imported {
+            return Err(format!("parameter `{}` of function `{}` cannot be variadic; only imported functions take extra arguments", param.name, name).into());
+        }
+
+        if index + 1 != signature.params.len() {
+            return Err(format!("variadic parameter `{}` of function `{}` must be the last parameter", param.name, name).into());
+        }
+
+        if param.default.is_some() || signature.params.iter().any(|x| return x.default.is_some()) {
+            return Err(format!("function `{}` cannot have both default values and the variadic parameter `{}`", name, param.name).into());
+        }
+
+        if !matches!(param.ty, ast::TypeExpression::Primitive(token::Type::I32 | token::Type::I64 | token::Type::U32 | token::Type::U64 | token::Type::F32 | token::Type::F64)) {
+            return Err(format!("variadic parameter `{}` of function `{}` must be a number type, found `{}`", param.name, name, param.ty).into());
+        }
+    }
+
+    return Ok(());
+}
+
+fn lower_block(block: &mut ast::Block, signatures: &HashMap<String, ast::Signature>, constants: &Constants) -> Result<(), Box<dyn Error>> {
+    for statement in block.statements.iter_mut() {
+        match &mut statement.node {
+            ast::Statement::Variable(x) => lower_expression(&mut x.value, signatures, constants)?,
+            ast::Statement::Expression(x) => lower_expression(x, signatures, constants)?,
+            ast::Statement::Assignment(target, value) => {
+                lower_expression(target, signatures, constants)?;
+                lower_expression(value, signatures, constants)?;
+            },
+            ast::Statement::If(x) => {
+                lower_expression(&mut x.condition, signatures, constants)?;
+                lower_block(&mut x.block, signatures, constants)?;
+
+                for (condition, block) in x.else_ifs.iter_mut() {
+                    lower_expression(condition, signatures, constants)?;
+                    lower_block(block, signatures, constants)?;
+                }
+
+                if let Some(block) = &mut x.else_block {
+                    lower_block(block, signatures, constants)?;
+                }
+            },
+            ast::Statement::While(x) => {
+                lower_expression(&mut x.condition, signatures, constants)?;
+                lower_block(&mut x.block, signatures, constants)?;
+            },
+            ast::Statement::Repeat(x) => lower_block(&mut x.block, signatures, constants)?,
+            ast::Statement::Defer(x) => lower_block(x, signatures, constants)?,
+            ast::Statement::Return(Some(x)) => lower_expression(x, signatures, constants)?,
+            ast::Statement::StaticAssert(x) => lower_expression(&mut x.condition, signatures, constants)?,
+            ast::Statement::Block(x) => lower_block(x, signatures, constants)?,
+            _ => {}
+        }
+    }
+
+    if let Some(x) = &mut block.tail {
+        lower_expression(x, signatures, constants)?;
+    }
+
+    return Ok(());
+}
+
+fn lower_expression(expr: &mut ast::Expression, signatures: &HashMap<String, ast::Signature>, constants: &Constants) -> Result<(), Box<dyn Error>> {
+    if let Some(x) = fold_builtin(expr, signatures, constants)? {
+        *expr = x;
+
+        return Ok(());
+    }
+
+    match expr {
+        ast::Expression::Call { callee, args } => {
+            for arg in args.iter_mut() {
+                lower_expression(arg, signatures, constants)?;
+            }
+
+            if let Some(signature) = signatures.get(callee.as_str()) {
+                fill_defaults(args, signature);
+                pack_variadic(args, signature);
+            }
+        },
+        ast::Expression::CallIndirect { target, args, .. } => {
+            lower_expression(target, signatures, constants)?;
+
+            for arg in args.iter_mut() {
+                lower_expression(arg, signatures, constants)?;
+            }
+        },
+        ast::Expression::Unary { operand, .. } | ast::Expression::Cast { operand, .. } => lower_expression(operand, signatures, constants)?,
+        ast::Expression::Binary { lhs, rhs, .. } => {
+            lower_expression(lhs, signatures, constants)?;
+            lower_expression(rhs, signatures, constants)?;
+        },
+        ast::Expression::Conditional { condition, consequent, alternative } => {
+            lower_expression(condition, signatures, constants)?;
+            lower_expression(consequent, signatures, constants)?;
+            lower_expression(alternative, signatures, constants)?;
+        },
+        ast::Expression::Tuple(list) => {
+            for x in list.iter_mut() {
+                lower_expression(x, signatures, constants)?;
+            }
+        },
+        ast::Expression::Offset { offset, .. } => lower_expression(offset, signatures, constants)?,
+        _ => {}
+    }
+
+    return Ok(());
+}
+
+// `size_of(T)`, `align_of(T)` and the built-in constants are known at compile
+// time, so they become literals before type checking
+fn fold_builtin(expr: &ast::Expression, signatures: &HashMap<String, ast::Signature>, constants: &Constants) -> Result<Option<ast::Expression>, Box<dyn Error>> {
+    return match expr {
+        ast::Expression::Identifier(x) => Ok(constants.get(x.as_str()).map(|x| return ast::Expression::Literal((*x).to_string()))),
+        ast::Expression::Call { callee, args } if (callee == "size_of" || callee == "align_of") && !signatures.contains_key(callee.as_str()) => match args.as_slice() {
+            [ast::Expression::Type(ty)] => Ok(Some(ast::Expression::Literal(byte_size(callee, ty)?.to_string()))),
+            _ => Err(format!("`{}` takes a single type, as in `{}(i64)`", callee, callee).into())
+        },
+        ast::Expression::Type(ty) => Err(format!("type {} is not a value; only `size_of` and `align_of` take a type", ast::type_name(ty)).into()),
+        _ => Ok(None)
+    };
+}
+
+// wasm numbers are naturally aligned to their size, references and page
+// counts have no layout in memory
+fn byte_size(callee: &str, ty: &token::Type) -> Result<u32, Box<dyn Error>> {
+    return match ty {
+        token::Type::I32 | token::Type::U32 | token::Type::F32 => Ok(4),
+        token::Type::I64 | token::Type::U64 | token::Type::F64 => Ok(8),
+        _ => Err(format!("`{}` is only defined for number types, found {}", callee, ast::type_name(ty)).into())
+    };
+}
+
+// wasm has no default arguments, so omitted trailing arguments are filled in,
+// arity mismatches are left for the type checker to report
+fn fill_defaults(args: &mut Vec<ast::Expression>, signature: &ast::Signature) {
+    let omitted = signature.params.iter().skip(args.len());
+
+    if let Some(defaults) = omitted.map(|x| return x.default.clone()).collect::<Option<Vec<_>>>() {
+        args.extend(defaults);
+    }
+}
+
+// the arguments past the fixed parameters become one `Variadic` argument,
+// which is there even when there are none
+fn pack_variadic(args: &mut Vec<ast::Expression>, signature: &ast::Signature) {
+    let fixed = signature.params.len().saturating_sub(1);
+
+    if let Some(ast::Param { ty: ast::TypeExpression::Primitive(ty), is_variadic: true, .. }) = signature.params.last() {
+        if args.len() >= fixed {
+            let extra = args.split_off(fixed);
+
+            args.push(ast::Expression::Variadic { ty: ty.clone(), args: extra });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::tokenizer;
+
+    fn lower_source(text: &str) -> Result<ast::Program, Box<dyn Error>> {
+        let tokens = tokenizer::tokenize(text)?;
+        let mut program = parser::parse_syntax(&tokens, false)?;
+
+        lower(&mut program)?;
+
+        return Ok(program);
+    }
+
+    fn first_call_args(program: &ast::Program) -> Vec<ast::Expression> {
+        for decl in program.declarations.iter() {
+            if let ast::Declaration::Function(x) = decl {
+                if let Some(ast::Statement::Expression(ast::Expression::Call { args, .. })) = x.body.statements.first().map(|x| return &x.node) {
+                    return args.clone();
+                }
+            }
+        }
+
+        panic!("no call found");
+    }
+
+    #[test]
+    fn fill_omitted_default_argument() {
+        let program = lower_source("fn f(a: i32, b: i32 = 7) {} fn g() { f(1); }").unwrap();
+
+        assert_eq!(first_call_args(&program), vec![
+            ast::Expression::Literal("1".to_string()),
+            ast::Expression::Literal("7".to_string())
+        ]);
+    }
+
+    #[test]
+    fn keep_given_argument() {
+        let program = lower_source("fn f(a: i32, b: i32 = 7) {} fn g() { f(1, 2); }").unwrap();
+
+        assert_eq!(first_call_args(&program), vec![
+            ast::Expression::Literal("1".to_string()),
+            ast::Expression::Literal("2".to_string())
+        ]);
+    }
+
+    #[test]
+    fn keep_call_missing_required_argument() {
+        let program = lower_source("fn f(a: i32, b: i32 = 7) {} fn g() { f(); }").unwrap();
+
+        assert_eq!(first_call_args(&program), vec![]);
+    }
+
+    fn function_names(program: &ast::Program) -> Vec<&str> {
+        return program.declarations.iter().filter_map(|x| return match x {
+            ast::Declaration::Function(x) => Some(x.name.as_str()),
+            _ => None
+        }).collect();
+    }
+
+    #[test]
+    fn lift_nested_function() {
+        let program = lower_source("let base <- 1;\nfn f(a: i32) -> i32 { let b <- twice(a); fn twice(x: i32) -> i32 { x * 2 + base } b }").unwrap();
+
+        assert_eq!(function_names(&program), vec!["f", "f$twice"]);
+        assert!(matches!(&program.declarations[1], ast::Declaration::Function(x) if matches!(
+            &x.body.statements[0].node,
+            ast::Statement::Variable(ast::VariableDeclaration { value: ast::Expression::Call { callee, .. }, .. }) if callee == "f$twice"
+        )));
+    }
+
+    #[test]
+    fn mangle_nested_functions_apart() {
+        let program = lower_source("fn f$g() {}\nfn f() { if (1) { fn g() {} g(); } else { fn g() { fn h() {} h(); } g(); } }").unwrap();
+
+        assert_eq!(function_names(&program), vec!["f$g", "f", "f$g$1", "f$g$2$h", "f$g$2"]);
+    }
+
+    #[test]
+    fn reject_captured_local() {
+        let message = |text| return lower_source(text).unwrap_err().to_string();
+
+        assert_eq!(
+            message("fn f(a: i32) -> i32 { fn g() -> i32 { a + 1 } g() }"),
+            "function `g` cannot capture `a` from the enclosing function `f`; pass it as a parameter instead"
+        );
+        assert!(message("fn f() { let n <- 1; fn g() { fn h() { n <- 2; } } }").contains("function `h` cannot capture `n`"));
+        assert!(lower_source("fn f() { let n <- 1; fn g(n: i32) -> i32 { let m <- n; m } }").is_ok());
+    }
+
+    #[test]
+    fn reject_non_trailing_default() {
+        let err = lower_source("fn f(a: i32 = 0, b: i32) {}").unwrap_err();
+
+        assert!(err.to_string().contains("must have a default value"));
+    }
+
+    #[test]
+    fn reject_non_constant_default() {
+        let err = lower_source("fn f(a: i32, b: i32 = a) {}").unwrap_err();
+
+        assert!(err.to_string().contains("must be a constant expression"));
+    }
+
+    #[test]
+    fn pack_extra_arguments() {
+        let program = lower_source("imp fn log(fmt: i32, ...args: i32) from \"env\"; fn g() { log(1, 2, 3); }").unwrap();
+
+        assert_eq!(first_call_args(&program), vec![
+            ast::Expression::Literal("1".to_string()),
+            ast::Expression::Variadic { ty: token::Type::I32, args: vec![
+                ast::Expression::Literal("2".to_string()),
+                ast::Expression::Literal("3".to_string())
+            ] }
+        ]);
+    }
+
+    #[test]
+    fn pack_no_extra_arguments() {
+        let program = lower_source("imp fn log(fmt: i32, ...args: i32) from \"env\"; fn g() { log(1); }").unwrap();
+
+        assert_eq!(first_call_args(&program), vec![
+            ast::Expression::Literal("1".to_string()),
+            ast::Expression::Variadic { ty: token::Type::I32, args: vec![] }
+        ]);
+    }
+
+    #[test]
+    fn reject_misplaced_variadic() {
+        assert!(lower_source("fn f(...args: i32) {}").err().unwrap().to_string().contains("only imported functions"));
+        assert!(lower_source("imp fn log(...args: i32, fmt: i32) from \"env\";").err().unwrap().to_string().contains("must be the last parameter"));
+    }
+    fn tail(program: &ast::Program) -> Option<&ast::Expression> {
+        return match program.declarations.last() {
+            Some(ast::Declaration::Function(x)) => x.body.tail.as_deref(),
+            _ => None
+        };
+    }
+
+    #[test]
+    fn fold_page_size() {
+        let program = lower_source("fn f() -> i32 { PAGE_SIZE }").unwrap();
+
+        assert_eq!(tail(&program), Some(&ast::Expression::Literal("65536".to_string())));
+    }
+
+    #[test]
+    fn keep_shadowed_page_size() {
+        let program = lower_source("fn f(PAGE_SIZE: i32) -> i32 { PAGE_SIZE }").unwrap();
+
+        assert_eq!(tail(&program), Some(&ast::Expression::Identifier("PAGE_SIZE".to_string())));
+    }
+
+    #[test]
+    fn fold_size_and_alignment() {
+        let program = lower_source("fn f() -> i32 { size_of(i64) }").unwrap();
+
+        assert_eq!(tail(&program), Some(&ast::Expression::Literal("8".to_string())));
+
+        let program = lower_source("fn f() -> i32 { align_of(f32) * 2 }").unwrap();
+
+        assert!(matches!(tail(&program), Some(ast::Expression::Binary { lhs, .. }) if **lhs == ast::Expression::Literal("4".to_string())));
+    }
+
+    #[test]
+    fn reject_type_as_value() {
+        assert!(lower_source("fn f() -> i32 { size_of(xref) }").err().unwrap().to_string().contains("`size_of` is only defined for number types, found xref"));
+        assert!(lower_source("fn f() -> i32 { size_of(1) }").err().unwrap().to_string().contains("`size_of` takes a single type"));
+        assert!(lower_source("fn f() -> i32 { i64 }").err().unwrap().to_string().contains("type i64 is not a value"));
+    }
+    #[test]
+    fn instantiate_generic_function() {
+        let program = lower_source("fn max<T>(a: T, b: T) -> T where T: i32 | i64 { if (a > b) { ret a; } b }\nfn f() -> i64 { max::<i32>(1, 2); max::<i64>(3, 4); max::<i32>(5, 6) }").unwrap();
+
+        assert_eq!(function_names(&program), vec!["f", "max$i32", "max$i64"]);
+        assert!(matches!(&program.declarations[2], ast::Declaration::Function(x) if x.signature.params.iter().all(|x| {
+            return x.ty == ast::TypeExpression::Primitive(token::Type::I64);
+        })));
+        assert_eq!(first_call_args(&program), vec![ast::Expression::Literal("1".to_string()), ast::Expression::Literal("2".to_string())]);
+        assert!(matches!(&program.declarations[0], ast::Declaration::Function(x) if matches!(
+            x.body.tail.as_deref(),
+            Some(ast::Expression::Call { callee, .. }) if callee == "max$i32"
+        )));
+    }
+
+    #[test]
+    fn substitute_type_parameters() {
+        let program = lower_source("fn bytes<T>(x: T) -> i64 { i64(size_of(T)) + T(x) }\nfn f() -> i64 { bytes::<i64>(1) }").unwrap();
+
+        assert!(matches!(&program.declarations[1], ast::Declaration::Function(x) if matches!(
+            x.body.tail.as_deref(),
+            Some(ast::Expression::Binary { lhs, rhs, .. })
+                if matches!(lhs.as_ref(), ast::Expression::Cast { operand, .. } if **operand == ast::Expression::Literal("8".to_string()))
+                && matches!(rhs.as_ref(), ast::Expression::Cast { ty: token::Type::I64, .. })
+        )));
+    }
+
+    #[test]
+    fn reject_invalid_generics() {
+        let message = |text| return lower_source(text).err().unwrap().to_string();
+
+        assert!(message("fn f<T, U>(a: T) -> T { a }").contains("type parameter `U` of function `f` is not used"));
+        assert!(message("fn f<T>(a: T) -> T where T: i32, T: f64 { a }").contains("no type satisfies all the constraints on `T`"));
+        assert!(message("fn f<T>(a: T) -> T where T: i32 { a } fn g() -> f64 { f::<f64>(1.0) }").contains("type f64 does not satisfy the constraint `T: i32`"));
+        assert!(message("fn f<T>(a: T) -> T { a } fn g() -> i32 { f(1) }").contains("needs its type arguments, as in `f::<i32>(..)`"));
+    }
+}
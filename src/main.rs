@@ -1,6 +1,8 @@
 #![warn(
     clippy::if_not_else
 )]
+// every function returns explicitly, as `implicit_return` requires
+#![allow(clippy::needless_return)]
 #![deny(
     clippy::as_conversions,
     clippy::default_trait_access,
@@ -16,41 +18,325 @@
     clippy::implicit_return
 )]
 
+use std::collections::VecDeque;
 use std::error::Error;
+use std::path::Path;
 use std::time::Instant;
 use structopt::StructOpt;
 
+mod analysis;
+mod ast;
+mod cfg;
+mod checker;
 mod definition;
+mod diagnostic;
+mod doc;
+mod dot;
+mod feature;
+mod formatter;
+mod importer;
 mod io;
+mod ir;
+mod lowering;
 mod optimizer;
 mod parser;
+mod parse_tree;
+mod preprocessor;
+mod resolver;
+mod span;
 mod grammar;
 mod token;
 mod token_grammar;
 mod token_stream;
 mod tokenizer;
 mod transpiler;
+#[cfg(test)]
+mod test_util;
+mod validator;
+mod visitor;
 mod cli;
 
-fn main() -> Result<(), Box<dyn Error>> {
+const COMPILER_STACK_SIZE: usize = 512 * 1024 * 1024;
+
+fn main() {
+    // parse cli options, help and version are printed to stdout and succeed
+    let opt = match cli::Opt::from_args_safe() {
+        Ok(x) => x,
+        Err(err) if err.use_stderr() => {
+            eprintln!("{}", err.message);
+
+            std::process::exit(diagnostic::EXIT_USAGE_ERROR);
+        },
+        Err(err) => err.exit()
+    };
+
+    // the passes recurse over the tree, and an operator chain of any length is
+    // as deep as it is long, so the compiler gets far more stack than the
+    // main thread has. only the pages it touches are committed
+    let compiler = std::thread::Builder::new().stack_size(COMPILER_STACK_SIZE).spawn(move || {
+        if let Err(err) = run(&opt) {
+            eprintln!("{}", paint(&opt, diagnostic::render(err.as_ref())));
+
+            std::process::exit(diagnostic::exit_code(err.as_ref()));
+        }
+    });
+
+    if let Err(panic) = compiler.expect("cannot start the compiler thread").join() {
+        std::panic::resume_unwind(panic);
+    }
+}
+
+// diagnostics are colored as `--color` asks
+fn paint(opt: &cli::Opt, report: diagnostic::Report) -> String {
+    if opt.color() {
+        return report.paint();
+    }
+
+    return report.to_string();
+}
+
+fn run(opt: &cli::Opt) -> Result<(), Box<dyn Error>> {
     let now = Instant::now();
 
-    // parse cli options
-    let opt = cli::Opt::from_args();
+    if let Some(code) = opt.explain() {
+        print!("{}", diagnostic::explain(code)?);
+
+        return Ok(());
+    }
+
+    if opt.list_features() {
+        print!("{}", feature::listing());
+
+        return Ok(());
+    }
+
+    let features = feature::Features { overflow: opt.overflow(), ..feature::enable(opt.features())? };
+
+    if let Some(cli::Command::ExplainToken { text }) = opt.command() {
+        print!("{}", tokenizer::explain(text)?);
+
+        return Ok(());
+    }
+
+    if let Some(cli::Command::Doc { file, outfile }) = opt.command() {
+        let file_text = io::read_file(file)?;
+        let tokens = preprocessor::preprocess(tokenizer::tokenize_file_with_limit(file, &file_text, opt.max_token_length())?, opt.defines())?;
+        let ast = parser::parse_syntax_with_limit(&tokens, false, opt.recursion_limit()).map_err(|x| return diagnostic::locate(x, file, &file_text))?;
+        let title = Path::new(file).file_stem().map_or_else(|| return file.clone(), |x| return x.to_string_lossy().to_string());
+        let out = doc::markdown(&title, &ast, &file_text)?;
+
+        match outfile {
+            Some(path) => io::write_file(path, out.as_bytes())?,
+            None => print!("{}", out)
+        }
+
+        return Ok(());
+    }
+
+    if opt.format() {
+        if opt.files().len() > 1 {
+            return Err(diagnostic::UsageError("--format takes a single file".to_string()).into());
+        }
+
+        print!("{}", formatter::format(&io::read_file(opt.file())?)?);
+
+        return Ok(());
+    }
+
+    let emits = opt.emits();
+    let mut units: Vec<resolver::Unit> = vec![];
+    let mut dumps = String::new();
+    let mut graphs = String::new();
+    // (path, whether its exports are kept, whether it was reached by `incl`)
+    let mut queue = opt.files().iter().map(|x| return (x.clone(), true, false)).collect::<VecDeque<_>>();
+
+    while let Some((file, is_exported, is_included)) = queue.pop_front() {
+        let file = &file;
+
+        // an included file is loaded once, and exported when any path to it is
+        if is_included {
+            if let Some(unit) = units.iter_mut().find(|x| return io::same_file(&x.path, file)) {
+                if is_exported && !unit.is_exported {
+                    unit.is_exported = true;
+                    queue.extend(resolver::includes(&unit.program).into_iter().map(|(path, is_public)| return (io::relative_to(&unit.path, path), is_public, true)));
+                }
+
+                continue;
+            }
+        }
+
+        // read file
+        let file_text = io::read_file(file)?;
+
+        // tokenize
+        let tokens = tokenizer::tokenize_file_with_limit(file, &file_text, opt.max_token_length())?;
+
+        // preprocess
+        let tokens = preprocessor::preprocess(tokens, opt.defines())?;
+
+        if emits.contains(&cli::EmitKind::Tokens) {
+            if opt.files().len() > 1 {
+                dumps.push_str(&format!("// {}\n", file));
+            }
+
+            dumps.push_str(&tokenizer::dump(&file_text, &tokens));
+        }
+
+        // later stages are skipped when only tokens are asked for
+        if !emits.is_empty() && emits.iter().all(|x| return x == &cli::EmitKind::Tokens) {
+            continue;
+        }
+
+        // parse
+        let mut program = parser::parse_syntax_with_limit(&tokens, opt.trace(), opt.recursion_limit()).map_err(|x| return diagnostic::locate(x, file, &file_text))?;
+
+        // import
+        importer::expand(&mut program, file)?;
+
+        if emits.contains(&cli::EmitKind::DotAst) {
+            graphs.push_str(&dot::ast(file, &program));
+        }
+
+        queue.extend(resolver::includes(&program).into_iter().map(|(path, is_public)| return (io::relative_to(file, path), is_exported && is_public, true)));
+        units.push(resolver::Unit { path: file.clone(), text: file_text.clone(), program, is_exported });
+    }
+
+    if emits.contains(&cli::EmitKind::Tokens) {
+        io::write_file(&opt.output_path(&cli::EmitKind::Tokens).to_string_lossy(), dumps.as_bytes())?;
+    }
+
+    if emits.contains(&cli::EmitKind::DotAst) {
+        io::write_file(&opt.output_path(&cli::EmitKind::DotAst).to_string_lossy(), graphs.as_bytes())?;
+    }
+
+    if units.is_empty() {
+        return Ok(());
+    }
+
+    // link
+    let (mut ast, tables) = resolver::link(&units)?;
+
+    // lower
+    lowering::lower(&mut ast)?;
+
+    // check
+    let defaults = opt.defaults();
+
+    checker::default_literals(&mut ast, &defaults);
+    checker::infer_results(&mut ast, &defaults)?;
+    checker::check(&ast)?;
+    checker::sign_operators(&mut ast, &defaults)?;
+
+    // resolve
+    let symbols = resolver::resolve(&ast)?;
+
+    if opt.dump_symbols() {
+        for (unit, table) in units.iter().zip(tables.iter()) {
+            if units.len() > 1 {
+                println!("// {}", unit.path);
+            }
+
+            print!("{}", table.dump(&unit.text));
+        }
+
+        return Ok(());
+    }
+
+    // analyze
+    let mut warnings = vec![];
+    let mut denied = 0;
+
+    for (unit, table) in units.iter().zip(tables.iter()) {
+        for warning in analysis::lints(&unit.program, table, &symbols) {
+            match opt.lint_level(warning.lint()) {
+                analysis::Level::Allow => {},
+                analysis::Level::Warn => {
+                    eprintln!("{}", paint(opt, warning.render(&unit.path, &unit.text)));
+
+                    warnings.push(warning);
+                },
+                analysis::Level::Deny => {
+                    eprintln!("{}", paint(opt, warning.render_denied(&unit.path, &unit.text)));
+
+                    denied += 1;
+                }
+            }
+        }
+    }
+
+    if denied > 0 {
+        return Err(format!("{} lint(s) denied by --strict", denied).into());
+    }
+
+    if opt.deny_warnings() && !warnings.is_empty() {
+        return Err(format!("{} warning(s) denied by --deny-warnings", warnings.len()).into());
+    }
+
+    // optimize
+    let mut remarks = vec![];
+
+    optimizer::optimize(&mut ast, &symbols, &opt.opt_level(), &defaults, &mut remarks);
+
+    // transpile, only when the module is written or drawn
+    let module = if opt.dump_cfg() || emits.iter().any(cli::EmitKind::needs_module) {
+        let mut module = transpiler::Module::build_with_features(&ast, &defaults, &features)?;
+
+        optimizer::optimize_module(&mut module, &ast, &opt.opt_level(), &mut remarks);
+
+        Some(module)
+    }
+    else {
+        None
+    };
+
+    if opt.opt_remarks() {
+        for remark in remarks.iter() {
+            // the file declaring the function the remark is about
+            let unit = units.iter().zip(tables.iter())
+                .find(|(_, table)| return table.get(remark.function()).is_some())
+                .map_or(&units[0], |(unit, _)| return unit);
+
+            eprintln!("{}", paint(opt, remark.render(&unit.path, &unit.text)));
+        }
+    }
+
+    // the graph is drawn from the optimized and lowered functions
+    if let (true, Some(module)) = (opt.dump_cfg(), &module) {
+        print!("{}", module.to_cfg());
+
+        return Ok(());
+    }
+
+    if let Some(module) = &module {
+        if emits.contains(&cli::EmitKind::Wasm) {
+            let path = opt.output_path(&cli::EmitKind::Wasm).to_string_lossy().to_string();
+
+            // each section is validated as it is streamed, so only valid ones reach the file
+            if opt.validate() {
+                io::write_stream(&path, |w| {
+                    let mut w = validator::ValidatingWriter::new(w);
 
-    // read file
-    let file_text = io::read_file(opt.file())?;
+                    module.write_wasm(&mut w)?;
 
-    // tokenize
-    let tokens = tokenizer::tokenize(&file_text)?;
+                    return w.finish().map(drop);
+                })?;
+            } else {
+                io::write_stream(&path, |w| return module.write_wasm(w))?;
+            }
+        }
 
-    // parse
-    let ast = parser::parse_syntax(&tokens)?;
+        if emits.contains(&cli::EmitKind::Wat) {
+            io::write_file(&opt.output_path(&cli::EmitKind::Wat).to_string_lossy(), module.to_wat().as_bytes())?;
+        }
 
-    // write file
-    // io::write_file("out/sample.wasm")?;
+        if emits.contains(&cli::EmitKind::Ir) {
+            io::write_file(&opt.output_path(&cli::EmitKind::Ir).to_string_lossy(), module.to_ir().as_bytes())?;
+        }
 
-    // println!("{}", std::mem::size_of::<std::ops::Range<usize>>());
+        if emits.contains(&cli::EmitKind::Metadata) {
+            io::write_file(&opt.output_path(&cli::EmitKind::Metadata).to_string_lossy(), module.to_metadata(&opt.opt_level().to_string()).as_bytes())?;
+        }
+    }
 
     println!("Process time: {}ms", now.elapsed().as_millis());
 
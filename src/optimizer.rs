@@ -0,0 +1,1046 @@
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+use crate::ast;
+use crate::checker;
+use crate::cli;
+use crate::diagnostic;
+use crate::ir::{BinaryOp, Instruction, ValType};
+use crate::resolver;
+use crate::span::{Span, Spanned};
+use crate::token;
+use crate::transpiler;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pass {
+    ConstantFolding,
+    BranchElimination,
+    DeadFunctionElimination,
+    LoopUnrolling,
+    Peephole
+}
+
+impl Pass {
+    pub const ALL: [Pass; 5] = [Pass::ConstantFolding, Pass::BranchElimination, Pass::DeadFunctionElimination, Pass::LoopUnrolling, Pass::Peephole];
+
+    pub const fn min_level(&self) -> cli::OptLevel {
+        return match self {
+            Pass::ConstantFolding => cli::OptLevel::O1,
+            Pass::BranchElimination => cli::OptLevel::O1,
+            Pass::DeadFunctionElimination => cli::OptLevel::O2,
+            Pass::LoopUnrolling => cli::OptLevel::O2,
+            Pass::Peephole => cli::OptLevel::O1
+        };
+    }
+
+    pub const fn name(&self) -> &'static str {
+        return match self {
+            Pass::ConstantFolding => "constant-folding",
+            Pass::BranchElimination => "branch-elimination",
+            Pass::DeadFunctionElimination => "dead-function-elimination",
+            Pass::LoopUnrolling => "loop-unrolling",
+            Pass::Peephole => "peephole"
+        };
+    }
+}
+
+// a transformation made by a pass, for `--opt-remarks`. expressions carry no
+// spans, so a remark points at the statement or function it happened in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Remark {
+    pass: Pass,
+    function: String,
+    message: String,
+    span: Span
+}
+
+impl Remark {
+    // the function the remark is about, which tells the file it is in
+    pub fn function(&self) -> &str {
+        return &self.function;
+    }
+
+    // `path:ln:col: remark: message [pass]`
+    pub fn render(&self, path: &str, text: &str) -> diagnostic::Report {
+        let message = format!("{} [{}]", self.message, self.pass.name());
+
+        return diagnostic::Report::new(diagnostic::Severity::Remark, message).with_span(self.span.clone()).locate(path, text);
+    }
+}
+
+#[cfg(test)]
+impl Remark {
+    pub const fn pass(&self) -> &Pass {
+        return &self.pass;
+    }
+
+    pub fn message(&self) -> &str {
+        return &self.message;
+    }
+
+    pub const fn span(&self) -> &Span {
+        return &self.span;
+    }
+}
+
+// main program section
+// every pass reports what it did to `remarks`
+pub fn optimize(program: &mut ast::Program, symbols: &resolver::SymbolTable, level: &cli::OptLevel, defaults: &checker::Defaults, remarks: &mut Vec<Remark>) {
+    for pass in Pass::ALL.iter().filter(|x| return level.enables(x)) {
+        match pass {
+            Pass::ConstantFolding => fold_constants(program, defaults, remarks),
+            Pass::BranchElimination => eliminate_branches(program, remarks),
+            Pass::DeadFunctionElimination => eliminate_dead_functions(program, symbols, remarks),
+            Pass::LoopUnrolling => unroll_loops(program, remarks),
+            // runs on the instructions, see `optimize_module`
+            Pass::Peephole => {}
+        }
+    }
+}
+
+// the passes over the built module, `program` is the optimized ast it was
+// built from, which tells where its functions are
+pub fn optimize_module(module: &mut transpiler::Module, program: &ast::Program, level: &cli::OptLevel, remarks: &mut Vec<Remark>) {
+    if level.enables(&Pass::Peephole) {
+        simplify_instructions(module, program, remarks);
+    }
+}
+
+// removes the algebraic identities left after constant folding, as `x + 0`,
+// `x * 1` or `~~x`. only integer operations are simplified, since a float
+// `x + 0.0` turns `-0.0` into `0.0` and `x * 1.0` may change a NaN
+pub fn simplify_instructions(module: &mut transpiler::Module, program: &ast::Program, remarks: &mut Vec<Remark>) {
+    for function in module.functions_mut().iter_mut() {
+        let mut body = Vec::with_capacity(function.body.len());
+        let mut count = 0;
+
+        for instruction in function.body.drain(..) {
+            body.push(instruction);
+
+            // a simplification may expose another, as in `x + 0 + 0`
+            while simplify_tail(&mut body) {
+                count += 1;
+            }
+        }
+
+        function.body = body;
+
+        if count > 0 {
+            let span = program.declarations.iter()
+                .find_map(|x| return match x {
+                    ast::Declaration::Function(x) if x.name == function.name => Some(x.span.clone()),
+                    ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Function(x), .. }) if x.name == function.name => Some(x.span.clone()),
+                    _ => None
+                })
+                .unwrap_or(0..0);
+
+            remarks.push(Remark {
+                pass: Pass::Peephole,
+                function: function.name.clone(),
+                message: format!("simplified {} algebraic identities", count),
+                span
+            });
+        }
+    }
+}
+
+// rewrites the last instructions of `body` when they form an identity. the
+// operand an instruction pops is whatever comes before it, so the patterns
+// only need the instructions pushing the constants
+fn simplify_tail(body: &mut Vec<Instruction>) -> bool {
+    let n = body.len();
+
+    let (removed, added) = match &body[n.saturating_sub(4)..] {
+        // ~~x
+        [.., a, Instruction::Binary(_, BinaryOp::Xor), b, Instruction::Binary(_, BinaryOp::Xor)] if is_integer(a, -1) && is_integer(b, -1) => (4, vec![]),
+        // x & x, x | x
+        [.., Instruction::LocalGet(a), Instruction::LocalGet(b), Instruction::Binary(ValType::I32 | ValType::I64, BinaryOp::And | BinaryOp::Or)] if a == b => (2, vec![]),
+        [.., x, Instruction::Binary(_, op)] if is_identity(x, *op) => (2, vec![]),
+        // x * 0, x & 0, keeping the effects of x
+        [.., x, Instruction::Binary(_, BinaryOp::Mul | BinaryOp::And)] if is_integer(x, 0) => (2, vec![Instruction::Drop, x.clone()]),
+        // a value dropped right after it is pushed
+        [.., Instruction::LocalGet(_) | Instruction::GlobalGet(_) | Instruction::I32Const(_) | Instruction::I64Const(_), Instruction::Drop] => (2, vec![]),
+        // --x, exact for floats as it only flips the sign
+        [.., Instruction::Neg(a), Instruction::Neg(b)] if a == b => (2, vec![]),
+        _ => return false
+    };
+
+    body.truncate(n - removed);
+
+    for instruction in added {
+        body.push(instruction);
+
+        while simplify_tail(body) {}
+    }
+
+    return true;
+}
+
+// the integer constant on the right of `op` that leaves its left unchanged
+fn is_identity(x: &Instruction, op: BinaryOp) -> bool {
+    return match op {
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Or | BinaryOp::Xor | BinaryOp::Shl | BinaryOp::ShrS | BinaryOp::ShrU => is_integer(x, 0),
+        BinaryOp::Mul | BinaryOp::Div | BinaryOp::DivU => is_integer(x, 1),
+        BinaryOp::And => is_integer(x, -1),
+        _ => false
+    };
+}
+
+fn is_integer(x: &Instruction, value: i64) -> bool {
+    return match x {
+        Instruction::I32Const(x) => i64::from(*x) == value,
+        Instruction::I64Const(x) => *x == value,
+        _ => false
+    };
+}
+
+// functions that are neither public nor exported, and never reached from one
+// that is, are internal to the module and can be dropped
+pub fn eliminate_dead_functions(program: &mut ast::Program, symbols: &resolver::SymbolTable, remarks: &mut Vec<Remark>) {
+    let mut queue = symbols.symbols().iter()
+        .filter(|x| return *x.kind() == resolver::SymbolKind::Function)
+        .filter(|x| return x.is_public() || x.is_exported())
+        .map(|x| return x.name())
+        .collect::<VecDeque<_>>();
+    let mut reachable = queue.iter().copied().collect::<HashSet<_>>();
+
+    while let Some(name) = queue.pop_front() {
+        for reference in symbols.references(name).iter() {
+            if reachable.insert(reference.as_str()) {
+                queue.push_back(reference);
+            }
+        }
+    }
+
+    program.declarations.retain(|decl| {
+        return match decl {
+            ast::Declaration::Function(x) if !reachable.contains(x.name.as_str()) => {
+                remarks.push(Remark {
+                    pass: Pass::DeadFunctionElimination,
+                    function: x.name.clone(),
+                    message: format!("removed unused function `{}`", x.name),
+                    span: x.span.clone()
+                });
+
+                false
+            },
+            _ => true
+        };
+    });
+}
+
+// drops the arms of an `if` whose condition is a literal zero, and ends the
+// chain at the first arm whose condition is a literal other than zero. an `if`
+// left with a single taken arm becomes that block, keeping its scope
+pub fn eliminate_branches(program: &mut ast::Program, remarks: &mut Vec<Remark>) {
+    for decl in program.declarations.iter_mut() {
+        let function = match decl {
+            ast::Declaration::Function(x) => x,
+            ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Function(x), .. }) => x,
+            _ => continue
+        };
+
+        eliminate_block(&mut function.body, &function.name, remarks);
+    }
+}
+
+fn eliminate_block(block: &mut ast::Block, function: &str, remarks: &mut Vec<Remark>) {
+    for statement in block.statements.iter_mut() {
+        if let ast::Statement::If(x) = &mut statement.node {
+            let mut arms = std::iter::once((x.condition.clone(), x.block.clone())).chain(x.else_ifs.drain(..)).collect::<Vec<_>>();
+            let mut else_block = x.else_block.take();
+            let count = arms.len() + usize::from(else_block.is_some());
+
+            arms.retain(|(condition, _)| return truth_of(condition) != Some(false));
+
+            if let Some(index) = arms.iter().position(|(condition, _)| return truth_of(condition) == Some(true)) {
+                else_block = Some(arms[index].1.clone());
+                arms.truncate(index);
+            }
+
+            let kept = arms.len() + usize::from(else_block.is_some());
+
+            if kept < count {
+                remarks.push(Remark {
+                    pass: Pass::BranchElimination,
+                    function: function.to_string(),
+                    message: format!("removed {} of {} branches with a constant condition", count - kept, count),
+                    span: statement.span.clone()
+                });
+            }
+
+            statement.node = if arms.is_empty() {
+                ast::Statement::Block(else_block.unwrap_or(ast::Block { statements: vec![], tail: None }))
+            }
+            else {
+                let mut arms = arms.into_iter();
+                let (condition, block) = arms.next().expect("unexpected empty branch list");
+
+                ast::Statement::If(ast::IfStatement { condition, block, else_ifs: arms.collect(), else_block })
+            };
+        }
+
+        match &mut statement.node {
+            ast::Statement::If(x) => {
+                eliminate_block(&mut x.block, function, remarks);
+
+                for (_, block) in x.else_ifs.iter_mut() {
+                    eliminate_block(block, function, remarks);
+                }
+
+                if let Some(block) = &mut x.else_block {
+                    eliminate_block(block, function, remarks);
+                }
+            },
+            ast::Statement::While(x) => eliminate_block(&mut x.block, function, remarks),
+            ast::Statement::Repeat(x) => eliminate_block(&mut x.block, function, remarks),
+            ast::Statement::Block(x) | ast::Statement::Defer(x) => eliminate_block(x, function, remarks),
+            _ => {}
+        }
+    }
+}
+
+// whether a condition is a literal known to be taken or not, whatever integer
+// type it has
+fn truth_of(condition: &ast::Expression) -> Option<bool> {
+    return match condition {
+        ast::Expression::Literal(x) => x.parse::<i128>().ok().map(|x| return x != 0),
+        _ => None
+    };
+}
+
+// the most statements an unrolled `repeat` may grow into
+const MAX_UNROLLED_STATEMENTS: usize = 16;
+
+// replaces a `repeat` with a small enough body by copies of its block, each
+// keeping its own scope. loops left early by `brk` or `cont` are kept
+pub fn unroll_loops(program: &mut ast::Program, remarks: &mut Vec<Remark>) {
+    for decl in program.declarations.iter_mut() {
+        let function = match decl {
+            ast::Declaration::Function(x) => x,
+            ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Function(x), .. }) => x,
+            _ => continue
+        };
+
+        unroll_block(&mut function.body, &function.name, remarks);
+    }
+}
+
+fn unroll_block(block: &mut ast::Block, function: &str, remarks: &mut Vec<Remark>) {
+    for statement in block.statements.iter_mut() {
+        // inner loops first, so an outer loop is measured by what remains
+        match &mut statement.node {
+            ast::Statement::If(x) => {
+                unroll_block(&mut x.block, function, remarks);
+
+                for (_, block) in x.else_ifs.iter_mut() {
+                    unroll_block(block, function, remarks);
+                }
+
+                if let Some(block) = &mut x.else_block {
+                    unroll_block(block, function, remarks);
+                }
+            },
+            ast::Statement::While(x) => unroll_block(&mut x.block, function, remarks),
+            ast::Statement::Repeat(x) => unroll_block(&mut x.block, function, remarks),
+            ast::Statement::Block(x) | ast::Statement::Defer(x) => unroll_block(x, function, remarks),
+            _ => {}
+        }
+
+        let unrolled = match &statement.node {
+            ast::Statement::Repeat(x) if x.block.tail.is_none() && !leaves_loop(&x.block) => {
+                checker::repeat_count(&x.count).ok()
+                    .and_then(|count| return usize::try_from(count).ok())
+                    .filter(|count| return count.saturating_mul(x.block.statements.len().max(1)) <= MAX_UNROLLED_STATEMENTS)
+                    .map(|count| return (count, vec![Spanned::new(ast::Statement::Block(x.block.clone()), statement.span.clone()); count]))
+            },
+            _ => None
+        };
+
+        if let Some((count, statements)) = unrolled {
+            statement.node = ast::Statement::Block(ast::Block { statements, tail: None });
+
+            remarks.push(Remark {
+                pass: Pass::LoopUnrolling,
+                function: function.to_string(),
+                message: format!("unrolled a loop of {} iterations", count),
+                span: statement.span.clone()
+            });
+        }
+    }
+}
+
+// whether a `brk` or `cont` of the enclosing loop is in the block, those of
+// nested loops belong to them
+fn leaves_loop(block: &ast::Block) -> bool {
+    return block.statements.iter().any(|x| {
+        return match &x.node {
+            ast::Statement::Break | ast::Statement::Continue => true,
+            ast::Statement::If(x) => {
+                leaves_loop(&x.block)
+                    || x.else_ifs.iter().any(|(_, block)| return leaves_loop(block))
+                    || x.else_block.as_ref().is_some_and(leaves_loop)
+            },
+            ast::Statement::Block(x) => leaves_loop(x),
+            _ => false
+        };
+    });
+}
+
+// where a fold happens, the statement holding it
+struct Site<'a> {
+    function: &'a str,
+    span: Span
+}
+
+impl Site<'_> {
+    fn remark(&self, expr: &ast::Expression, remarks: &mut Vec<Remark>) {
+        if let ast::Expression::Literal(value) = expr {
+            remarks.push(Remark {
+                pass: Pass::ConstantFolding,
+                function: self.function.to_string(),
+                message: format!("folded a constant expression to `{}`", value),
+                span: self.span.clone()
+            });
+        }
+    }
+}
+
+// evaluates operations on integer literals ahead of time, matching the
+// wrapping semantics of the wasm instructions they would otherwise lower to,
+// and concatenates string literals joined by `+`. an operation is folded at
+// the type the transpiler emits it at, and left alone when that is unknown
+pub fn fold_constants(program: &mut ast::Program, defaults: &checker::Defaults, remarks: &mut Vec<Remark>) {
+    let owned = checker::owned_signatures(program);
+    let signatures = checker::borrow_signatures(&owned);
+    let globals = checker::global_scope(program, &defaults.int);
+
+    for decl in program.declarations.iter_mut() {
+        let function = match decl {
+            ast::Declaration::Function(x) => x,
+            ast::Declaration::Export(ast::ExportDeclaration { item: ast::ExportItem::Function(x), .. }) => x,
+            _ => continue
+        };
+
+        let site = Site { function: &function.name, span: function.span.clone() };
+        let typing = checker::Typing::new(&globals, function, &signatures, &defaults.int);
+
+        fold_block(&mut function.body, &site, &typing, remarks);
+    }
+}
+
+fn fold_block(block: &mut ast::Block, site: &Site, typing: &checker::Typing, remarks: &mut Vec<Remark>) {
+    let mut typing = typing.clone();
+    let int = typing.int().clone();
+
+    for statement in block.statements.iter_mut() {
+        let site = &Site { function: site.function, span: statement.span.clone() };
+
+        match &mut statement.node {
+            ast::Statement::Variable(x) => {
+                let expected = match &x.ty {
+                    Some(ast::TypeExpression::Primitive(ty)) => ty.clone(),
+                    _ => int.clone()
+                };
+
+                fold(&mut x.value, Some(&expected), site, &typing, remarks);
+                typing.bind(x);
+            },
+            ast::Statement::Expression(x) => fold(x, Some(&int), site, &typing, remarks),
+            ast::Statement::Assignment(target, value) => {
+                let expected = match target {
+                    ast::Expression::Identifier(x) => typing.variable(x),
+                    ast::Expression::Offset { signature: Some(ast::TypeExpression::Primitive(x)), .. } => Some(x.clone()),
+                    _ => None
+                };
+
+                fold(value, expected.as_ref(), site, &typing, remarks);
+            },
+            ast::Statement::If(x) => {
+                fold(&mut x.condition, Some(&token::Type::I32), site, &typing, remarks);
+                fold_block(&mut x.block, site, &typing, remarks);
+
+                for (condition, block) in x.else_ifs.iter_mut() {
+                    fold(condition, Some(&token::Type::I32), site, &typing, remarks);
+                    fold_block(block, site, &typing, remarks);
+                }
+
+                if let Some(block) = &mut x.else_block {
+                    fold_block(block, site, &typing, remarks);
+                }
+            },
+            ast::Statement::While(x) => {
+                fold(&mut x.condition, Some(&token::Type::I32), site, &typing, remarks);
+                fold_block(&mut x.block, site, &typing, remarks);
+            },
+            ast::Statement::Repeat(x) => fold_block(&mut x.block, site, &typing, remarks),
+            ast::Statement::Defer(x) => fold_block(x, site, &typing, remarks),
+            ast::Statement::Return(Some(x)) => fold_value(x, site, &typing, remarks),
+            ast::Statement::Block(x) => fold_block(x, site, &typing, remarks),
+            _ => {}
+        }
+    }
+
+    if let Some(x) = &mut block.tail {
+        let site = &Site { function: site.function, span: x.span.clone() };
+
+        fold_value(x, site, &typing, remarks);
+    }
+}
+
+// a returned value, whose elements a tuple result types one by one
+fn fold_value(expr: &mut ast::Expression, site: &Site, typing: &checker::Typing, remarks: &mut Vec<Remark>) {
+    let expected = typing.results(expr);
+
+    match expr {
+        ast::Expression::Tuple(list) => {
+            for (x, ty) in list.iter_mut().zip(expected.iter()) {
+                fold(x, ty.as_ref(), site, typing, remarks);
+            }
+        },
+        x => fold(x, expected[0].as_ref(), site, typing, remarks)
+    }
+}
+
+// folds an expression, remarking on each outermost expression that is folded
+fn fold(expr: &mut ast::Expression, expected: Option<&token::Type>, site: &Site, typing: &checker::Typing, remarks: &mut Vec<Remark>) {
+    if fold_expression(expr, expected, site, typing, remarks) {
+        site.remark(expr, remarks);
+    }
+}
+
+// whether the expression itself is folded into a literal. operands folded
+// under an expression that is not are remarked on here
+fn fold_expression(expr: &mut ast::Expression, expected: Option<&token::Type>, site: &Site, typing: &checker::Typing, remarks: &mut Vec<Remark>) -> bool {
+    let ty = typing.operation(expr, expected);
+    let int = typing.int().clone();
+    let folded = match expr {
+        ast::Expression::Call { callee, args } => {
+            let types = typing.arguments(callee, args);
+
+            for (x, ty) in args.iter_mut().zip(types) {
+                fold(x, ty.as_ref(), site, typing, remarks);
+            }
+
+            None
+        },
+        ast::Expression::CallIndirect { args, .. } => {
+            args.iter_mut().for_each(|x| return fold(x, None, site, typing, remarks));
+
+            None
+        },
+        ast::Expression::Tuple(args) => {
+            args.iter_mut().for_each(|x| return fold(x, Some(&int), site, typing, remarks));
+
+            None
+        },
+        ast::Expression::Variadic { ty, args } => {
+            args.iter_mut().for_each(|x| return fold(x, Some(ty), site, typing, remarks));
+
+            None
+        },
+        ast::Expression::Unary { op, operand } => {
+            let is_operand_folded = fold_expression(operand, ty.as_ref(), site, typing, remarks);
+            let folded = match (&ty, integer_of(operand, ty.as_ref())) {
+                (Some(ty), Some(x)) => fold_integer(ty, op, &[x]),
+                _ => None
+            };
+
+            if folded.is_none() && is_operand_folded {
+                site.remark(operand, remarks);
+            }
+
+            folded
+        },
+        ast::Expression::Binary { op, lhs, rhs } => {
+            let is_lhs_folded = fold_expression(lhs, ty.as_ref(), site, typing, remarks);
+            let is_rhs_folded = fold_expression(rhs, ty.as_ref(), site, typing, remarks);
+            let folded = match (string_of(lhs), string_of(rhs), integer_of(lhs, ty.as_ref()), integer_of(rhs, ty.as_ref()), &ty) {
+                (Some(lhs), Some(rhs), ..) if *op == token::Symbol::Plus => Some(format!("\"{}{}\"", lhs, rhs)),
+                (.., Some(lhs), Some(rhs), Some(ty)) => fold_integer(ty, op, &[lhs, rhs]),
+                _ => None
+            };
+
+            if folded.is_none() {
+                if is_lhs_folded {
+                    site.remark(lhs, remarks);
+                }
+
+                if is_rhs_folded {
+                    site.remark(rhs, remarks);
+                }
+            }
+
+            folded
+        },
+        ast::Expression::Conditional { condition, consequent, alternative } => {
+            fold(condition, Some(&token::Type::I32), site, typing, remarks);
+            fold(consequent, ty.as_ref(), site, typing, remarks);
+            fold(alternative, ty.as_ref(), site, typing, remarks);
+
+            None
+        },
+        // the operand keeps its own type, so it is folded but the cast is kept
+        ast::Expression::Cast { operand, .. } => {
+            fold(operand, ty.as_ref(), site, typing, remarks);
+
+            None
+        },
+        _ => None
+    };
+
+    return match folded {
+        Some(value) => {
+            *expr = ast::Expression::Literal(value);
+
+            true
+        },
+        None => false
+    };
+}
+
+// escapes are complete on either side, so the raw contents join as they are
+fn string_of(expr: &ast::Expression) -> Option<&str> {
+    return match expr {
+        ast::Expression::Literal(x) => ast::string_contents(x),
+        _ => None
+    };
+}
+
+// the bits of an integer literal read as `ty`, sign extended to an i64
+fn integer_of(expr: &ast::Expression, ty: Option<&token::Type>) -> Option<i64> {
+    let literal = match expr {
+        ast::Expression::Literal(x) => x,
+        _ => return None
+    };
+
+    return match ty? {
+        token::Type::I32 => literal.parse::<i32>().ok().map(i64::from),
+        token::Type::U32 => literal.parse::<u32>().ok().map(|x| return i64::from(signed(x))),
+        token::Type::I64 => literal.parse().ok(),
+        token::Type::U64 => literal.parse::<u64>().ok().map(|x| return i64::from_ne_bytes(x.to_ne_bytes())),
+        _ => None
+    };
+}
+
+// folds at the width of `ty`, an unsigned result is written unsigned so the
+// literal still fits the type
+fn fold_integer(ty: &token::Type, op: &token::Symbol, operands: &[i64]) -> Option<String> {
+    let narrow = |x: i64| return i32::try_from(x).ok();
+    let value = match (ty, operands) {
+        (token::Type::I64 | token::Type::U64, [x]) => fold_unary_i64(op, *x)?,
+        (token::Type::I64 | token::Type::U64, [x, y]) => fold_binary_i64(op, *x, *y)?,
+        (_, [x]) => i64::from(fold_unary(op, narrow(*x)?)?),
+        (_, [x, y]) => i64::from(fold_binary(op, narrow(*x)?, narrow(*y)?)?),
+        _ => return None
+    };
+
+    return Some(match ty {
+        token::Type::U32 => unsigned(narrow(value)?).to_string(),
+        token::Type::U64 => u64::from_ne_bytes(value.to_ne_bytes()).to_string(),
+        _ => value.to_string()
+    });
+}
+
+pub fn fold_unary(op: &token::Symbol, value: i32) -> Option<i32> {
+    return match op {
+        token::Symbol::Minus => Some(value.wrapping_neg()),
+        token::Symbol::BitwiseNot => Some(!value),
+        _ => None
+    };
+}
+
+// shift amounts are taken modulo the operand width, as `i32.shl` and friends do
+pub fn fold_binary(op: &token::Symbol, lhs: i32, rhs: i32) -> Option<i32> {
+    return match op {
+        token::Symbol::Plus | token::Symbol::PlusUnsigned => Some(lhs.wrapping_add(rhs)),
+        token::Symbol::Minus | token::Symbol::MinusUnsigned => Some(lhs.wrapping_sub(rhs)),
+        token::Symbol::Asterisk | token::Symbol::AsteriskUnsigned => Some(lhs.wrapping_mul(rhs)),
+        token::Symbol::BitwiseAnd => Some(lhs & rhs),
+        token::Symbol::BitwiseOr => Some(lhs | rhs),
+        token::Symbol::BitwiseXor => Some(lhs ^ rhs),
+        token::Symbol::ShiftLeftLogical => Some(lhs.wrapping_shl(unsigned(rhs))),
+        token::Symbol::ShiftRightArithmatic => Some(lhs.wrapping_shr(unsigned(rhs))),
+        token::Symbol::ShiftRightLogical => Some(signed(unsigned(lhs).wrapping_shr(unsigned(rhs)))),
+        _ => None
+    };
+}
+
+fn fold_unary_i64(op: &token::Symbol, value: i64) -> Option<i64> {
+    return match op {
+        token::Symbol::Minus => Some(value.wrapping_neg()),
+        token::Symbol::BitwiseNot => Some(!value),
+        _ => None
+    };
+}
+
+// as `fold_binary`, with i64 semantics
+fn fold_binary_i64(op: &token::Symbol, lhs: i64, rhs: i64) -> Option<i64> {
+    let amount = u32::try_from(rhs.rem_euclid(64)).expect("unexpected shift amount");
+
+    return match op {
+        token::Symbol::Plus | token::Symbol::PlusUnsigned => Some(lhs.wrapping_add(rhs)),
+        token::Symbol::Minus | token::Symbol::MinusUnsigned => Some(lhs.wrapping_sub(rhs)),
+        token::Symbol::Asterisk | token::Symbol::AsteriskUnsigned => Some(lhs.wrapping_mul(rhs)),
+        token::Symbol::BitwiseAnd => Some(lhs & rhs),
+        token::Symbol::BitwiseOr => Some(lhs | rhs),
+        token::Symbol::BitwiseXor => Some(lhs ^ rhs),
+        token::Symbol::ShiftLeftLogical => Some(lhs.wrapping_shl(amount)),
+        token::Symbol::ShiftRightArithmatic => Some(lhs.wrapping_shr(amount)),
+        token::Symbol::ShiftRightLogical => Some(i64::from_ne_bytes(u64::from_ne_bytes(lhs.to_ne_bytes()).wrapping_shr(amount).to_ne_bytes())),
+        _ => None
+    };
+}
+
+const fn unsigned(value: i32) -> u32 {
+    return u32::from_ne_bytes(value.to_ne_bytes());
+}
+
+const fn signed(value: u32) -> i32 {
+    return i32::from_ne_bytes(value.to_ne_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::tokenizer;
+
+    fn function_names(text: &str) -> Vec<String> {
+        let mut program = parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap();
+        let symbols = resolver::resolve(&program).unwrap();
+
+        eliminate_dead_functions(&mut program, &symbols, &mut vec![]);
+
+        return program.declarations.iter()
+            .filter_map(|x| return match x {
+                ast::Declaration::Function(x) => Some(x.name.clone()),
+                _ => None
+            })
+            .collect();
+    }
+
+    fn fold(text: &str) -> ast::Expression {
+        let source = format!("fn f() {{ {}; }}", text);
+        let mut program = parser::parse_syntax(&tokenizer::tokenize(&source).unwrap(), false).unwrap();
+
+        fold_constants(&mut program, &checker::Defaults::default(), &mut vec![]);
+
+        return match &program.declarations[0] {
+            ast::Declaration::Function(x) => match &x.body.statements[0].node {
+                ast::Statement::Expression(x) => x.clone(),
+                x => panic!("unexpected statement: {:?}", x)
+            },
+            x => panic!("unexpected declaration: {:?}", x)
+        };
+    }
+
+    // the tail of the first function after folding
+    fn fold_tail(text: &str) -> ast::Expression {
+        let mut program = parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap();
+
+        fold_constants(&mut program, &checker::Defaults::default(), &mut vec![]);
+
+        return match &program.declarations[0] {
+            ast::Declaration::Function(x) => x.body.tail.clone().unwrap().into_node(),
+            x => panic!("unexpected declaration: {:?}", x)
+        };
+    }
+
+    // the body of the only function after the peephole pass, without its `end`
+    fn simplify(text: &str) -> Vec<Instruction> {
+        let program = parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap();
+        let mut module = transpiler::Module::build(&program, &checker::Defaults::default()).unwrap();
+        let mut remarks = vec![];
+
+        simplify_instructions(&mut module, &program, &mut remarks);
+
+        let mut body = module.functions()[0].body.clone();
+
+        assert_eq!(body.pop(), Some(Instruction::End));
+        assert_eq!(remarks.len(), usize::from(body.len() < 3));
+
+        return body;
+    }
+
+    #[test]
+    fn simplify_integer_identities() {
+        for text in ["a + 0", "a - 0", "a * 1", "a / 1", "a | 0", "a ^ 0", "a << 0", "a & -1", "a & a", "a | a", "~~a", "a + 0 * 1 - 0"].iter() {
+            assert_eq!(simplify(&format!("fn f(a: i32) -> i32 {{ {} }}", text)), vec![Instruction::LocalGet(0)], "{}", text);
+        }
+
+        assert_eq!(simplify("fn f(a: i64) -> i64 { a * 1 + 0 }"), vec![Instruction::LocalGet(0)]);
+        assert_eq!(simplify("fn f(a: i32) -> i32 { a * 0 }"), vec![Instruction::I32Const(0)]);
+    }
+
+    #[test]
+    fn keep_effects_of_zeroed_operand() {
+        let text = "fn f() -> i32 { g() * 0 } fn g() -> i32 { 1 }";
+        let program = parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap();
+        let mut module = transpiler::Module::build(&program, &checker::Defaults::default()).unwrap();
+
+        simplify_instructions(&mut module, &program, &mut vec![]);
+
+        assert_eq!(module.functions()[0].body, vec![Instruction::Call(1), Instruction::Drop, Instruction::I32Const(0), Instruction::End]);
+    }
+
+    #[test]
+    fn keep_float_identities() {
+        assert_eq!(simplify("fn f(a: f64) -> f64 { a + 0.0 }"), vec![
+            Instruction::LocalGet(0),
+            Instruction::F64Const(0),
+            Instruction::Binary(ValType::F64, BinaryOp::Add)
+        ]);
+        assert_eq!(simplify("fn f(a: f64) -> f64 { a * 1.0 }").len(), 3);
+        assert_eq!(simplify("fn f(a: i32, b: i32) -> i32 { a & b }").len(), 3);
+    }
+
+    #[test]
+    fn fold_shift_by_width() {
+        assert_eq!(fold("1 << 32"), ast::Expression::Literal("1".to_string()));
+    }
+
+    #[test]
+    fn fold_arithmetic_shift_right() {
+        assert_eq!(fold("-8 >> 1"), ast::Expression::Literal("-4".to_string()));
+    }
+
+    #[test]
+    fn fold_logical_shift_right() {
+        assert_eq!(fold("-8 >>> 1"), ast::Expression::Literal("2147483644".to_string()));
+    }
+
+    #[test]
+    fn fold_shift_by_width_i64() {
+        assert_eq!(fold_tail("fn f() -> i64 { 1 << 32 }"), ast::Expression::Literal("4294967296".to_string()));
+        assert_eq!(fold_tail("fn f() -> i64 { 1 << 64 }"), ast::Expression::Literal("1".to_string()));
+    }
+
+    #[test]
+    fn fold_arithmetic_shift_right_i64() {
+        assert_eq!(fold_tail("fn f() -> i64 { -8 >> 1 }"), ast::Expression::Literal("-4".to_string()));
+    }
+
+    #[test]
+    fn fold_logical_shift_right_i64() {
+        assert_eq!(fold_tail("fn f() -> i64 { -8 >>> 1 }"), ast::Expression::Literal("9223372036854775804".to_string()));
+        assert_eq!(fold_tail("fn f() -> u32 { 0 - 1 }"), ast::Expression::Literal("4294967295".to_string()));
+    }
+
+    #[test]
+    fn fold_at_operand_type() {
+        let rhs = |text| return match fold_tail(text) {
+            ast::Expression::Binary { rhs, .. } => *rhs,
+            x => panic!("unexpected expression: {:?}", x)
+        };
+
+        assert_eq!(rhs("fn f(a: i64) -> i64 { a + (1 << 40) }"), ast::Expression::Literal("1099511627776".to_string()));
+        assert_eq!(rhs("fn f(a: i32) -> i64 { i64(a) + (1 << 40) }"), ast::Expression::Literal("1099511627776".to_string()));
+        // the type of a destructured binding is not told, so neither is the operation
+        assert!(matches!(rhs("fn f() -> i64 { let (a, b) <- g(); a + (1 << 40) } fn g() -> (i64, i64) { (1, 2) }"), ast::Expression::Binary { .. }));
+    }
+
+    #[test]
+    fn fold_string_concatenation() {
+        assert_eq!(fold("\"a\" + \"b\""), ast::Expression::Literal("\"ab\"".to_string()));
+        assert_eq!(fold("\"x\\n\" + \"y.\" + \"z\""), ast::Expression::Literal("\"x\\ny.z\"".to_string()));
+    }
+
+    #[test]
+    fn drop_unused_private_function() {
+        assert_eq!(function_names("fn a() {} pub fn b() {}"), vec!["b"]);
+    }
+
+    #[test]
+    fn keep_private_function_called_from_export() {
+        assert_eq!(function_names("fn a() {} fn b() { a(); } exp fn c() { b(); }"), vec!["a", "b"]);
+    }
+
+    fn optimize_source(text: &str, level: &cli::OptLevel) -> Vec<Remark> {
+        let mut program = parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap();
+        let symbols = resolver::resolve(&program).unwrap();
+        let mut remarks = vec![];
+
+        optimize(&mut program, &symbols, level, &checker::Defaults::default(), &mut remarks);
+
+        return remarks;
+    }
+
+    #[test]
+    fn remark_on_folded_constant() {
+        let text = "exp fn f() -> i32 {\n    let a <- (1 + 2) * 3;\n    a + 0\n}";
+        let remarks = optimize_source(text, &cli::OptLevel::O1);
+
+        assert_eq!(remarks.len(), 1);
+        assert_eq!(remarks[0].pass(), &Pass::ConstantFolding);
+        assert_eq!(remarks[0].message(), "folded a constant expression to `9`");
+        assert_eq!(&text[remarks[0].span().clone()], "let a <- (1 + 2) * 3;");
+        assert_eq!(remarks[0].render("main.cwal", text).to_string(), "main.cwal:2:5: remark: folded a constant expression to `9` [constant-folding]");
+        assert!(optimize_source(text, &cli::OptLevel::O0).is_empty());
+    }
+
+    #[test]
+    fn remark_on_each_folded_operand() {
+        let text = "exp fn f(a: i32) -> i32 { a + (2 << 1) + g(1 - 2) } fn g(a: i32) -> i32 { a }";
+        let remarks = optimize_source(text, &cli::OptLevel::O1).iter()
+            .map(|x| return (x.message().to_string(), &text[x.span().clone()]))
+            .collect::<Vec<_>>();
+
+        assert_eq!(remarks, vec![
+            ("folded a constant expression to `4`".to_string(), "a + (2 << 1) + g(1 - 2)"),
+            ("folded a constant expression to `-1`".to_string(), "a + (2 << 1) + g(1 - 2)")
+        ]);
+    }
+
+    fn unrolled(text: &str) -> ast::Block {
+        let mut program = parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap();
+
+        unroll_loops(&mut program, &mut vec![]);
+
+        return match &program.declarations[0] {
+            ast::Declaration::Function(x) => x.body.clone(),
+            x => panic!("unexpected declaration: {:?}", x)
+        };
+    }
+
+    // the span of `part` in the source
+    fn span_of(text: &str, part: &str) -> Span {
+        let start = text.find(part).unwrap();
+
+        return start..start + part.len();
+    }
+
+    // the span of the only statement of `fn f() { .. }`
+    fn only_statement(text: &str) -> Span {
+        return span_of(text, &text["fn f() { ".len()..text.len() - " }".len()]);
+    }
+
+    #[test]
+    fn unroll_small_repeat() {
+        let text = "fn f() { repeat 3 { g(); } }";
+        // each copy keeps the span of the loop
+        let step = Spanned::new(ast::Statement::Block(call(text, "g")), only_statement(text));
+
+        assert_eq!(unrolled(text).statements, vec![Spanned::new(ast::Statement::Block(ast::Block {
+            statements: vec![step.clone(), step.clone(), step],
+            tail: None
+        }), only_statement(text))]);
+
+        let text = "fn f() { repeat 0 { g(); } }";
+
+        assert_eq!(unrolled(text).statements, vec![Spanned::new(ast::Statement::Block(ast::Block { statements: vec![], tail: None }), only_statement(text))]);
+    }
+
+    #[test]
+    fn keep_large_or_escaping_repeat() {
+        let is_repeat = |text| return matches!(unrolled(text).statements[0].node, ast::Statement::Repeat(_));
+
+        assert!(is_repeat("fn f() { repeat 17 { g(); } }"));
+        assert!(is_repeat("fn f() { repeat 3 { g(); g(); g(); g(); g(); g(); } }"));
+        assert!(is_repeat("fn f() { repeat 2 { if (g()) { brk; } } }"));
+        assert!(!is_repeat("fn f() { repeat 2 { while (g()) { cont; } } }"));
+    }
+
+    #[test]
+    fn remark_on_unrolled_loop() {
+        let text = "exp fn f() { repeat 4 { g(); } } fn g() {}";
+        let remarks = optimize_source(text, &cli::OptLevel::O2);
+
+        assert_eq!(remarks.len(), 1);
+        assert_eq!(remarks[0].pass(), &Pass::LoopUnrolling);
+        assert_eq!(remarks[0].message(), "unrolled a loop of 4 iterations");
+        assert_eq!(&text[remarks[0].span().clone()], "repeat 4 { g(); }");
+        assert!(optimize_source("exp fn f() { repeat 4 { g(); } } fn g() {}", &cli::OptLevel::O1).is_empty());
+    }
+
+    fn eliminated(text: &str) -> ast::Block {
+        let mut program = parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap();
+
+        fold_constants(&mut program, &checker::Defaults::default(), &mut vec![]);
+        eliminate_branches(&mut program, &mut vec![]);
+
+        return match &program.declarations[0] {
+            ast::Declaration::Function(x) => x.body.clone(),
+            x => panic!("unexpected declaration: {:?}", x)
+        };
+    }
+
+    // a block calling `name()`, at its span in the source
+    fn call(text: &str, name: &str) -> ast::Block {
+        return ast::Block {
+            statements: vec![Spanned::new(ast::Statement::Expression(ast::Expression::Call { callee: name.to_string(), args: vec![] }), span_of(text, &format!("{}();", name)))],
+            tail: None
+        };
+    }
+
+    // the only statement of the eliminated function, with its span checked
+    // to be that of the `if` it replaces
+    fn eliminated_statement(text: &str) -> ast::Statement {
+        let mut statements = eliminated(text).statements;
+
+        assert_eq!(statements.len(), 1);
+        assert_eq!(statements[0].span, only_statement(text));
+
+        return statements.remove(0).into_node();
+    }
+
+    #[test]
+    fn keep_taken_branch_of_constant_true_if() {
+        let text = "fn f() { if (1) { a(); } else { b(); } }";
+
+        assert_eq!(eliminated_statement(text), ast::Statement::Block(call(text, "a")));
+
+        let text = "fn f() { if (2 - 1) { a(); } elif (g()) { b(); } }";
+
+        assert_eq!(eliminated_statement(text), ast::Statement::Block(call(text, "a")));
+
+        // a later constant arm ends the chain as its `else`
+        let text = "fn f() { if (g()) { a(); } elif (1) { b(); } else { c(); } }";
+
+        assert_eq!(eliminated_statement(text), ast::Statement::If(ast::IfStatement {
+            condition: ast::Expression::Call { callee: "g".to_string(), args: vec![] },
+            block: call(text, "a"),
+            else_ifs: vec![],
+            else_block: Some(call(text, "b"))
+        }));
+    }
+
+    #[test]
+    fn drop_branch_of_constant_false_if() {
+        let text = "fn f() { if (0) { a(); } else { b(); } }";
+
+        assert_eq!(eliminated_statement(text), ast::Statement::Block(call(text, "b")));
+        assert_eq!(eliminated_statement("fn f() { if (1 - 1) { a(); } }"), ast::Statement::Block(ast::Block { statements: vec![], tail: None }));
+
+        let text = "fn f() { if (0) { a(); } elif (g()) { b(); } }";
+
+        assert_eq!(eliminated_statement(text), ast::Statement::If(ast::IfStatement {
+            condition: ast::Expression::Call { callee: "g".to_string(), args: vec![] },
+            block: call(text, "b"),
+            else_ifs: vec![],
+            else_block: None
+        }));
+    }
+
+    #[test]
+    fn remark_on_eliminated_branch() {
+        let text = "exp fn f() { if (0) { g(); } elif (1) { g(); } else { g(); } } fn g() {}";
+        let remarks = optimize_source(text, &cli::OptLevel::O1);
+
+        assert_eq!(remarks.len(), 1);
+        assert_eq!(remarks[0].pass(), &Pass::BranchElimination);
+        assert_eq!(remarks[0].message(), "removed 2 of 3 branches with a constant condition");
+        assert_eq!(&text[remarks[0].span().clone()], "if (0) { g(); } elif (1) { g(); } else { g(); }");
+        assert!(optimize_source("exp fn f() { if (1) { g(); } } fn g() {}", &cli::OptLevel::O0).is_empty());
+    }
+
+    #[test]
+    fn remark_on_removed_function() {
+        let remarks = optimize_source("fn a() {} exp fn b() {}", &cli::OptLevel::O2);
+
+        assert_eq!(remarks.len(), 1);
+        assert_eq!(remarks[0].function(), "a");
+        assert_eq!(remarks[0].message(), "removed unused function `a`");
+    }
+}
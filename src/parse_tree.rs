@@ -0,0 +1,75 @@
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Tree(Tree),
+    Token(usize)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tree {
+    name: &'static str,
+    children: Vec<Node>
+}
+
+impl Tree {
+    pub const fn new(name: &'static str, children: Vec<Node>) -> Self {
+        return Self {
+            name,
+            children
+        };
+    }
+
+    pub const fn name(&self) -> &'static str {
+        return self.name;
+    }
+
+    pub fn children(&self) -> &[Node] {
+        return &self.children;
+    }
+
+    pub fn trees(&self) -> impl Iterator<Item = &Tree> {
+        return self.children.iter().filter_map(|x| return match x {
+            Node::Tree(tree) => Some(tree),
+            _ => None
+        });
+    }
+
+    pub fn tree(&self, name: &str) -> Option<&Tree> {
+        return self.trees().find(|x| return x.name == name);
+    }
+
+    pub fn tokens(&self) -> impl Iterator<Item = usize> + '_ {
+        return self.children.iter().filter_map(|x| return match x {
+            Node::Token(index) => Some(*index),
+            _ => None
+        });
+    }
+
+    // first and last token leaves in pre-order
+    pub fn token_bounds(&self) -> Option<(usize, usize)> {
+        let mut bounds: Option<(usize, usize)> = None;
+
+        for child in self.children.iter() {
+            let inner = match child {
+                Node::Tree(tree) => tree.token_bounds(),
+                Node::Token(index) => Some((*index, *index))
+            };
+
+            if let Some((first, last)) = inner {
+                bounds = Some((bounds.map_or(first, |x| return x.0), last));
+            }
+        }
+
+        return bounds;
+    }
+
+    // token leaves are recorded in consumption order, so numbering them in
+    // pre-order with the indices of the consumed tokens restores the mapping
+    pub fn assign_tokens(&mut self, indices: &mut dyn Iterator<Item = usize>) {
+        for child in self.children.iter_mut() {
+            match child {
+                Node::Tree(tree) => tree.assign_tokens(indices),
+                Node::Token(index) => *index = indices.next().expect("unexpected unmatched token leaf")
+            }
+        }
+    }
+}
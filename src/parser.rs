@@ -1,35 +1,69 @@
 use std::collections::VecDeque;
 use std::error::Error;
+use std::fmt;
 
+use crate::ast;
+use crate::definition;
+use crate::diagnostic;
 use crate::token;
 use crate::grammar;
 use crate::grammar::Grammar;
+use crate::parse_tree;
+use crate::span::{Span, Spanned};
 
+// the depth counts nesting levels: each open expression and block is one,
+// so parentheses, a call argument or a block inside a block each go a level
+// deeper. an operator chain is read flat, so its length costs no depth. every
+// later pass walks the tree recursively, so this keeps them well within the
+// stack of a test thread
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+// the grammars that open a nesting level. an expression only opens inside
+// another one through parentheses, brackets or a branch of `?:`, never for
+// the operands of a chain
+const NESTING_GRAMMARS: [&str; 2] = ["Expression", "FunctionBlock"];
+
+// cloning snapshots the whole process stack, so a clone can try a parse
+// and be dropped without affecting the original
+#[derive(Clone)]
 pub struct Parser {
     process_stack: VecDeque<Box<dyn Grammar>>,
-    counter: usize
+    counter: usize,
+    is_traced: bool,
+    // the nesting levels open on the process stack
+    depth: usize,
+    max_depth: usize
 }
 
 impl Parser {
-    pub fn new() -> Self {
+    pub fn new(is_traced: bool) -> Self {
+        return Self::with_root(Box::new(grammar::Program::new()), is_traced);
+    }
+
+    pub fn with_root(root: Box<dyn Grammar>, is_traced: bool) -> Self {
         let mut process_stack = VecDeque::<Box<dyn Grammar>>::new();
 
-        process_stack.push_back(Box::new(grammar::Program::new()));
+        process_stack.push_back(root);
 
         return Self {
             process_stack,
             counter: 0,
+            is_traced,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH
         };
     }
 
-    pub fn show_status(&self, token: &token::Token) {
-        println!("proc: {}, {:?}, stack len: {}", self.counter, token, self.process_stack.len());
+    pub const fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
 
-        for p in self.process_stack.iter() {
-            print!("-> {} ", p.info());
-        }
+        return self;
+    }
 
-        println!("\n--");
+    pub fn show_status(&self, token: &token::Token) {
+        if self.is_traced {
+            eprintln!("proc: {}, {:?}\n{}--", self.counter, token, self);
+        }
     }
 
     pub fn process(&mut self, token: &token::Token) -> Result<(), Box<dyn Error>> {
@@ -41,25 +75,51 @@ impl Parser {
             return Ok(());
         }
 
+        if !self.feed(token)? {
+            return Err(diagnostic::UNEXPECTED_TOKEN.message(format!("unexpected token `{}`", token)).into());
+        }
+
+        return Ok(());
+    }
+
+    // returns false when the root grammar is complete and passes the token on
+    pub fn feed(&mut self, token: &token::Token) -> Result<bool, Box<dyn Error>> {
         // while the token is not consumed
         loop {
             let top = self.top_process();
 
             match top.process(token) {
                 grammar::Result::Consumed(mut list) => {
+                    self.depth += list.iter().filter(|x| return opens_level(x.as_ref())).count();
                     self.process_stack.append(&mut list);
 
+                    if self.depth > self.max_depth {
+                        return Err(Box::new(diagnostic::CompileError::RecursionLimit(self.max_depth)));
+                    }
+
                     self.update_process_stack();
 
-                    return Ok(());
+                    return Ok(true);
                 },
                 grammar::Result::Passed => {
+                    if self.process_stack.len() == 1 {
+                        return Ok(false);
+                    }
+
                     self.update_process_stack();
 
                     continue;
                 },
                 grammar::Result::Unexpected(err) => {
-                    return Err(err);
+                    if let Some(x @ grammar::GrammarError::ReservedKeyword(_)) = err.downcast_ref() {
+                        return Err(diagnostic::RESERVED_KEYWORD.message(x).into());
+                    }
+
+                    if token == &token::Token::Symbol(token::Symbol::Decrement) {
+                        return Err(diagnostic::UNEXPECTED_TOKEN.message(format!("unexpected token `{}`; {}", token, definition::DECREMENT_HINT)).into());
+                    }
+
+                    return Err(diagnostic::UNEXPECTED_TOKEN.message(format!("unexpected token `{}`, {}", token, err)).into());
                 },
             }
         }
@@ -67,7 +127,8 @@ impl Parser {
 
     fn update_process_stack(&mut self) {
         let mut pop_count = 0;
-        for proc in self.process_stack.iter().rev() {
+        // the root grammar stays until the parser is finished
+        for proc in self.process_stack.iter().skip(1).rev() {
             if proc.is_done() {
                 pop_count += 1;
             }
@@ -77,10 +138,122 @@ impl Parser {
         }
 
         for _ in 0..pop_count {
-            let removed = self.process_stack.pop_back();
-            
-            println!("--#( remove: {:?} )", removed.unwrap().info());
+            let removed = self.process_stack.pop_back().expect("unexpected empty process stack");
+
+            if opens_level(removed.as_ref()) {
+                self.depth -= 1;
+            }
+
+            self.top_process().attach(removed.into_node());
+        }
+    }
+
+    pub fn finish(mut self) -> Result<parse_tree::Tree, Box<dyn Error>> {
+        while let Some(mut proc) = self.process_stack.pop_back() {
+            if let grammar::Result::Unexpected(err) = proc.finish() {
+                return Err(err);
+            }
+
+            let node = proc.into_node();
+
+            match (self.process_stack.back_mut(), node) {
+                (Some(parent), node) => parent.attach(node),
+                (None, parse_tree::Node::Tree(tree)) => return Ok(tree),
+                (None, parse_tree::Node::Token(_)) => break
+            }
+        }
+
+        return Err("unexpected empty process stack".into());
+    }
+
+    // a best-effort program for editors, which keeps parsing past errors. a
+    // declaration that fails is skipped up to the `;` or `}` ending it and
+    // left in the tree as an error node, so the declarations around it are
+    // still built. the compiler stops at the first error instead
+    #[allow(dead_code)]
+    pub fn into_ast(mut self, tokens: &[Spanned<token::Token>]) -> (Option<ast::Program>, Vec<diagnostic::Report>) {
+        let mut errors = vec![];
+        // the parser between declarations, and the token it stopped at
+        let mut checkpoint = (self.clone(), 0);
+        let mut i = 0;
+
+        while i < tokens.len() {
+            if self.process_stack.len() == 1 {
+                checkpoint = (self.clone(), i);
+            }
+
+            if let Err(err) = self.process(&tokens[i].node) {
+                let (parser, start) = &checkpoint;
+                let end = recovery_point(tokens, *start, i);
+
+                errors.push(syntax_error(err.as_ref(), &tokens[i].span));
+                self = parser.clone();
+                self.skip(&tokens[*start..=end]);
+                i = end;
+            }
+
+            i += 1;
         }
+
+        let mut tree = match self.clone().finish() {
+            Ok(x) => x,
+            Err(err) => {
+                let (mut parser, start) = checkpoint;
+
+                errors.push(syntax_error(err.as_ref(), &end_of(tokens)));
+                parser.skip(&tokens[start..]);
+
+                match parser.finish() {
+                    Ok(x) => x,
+                    Err(_) => return (None, errors)
+                }
+            }
+        };
+
+        tree.assign_tokens(&mut significant_indices(tokens));
+
+        return match ast::Program::build(&tree, tokens) {
+            Ok(x) => (Some(x), errors),
+            Err(err) => {
+                errors.push(diagnostic::render(err.as_ref()));
+
+                (None, errors)
+            }
+        };
+    }
+
+    // attaches the tokens as an error node under the root
+    fn skip(&mut self, tokens: &[Spanned<token::Token>]) {
+        let leaves = tokens.iter()
+            .filter(|x| return !matches!(x.node, token::Token::Comment(_)))
+            .map(|_| return parse_tree::Node::Token(0))
+            .collect();
+
+        self.top_process().attach(parse_tree::Node::Tree(parse_tree::Tree::new("ErrorDeclaration", leaves)));
+    }
+
+    // the kinds of token that may come next. the frame on top is asked first,
+    // and a frame that may end here lets the one below it continue. asked by
+    // completion in an editor, never while compiling
+    #[allow(dead_code)]
+    pub fn expected_tokens(&self) -> Vec<grammar::TokenKind> {
+        return self.expected().0;
+    }
+
+    pub fn expected(&self) -> (Vec<grammar::TokenKind>, bool) {
+        let mut kinds = vec![];
+
+        for proc in self.process_stack.iter().rev() {
+            let (first, can_finish) = proc.expected();
+
+            grammar::extend_unique(&mut kinds, first);
+
+            if !can_finish {
+                return (kinds, false);
+            }
+        }
+
+        return (kinds, true);
     }
 
     fn top_process(&mut self) -> &mut Box<dyn Grammar> {
@@ -88,12 +261,673 @@ impl Parser {
     }
 }
 
-pub fn parse_syntax(tokens: &Vec<token::Token>) -> Result<(), Box<dyn Error>> {
-    let mut process_state_machine = Parser::new();
+// each frame is the parent of the one above it, so the stack renders as a tree
+impl fmt::Display for Parser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (depth, proc) in self.process_stack.iter().enumerate() {
+            writeln!(f, "{:indent$}{}", "", proc.info(), indent = depth * 2)?;
+        }
+
+        return Ok(());
+    }
+}
 
+pub fn parse_syntax(tokens: &[Spanned<token::Token>], is_traced: bool) -> Result<ast::Program, Box<dyn Error>> {
+    return parse_syntax_with_limit(tokens, is_traced, DEFAULT_MAX_DEPTH);
+}
+
+pub fn parse_syntax_with_limit(tokens: &[Spanned<token::Token>], is_traced: bool, max_depth: usize) -> Result<ast::Program, Box<dyn Error>> {
+    let tree = build_tree(Parser::new(is_traced).with_max_depth(max_depth), tokens)?;
+
+    return ast::Program::build(&tree, tokens);
+}
+
+// leftover tokens after the expression are rejected by the root grammar. for
+// a repl or tests, a source file is always parsed as a whole program
+#[allow(dead_code)]
+pub fn parse_expression(tokens: &[Spanned<token::Token>]) -> Result<ast::Expression, Box<dyn Error>> {
+    let tree = build_tree(Parser::with_root(Box::new(grammar::Expression::new()), false), tokens)?;
+
+    return ast::Expression::build(&tree, tokens);
+}
+
+fn build_tree(mut process_state_machine: Parser, tokens: &[Spanned<token::Token>]) -> Result<parse_tree::Tree, Box<dyn Error>> {
     for token in tokens.iter() {
-        process_state_machine.process(token)?;
+        process_state_machine.process(token).map_err(|x| return located(x, &token.span))?;
+    }
+
+    let mut tree = process_state_machine.finish().map_err(|x| return located(x, &end_of(tokens)))?;
+
+    tree.assign_tokens(&mut significant_indices(tokens));
+
+    return Ok(tree);
+}
+
+// whether the grammar's frame adds a level toward the recursion limit
+fn opens_level(grammar: &dyn Grammar) -> bool {
+    return NESTING_GRAMMARS.contains(&grammar.name());
+}
+
+// a parse error at the token it failed on. errors without a kind of their
+// own come from the grammars, which only fail on an unexpected token
+fn syntax_error(err: &(dyn Error + 'static), span: &Span) -> diagnostic::Report {
+    if err.is::<diagnostic::Report>() || err.is::<diagnostic::CompileError>() {
+        return diagnostic::render(err).with_span(span.clone());
+    }
+
+    return diagnostic::UNEXPECTED_TOKEN.message(err).with_span(span.clone());
+}
+
+// the recursion limit is kept apart, so callers can still downcast it. an
+// error of the tree builder has a span of its own already
+fn located(err: Box<dyn Error>, span: &Span) -> Box<dyn Error> {
+    if err.is::<diagnostic::CompileError>() {
+        return err;
+    }
+
+    return Box::new(syntax_error(err.as_ref(), span));
+}
+
+// the empty span after the last token, where the input ended too early
+fn end_of(tokens: &[Spanned<token::Token>]) -> Span {
+    return tokens.last().map_or(0..0, |x| return x.span.end..x.span.end);
+}
+
+// comments are skipped by the parser, so they have no leaves in the tree
+fn significant_indices<'a>(tokens: &'a [Spanned<token::Token>]) -> impl Iterator<Item = usize> + 'a {
+    return tokens.iter()
+        .enumerate()
+        .filter(|(_, x)| return !matches!(x.node, token::Token::Comment(_)))
+        .map(|(i, _)| return i);
+}
+
+// the last token of a declaration starting at `start` that fails at `failed`:
+// the first `;` or closing `}` at its outer level from the failing token on,
+// or the token before `failed` when that starts the next declaration
+fn recovery_point(tokens: &[Spanned<token::Token>], start: usize, failed: usize) -> usize {
+    let mut depth = 0_usize;
+
+    for (i, x) in tokens.iter().enumerate().skip(start) {
+        match x.node {
+            token::Token::Symbol(token::Symbol::LeftBrace) => depth += 1,
+            token::Token::Symbol(token::Symbol::RightBrace) => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+
+        if i == failed && i > start && depth == 0 && starts_declaration(&x.node) {
+            return i - 1;
+        }
+
+        if i >= failed && depth == 0 && matches!(x.node, token::Token::Symbol(token::Symbol::SemiColon | token::Symbol::RightBrace)) {
+            return i;
+        }
+    }
+
+    return tokens.len() - 1;
+}
+
+fn starts_declaration(token: &token::Token) -> bool {
+    return matches!(token, token::Token::Keyword(
+        token::Keyword::Function
+        | token::Keyword::Public
+        | token::Keyword::Type
+        | token::Keyword::Table
+        | token::Keyword::Memory
+        | token::Keyword::Let
+        | token::Keyword::Import
+        | token::Keyword::Export
+        | token::Keyword::Include
+        | token::Keyword::StaticAssert
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use crate::tokenizer;
+
+    fn parse(text: &str) -> Result<ast::Program, Box<dyn Error>> {
+        return parse_syntax(&tokenizer::tokenize(text)?, false);
+    }
+
+    fn function(program: &ast::Program, index: usize) -> &ast::FunctionDeclaration {
+        return match &program.declarations[index] {
+            ast::Declaration::Function(x) => x,
+            x => panic!("unexpected declaration: {:?}", x)
+        };
+    }
+
+    fn statements(block: &ast::Block) -> Vec<ast::Statement> {
+        return block.statements.iter().cloned().map(Spanned::into_node).collect();
+    }
+
+    #[test]
+    fn locate_syntax_errors() {
+        let report = |text: &str| return diagnostic::render(parse(text).unwrap_err().as_ref());
+        let text = "fn f() {\n  let a: <- 1;\n}";
+        let unexpected = report(text);
+
+        assert_eq!(unexpected.code, Some("E0001"));
+        assert_eq!(&text[unexpected.span.clone().unwrap()], "<-");
+        assert_eq!(unexpected.locate("main.cwal", text).to_string(), "main.cwal:2:10: error[E0001]: unexpected token `<-`, no pattern matches");
+        assert_eq!(report("fn f() {").span, Some(8..8));
+        assert!(report("fn f() {").message.contains("unexpected end of input"));
+    }
+
+    #[test]
+    fn suggest_misspelled_keyword() {
+        let message = |text| return parse(text).unwrap_err().to_string();
+
+        let text = "fn f() -> i32 {\n    retrun 1;\n}";
+        let report = diagnostic::render(parse(text).unwrap_err().as_ref());
+
+        assert_eq!(report.to_string(), "error[E0001]: expected `;` after expression; `retrun` is not a keyword, did you mean `ret`?");
+        assert_eq!(report.locate("main.cwal", text).location.as_deref(), Some("main.cwal:2:5"));
+        assert!(message("fn f() { let x <- 1; whiel (x) {} }").ends_with("`whiel` is not a keyword, did you mean `while`?"));
+        assert_eq!(message("fn f() { total 1; }"), "error[E0001]: expected `;` after expression");
+    }
+
+    #[test]
+    fn clone_mid_parse() {
+        let mut parser = Parser::new(false);
+
+        for token in tokenizer::tokenize("fn f() -> i32 { 1").unwrap().iter() {
+            parser.process(token).unwrap();
+        }
+
+        let mut trial = parser.clone();
+        let unfinished = parser.clone();
+
+        for token in tokenizer::tokenize("+ 2 }").unwrap().iter() {
+            trial.process(token).unwrap();
+        }
+
+        parser.process(&token::Token::Symbol(token::Symbol::RightBrace)).unwrap();
+
+        assert!(unfinished.finish().is_err());
+        assert_ne!(parser.finish().unwrap(), trial.finish().unwrap());
+    }
+
+    #[test]
+    fn parse_default_param() {
+        let program = parse("fn f(a: i32, b: i32 = 0) {}").unwrap();
+        let params = &function(&program, 0).signature.params;
+
+        assert_eq!(params.len(), 2);
+        assert_eq!(params[0].default, None);
+        assert_eq!(params[1].default, Some(ast::Expression::Literal("0".to_string())));
     }
 
-    return Ok(());
+    #[test]
+    fn parse_call_omitting_default() {
+        let program = parse("fn f(a: i32, b: i32 = 0) {} fn g() { f(1); }").unwrap();
+
+        assert_eq!(statements(&function(&program, 1).body), vec![
+            ast::Statement::Expression(ast::Expression::Call {
+                callee: "f".to_string(),
+                args: vec![ast::Expression::Literal("1".to_string())]
+            })
+        ]);
+    }
+
+    #[test]
+    fn parse_function_visibility() {
+        let program = parse("pub fn f() {} fn g() {}").unwrap();
+
+        assert!(function(&program, 0).is_public);
+        assert!(!function(&program, 1).is_public);
+    }
+
+    #[test]
+    fn parse_attributed_function() {
+        let text = "@export(\"twice\") @inline pub fn f() {}\n@cold fn g() {} fn h() {}";
+        let program = parse(text).unwrap();
+        let attributes = |index| return function(&program, index).attributes.iter()
+            .map(|x| return (&text[x.span.clone()], x.name.as_str(), x.argument.as_deref()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(attributes(0), vec![("@export(\"twice\")", "export", Some("twice")), ("@inline", "inline", None)]);
+        assert_eq!(attributes(1), vec![("@cold", "cold", None)]);
+        assert!(attributes(2).is_empty());
+        assert!(function(&program, 0).is_public);
+        assert!(parse("@inline let g = 1;").is_err());
+        assert!(parse("@export(1) fn f() {}").is_err());
+    }
+
+    #[test]
+    fn parse_nested_function() {
+        let program = parse("fn f(a: i32) -> i32 { fn twice(x: i32) -> i32 { x * 2 } twice(a) }").unwrap();
+        let body = &function(&program, 0).body;
+
+        assert_eq!(program.declarations.len(), 1);
+        assert!(matches!(&body.statements[0].node, ast::Statement::Function(x) if x.name == "twice" && x.signature.params.len() == 1));
+        assert!(matches!(body.tail.as_deref(), Some(ast::Expression::Call { callee, .. }) if callee == "twice"));
+    }
+
+    #[test]
+    fn locate_statements() {
+        let text = "fn f(a: i32) -> i32 {\n    let b <- a;\n    if (b) { g(b); }\n    b + 1\n}";
+        let program = parse(text).unwrap();
+        let body = &function(&program, 0).body;
+        let located = body.statements.iter().map(|x| return &text[x.span.clone()]).collect::<Vec<_>>();
+
+        assert_eq!(located, vec!["let b <- a;", "if (b) { g(b); }"]);
+        assert_eq!(&text[body.tail.as_ref().unwrap().span.clone()], "b + 1");
+
+        match &body.statements[1].node {
+            ast::Statement::If(x) => assert_eq!(&text[x.block.statements[0].span.clone()], "g(b);"),
+            x => panic!("unexpected statement {:?}", x)
+        }
+    }
+
+    #[test]
+    fn parse_tail_expression() {
+        let program = parse("fn f(a: i32) -> i32 { ret a; a + 1 }").unwrap();
+        let body = &function(&program, 0).body;
+
+        assert_eq!(body.statements.len(), 1);
+        assert!(matches!(body.tail.as_deref(), Some(ast::Expression::Binary { .. })));
+    }
+
+    #[test]
+    fn desugar_increment() {
+        let program = parse("fn f(i: i32) { i++; i--; }").unwrap();
+        let step = |op| return ast::Statement::Assignment(ast::Expression::Identifier("i".to_string()), ast::Expression::Binary {
+            op,
+            lhs: Box::new(ast::Expression::Identifier("i".to_string())),
+            rhs: Box::new(ast::Expression::Literal("1".to_string()))
+        });
+
+        assert_eq!(statements(&function(&program, 0).body), vec![step(token::Symbol::Plus), step(token::Symbol::Minus)]);
+    }
+
+    #[test]
+    fn reject_increment_of_literal() {
+        assert!(parse("fn f() { 5++; }").unwrap_err().to_string().contains("cannot increment or decrement the literal `5`, only variables can be"));
+        assert!(parse("fn f() { g()--; }").unwrap_err().to_string().contains("cannot increment or decrement the result of `g`"));
+    }
+
+    // `--` is one symbol, as in C, so it never reads as a minus and a negation
+    #[test]
+    fn reject_decrement_between_operands() {
+        let hint = "`--` only decrements a variable, as in `i--`, so write `a - -b` to subtract a negation";
+
+        assert!(parse("fn f(a: i32, b: i32) -> i32 { a--b }").unwrap_err().to_string().contains(&format!("expected `;` after decrement; {}", hint)));
+        assert!(parse("fn f(a: i32, b: i32) -> i32 { ret a--b; }").unwrap_err().to_string().contains(&format!("unexpected token `--`; {}", hint)));
+        assert!(parse("fn f(a: i32, b: i32) -> i32 { a - -b }").is_ok());
+    }
+
+    #[test]
+    fn parse_repeat_statement() {
+        let program = parse("fn f() { repeat 0x3 { g(); } }").unwrap();
+
+        assert_eq!(statements(&function(&program, 0).body), vec![ast::Statement::Repeat(ast::RepeatStatement {
+            count: "0x3".to_string(),
+            block: ast::Block {
+                statements: vec![Spanned::new(ast::Statement::Expression(ast::Expression::Call { callee: "g".to_string(), args: vec![] }), 22..26)],
+                tail: None
+            }
+        })]);
+    }
+
+    #[test]
+    fn reject_repeat_of_non_constant_count() {
+        assert!(parse("fn f(n: i32) { repeat n { g(); } }").is_err());
+        assert!(parse("fn f() { repeat (3) { g(); } }").is_err());
+    }
+
+    #[test]
+    fn limit_nesting_depth() {
+        let nested = |depth| return format!("fn f() -> i32 {{ {}1{} }}", "(".repeat(depth), ")".repeat(depth));
+        let err = parse(&nested(10000)).unwrap_err();
+
+        assert_eq!(err.downcast_ref(), Some(&diagnostic::CompileError::RecursionLimit(DEFAULT_MAX_DEPTH)));
+        assert!(parse(&nested(40)).is_ok());
+        assert!(parse_syntax_with_limit(&tokenizer::tokenize(&nested(40)).unwrap(), false, 16).is_err());
+    }
+
+    #[test]
+    fn limit_nesting_depth_at_boundary() {
+        let within = |text: String, limit| return parse_syntax_with_limit(&tokenizer::tokenize(&text).unwrap(), false, limit).is_ok();
+        // the body and the expression in it take the first two levels
+        let parens = |depth| return format!("fn f() -> i32 {{ {}1{} }}", "(".repeat(depth), ")".repeat(depth));
+        let calls = |depth| return format!("fn f() -> i32 {{ {}1{} }}", "g(".repeat(depth), ")".repeat(depth));
+        // the body takes the first level
+        let blocks = |depth| return format!("fn f(a: i32) {{ {}{} }}", "if (a) { ".repeat(depth), "}".repeat(depth));
+
+        for limit in [8, DEFAULT_MAX_DEPTH] {
+            assert!(within(parens(limit - 2), limit));
+            assert!(!within(parens(limit - 1), limit));
+            assert!(within(calls(limit - 2), limit));
+            assert!(!within(calls(limit - 1), limit));
+            assert!(within(blocks(limit - 1), limit));
+            assert!(!within(blocks(limit), limit));
+        }
+    }
+
+    #[test]
+    fn read_operator_chain_flat() {
+        let chain = |operators: &str| return format!("fn f(a: i32) -> i32 {{ a{} }}", operators.repeat(2000));
+
+        // a chain is two levels deep however long it is
+        assert!(parse_syntax_with_limit(&tokenizer::tokenize(&chain(" + a")).unwrap(), false, 2).is_ok());
+        assert!(parse_syntax_with_limit(&tokenizer::tokenize(&chain(" * -a")).unwrap(), false, 2).is_ok());
+
+        let program = parse("fn f(a: i32) -> i32 { -a * a + a < a ? !a : a }").unwrap();
+        let unary = |op, operand| return ast::Expression::Unary { op, operand: Box::new(operand) };
+        let binary = |op, lhs, rhs| return ast::Expression::Binary { op, lhs: Box::new(lhs), rhs: Box::new(rhs) };
+        let a = || return ast::Expression::Identifier("a".to_string());
+
+        assert_eq!(function(&program, 0).body.tail.as_deref(), Some(&ast::Expression::Conditional {
+            condition: Box::new(binary(
+                token::Symbol::LessThan,
+                binary(token::Symbol::Plus, binary(token::Symbol::Asterisk, unary(token::Symbol::Minus, a()), a()), a()),
+                a()
+            )),
+            consequent: Box::new(unary(token::Symbol::LogicalNegation, a())),
+            alternative: Box::new(a())
+        }));
+    }
+
+    #[test]
+    fn reject_reserved_keyword_as_name() {
+        let message = |text| return parse(text).unwrap_err().to_string();
+
+        assert_eq!(message("fn if() {}"), "error[E0008]: `if` is a reserved keyword and cannot be used as a name");
+        assert!(message("fn f(a: i32, while: i32) {}").contains("`while` is a reserved keyword"));
+        assert!(message("fn f() { let ret <- 1; }").contains("`ret` is a reserved keyword"));
+        assert!(message("type fn = i32;").contains("`fn` is a reserved keyword"));
+    }
+
+    #[test]
+    fn parse_function_type_alias() {
+        let program = parse("type BinOp = fn (i32, i32) -> i32;").unwrap();
+
+        match &program.declarations[0] {
+            ast::Declaration::Type(x) => {
+                assert_eq!(x.name, "BinOp");
+                assert_eq!(x.ty, ast::TypeExpression::Function(
+                    vec![token::Type::I32, token::Type::I32],
+                    Some(Box::new(ast::TypeExpression::Primitive(token::Type::I32)))
+                ));
+            },
+            x => panic!("unexpected declaration: {:?}", x)
+        }
+    }
+
+    #[test]
+    fn parse_memory_offset() {
+        let program = parse("fn f(i: i32) -> i64 { @(i * 8)<i64>(memory) }").unwrap();
+
+        match function(&program, 0).body.tail.as_deref() {
+            Some(ast::Expression::Offset { offset, signature, base, .. }) => {
+                assert!(matches!(offset.as_ref(), ast::Expression::Binary { op: token::Symbol::Asterisk, .. }));
+                assert_eq!(signature, &Some(ast::TypeExpression::Primitive(token::Type::I64)));
+                assert_eq!(base, "memory");
+            },
+            x => panic!("unexpected tail: {:?}", x)
+        }
+    }
+
+    #[test]
+    fn parse_call_indirect_with_alias() {
+        let program = parse("fn f(i: i32) -> i32 { *i(table)::<BinOp>(1, 2) }").unwrap();
+
+        match function(&program, 0).body.tail.as_deref() {
+            Some(ast::Expression::CallIndirect { target, signature, args }) => {
+                assert!(matches!(target.as_ref(), ast::Expression::Offset { base, .. } if base == "table"));
+                assert_eq!(signature, &ast::TypeExpression::Named("BinOp".to_string()));
+                assert_eq!(args.len(), 2);
+            },
+            x => panic!("unexpected tail: {:?}", x)
+        }
+    }
+
+    #[test]
+    fn reject_unterminated_inner_expression() {
+        assert!(parse("fn f(a: i32) -> i32 { a a }").is_err());
+    }
+
+    #[test]
+    fn display_mid_parse_stack() {
+        let tokens = tokenizer::tokenize("fn f(a: i32, b").unwrap();
+        let mut parser = Parser::new(false);
+
+        for token in tokens.iter() {
+            parser.process(token).unwrap();
+        }
+
+        assert_eq!(parser.to_string(), [
+            "Program:[0]",
+            "  FunctionDeclaration:[5]",
+            "    Signature:[1]",
+            "      Parameter:[2]",
+            "        ParamSequence:[1]",
+            "          ConParamType:[2]",
+            "            ParamType:[2]",
+            ""
+        ].join("\n"));
+    }
+
+    fn expected_after(text: &str) -> Vec<grammar::TokenKind> {
+        let mut parser = Parser::new(false);
+
+        for token in tokenizer::tokenize(text).unwrap().iter() {
+            parser.process(token).unwrap();
+        }
+
+        return parser.expected_tokens();
+    }
+
+    #[test]
+    fn expect_tokens_in_function_declaration() {
+        assert_eq!(expected_after("fn"), vec![grammar::TokenKind::any_identifier()]);
+        assert_eq!(expected_after("fn f"), vec![
+            grammar::TokenKind::from_symbol(token::Symbol::LessThan),
+            grammar::TokenKind::from_symbol(token::Symbol::LeftParenthese)
+        ]);
+
+        let kinds = expected_after("fn f(a: i32");
+
+        assert!(kinds.contains(&grammar::TokenKind::from_symbol(token::Symbol::Comma)));
+        assert!(kinds.contains(&grammar::TokenKind::from_symbol(token::Symbol::RightParenthese)));
+        assert!(!kinds.contains(&grammar::TokenKind::any_identifier()));
+
+        let kinds = expected_after("fn f(a: i32)");
+
+        assert!(kinds.contains(&grammar::TokenKind::from_symbol(token::Symbol::LeftBrace)));
+        assert!(kinds.contains(&grammar::TokenKind::from_symbol(token::Symbol::RightArrow)));
+
+        let kinds = expected_after("fn f() { 1 +");
+
+        assert!(kinds.contains(&grammar::TokenKind::any_numeric_literal()));
+        assert!(kinds.contains(&grammar::TokenKind::any_identifier()));
+        assert!(!kinds.contains(&grammar::TokenKind::from_symbol(token::Symbol::RightBrace)));
+    }
+
+    #[test]
+    fn parse_single_expression() {
+        let expr = parse_expression(&tokenizer::tokenize("1 + 2 * 3").unwrap()).unwrap();
+        let literal = |x: &str| return Box::new(ast::Expression::Literal(x.to_string()));
+
+        assert_eq!(expr, ast::Expression::Binary {
+            op: token::Symbol::Plus,
+            lhs: literal("1"),
+            rhs: Box::new(ast::Expression::Binary { op: token::Symbol::Asterisk, lhs: literal("2"), rhs: literal("3") })
+        });
+    }
+
+    #[test]
+    fn reject_incomplete_expression() {
+        assert!(parse_expression(&tokenizer::tokenize("1 +").unwrap()).is_err());
+        assert!(parse_expression(&tokenizer::tokenize("1 2").unwrap()).is_err());
+    }
+
+    #[test]
+    fn parse_grammar_constructs() {
+        test_util::assert_parses("imp fn log(a: i32) from \"env\";");
+        test_util::assert_parses("mem memory = (1; page; 1);");
+        test_util::assert_parses("type BinOp = fn (i32, i32) -> i32;");
+        test_util::assert_parses("fn f(a: i32, b: i32 = 7) -> i32 { if (a) { ret b; } elif (b) { a } else { 0 } }");
+        test_util::assert_parses("fn f() { let x <- 0; while (x < 3) { x <- x + 1; } repeat 2 { g(); } }");
+    }
+
+    #[test]
+    fn reject_grammar_constructs() {
+        test_util::assert_parse_error("fn f() { ret a + b }", &diagnostic::UNEXPECTED_TOKEN);
+        test_util::assert_parse_error("fn f(a: i32 b: i32) {}", &diagnostic::UNEXPECTED_TOKEN);
+        test_util::assert_parse_error("let a <- 1 # 2;", &diagnostic::UNKNOWN_TOKEN);
+        test_util::assert_parse_error("imp \"env", &diagnostic::UNCLOSED_STRING);
+        test_util::assert_parse_error("fn f(a: i32, while: i32) {}", &diagnostic::RESERVED_KEYWORD);
+    }
+
+    #[test]
+    fn parse_generic_function() {
+        let program = parse("fn pair<T, U>(a: T, b: U) -> T where T: i32 | i64, U: f64 { a }\nfn f() -> i32 { pair::<i32, f64>(1, 2.0) }").unwrap();
+        let generic = function(&program, 0);
+
+        assert_eq!(generic.generics, vec!["T".to_string(), "U".to_string()]);
+        assert_eq!(generic.constraints, vec![
+            ast::Constraint { param: "T".to_string(), types: vec![token::Type::I32, token::Type::I64] },
+            ast::Constraint { param: "U".to_string(), types: vec![token::Type::F64] }
+        ]);
+        assert_eq!(generic.signature.params[1].ty, ast::TypeExpression::Named("U".to_string()));
+        assert_eq!(function(&program, 1).body.tail.as_deref(), Some(&ast::Expression::GenericCall {
+            callee: "pair".to_string(),
+            types: vec![ast::TypeExpression::Primitive(token::Type::I32), ast::TypeExpression::Primitive(token::Type::F64)],
+            args: vec![ast::Expression::Literal("1".to_string()), ast::Expression::Literal("2.0".to_string())]
+        }));
+    }
+
+    #[test]
+    fn reject_several_types_for_memory_access() {
+        assert!(parse("fn f() -> i32 { @0<i32, i64>(memory) }").unwrap_err().to_string().contains("expected a single type in `<..>`, found 2"));
+    }
+
+    #[test]
+    fn parse_empty_input() {
+        assert_eq!(parse("").unwrap().declarations, vec![]);
+        assert_eq!(parse(" \n\t\r\n").unwrap().declarations, vec![]);
+        assert_eq!(parse("// nothing here\n").unwrap().declarations, vec![]);
+    }
+
+    #[test]
+    fn reject_incomplete_input() {
+        assert!(parse("fn f(a: i32").is_err());
+    }
+
+    #[test]
+    fn parse_null_reference() {
+        let program = parse("fn f(r: xref) -> i32 { let s <- Null; is_null(r) }").unwrap();
+        let body = &function(&program, 0).body;
+
+        match &body.statements[0].node {
+            ast::Statement::Variable(x) => assert_eq!(x.value, ast::Expression::Literal("Null".to_string())),
+            x => panic!("unexpected statement {:?}", x)
+        }
+
+        assert_eq!(body.tail.as_deref(), Some(&ast::Expression::Call { callee: "is_null".to_string(), args: vec![ast::Expression::Identifier("r".to_string())] }));
+    }
+
+    #[test]
+    fn parse_typed_variable() {
+        let program = parse("let g: xref <- Null; fn f() { let r: fref <- Null; let n: i64 <- 5; }").unwrap();
+        let declared = statements(&function(&program, 1).body).into_iter().map(|x| return match x {
+            ast::Statement::Variable(x) => (x.ty.clone(), x.value.clone()),
+            x => panic!("unexpected statement {:?}", x)
+        }).collect::<Vec<_>>();
+
+        match &program.declarations[0] {
+            ast::Declaration::Variable(x) => assert_eq!(x.ty, Some(ast::TypeExpression::Primitive(token::Type::Xref))),
+            x => panic!("unexpected declaration {:?}", x)
+        }
+
+        assert_eq!(declared, vec![
+            (Some(ast::TypeExpression::Primitive(token::Type::Fref)), ast::Expression::Literal("Null".to_string())),
+            (Some(ast::TypeExpression::Primitive(token::Type::I64)), ast::Expression::Literal("5".to_string()))
+        ]);
+        assert!(parse("fn f() { let (a, b): i32 <- (1, 2); }").unwrap_err().to_string().contains("a destructuring `let` cannot have a type"));
+    }
+
+    #[test]
+    fn parse_uninitialized_variable() {
+        let program = parse("fn f() -> f64 { let mut n: i64; let x: f64; x }").unwrap();
+        let values = statements(&function(&program, 0).body).into_iter().map(|x| return match x {
+            ast::Statement::Variable(x) => x.value.clone(),
+            x => panic!("unexpected statement {:?}", x)
+        }).collect::<Vec<_>>();
+
+        assert_eq!(values, vec![
+            ast::Expression::Cast { ty: token::Type::I64, operand: Box::new(ast::Expression::Literal("0".to_string())) },
+            ast::Expression::Literal("0.0f64".to_string())
+        ]);
+    }
+
+    #[test]
+    fn reject_uninitialized_variable_without_type() {
+        assert!(parse("fn f() { let x; }").unwrap_err().to_string().contains("`let x` needs a type when it has no initializer"));
+        assert!(parse("fn f() { let (a, b): i32; }").unwrap_err().to_string().contains("a destructuring `let` needs an initializer"));
+        assert!(parse("fn f() { let r: fref; }").unwrap_err().to_string().contains("only number types start at zero"));
+        assert!(parse("let g: i32;").unwrap_err().to_string().contains("global `g` needs an initializer"));
+    }
+
+    #[test]
+    fn parse_variadic_import() {
+        let program = parse("imp fn log(fmt: i32, ...args: i32) from \"env\";").unwrap();
+
+        match &program.declarations[0] {
+            ast::Declaration::Import(ast::ImportDeclaration { item: ast::ImportItem::Function(_, signature), .. }) => {
+                assert_eq!(signature.params.iter().map(|x| return x.is_variadic).collect::<Vec<_>>(), vec![false, true]);
+            },
+            x => panic!("unexpected declaration {:?}", x)
+        }
+    }
+
+    fn names(program: &ast::Program) -> Vec<&str> {
+        return program.declarations.iter().map(|x| return match x {
+            ast::Declaration::Function(x) => x.name.as_str(),
+            ast::Declaration::Variable(_) => "let",
+            ast::Declaration::Error(_) => "error",
+            _ => "other"
+        }).collect();
+    }
+
+    #[test]
+    fn recover_around_broken_declaration() {
+        let text = "fn a() -> i32 { 1 }\nfn b() { let <- 1; }\nfn c() {}";
+        let tokens = tokenizer::tokenize(text).unwrap();
+        let (program, errors) = Parser::new(false).into_ast(&tokens);
+        let program = program.unwrap();
+
+        assert_eq!(names(&program), vec!["a", "error", "c"]);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].to_string().contains("unexpected token"));
+
+        match (&program.declarations[1], &errors[0]) {
+            (ast::Declaration::Error(x), diagnostic::Report { span: Some(span), .. }) => {
+                assert_eq!(&text[x.span.clone()], "fn b() { let <- 1; }");
+                assert_eq!(&text[span.clone()], "<-");
+            },
+            _ => panic!("expected an error node")
+        }
+    }
+
+    #[test]
+    fn recover_at_next_declaration() {
+        let tokens = tokenizer::tokenize("let x <- 1\nfn f() {}\nfn g( {}\nlet y <- 2;").unwrap();
+        let (program, errors) = Parser::new(false).into_ast(&tokens);
+
+        assert_eq!(names(&program.unwrap()), vec!["error", "f", "error", "let"]);
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn recover_from_unexpected_end() {
+        let tokens = tokenizer::tokenize("fn f() {}\nfn g(a: i32").unwrap();
+        let (program, errors) = Parser::new(false).into_ast(&tokens);
+
+        assert_eq!(names(&program.unwrap()), vec!["f", "error"]);
+        assert_eq!(errors.len(), 1);
+    }
 }
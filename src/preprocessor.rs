@@ -0,0 +1,118 @@
+use std::error::Error;
+
+use crate::span::Spanned;
+use crate::token;
+use crate::tokenizer::TokenList;
+
+// main program section
+// `incl NAME { ... }` keeps its contents when `NAME` is defined with `-D NAME`,
+// and drops them otherwise, before the tokens reach the parser. `incl "path"`
+// includes a file and is left to the parser
+pub fn preprocess<'a>(tokens: TokenList<'a>, defines: &[String]) -> Result<TokenList<'a>, Box<dyn Error>> {
+    let mut res = Vec::with_capacity(tokens.len());
+    // one entry per open brace, holding the name of the `incl` block it opens
+    let mut braces: Vec<Option<&str>> = vec![];
+    // brace depth inside a dropped block
+    let mut dropped = 0;
+    let mut tokens = tokens.into_iter();
+
+    while let Some(spanned) = tokens.next() {
+        if dropped > 0 {
+            match spanned.node {
+                token::Token::Symbol(token::Symbol::LeftBrace) => dropped += 1,
+                token::Token::Symbol(token::Symbol::RightBrace) => dropped -= 1,
+                _ => {}
+            }
+
+            continue;
+        }
+
+        match spanned.node {
+            token::Token::Keyword(token::Keyword::Include) => {
+                let next = next_significant(&mut tokens);
+                let name = match next.as_ref().map(|x| return &x.node) {
+                    Some(token::Token::Identifier(x)) => x.value(),
+                    Some(token::Token::Literal(token::Literal::String(_))) => {
+                        res.push(spanned);
+                        res.extend(next);
+
+                        continue;
+                    },
+                    _ => return Err("expected a name after `incl`".into())
+                };
+
+                if next_significant(&mut tokens).map(Spanned::into_node) != Some(token::Token::Symbol(token::Symbol::LeftBrace)) {
+                    return Err(format!("expected `{{` after `incl {}`", name).into());
+                }
+
+                if defines.iter().any(|x| return x == name) {
+                    braces.push(Some(name));
+                }
+                else {
+                    dropped = 1;
+                }
+            },
+            token::Token::Symbol(token::Symbol::LeftBrace) => {
+                braces.push(None);
+                res.push(spanned);
+            },
+            token::Token::Symbol(token::Symbol::RightBrace) => {
+                if let Some(None) | None = braces.pop() {
+                    res.push(spanned);
+                }
+            },
+            _ => res.push(spanned)
+        }
+    }
+
+    if dropped > 0 {
+        return Err("unclosed `incl` block".into());
+    }
+
+    if let Some(name) = braces.into_iter().flatten().last() {
+        return Err(format!("unclosed `incl {}` block", name).into());
+    }
+
+    return Ok(res);
+}
+
+// the next token that is not a comment
+fn next_significant<'a>(tokens: &mut impl Iterator<Item = Spanned<token::Token<'a>>>) -> Option<Spanned<token::Token<'a>>> {
+    return tokens.find(|x| return !matches!(x.node, token::Token::Comment(_)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer;
+
+    fn preprocess_source<'a>(text: &'a str, defines: &[&str]) -> Result<Vec<token::Token<'a>>, Box<dyn Error>> {
+        let defines = defines.iter().map(|x| return x.to_string()).collect::<Vec<_>>();
+        let tokens = preprocess(tokenizer::tokenize(text)?, &defines)?;
+
+        return Ok(tokens.into_iter().map(Spanned::into_node).collect());
+    }
+
+    #[test]
+    fn keep_defined_section() {
+        assert_eq!(
+            preprocess_source("incl DEBUG /* on */ { fn f() {} }", &["DEBUG"]).unwrap(),
+            preprocess_source("fn f() {}", &[]).unwrap()
+        );
+    }
+
+    #[test]
+    fn drop_undefined_section() {
+        assert_eq!(
+            preprocess_source("fn f() {} incl DEBUG { fn g() { if (1) {} } } fn h() {}", &["TRACE"]).unwrap(),
+            preprocess_source("fn f() {} fn h() {}", &[]).unwrap()
+        );
+    }
+
+    #[test]
+    fn reject_unclosed_section() {
+        assert!(preprocess_source("incl DEBUG { fn f() {}", &["DEBUG"]).is_err());
+        assert!(preprocess_source("incl DEBUG { fn f() {}", &[]).is_err());
+        assert!(preprocess_source("incl { fn f() {} }", &[]).is_err());
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::ast;
+use crate::diagnostic;
+use crate::span::Span;
+use crate::tokenizer;
+use crate::visitor::{self, Visitor};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SymbolKind {
+    Function,
+    Global,
+    Type,
+    Table,
+    Memory
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    name: String,
+    kind: SymbolKind,
+    ty: Option<String>,
+    span: Span,
+    is_public: bool,
+    is_imported: bool,
+    is_exported: bool
+}
+
+// a parsed source file taking part in linking. files given on the command
+// line and those reached through `pub incl` only are exported, the exports of
+// any other included file stay internal to the module
+#[derive(Debug)]
+pub struct Unit {
+    pub path: String,
+    pub text: String,
+    pub program: ast::Program,
+    pub is_exported: bool
+}
+
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+    references: HashMap<String, Vec<String>>
+}
+
+impl Symbol {
+    fn new(name: &str, kind: SymbolKind, ty: Option<String>, span: &Span) -> Self {
+        return Self {
+            name: name.to_string(),
+            kind,
+            ty,
+            span: span.clone(),
+            is_public: false,
+            is_imported: false,
+            is_exported: false
+        };
+    }
+
+    pub fn name(&self) -> &str {
+        return &self.name;
+    }
+
+    pub const fn kind(&self) -> &SymbolKind {
+        return &self.kind;
+    }
+
+    pub const fn span(&self) -> &Span {
+        return &self.span;
+    }
+
+    pub const fn is_public(&self) -> bool {
+        return self.is_public;
+    }
+
+    pub const fn is_imported(&self) -> bool {
+        return self.is_imported;
+    }
+
+    pub const fn is_exported(&self) -> bool {
+        return self.is_exported;
+    }
+}
+
+impl SymbolTable {
+    pub fn symbols(&self) -> &[Symbol] {
+        return &self.symbols;
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        return self.symbols.iter().find(|x| return x.name == name);
+    }
+
+    // one line per symbol, with spans resolved against the source text
+    pub fn dump(&self, text: &str) -> String {
+        let mut out = String::new();
+
+        for symbol in self.symbols.iter() {
+            let (ln, col) = tokenizer::line_col(text, symbol.span.start);
+
+            out.push_str(&format!(
+                "{:<8} {:<16} {:<24} {}:{}{}\n",
+                symbol.kind,
+                symbol.name,
+                symbol.ty.as_deref().unwrap_or("_"),
+                ln,
+                col,
+                if symbol.is_exported { " exported" } else { "" }
+            ));
+        }
+
+        return out;
+    }
+
+    // names referenced from the body of the given function
+    pub fn references(&self, name: &str) -> &[String] {
+        return self.references.get(name).map_or(&[], |x| return x.as_slice());
+    }
+
+    // whether the name is referenced from the body of any function
+    pub fn is_referenced(&self, name: &str) -> bool {
+        return self.references.values().any(|x| return x.iter().any(|x| return x == name));
+    }
+
+    fn declare(&mut self, symbol: Symbol) -> Result<(), Box<dyn Error>> {
+        if self.get(&symbol.name).is_some() {
+            return Err(format!("`{}` is defined multiple times", symbol.name).into());
+        }
+
+        self.symbols.push(symbol);
+
+        return Ok(());
+    }
+
+    fn declare_function(&mut self, function: &ast::FunctionDeclaration, is_exported: bool) -> Result<(), Box<dyn Error>> {
+        let mut symbol = Symbol::new(&function.name, SymbolKind::Function, Some(function.signature.to_string()), &function.span);
+        symbol.is_public = function.is_public;
+        symbol.is_exported = is_exported;
+
+        let mut references = References::default();
+        references.visit_block(&function.body);
+
+        self.references.insert(function.name.clone(), references.names);
+
+        return self.declare(symbol);
+    }
+
+    fn declare_variable(&mut self, variable: &ast::VariableDeclaration, is_exported: bool) -> Result<(), Box<dyn Error>> {
+        let bindings = match &variable.pattern {
+            ast::Pattern::Single(x) => std::slice::from_ref(x),
+            ast::Pattern::Tuple(list) => list.as_slice()
+        };
+
+        for binding in bindings.iter() {
+            let mut symbol = Symbol::new(&binding.name, SymbolKind::Global, None, &variable.span);
+            symbol.is_exported = is_exported;
+
+            self.declare(symbol)?;
+        }
+
+        return Ok(());
+    }
+}
+
+impl fmt::Display for SymbolKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return f.pad(match self {
+            SymbolKind::Function => "function",
+            SymbolKind::Global => "global",
+            SymbolKind::Type => "type",
+            SymbolKind::Table => "table",
+            SymbolKind::Memory => "memory"
+        });
+    }
+}
+
+// main program section
+pub fn resolve(program: &ast::Program) -> Result<SymbolTable, Box<dyn Error>> {
+    let mut table = SymbolTable::default();
+    let mut aliased = vec![];
+
+    for decl in program.declarations.iter() {
+        match decl {
+            ast::Declaration::Function(x) => table.declare_function(x, x.attribute("export").is_some())?,
+            ast::Declaration::Type(x) => table.declare(Symbol::new(&x.name, SymbolKind::Type, Some(x.ty.to_string()), &x.span))?,
+            ast::Declaration::Table(x) => table.declare(Symbol::new(&x.name, SymbolKind::Table, Some(x.ty.to_string()), &x.span))?,
+            ast::Declaration::Memory(x) => table.declare(Symbol::new(&x.name, SymbolKind::Memory, Some(x.ty.to_string()), &x.span))?,
+            ast::Declaration::Variable(x) => table.declare_variable(x, false)?,
+            ast::Declaration::Import(x) => {
+                let mut symbol = match &x.item {
+                    ast::ImportItem::Function(name, signature) => Symbol::new(name, SymbolKind::Function, Some(signature.to_string()), &x.span),
+                    ast::ImportItem::Table(name, ty) => Symbol::new(name, SymbolKind::Table, Some(ty.to_string()), &x.span),
+                    ast::ImportItem::Memory(name, ty) => Symbol::new(name, SymbolKind::Memory, Some(ty.to_string()), &x.span),
+                    ast::ImportItem::Variable(binding, ty) => Symbol::new(&binding.name, SymbolKind::Global, Some(ty.to_string()), &x.span)
+                };
+                symbol.is_imported = true;
+
+                table.declare(symbol)?;
+            },
+            ast::Declaration::Export(x) => match &x.item {
+                ast::ExportItem::Function(x) => table.declare_function(x, true)?,
+                ast::ExportItem::Table(x) => {
+                    let mut symbol = Symbol::new(&x.name, SymbolKind::Table, Some(x.ty.to_string()), &x.span);
+                    symbol.is_exported = true;
+
+                    table.declare(symbol)?;
+                },
+                ast::ExportItem::Memory(x) => {
+                    let mut symbol = Symbol::new(&x.name, SymbolKind::Memory, Some(x.ty.to_string()), &x.span);
+                    symbol.is_exported = true;
+
+                    table.declare(symbol)?;
+                },
+                ast::ExportItem::Variable(x) => table.declare_variable(x, true)?,
+                ast::ExportItem::Aliased(name, _) => aliased.push(name)
+            },
+            // the included file is resolved as a unit of its own, an imported
+            // module is expanded into imports by `importer::expand`
+            ast::Declaration::Include(_) | ast::Declaration::ImportModule(_) | ast::Declaration::StaticAssert(_) | ast::Declaration::Error(_) => {}
+        }
+    }
+
+    // items can be exported by name before they are declared
+    for name in aliased {
+        match table.symbols.iter_mut().find(|x| return &x.name == name) {
+            Some(symbol) => symbol.is_exported = true,
+            None => return Err(format!("cannot find `{}` to export", name).into())
+        }
+    }
+
+    return Ok(table);
+}
+
+// merges every unit into one program. names are shared by the whole module,
+// so a function in one file may call one declared in another, and a name
+// declared in two files is a collision. the symbols of each unit are
+// returned in unit order, with spans into the text of their own file
+pub fn link(units: &[Unit]) -> Result<(ast::Program, Vec<SymbolTable>), Box<dyn Error>> {
+    let mut declarations = vec![];
+    let mut tables = vec![];
+    // the first declaration of each name, as `path:ln:col`
+    let mut locations: HashMap<String, String> = HashMap::new();
+
+    for unit in units.iter() {
+        let mut table = resolve(&unit.program).map_err(|x| return format!("{}: {}", unit.path, x))?;
+
+        for symbol in table.symbols() {
+            let (ln, col) = tokenizer::line_col(&unit.text, symbol.span.start);
+            let location = format!("{}:{}:{}", unit.path, ln, col);
+
+            if let Some(first) = locations.get(&symbol.name) {
+                return Err(diagnostic::Report::new(diagnostic::Severity::Error, format!("`{}` is defined multiple times, first at {}", symbol.name, first)).at(location).into());
+            }
+
+            locations.insert(symbol.name.clone(), location);
+        }
+
+        if unit.is_exported {
+            declarations.extend(unit.program.declarations.iter().filter(|x| return !matches!(x, ast::Declaration::Include(_))).cloned());
+        }
+        else {
+            declarations.extend(unit.program.declarations.iter().filter_map(hide_export));
+            table.symbols.iter_mut().for_each(|x| x.is_exported = false);
+        }
+
+        tables.push(table);
+    }
+
+    return Ok((ast::Program { declarations }, tables));
+}
+
+// the declaration without its export, exports by name are dropped
+fn hide_export(decl: &ast::Declaration) -> Option<ast::Declaration> {
+    return match decl {
+        ast::Declaration::Export(x) => match &x.item {
+            ast::ExportItem::Function(x) => Some(ast::Declaration::Function(x.clone())),
+            ast::ExportItem::Table(x) => Some(ast::Declaration::Table(x.clone())),
+            ast::ExportItem::Memory(x) => Some(ast::Declaration::Memory(x.clone())),
+            ast::ExportItem::Variable(x) => Some(ast::Declaration::Variable(x.clone())),
+            ast::ExportItem::Aliased(..) => None
+        },
+        ast::Declaration::Function(x) => Some(ast::Declaration::Function(ast::FunctionDeclaration {
+            attributes: x.attributes.iter().filter(|x| return x.name != "export").cloned().collect(),
+            ..x.clone()
+        })),
+        ast::Declaration::Include(_) => None,
+        x => Some(x.clone())
+    };
+}
+
+// the files included by a program, with whether their exports are kept
+pub fn includes(program: &ast::Program) -> Vec<(&str, bool)> {
+    return program.declarations.iter().filter_map(|x| return match x {
+        ast::Declaration::Include(x) => Some((x.path.as_str(), x.is_public)),
+        _ => None
+    }).collect();
+}
+
+// names used by a function body or a program, in order of appearance
+#[derive(Default)]
+pub struct References {
+    pub names: Vec<String>
+}
+
+impl Visitor for References {
+    fn visit_expr(&mut self, expr: &ast::Expression) {
+        match expr {
+            ast::Expression::Identifier(name)
+            | ast::Expression::TypeOf(name)
+            | ast::Expression::Call { callee: name, .. }
+            | ast::Expression::GenericCall { callee: name, .. }
+            | ast::Expression::Offset { base: name, .. } => self.names.push(name.clone()),
+            _ => {}
+        }
+
+        visitor::walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn resolve_source(text: &str) -> Result<SymbolTable, Box<dyn Error>> {
+        return resolve(&parser::parse_syntax(&tokenizer::tokenize(text)?, false)?);
+    }
+
+    #[test]
+    fn resolve_visibility() {
+        let table = resolve_source("pub fn a() {} fn b() { a(); } exp fn c() {}").unwrap();
+
+        assert!(table.get("a").unwrap().is_public());
+        assert!(!table.get("b").unwrap().is_public());
+        assert!(table.get("c").unwrap().is_exported());
+        assert_eq!(table.references("b"), &["a".to_string()]);
+    }
+
+    #[test]
+    fn dump_symbols() {
+        let text = "mem memory = (1; page; 2);\nexp fn add(a: i32, b: i32) -> i32 { a + b }";
+
+        assert_eq!(resolve_source(text).unwrap().dump(text), [
+            "memory   memory           (1; page; 2)             1:1",
+            "function add              fn(i32, i32) -> i32      2:5 exported",
+            ""
+        ].join("\n"));
+    }
+
+    #[test]
+    fn resolve_destructured_global() {
+        let table = resolve_source("let (a, mut b) <- (1, 2);").unwrap();
+
+        assert_eq!(table.get("a").unwrap().kind(), &SymbolKind::Global);
+        assert_eq!(table.get("b").unwrap().kind(), &SymbolKind::Global);
+    }
+
+    #[test]
+    fn reject_duplicate_symbol() {
+        assert!(resolve_source("fn a() {} fn a() {}").is_err());
+    }
+
+    fn unit(path: &str, text: &str) -> Unit {
+        return Unit {
+            path: path.to_string(),
+            text: text.to_string(),
+            program: parser::parse_syntax(&tokenizer::tokenize(text).unwrap(), false).unwrap(),
+            is_exported: true
+        };
+    }
+
+    #[test]
+    fn link_units() {
+        let (program, tables) = link(&[unit("a.cwal", "fn add(a: i32, b: i32) -> i32 { a + b }"), unit("b.cwal", "exp fn f() -> i32 { add(1, 2) }")]).unwrap();
+
+        assert_eq!(program.declarations.len(), 2);
+        assert_eq!(tables.len(), 2);
+        assert!(tables[1].get("f").unwrap().is_exported());
+        assert_eq!(resolve(&program).unwrap().references("f"), &["add".to_string()]);
+    }
+
+    #[test]
+    fn reject_name_defined_in_two_units() {
+        let err = link(&[unit("a.cwal", "fn add() {}"), unit("b.cwal", "fn f() {}\nfn add() {}")]).unwrap_err();
+
+        assert_eq!(err.to_string(), "b.cwal:2:1: error: `add` is defined multiple times, first at a.cwal:1:1");
+    }
+
+    #[test]
+    fn list_includes() {
+        let program = unit("a.cwal", "incl \"b.cwal\";\npub incl \"c.cwal\";\nfn f() {}").program;
+
+        assert_eq!(includes(&program), vec![("b.cwal", false), ("c.cwal", true)]);
+    }
+
+    #[test]
+    fn hide_exports_of_private_include() {
+        let mut lib = unit("b.cwal", "exp \"g\" fn g() -> i32 { 1 }\nfn h() {}\nexp h as \"h\";");
+        lib.is_exported = false;
+
+        let (program, tables) = link(&[unit("a.cwal", "incl \"b.cwal\";\nexp fn f() -> i32 { g() }"), lib]).unwrap();
+
+        assert_eq!(program.declarations.len(), 3);
+        assert!(tables[0].get("f").unwrap().is_exported());
+        assert!(!tables[1].get("g").unwrap().is_exported());
+        assert_eq!(program.declarations.iter().filter(|x| return matches!(x, ast::Declaration::Export(_))).count(), 1);
+    }
+}
@@ -0,0 +1,55 @@
+use std::ops::{Deref, DerefMut, Range};
+
+// byte range in the source text
+pub type Span = Range<usize>;
+
+// a value paired with the span it was read from, as tokens and the
+// statements and tail expression of a block are
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span
+}
+
+impl<T> Spanned<T> {
+    pub const fn new(node: T, span: Span) -> Self {
+        return Self {
+            node,
+            span
+        };
+    }
+
+    pub fn into_node(self) -> T {
+        return self.node;
+    }
+}
+
+impl<T> Deref for Spanned<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        return &self.node;
+    }
+}
+
+impl<T> DerefMut for Spanned<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        return &mut self.node;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deref_to_node() {
+        let mut spanned = Spanned::new(String::from("abc"), 0..3);
+
+        assert_eq!(spanned.len(), 3);
+
+        spanned.push('d');
+
+        assert_eq!(spanned.span, 0..3);
+        assert_eq!(spanned.into_node(), "abcd");
+    }
+}
@@ -0,0 +1,51 @@
+use crate::ast;
+use crate::diagnostic;
+use crate::parser;
+use crate::tokenizer;
+
+// tokenizes and parses the source, panicking with the error when either fails
+#[track_caller]
+pub fn assert_parses(text: &str) -> ast::Program {
+    let result = tokenizer::tokenize(text).and_then(|tokens| return parser::parse_syntax(&tokens, false));
+
+    return match result {
+        Ok(program) => program,
+        Err(err) => panic!("expected `{}` to parse, but it failed with: {}", text, err)
+    };
+}
+
+// tokenizes and parses the source, which must fail with the given kind of error
+#[track_caller]
+pub fn assert_parse_error(text: &str, expected: &diagnostic::Diagnostic) {
+    let result = tokenizer::tokenize(text).and_then(|tokens| return parser::parse_syntax(&tokens, false));
+
+    match result {
+        Ok(program) => panic!("expected `{}` to fail with {}, but it parsed as {:?}", text, expected.code, program),
+        Err(err) => assert!(
+            err.to_string().contains(&format!("error[{}]", expected.code)),
+            "expected `{}` to fail with {} ({}), but it failed with: {}", text, expected.code, expected.title, err
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_parsed_source() {
+        assert_eq!(assert_parses("fn f() {}").declarations.len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected `fn f( {}` to parse")]
+    fn panic_on_unexpected_error() {
+        assert_parses("fn f( {}");
+    }
+
+    #[test]
+    #[should_panic(expected = "expected `fn if() {}` to fail with E0001")]
+    fn panic_on_other_error_kind() {
+        assert_parse_error("fn if() {}", &diagnostic::UNEXPECTED_TOKEN);
+    }
+}
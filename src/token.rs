@@ -1,4 +1,5 @@
 use std::convert::TryFrom;
+use std::fmt;
 use std::ops::Range;
 use regex::Regex;
 
@@ -31,6 +32,8 @@ pub enum Keyword {
     Else,
     ElseIf,
     While,
+    Repeat,
+    Defer,
     Break,
     Cont,
     TypeOf,
@@ -38,7 +41,10 @@ pub enum Keyword {
     Import,
     As,
     From,
-    Include
+    Include,
+    Public,
+    Where,
+    StaticAssert
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -48,6 +54,7 @@ pub struct Identifier<'a>(&'a str);
 pub enum Symbol {
     // general
     Dot,                    // .
+    Ellipsis,               // ...
     Comma,                  // ,
     Colon,                  // :
     SemiColon,              // ;
@@ -55,9 +62,18 @@ pub enum Symbol {
     // operation
     Plus,                   // +
     Minus,                  // -
+    Increment,              // ++
+    Decrement,              // --
     Asterisk,               // *
     Solidus,                // /
     Modulo,                 // %
+    SolidusUnsigned,        // /u
+    ModuloUnsigned,         // %u
+    // `+`, `-` and `*` of unsigned operands, which the checker sets so an
+    // overflow check knows the signedness. they have no spelling of their own
+    PlusUnsigned,
+    MinusUnsigned,
+    AsteriskUnsigned,
     Assignment,             // =
     Equal,                  // ==
     NotEqual,               // !=
@@ -65,6 +81,10 @@ pub enum Symbol {
     GreaterThan,            // >
     LessThanOrEqual,        // <=
     GreaterThanOrEqual,     // >=
+    LessThanUnsigned,       // <u
+    GreaterThanUnsigned,    // >u
+    LessThanOrEqualUnsigned,    // <=u
+    GreaterThanOrEqualUnsigned, // >=u
     LeftArrow,              // <-
     RightArrow,             // ->
     BitwiseAnd,             // &
@@ -80,6 +100,7 @@ pub enum Symbol {
     Query,                  // ?
     PipeForward,            // |>
     DoubleColon,            // ::
+    At,                     // @
 
     // brackets
     LeftBrace,              // {
@@ -90,9 +111,11 @@ pub enum Symbol {
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Type {
-    // number types
+    // number types, unsigned integers share the representation of signed ones
     I32,
     I64,
+    U32,
+    U64,
     F32,
     F64,
 
@@ -116,6 +139,14 @@ pub struct RawToken<'a> {
 }
 
 impl<'a> Identifier<'a> {
+    pub const fn new(value: &'a str) -> Self {
+        return Self(value);
+    }
+
+    pub const fn value(&self) -> &'a str {
+        return self.0;
+    }
+
     pub fn is_alphabetic_valid_char(c: char) -> bool {
         return c.is_alphabetic() || Identifier::is_extended_symbol(c);
     }
@@ -129,7 +160,39 @@ impl<'a> Identifier<'a> {
     }
 }
 
+impl Keyword {
+    // the source spelling of the keyword
+    pub fn name(&self) -> &'static str {
+        return definition::KEYWORD_TOKENS.iter()
+            .find(|x| return &x.1 == self)
+            .map_or("?", |x| return x.0);
+    }
+}
+
+impl Type {
+    // the source spelling of the type
+    pub fn name(&self) -> &'static str {
+        return definition::TYPE_TOKENS.iter()
+            .find(|x| return &x.1 == self)
+            .map_or("?", |x| return x.0);
+    }
+}
+
 impl Symbol {
+    // the source spelling of the symbol
+    pub fn name(&self) -> &'static str {
+        let spelled = match self {
+            Symbol::PlusUnsigned => &Symbol::Plus,
+            Symbol::MinusUnsigned => &Symbol::Minus,
+            Symbol::AsteriskUnsigned => &Symbol::Asterisk,
+            x => x
+        };
+
+        return definition::SYMBOL_TOKENS.iter()
+            .find(|x| return &x.1 == spelled)
+            .map_or("?", |x| return x.0);
+    }
+
     pub fn match_str(s: &str) -> bool {
         return definition::SYMBOL_TOKENS.iter().any(|x| return x.0.starts_with(s));
     }
@@ -157,8 +220,9 @@ impl<'a> RawToken<'a> {
 }
 
 // implement tryFrom<T>
+// errors are paired with the byte range of the raw token
 impl<'a> TryFrom<RawToken<'a>> for Token<'a> {
-    type Error = String;
+    type Error = (Range<usize>, String);
     fn try_from(value: RawToken<'a>) -> Result<Self, Self::Error> {
         let RawToken { value, range } = value;
 
@@ -171,17 +235,18 @@ impl<'a> TryFrom<RawToken<'a>> for Token<'a> {
         else if let Ok(x) = Symbol::try_from(value) {
             return Ok(x.into());
         }
-        else if let Ok(x) = Identifier::try_from(value) {
+        // literals go first, since `NaN`, `Inf` and `Null` are also valid identifiers
+        else if let Ok(x) = Literal::try_from(value) {
             return Ok(x.into());
         }
-        else if let Ok(x) = Comment::try_from(value) {
+        else if let Ok(x) = Identifier::try_from(value) {
             return Ok(x.into());
         }
-        else if let Ok(x) = Literal::try_from(value) {
+        else if let Ok(x) = Comment::try_from(value) {
             return Ok(x.into());
         }
         
-        return Err(format!("unexpected token: {}", value));
+        return Err((range, format!("unexpected token: {}", value)));
     }
 }
 
@@ -189,12 +254,10 @@ impl<'a> TryFrom<RawToken<'a>> for Token<'a> {
 impl<'a> TryFrom<&'a str> for Comment<'a> {
     type Error = &'static str;
     fn try_from(s: &'a str) -> Result<Self, Self::Error> {
-        // single line comment
-        if s.starts_with("//") {
-            return Ok(Comment(s));
-        }
-        // multi line comment
-        else if s.starts_with("/*") && s.ends_with("*/") {
+        let is_single_line = s.starts_with("//");
+        let is_multi_line = s.starts_with("/*") && s.ends_with("*/");
+
+        if is_single_line || is_multi_line {
             return Ok(Comment(s));
         }
 
@@ -205,8 +268,8 @@ impl<'a> TryFrom<&'a str> for Comment<'a> {
 impl TryFrom<&str> for Keyword {
     type Error = &'static str;
     fn try_from(s: &str) -> Result<Self, Self::Error> {
-        if let Some(x) = definition::KEYWORD_TOKENS.iter().find(|x| return x.0 == s) {
-            return Ok(x.1.clone());
+        if let Some(x) = definition::keyword_of(s) {
+            return Ok(x);
         }
         
         return Err("cannot parse the given raw value");
@@ -216,8 +279,8 @@ impl TryFrom<&str> for Keyword {
 impl TryFrom<&str> for Type {
     type Error = &'static str;
     fn try_from(s: &str) -> Result<Self, Self::Error> {
-        if let Some(x) = definition::TYPE_TOKENS.iter().find(|x| return x.0 == s) {
-            return Ok(x.1.clone());
+        if let Some(x) = definition::type_of(s) {
+            return Ok(x);
         }
         
         return Err("cannot parse the given raw value");
@@ -250,32 +313,22 @@ impl<'a> TryFrom<&'a str> for Literal<'a> {
         if s.starts_with('\"') && s.ends_with('\"') {
             return Ok(Literal::String(s))
         }
-        // is nan
-        else if Regex::new(r"^NaN$").unwrap().is_match(s) {
-            return Ok(Literal::Numeric(s));
-        }
-        // is inf
-        else if Regex::new(r"^Inf$").unwrap().is_match(s) {
-            return Ok(Literal::Numeric(s));
-        }
-        // is integer
-        else if Regex::new(r"^\d+$").unwrap().is_match(s) {
-            return Ok(Literal::Numeric(s));
-        }
-        // is float
-        else if Regex::new(r"^\d+\.\d+$").unwrap().is_match(s) {
-            return Ok(Literal::Numeric(s));
-        }
-        // is binary
-        else if Regex::new(r"^0b[01]+$").unwrap().is_match(s) {
-            return Ok(Literal::Numeric(s));
-        }
-        // is octal
-        else if Regex::new(r"^0o?[0-7]+$").unwrap().is_match(s) {
-            return Ok(Literal::Numeric(s));
-        }
-        // is hex
-        else if Regex::new(r"^0x[a-fA-F0-9]+$").unwrap().is_match(s) {
+
+        let numeric_patterns = [
+            // nan, inf and the null reference
+            r"^NaN$",
+            r"^Inf$",
+            r"^Null$",
+            // integer and float
+            r"^\d+$",
+            r"^\d+\.\d+(f32|f64)?$",
+            // binary, octal and hex
+            r"^0b[01]+$",
+            r"^0o?[0-7]+$",
+            r"^0x[a-fA-F0-9]+$"
+        ];
+
+        if numeric_patterns.iter().any(|x| return Regex::new(x).unwrap().is_match(s)) {
             return Ok(Literal::Numeric(s));
         }
         
@@ -286,8 +339,8 @@ impl<'a> TryFrom<&'a str> for Literal<'a> {
 impl TryFrom<&str> for Symbol {
     type Error = &'static str;
     fn try_from(s: &str) -> Result<Self, Self::Error> {
-        if let Some(x) = definition::SYMBOL_TOKENS.iter().find(|x| return x.0 == s) {
-            return Ok(x.1.clone());
+        if let Some(x) = definition::symbol_of(s) {
+            return Ok(x);
         }
         
         return Err("cannot parse the given raw value");
@@ -329,4 +382,17 @@ impl From<Symbol> for Token<'_> {
     fn from(t: Symbol) -> Self {
         return Token::Symbol(t);
     }
-}
\ No newline at end of file
+}
+
+// source form of tokens, reading it back gives the same token
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Token::Comment(Comment(x)) | Token::Identifier(Identifier(x)) => f.write_str(x),
+            Token::Literal(Literal::Numeric(x) | Literal::String(x)) => f.write_str(x),
+            Token::Keyword(x) => f.write_str(x.name()),
+            Token::Type(x) => f.write_str(x.name()),
+            Token::Symbol(x) => f.write_str(x.name())
+        };
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::VecDeque;
 
 use crate::token;
+use crate::parse_tree;
 use crate::grammar;
 use crate::grammar::Grammar;
 
@@ -15,37 +16,40 @@ pub enum TokenGrammar {
 }
 
 #[derive(Debug, Clone, PartialEq)]
-enum Comment {
+pub enum Comment {
     Any
 }
 
 #[derive(Debug, Clone, PartialEq)]
-enum Keyword {
+pub enum Keyword {
     ByOriginal(token::Keyword),
     Any
 }
 
 #[derive(Debug, Clone, PartialEq)]
-enum Type {
+pub enum Type {
     ByOriginal(token::Type),
     Any
 }
 
 #[derive(Debug, Clone, PartialEq)]
-enum Identifier {
+pub enum Identifier {
     Any
 }
 
 #[derive(Debug, Clone, PartialEq)]
-enum Literal {
+pub enum Literal {
     AnyString,
     AnyNumeric,
     Any
 }
 
 #[derive(Debug, Clone, PartialEq)]
-enum Symbol {
+pub enum Symbol {
     ByOriginal(token::Symbol),
+    OneOf(&'static [token::Symbol]),
+    AnyOpenBracket,
+    AnyCloseBracket,
     AnyUnary,
     AnyBinary,
     Any
@@ -93,6 +97,17 @@ impl TokenGrammar {
             (TokenGrammar::Symbol(Symbol::ByOriginal(x)), token::Token::Symbol(y)) => {
                 x == y
             },
+            (TokenGrammar::Symbol(Symbol::OneOf(x)), token::Token::Symbol(y)) => {
+                x.contains(y)
+            },
+            (TokenGrammar::Symbol(Symbol::AnyOpenBracket), token::Token::Symbol(y)) => {
+                y == &token::Symbol::LeftParenthese
+                || y == &token::Symbol::LeftBrace
+            },
+            (TokenGrammar::Symbol(Symbol::AnyCloseBracket), token::Token::Symbol(y)) => {
+                y == &token::Symbol::RightParenthese
+                || y == &token::Symbol::RightBrace
+            },
             (TokenGrammar::Symbol(Symbol::AnyUnary), token::Token::Symbol(y)) => {
                 y == &token::Symbol::Plus
                 || y == &token::Symbol::Minus
@@ -105,12 +120,18 @@ impl TokenGrammar {
                 || y == &token::Symbol::Asterisk
                 || y == &token::Symbol::Solidus
                 || y == &token::Symbol::Modulo
+                || y == &token::Symbol::SolidusUnsigned
+                || y == &token::Symbol::ModuloUnsigned
                 || y == &token::Symbol::Equal
                 || y == &token::Symbol::NotEqual
                 || y == &token::Symbol::LessThan
                 || y == &token::Symbol::GreaterThan
                 || y == &token::Symbol::LessThanOrEqual
                 || y == &token::Symbol::GreaterThanOrEqual
+                || y == &token::Symbol::LessThanUnsigned
+                || y == &token::Symbol::GreaterThanUnsigned
+                || y == &token::Symbol::LessThanOrEqualUnsigned
+                || y == &token::Symbol::GreaterThanOrEqualUnsigned
                 || y == &token::Symbol::BitwiseAnd
                 || y == &token::Symbol::BitwiseOr
                 || y == &token::Symbol::BitwiseXor
@@ -166,6 +187,19 @@ impl TokenGrammar {
         return TokenGrammar::Symbol(Symbol::ByOriginal(o));
     }
 
+    // matches any one of the given symbols
+    pub const fn from_symbols(o: &'static [token::Symbol]) -> Self {
+        return TokenGrammar::Symbol(Symbol::OneOf(o));
+    }
+
+    pub const fn any_open_bracket() -> Self {
+        return TokenGrammar::Symbol(Symbol::AnyOpenBracket);
+    }
+
+    pub const fn any_close_bracket() -> Self {
+        return TokenGrammar::Symbol(Symbol::AnyCloseBracket);
+    }
+
     pub const fn any_unary_symbol() -> Self {
         return TokenGrammar::Symbol(Symbol::AnyUnary);
     }
@@ -189,11 +223,71 @@ impl Grammar for TokenGrammar {
         }
     }
 
+    fn finish(&mut self) -> grammar::Result {
+        return grammar::Result::Passed;
+    }
+
     fn is_done(&self) -> bool {
         return true;
     }
 
     fn info(&self) -> String {
-        return format!("Token");
+        return "Token".to_string();
+    }
+
+    fn name(&self) -> &'static str {
+        return "Token";
+    }
+
+    fn attach(&mut self, _: parse_tree::Node) {
+        unreachable!("token grammar cannot have children");
+    }
+
+    // the token index is assigned once the whole tree is built
+    fn into_node(self: Box<Self>) -> parse_tree::Node {
+        return parse_tree::Node::Token(0);
+    }
+
+    fn clone_box(&self) -> Box<dyn Grammar> {
+        return Box::new(self.clone());
+    }
+
+    fn expected(&self) -> (Vec<grammar::TokenKind>, bool) {
+        return (vec![self.clone()], false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(x: token::Symbol) -> token::Token<'static> {
+        return token::Token::Symbol(x);
+    }
+
+    #[test]
+    fn match_brackets() {
+        let open = TokenGrammar::any_open_bracket();
+        let close = TokenGrammar::any_close_bracket();
+
+        assert!(open.is_match(&symbol(token::Symbol::LeftParenthese)));
+        assert!(open.is_match(&symbol(token::Symbol::LeftBrace)));
+        assert!(!open.is_match(&symbol(token::Symbol::RightParenthese)));
+        assert!(!open.is_match(&symbol(token::Symbol::LessThan)));
+        assert!(close.is_match(&symbol(token::Symbol::RightParenthese)));
+        assert!(close.is_match(&symbol(token::Symbol::RightBrace)));
+        assert!(!close.is_match(&symbol(token::Symbol::LeftBrace)));
+        assert!(!close.is_match(&token::Token::Keyword(token::Keyword::Function)));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn match_one_of_symbols() {
+        let grammar = TokenGrammar::from_symbols(&[token::Symbol::Increment, token::Symbol::Decrement]);
+
+        assert!(grammar.is_match(&symbol(token::Symbol::Increment)));
+        assert!(grammar.is_match(&symbol(token::Symbol::Decrement)));
+        assert!(!grammar.is_match(&symbol(token::Symbol::Plus)));
+        assert!(!grammar.is_match(&token::Token::Keyword(token::Keyword::Function)));
+        assert!(!TokenGrammar::from_symbols(&[]).is_match(&symbol(token::Symbol::Plus)));
+    }
+}
@@ -5,7 +5,8 @@ use crate::token;
 pub struct RawTokenStream<'a> {
     ctx: &'a str,
     tokens: Vec<token::RawToken<'a>>,
-    range: Range<usize>
+    // the token being read, none between tokens
+    range: Option<Range<usize>>
 }
 
 impl<'a> RawTokenStream<'a> {
@@ -13,7 +14,7 @@ impl<'a> RawTokenStream<'a> {
         return Self {
             ctx,
             tokens: vec![],
-            range: usize::MAX..0
+            range: None
         };
     }
 
@@ -22,45 +23,38 @@ impl<'a> RawTokenStream<'a> {
     }
 
     pub fn temp(&self) -> &'a str {
-        if self.range.is_empty() {
-            return "";
-        }
-        
-        return &self.ctx[self.range.clone()];
+        return match &self.range {
+            Some(x) => &self.ctx[x.clone()],
+            None => ""
+        };
     }
 
-    pub fn temp_prejoined(&self, dif: usize) -> &'a str {
-        if self.range.start != usize::MAX {
-            let pre_len = self.range.end + dif;
-            return &self.ctx[self.range.start..pre_len];
-        }
+    pub fn temp_start(&self) -> usize {
+        return self.range.as_ref().expect("unexpected read outside a token").start;
+    }
 
-        return self.temp();
+    pub fn temp_prejoined(&self, dif: usize) -> &'a str {
+        return match &self.range {
+            Some(x) => &self.ctx[x.start..x.end + dif],
+            None => ""
+        };
     }
 
     pub fn set_start(&mut self, offset: usize, dif: usize) {
-        self.range.start = offset;
-        self.range.end = offset + dif;
+        self.range = Some(offset..offset + dif);
     }
 
     pub fn add(&mut self, dif: usize) -> &mut Self {
-        self.range.end += dif;
+        if let Some(x) = &mut self.range {
+            x.end += dif;
+        }
 
         return self;
     }
 
     pub fn cut(&mut self) {
-        if !self.range.is_empty() {
-            let x_str = &self.ctx[self.range.clone()];
-            let new_token = token::RawToken::new(x_str, self.range.clone());
-
-            self.tokens.push(new_token);
+        if let Some(x) = self.range.take().filter(|x| return !x.is_empty()) {
+            self.tokens.push(token::RawToken::new(&self.ctx[x.clone()], x));
         }
-        
-        self.reset_range();
-    }
-
-    fn reset_range(&mut self) {
-        self.range = usize::MAX..0;
     }
 }
\ No newline at end of file
@@ -1,7 +1,8 @@
 use std::convert::TryFrom;
 use std::error::Error;
-use std::fmt;
-
+use crate::definition;
+use crate::diagnostic;
+use crate::span::{Span, Spanned};
 use crate::token_stream;
 use crate::token;
 
@@ -16,55 +17,114 @@ enum TokenSequence {
     NumericLiteral
 }
 
-// struct section
-struct CharPositionCounter {
-    ln: usize,
-    col: usize
+// the default byte length an identifier or literal may have, generous enough
+// for any hand written name while keeping export names loadable
+pub const DEFAULT_MAX_TOKEN_LENGTH: usize = 65536;
+
+// type section
+pub type TokenList<'a> = Vec<Spanned<token::Token<'a>>>;
+
+// a token, or a run of the whitespace the parser never sees between tokens
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lexeme<'a> {
+    Token(token::Token<'a>),
+    Whitespace(&'a str)
 }
 
-impl CharPositionCounter {
-    const fn new() -> Self {
-        return Self {
-            ln: 0,
-            col: 0
-        };
-    }
+// main program section
+// each token is paired with the byte range it was read from
+pub fn tokenize(text: &str) -> Result<TokenList<'_>, Box<dyn Error>> {
+    return scan(text).and_then(|x| return check_lengths(x, DEFAULT_MAX_TOKEN_LENGTH)).map_err(|(offset, message)| {
+        let (ln, col) = line_col(text, offset);
 
-    fn next_char(&mut self) {
-        self.col += 0;
-    }
+        return message.at(format!("{}:{}", ln, col)).into();
+    });
+}
 
-    fn next_line(&mut self) {
-        self.ln += 1;
-        self.col = 0;
-    }
+// same as `tokenize`, with errors located as `path:ln:col`
+pub fn tokenize_file_with_limit<'a>(path: &str, text: &'a str, max_length: usize) -> Result<TokenList<'a>, Box<dyn Error>> {
+    return scan(text).and_then(|x| return check_lengths(x, max_length)).map_err(|(offset, message)| {
+        let (ln, col) = line_col(text, offset);
+
+        return message.at(format!("{}:{}:{}", path, ln, col)).into();
+    });
+}
+
+// same as `tokenize`, keeping the whitespace between tokens as lexemes of its
+// own, so the lexemes spell out the whole source in order. for tools that
+// must reproduce the source exactly, such as a formatter keeping blank lines.
+// the formatter in this crate reads the text between tokens itself
+#[allow(dead_code)]
+pub fn tokenize_with_whitespace(text: &str) -> Result<Vec<Spanned<Lexeme<'_>>>, Box<dyn Error>> {
+    let mut res = vec![];
+    let mut offset = 0;
+
+    for x in tokenize(text)? {
+        // whatever the scanner skipped before the token
+        if offset < x.span.start {
+            res.push(Spanned::new(Lexeme::Whitespace(&text[offset..x.span.start]), offset..x.span.start));
+        }
 
-    const fn ln(&self) -> usize {
-        return self.ln;
+        offset = x.span.end;
+        res.push(Spanned::new(Lexeme::Token(x.node), x.span));
     }
 
-    const fn col(&self) -> usize {
-        return self.col;
+    if offset < text.len() {
+        res.push(Spanned::new(Lexeme::Whitespace(&text[offset..]), offset..text.len()));
     }
+
+    return Ok(res);
 }
 
-impl fmt::Debug for CharPositionCounter {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        return write!(f, "{}:{}", self.ln, self.col);
+// tokenizes `text` after the `edit` range of the old text was replaced,
+// reusing the old tokens before the edited line. scanning restarts at a token
+// boundary, so an edit inside a comment or string spanning lines is still
+// read from where that token starts. the old text must outlive the result.
+// an editor reparsing as the user types needs it, the compiler reads a file once
+#[allow(dead_code)]
+pub fn retokenize<'a>(tokens: &[Spanned<token::Token<'a>>], text: &'a str, edit: &Span) -> Result<TokenList<'a>, Box<dyn Error>> {
+    let line_start = text[..edit.start].rfind('\n').map_or(0, |x| return x + 1);
+    let reused = tokens.iter().rposition(|x| return x.span.start <= line_start).unwrap_or(0);
+    let restart = tokens.get(reused).map_or(0, |x| return x.span.start.min(line_start));
+
+    let rest = scan(&text[restart..]).map_err(|(offset, message)| {
+        let (ln, col) = line_col(text, restart + offset);
+
+        return message.at(format!("{}:{}", ln, col));
+    })?;
+
+    let mut res = tokens[..reused].to_vec();
+
+    res.extend(rest.into_iter().map(|x| return Spanned::new(x.node, x.span.start + restart..x.span.end + restart)));
+
+    return Ok(res);
+}
+
+// the length of a string literal excludes its quotes
+fn check_lengths(tokens: TokenList<'_>, max_length: usize) -> Result<TokenList<'_>, (usize, diagnostic::Report)> {
+    for token in tokens.iter() {
+        let (kind, length) = match &token.node {
+            token::Token::Identifier(_) => ("identifier", token.span.len()),
+            token::Token::Literal(token::Literal::String(_)) => ("string literal", token.span.len().saturating_sub(2)),
+            token::Token::Literal(token::Literal::Numeric(_)) => ("numeric literal", token.span.len()),
+            _ => continue
+        };
+
+        if length > max_length {
+            return Err((token.span.start, diagnostic::TOKEN_TOO_LONG.message(format!("{} exceeds maximum length of {} bytes, it has {}", kind, max_length, length))));
+        }
     }
+
+    return Ok(tokens);
 }
 
-// main program section
-pub fn tokenize(text: &str) -> Result<Vec<token::Token>, Box<dyn Error>> {
+// errors are paired with the byte offset they occurred at
+fn scan(text: &str) -> Result<TokenList<'_>, (usize, diagnostic::Report)> {
     let mut token_collector = token_stream::RawTokenStream::new(text);
-    let mut char_pos_counter = CharPositionCounter::new();
     let mut mode = TokenSequence::None;
     let mut offset = 0;
 
-    let mut iter = text.chars();
-    while let Some(c) = iter.next() {
-        char_pos_counter.next_char();
-        
+    for c in text.chars() {
         let z = c.len_utf8();
 
         // (con.) check for identifier
@@ -100,7 +160,8 @@ pub fn tokenize(text: &str) -> Result<Vec<token::Token>, Box<dyn Error>> {
                     offset += z;
                     continue;
                 }
-                else if token::Symbol::match_str(&prejoined) {
+                // a trailing letter, as in `<u`, is only taken when it does not start a word
+                else if token::Symbol::match_str(prejoined) && !(token::Identifier::is_alphanumeric_valid_char(c) && text[offset + z..].starts_with(token::Identifier::is_alphanumeric_valid_char)) {
                     token_collector.add(z);
 
                     offset += z;
@@ -136,7 +197,8 @@ pub fn tokenize(text: &str) -> Result<Vec<token::Token>, Box<dyn Error>> {
                 continue;
             },
             TokenSequence::StringLiteral => {
-                if c == '\"' && !token_collector.temp().ends_with('\\') {
+                // a quote is escaped by an odd number of backslashes before it
+                if c == '\"' && token_collector.temp().chars().rev().take_while(|x| return *x == '\\').count() % 2 == 0 {
                     token_collector.add(z).cut();
                     mode = TokenSequence::None;
                 }
@@ -168,10 +230,6 @@ pub fn tokenize(text: &str) -> Result<Vec<token::Token>, Box<dyn Error>> {
         if c.is_whitespace() || c == '\n' || c == '\t' || c == '\r' || c == '\0' {
             token_collector.cut();
 
-            if c == '\n' {
-                char_pos_counter.next_line();
-            }
-
             offset += z;
             continue;
         }
@@ -193,31 +251,358 @@ pub fn tokenize(text: &str) -> Result<Vec<token::Token>, Box<dyn Error>> {
         }
         // others will be error
         else {
-            return Err(format!("unknown start of token: `{}` at {:?}", c, char_pos_counter).into());
+            return Err((offset, diagnostic::UNKNOWN_TOKEN.message(format!("unknown start of token: `{}`", c))));
         }
         
         token_collector.set_start(offset, z);
         offset += z;
     }
 
+    // cut the last token if the input ends right after it
+    match mode {
+        TokenSequence::StringLiteral | TokenSequence::MultiLineComment => {},
+        _ => token_collector.cut()
+    };
+
     // termination validation
     if !token_collector.temp().is_empty() {
         return match mode {
-            TokenSequence::StringLiteral => Err("unexpected unclosed string".into()),
-            _ => Err("unexpected tokenization error".into())
+            TokenSequence::StringLiteral => Err((token_collector.temp_start(), diagnostic::UNCLOSED_STRING.message("unexpected unclosed string"))),
+            _ => Err((token_collector.temp_start(), diagnostic::Report::new(diagnostic::Severity::Error, "unexpected tokenization error")))
         }
     }
 
     // validate tokens
     let collected = token_collector.collect();
     let mut res = Vec::with_capacity(collected.len());
-    let mut iter = collected.into_iter();
-    
-    while let Some(raw_token) = iter.next() {
-        let token = token::Token::try_from(raw_token)?;
 
-        res.push(token);
+    for raw_token in collected {
+        let range = raw_token.range().clone();
+        let token = token::Token::try_from(raw_token).map_err(|(range, x)| return (range.start, diagnostic::Report::new(diagnostic::Severity::Error, x)))?;
+
+        res.push(Spanned::new(token, range));
     }
 
     return Ok(res);
-}
\ No newline at end of file
+}
+
+// 1-based line and column of a byte offset
+pub fn line_col(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let ln = before.matches('\n').count() + 1;
+    let col = before.rfind('\n').map_or(before, |x| return &before[x + 1..]).chars().count() + 1;
+
+    return (ln, col);
+}
+
+// one token per line, with its position in the source
+pub fn dump(text: &str, tokens: &[Spanned<token::Token>]) -> String {
+    let mut out = String::new();
+
+    for token in tokens {
+        let (ln, col) = line_col(text, token.span.start);
+
+        out.push_str(&format!("{}:{}\t{:?}\n", ln, col, token.node));
+    }
+
+    return out;
+}
+
+// one token of a fragment per line, with what it stands for, as
+// `"ret" → Keyword::Return (return statement)`
+pub fn explain(text: &str) -> Result<String, Box<dyn Error>> {
+    let mut out = String::new();
+
+    for token in tokenize(text)? {
+        let kind = match &token.node {
+            token::Token::Comment(_) => "Comment".to_string(),
+            token::Token::Keyword(x) => format!("Keyword::{:?}", x),
+            token::Token::Type(x) => format!("Type::{:?}", x),
+            token::Token::Identifier(_) => "Identifier".to_string(),
+            token::Token::Literal(token::Literal::Numeric(_)) => "Literal::Numeric".to_string(),
+            token::Token::Literal(token::Literal::String(_)) => "Literal::String".to_string(),
+            token::Token::Symbol(x) => format!("Symbol::{:?}", x)
+        };
+
+        out.push_str(&format!("{:?} → {} ({})\n", &text[token.span], kind, definition::description(&token.node)));
+    }
+
+    return Ok(out);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use proptest::sample::select;
+
+    fn symbols(text: &str) -> Vec<token::Token<'_>> {
+        return tokenize(text).unwrap().into_iter().map(Spanned::into_node).collect();
+    }
+
+    #[test]
+    fn tokenize_increment() {
+        assert_eq!(symbols("++ + --"), vec![
+            token::Token::Symbol(token::Symbol::Increment),
+            token::Token::Symbol(token::Symbol::Plus),
+            token::Token::Symbol(token::Symbol::Decrement)
+        ]);
+        assert_eq!(symbols("-->"), vec![
+            token::Token::Symbol(token::Symbol::Decrement),
+            token::Token::Symbol(token::Symbol::GreaterThan)
+        ]);
+        assert_eq!(symbols("- ->"), vec![
+            token::Token::Symbol(token::Symbol::Minus),
+            token::Token::Symbol(token::Symbol::RightArrow)
+        ]);
+    }
+
+    #[test]
+    fn tokenize_unsigned_comparison() {
+        assert_eq!(symbols("<u <=u >u >=u <"), vec![
+            token::Token::Symbol(token::Symbol::LessThanUnsigned),
+            token::Token::Symbol(token::Symbol::LessThanOrEqualUnsigned),
+            token::Token::Symbol(token::Symbol::GreaterThanUnsigned),
+            token::Token::Symbol(token::Symbol::GreaterThanOrEqualUnsigned),
+            token::Token::Symbol(token::Symbol::LessThan)
+        ]);
+    }
+
+    // source text of each token
+    fn spelled(text: &str) -> Vec<&str> {
+        return tokenize(text).unwrap().into_iter().map(|x| return &text[x.span]).collect();
+    }
+
+    #[test]
+    fn tokenize_each_symbol_alone() {
+        for (text, symbol) in crate::definition::SYMBOL_TOKENS.iter() {
+            assert_eq!(symbols(text), vec![token::Token::Symbol(symbol.clone())], "`{}`", text);
+        }
+    }
+
+    #[test]
+    fn tokenize_each_symbol_between_operands() {
+        for (text, _) in crate::definition::SYMBOL_TOKENS.iter() {
+            assert_eq!(spelled(&format!("a{}(b)", text)), vec!["a", text, "(", "b", ")"], "`{}`", text);
+            assert_eq!(spelled(&format!("(a){} b", text)), vec!["(", "a", ")", text, "b"], "`{}`", text);
+        }
+    }
+
+    #[test]
+    fn tokenize_longest_symbol_first() {
+        let cases: &[(&str, &[&str])] = &[
+            ("a<<=b", &["a", "<<", "=", "b"]),
+            ("a>>>=b", &["a", ">>>", "=", "b"]),
+            ("a>>>>b", &["a", ">>>", ">", "b"]),
+            ("a>>=b", &["a", ">>", "=", "b"]),
+            ("a<=-b", &["a", "<=", "-", "b"]),
+            ("a<--b", &["a", "<-", "-", "b"]),
+            ("a<-b", &["a", "<-", "b"]),
+            ("a->b", &["a", "->", "b"]),
+            ("a|>b", &["a", "|>", "b"]),
+            ("a||b", &["a", "||", "b"]),
+            ("a|||b", &["a", "||", "|", "b"]),
+            ("a||!b", &["a", "||", "!", "b"]),
+            ("a&&b", &["a", "&&", "b"]),
+            ("a&&&b", &["a", "&&", "&", "b"]),
+            ("a&&~b", &["a", "&&", "~", "b"]),
+            ("x::y", &["x", "::", "y"]),
+            ("x:::y", &["x", "::", ":", "y"]),
+            ("a==b", &["a", "==", "b"]),
+            ("a===b", &["a", "==", "=", "b"]),
+            ("a==-b", &["a", "==", "-", "b"]),
+            ("a!==b", &["a", "!=", "=", "b"]),
+            ("a+++b", &["a", "++", "+", "b"]),
+            ("a<u b", &["a", "<u", "b"]),
+            ("a<=u(b)", &["a", "<=u", "(", "b", ")"]),
+            ("a>=u-b", &["a", ">=u", "-", "b"]),
+            ("a<ub", &["a", "<", "ub"]),
+            ("a<=ub", &["a", "<=", "ub"]),
+            ("a>u8", &["a", ">", "u8"]),
+            ("a<u", &["a", "<u"]),
+            ("a/u b%u c", &["a", "/u", "b", "%u", "c"]),
+            ("a/ub", &["a", "/", "ub"]),
+            ("a+//b", &["a", "+", "//b"]),
+            ("a-/*b*/c", &["a", "-", "/*b*/", "c"]),
+            ("\"a\\\"b\"c", &["\"a\\\"b\"", "c"]),
+            ("\"a\\\\\"b", &["\"a\\\\\"", "b"])
+        ];
+
+        for (text, expected) in cases.iter() {
+            assert_eq!(&spelled(text), expected, "`{}`", text);
+        }
+    }
+
+    #[test]
+    fn locate_error_in_file() {
+        let err = tokenize_file_with_limit("src/main.cwal", "fn f() {\n    let a <- 1 # 2;\n}", DEFAULT_MAX_TOKEN_LENGTH).unwrap_err();
+
+        assert_eq!(err.to_string(), "src/main.cwal:2:16: error[E0002]: unknown start of token: `#`");
+    }
+
+    #[test]
+    fn locate_malformed_token() {
+        let err = token::Token::try_from(token::RawToken::new("1.2.3", 9..14)).unwrap_err();
+
+        assert_eq!(err, (9..14, "unexpected token: 1.2.3".to_string()));
+        assert_eq!(tokenize("fn f() { 1.2.3; }").unwrap_err().to_string(), "1:10: error: unexpected token: 1.2.3");
+    }
+
+    #[test]
+    fn explain_tokens() {
+        assert_eq!(explain("ret").unwrap(), "\"ret\" → Keyword::Return (return statement)\n");
+        assert_eq!(explain("|>").unwrap(), "\"|>\" → Symbol::PipeForward (pipe forward)\n");
+        assert_eq!(explain("a <- count").unwrap(), concat!(
+            "\"a\" → Identifier (name of a function, variable, type, table or memory)\n",
+            "\"<-\" → Symbol::LeftArrow (assignment)\n",
+            "\"count\" → Identifier (name of a function, variable, type, table or memory)\n"
+        ));
+        assert!(explain("#").is_err());
+    }
+
+    #[test]
+    fn reject_long_tokens() {
+        let alias = "a".repeat(DEFAULT_MAX_TOKEN_LENGTH + 1);
+        let text = format!("exp \"{}\" fn f() {{}}", alias);
+
+        assert_eq!(
+            tokenize(&text).unwrap_err().to_string(),
+            "1:5: error[E0014]: string literal exceeds maximum length of 65536 bytes, it has 65537"
+        );
+        assert_eq!(
+            tokenize_file_with_limit("a.cwal", "fn f() { let abcdefghi <- 1; }", 8).unwrap_err().to_string(),
+            "a.cwal:1:14: error[E0014]: identifier exceeds maximum length of 8 bytes, it has 9"
+        );
+        assert!(tokenize_file_with_limit("a.cwal", "fn f() { 123456789; }", 8).is_err());
+    }
+
+    #[test]
+    fn accept_tokens_at_limit() {
+        let text = format!("exp \"{}\" fn f() {{}}", "a".repeat(DEFAULT_MAX_TOKEN_LENGTH));
+
+        assert!(tokenize(&text).is_ok());
+        assert!(tokenize_file_with_limit("a.cwal", "exp \"abcdefgh\" fn abcdefgh() {}", 8).is_ok());
+    }
+
+    fn apply_edit(text: &str, edit: Span, inserted: &str) {
+        let edited = format!("{}{}{}", &text[..edit.start], inserted, &text[edit.end..]);
+        let tokens = tokenize(text).unwrap();
+
+        assert_eq!(retokenize(&tokens, &edited, &edit).unwrap(), tokenize(&edited).unwrap());
+    }
+
+    #[test]
+    fn retokenize_edited_line() {
+        let text = "fn f() -> i32 {\n    let a <- 1;\n    a\n}\n";
+        let start = text.find('1').unwrap();
+
+        apply_edit(text, start..start + 1, "/* x */ 42 + \"s\"");
+        apply_edit(text, 0..0, "// header\n");
+        apply_edit(text, text.len()..text.len(), "fn g() {}");
+    }
+
+    #[test]
+    fn retokenize_inside_multiline_comment() {
+        let text = "fn f() {}\n/* a\nb */ fn g() {}";
+        let start = text.find('b').unwrap();
+
+        apply_edit(text, start..start + 1, "*/ fn h() {} /*");
+    }
+
+    // the lexemes spelled out one after another
+    fn rejoined(text: &str) -> String {
+        return tokenize_with_whitespace(text).unwrap().iter()
+            .map(|x| return &text[x.span.clone()])
+            .collect();
+    }
+
+    #[test]
+    fn keep_whitespace_between_tokens() {
+        let text = "\t// f\nfn f() -> i32 {\r\n\n    /* x */ let a <- \"a  b\";\u{a0}1 }\n  ";
+        let lexemes = tokenize_with_whitespace(text).unwrap();
+
+        assert_eq!(rejoined(text), text);
+        assert_eq!(lexemes[0].node, Lexeme::Whitespace("\t"));
+        assert_eq!(lexemes[2].node, Lexeme::Whitespace("\n"));
+        assert_eq!(lexemes[14].node, Lexeme::Whitespace("\r\n\n    "));
+        assert_eq!(lexemes.last().unwrap().node, Lexeme::Whitespace("\n  "));
+        assert_eq!(lexemes.iter().filter(|x| return matches!(x.node, Lexeme::Token(_))).count(), tokenize(text).unwrap().len());
+        assert!(tokenize_with_whitespace("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn locate_unclosed_string() {
+        let err = tokenize_file_with_limit("a.cwal", "imp \"env", DEFAULT_MAX_TOKEN_LENGTH).unwrap_err();
+
+        assert_eq!(err.to_string(), "a.cwal:1:5: error[E0003]: unexpected unclosed string");
+    }
+
+    // owned tokens, which the strategies below can produce
+    #[derive(Debug, Clone)]
+    enum Sample {
+        Keyword(token::Keyword),
+        Type(token::Type),
+        Symbol(token::Symbol),
+        Identifier(String),
+        Numeric(String),
+        String(String),
+        LineComment(String),
+        BlockComment(String)
+    }
+
+    impl Sample {
+        fn token(&self) -> token::Token<'_> {
+            return match self {
+                Sample::Keyword(x) => x.clone().into(),
+                Sample::Type(x) => x.clone().into(),
+                Sample::Symbol(x) => x.clone().into(),
+                Sample::Identifier(x) => token::Identifier::new(x).into(),
+                Sample::Numeric(x) => token::Literal::Numeric(x).into(),
+                Sample::String(x) => token::Literal::String(x).into(),
+                Sample::LineComment(x) | Sample::BlockComment(x) => token::Token::try_from(token::RawToken::new(x, 0..x.len())).unwrap()
+            };
+        }
+    }
+
+    fn sample() -> impl Strategy<Value = Sample> {
+        let words = crate::definition::KEYWORD_TOKENS.iter().map(|x| return x.0)
+            .chain(crate::definition::TYPE_TOKENS.iter().map(|x| return x.0))
+            .chain(["NaN", "Inf", "Null"])
+            .collect::<Vec<_>>();
+
+        return prop_oneof![
+            select(crate::definition::KEYWORD_TOKENS).prop_map(|x| return Sample::Keyword(x.1)),
+            select(crate::definition::TYPE_TOKENS).prop_map(|x| return Sample::Type(x.1)),
+            select(crate::definition::SYMBOL_TOKENS).prop_map(|x| return Sample::Symbol(x.1)),
+            "[a-zA-Z_$][a-zA-Z0-9_$]{0,8}".prop_filter("reserved word", move |x| return !words.contains(&x.as_str())).prop_map(Sample::Identifier),
+            prop_oneof!["[1-9][0-9]{0,6}", "[0-9]{1,3}\\.[0-9]{1,3}(f32|f64)?", "0x[0-9a-fA-F]{1,8}", "0b[01]{1,8}", "0o[0-7]{1,8}"].prop_map(Sample::Numeric),
+            "[a-z0-9 .,;]{0,8}".prop_map(|x| return Sample::String(format!("\"{}\"", x))),
+            "[a-z0-9 ]{0,8}".prop_map(|x| return Sample::LineComment(format!("//{}", x))),
+            "[a-z0-9 \n]{0,8}".prop_map(|x| return Sample::BlockComment(format!("/*{}*/", x)))
+        ];
+    }
+
+    // tokens are separated by a space, so no two of them merge into a longer
+    // one, and a line comment is ended by a line break
+    fn render(tokens: &[token::Token]) -> String {
+        let mut out = String::new();
+
+        for token in tokens.iter() {
+            out.push_str(&token.to_string());
+            out.push(if matches!(token, token::Token::Comment(_)) { '\n' } else { ' ' });
+        }
+
+        return out;
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip_rendered_tokens(samples in proptest::collection::vec(sample(), 0..32)) {
+            let tokens = samples.iter().map(Sample::token).collect::<Vec<_>>();
+            let text = render(&tokens);
+
+            prop_assert_eq!(symbols(&text), tokens);
+            prop_assert_eq!(rejoined(&text), text);
+        }
+    }
+}
@@ -0,0 +1,3594 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::Write;
+
+use crate::ast;
+use crate::cfg;
+use crate::checker;
+use crate::cli;
+use crate::feature;
+use crate::ir;
+use crate::lowering;
+use crate::optimizer;
+use crate::parser;
+use crate::preprocessor;
+use crate::resolver;
+use crate::span::Spanned;
+use crate::tokenizer;
+use crate::visitor::{self, Visitor};
+use crate::ir::{BinaryOp, BlockType, FuncType, Instruction, Intrinsic, MemArg, ValType};
+use crate::ir::{write_unsigned, F32_CANONICAL_NAN, F64_CANONICAL_NAN};
+use crate::token;
+
+// runs a single source through every stage with the default options of the
+// command line, stopping before serialization so the module can be inspected
+// or changed before `to_wasm`. an entry point for embedders, the command line
+// reads its own options
+#[allow(dead_code)]
+pub fn compile_to_module(source: &str) -> Result<Module, Box<dyn Error>> {
+    let defaults = checker::Defaults::default();
+    let tokens = preprocessor::preprocess(tokenizer::tokenize(source)?, &[])?;
+    let mut program = parser::parse_syntax(&tokens, false)?;
+
+    lowering::lower(&mut program)?;
+    checker::default_literals(&mut program, &defaults);
+    checker::infer_results(&mut program, &defaults)?;
+    checker::check(&program)?;
+    checker::sign_operators(&mut program, &defaults)?;
+
+    let symbols = resolver::resolve(&program)?;
+
+    optimizer::optimize(&mut program, &symbols, &cli::OptLevel::O3, &defaults, &mut vec![]);
+
+    let mut module = Module::build(&program, &defaults)?;
+
+    optimizer::optimize_module(&mut module, &program, &cli::OptLevel::O3, &mut vec![]);
+
+    return Ok(module);
+}
+
+// type section
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    pub min: u32,
+    pub max: Option<u32>
+}
+
+// `(min; fref; max)` holds function references, `(min; xref; max)` host ones
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableType {
+    pub element: ValType,
+    pub limits: Limits
+}
+
+// module section
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportKind {
+    Function(u32),
+    Table(TableType),
+    Memory(Limits),
+    Global(ValType, bool)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportKind {
+    Function,
+    Table,
+    Memory,
+    Global
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+    pub kind: ImportKind
+}
+
+// `ty` is an index in the type section, `locals` excludes the params
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub ty: u32,
+    pub locals: Vec<ValType>,
+    pub body: Vec<Instruction>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub name: String,
+    pub ty: ValType,
+    pub is_mutable: bool,
+    pub init: Instruction
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Export {
+    pub name: String,
+    pub kind: ExportKind,
+    pub index: u32
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    types: Vec<FuncType>,
+    type_indices: HashMap<FuncType, u32>,
+    imports: Vec<Import>,
+    functions: Vec<Function>,
+    tables: Vec<TableType>,
+    memories: Vec<Limits>,
+    globals: Vec<Global>,
+    exports: Vec<Export>,
+    // functions taken by `ref.func`, which must be declared up front
+    declared: Vec<u32>
+}
+
+// names visible at module level, indices count imports first
+#[derive(Default)]
+struct Context {
+    type_names: HashMap<String, ast::TypeExpression>,
+    // function type aliases, by their index in the type section
+    signatures: HashMap<String, (u32, FuncType)>,
+    functions: HashMap<String, (u32, FuncType)>,
+    // index and element type
+    tables: HashMap<String, (u32, ValType)>,
+    memories: HashMap<String, u32>,
+    // bytes taken by the extra arguments of the largest variadic call
+    scratch_size: u64,
+    // where the first memory holds those arguments, in pages reserved past
+    // its declared minimum
+    scratch_base: Option<u32>,
+    globals: HashMap<String, (u32, ValType, bool)>,
+    // types of literals without a suffix
+    defaults: checker::Defaults,
+    features: feature::Features
+}
+
+impl Context {
+    // grows the limits of the first memory by the pages the extra arguments
+    // of variadic calls take, so no address the program may use overlaps them
+    fn reserve_scratch(&mut self, name: &str, limits: &mut Limits) -> Result<(), Box<dyn Error>> {
+        if !self.memories.is_empty() || self.scratch_size == 0 {
+            return Ok(());
+        }
+
+        let pages = u32::try_from(self.scratch_size.div_ceil(65536))?;
+        let max = limits.max.unwrap_or(checker::MAX_PAGE_COUNT).min(checker::MAX_PAGE_COUNT);
+
+        match limits.min.checked_add(pages) {
+            Some(min) if min <= max => {
+                self.scratch_base = Some(limits.min.checked_mul(65536).ok_or("unexpected memory size overflow")?);
+                limits.min = min;
+
+                return Ok(());
+            },
+            _ => return Err(format!(
+                "the extra arguments of variadic calls need {} more page(s) of memory `{}`, past its maximum of {}",
+                pages, name, max
+            ).into())
+        }
+    }
+
+    fn value_types(&self, ty: &ast::TypeExpression) -> Result<Vec<ValType>, Box<dyn Error>> {
+        return match ty {
+            ast::TypeExpression::Primitive(x) => Ok(vec![ValType::from_token(x)]),
+            ast::TypeExpression::Tuple(list) => {
+                let mut types = vec![];
+
+                for x in list.iter() {
+                    types.extend(self.value_types(x)?);
+                }
+
+                Ok(types)
+            },
+            ast::TypeExpression::Vector(x, count) => {
+                Ok(vec![ValType::from_token(x); checker::vector_length(count)?])
+            },
+            ast::TypeExpression::Named(name) => match self.type_names.get(name) {
+                Some(x) => self.value_types(x),
+                None => Err(format!("cannot find type `{}`", name).into())
+            },
+            x => Err(format!("type `{}` is not supported yet", x).into())
+        };
+    }
+
+    // the function type behind a `type` alias, or an inline `fn (..) -> ..`
+    fn function_type(&self, ty: &ast::TypeExpression) -> Result<FuncType, Box<dyn Error>> {
+        return match ty {
+            ast::TypeExpression::Function(params, result) => Ok(FuncType {
+                params: params.iter().map(ValType::from_token).collect(),
+                results: match result {
+                    Some(x) => self.value_types(x)?,
+                    None => vec![]
+                }
+            }),
+            ast::TypeExpression::Named(name) => match self.type_names.get(name) {
+                Some(x) => self.function_type(x),
+                None => Err(format!("cannot find type `{}`", name).into())
+            },
+            x => Err(format!("type `{}` is not a function type", x).into())
+        };
+    }
+
+    fn func_type(&self, signature: &ast::Signature) -> Result<FuncType, Box<dyn Error>> {
+        let mut params = vec![];
+
+        for param in signature.params.iter() {
+            // a pointer to the extra arguments and their count
+            if param.is_variadic {
+                params.extend([ValType::I32, ValType::I32]);
+            }
+            else {
+                params.extend(self.value_types(&param.ty)?);
+            }
+        }
+
+        let results = match &signature.result {
+            Some(x) => self.value_types(x)?,
+            None => vec![]
+        };
+
+        return Ok(FuncType { params, results });
+    }
+}
+
+impl Limits {
+    fn from_type(ty: &ast::TypeExpression) -> Result<Self, Box<dyn Error>> {
+        let (min, max) = match ty {
+            ast::TypeExpression::Range(min, _, max) => (min, max),
+            x => return Err(format!("expected a range type, found `{}`", x).into())
+        };
+
+        let parse = |x: &str| return x.parse::<u32>().map_err(|_| return format!("invalid limit `{}`", x));
+
+        return Ok(Self {
+            min: parse(min)?,
+            max: if max.is_empty() { None } else { Some(parse(max)?) }
+        });
+    }
+
+    fn to_wasm(&self, buf: &mut Vec<u8>) {
+        match self.max {
+            Some(max) => {
+                buf.push(0x01);
+                write_unsigned(buf, u64::from(self.min));
+                write_unsigned(buf, u64::from(max));
+            },
+            None => {
+                buf.push(0x00);
+                write_unsigned(buf, u64::from(self.min));
+            }
+        }
+    }
+
+    fn to_json(&self) -> String {
+        return format!("\"min\": {}, \"max\": {}", self.min, self.max.map_or_else(|| return "null".to_string(), |x| return x.to_string()));
+    }
+
+    fn to_wat(&self) -> String {
+        return match self.max {
+            Some(max) => format!("{} {}", self.min, max),
+            None => self.min.to_string()
+        };
+    }
+}
+
+impl TableType {
+    fn from_type(ty: &ast::TypeExpression) -> Result<Self, Box<dyn Error>> {
+        let element = match ty {
+            ast::TypeExpression::Range(_, x @ (token::Type::Fref | token::Type::Xref), _) => ValType::from_token(x),
+            ast::TypeExpression::Range(_, x, _) => return Err(format!("table elements must be fref or xref, found {}", ast::type_name(x)).into()),
+            x => return Err(format!("expected a range type, found `{}`", x).into())
+        };
+
+        return Ok(Self {
+            element,
+            limits: Limits::from_type(ty)?
+        });
+    }
+
+    fn to_wasm(&self, buf: &mut Vec<u8>) {
+        buf.push(self.element.code());
+        self.limits.to_wasm(buf);
+    }
+
+    fn to_wat(&self) -> String {
+        return format!("{} {}", self.limits.to_wat(), self.element.name());
+    }
+}
+
+impl Function {
+    // the body without its size, locals declared in runs of the same type
+    fn to_wasm(&self, buf: &mut Vec<u8>) {
+        let mut runs: Vec<(u32, ValType)> = vec![];
+
+        for ty in self.locals.iter() {
+            match runs.last_mut() {
+                Some((count, last)) if last == ty => *count += 1,
+                _ => runs.push((1, *ty))
+            }
+        }
+
+        write_vec(buf, &runs, |buf, (count, ty)| {
+            write_unsigned(buf, u64::from(*count));
+            buf.push(ty.code());
+        });
+
+        for instruction in self.body.iter() {
+            instruction.to_wasm(buf);
+        }
+    }
+}
+
+impl Module {
+    pub fn build(program: &ast::Program, defaults: &checker::Defaults) -> Result<Self, Box<dyn Error>> {
+        return Self::build_with_features(program, defaults, &feature::Features::default());
+    }
+
+    pub fn build_with_features(program: &ast::Program, defaults: &checker::Defaults, features: &feature::Features) -> Result<Self, Box<dyn Error>> {
+        let mut module = Self {
+            types: vec![],
+            type_indices: HashMap::new(),
+            imports: vec![],
+            functions: vec![],
+            tables: vec![],
+            memories: vec![],
+            globals: vec![],
+            exports: vec![],
+            declared: vec![]
+        };
+        let mut context = Context {
+            scratch_size: scratch_size(program),
+            defaults: defaults.clone(),
+            features: features.clone(),
+            ..Context::default()
+        };
+        let mut functions = vec![];
+        let mut tables = vec![];
+        let mut memories = vec![];
+        let mut globals = vec![];
+        let mut aliased = vec![];
+        let mut aliases = vec![];
+
+        // sort declarations into index spaces, remembering export names
+        for decl in program.declarations.iter() {
+            match decl {
+                ast::Declaration::Function(x) => functions.push((x, x.attribute("export").map(|a| return export_name(&a.argument, &x.name)).transpose()?)),
+                ast::Declaration::Type(x) => {
+                    context.type_names.insert(x.name.clone(), x.ty.clone());
+                    aliases.push(&x.name);
+                },
+                ast::Declaration::Table(x) => tables.push((x, None)),
+                ast::Declaration::Memory(x) => memories.push((x, None)),
+                ast::Declaration::Variable(x) => globals.push((x, None)),
+                ast::Declaration::Import(x) => module.import(&mut context, x)?,
+                ast::Declaration::Export(x) => match &x.item {
+                    ast::ExportItem::Function(item) => functions.push((item, Some(export_name(&x.alias, &item.name)?))),
+                    ast::ExportItem::Table(item) => tables.push((item, Some(export_name(&x.alias, &item.name)?))),
+                    ast::ExportItem::Memory(item) => memories.push((item, Some(export_name(&x.alias, &item.name)?))),
+                    ast::ExportItem::Variable(item) => globals.push((item, Some(x.alias.as_deref().map(ast::unescape).transpose()?))),
+                    ast::ExportItem::Aliased(name, alias) => aliased.push((name, ast::unescape(alias)?))
+                },
+                // included files are merged in by `resolver::link`
+                ast::Declaration::Include(_) => {},
+                ast::Declaration::ImportModule(x) => return Err(format!("imported module `{}` was not expanded into imports", x.path).into()),
+                // checked by `checker::check`, nothing is left to emit
+                ast::Declaration::StaticAssert(_) => {},
+                ast::Declaration::Error(_) => return Err("cannot emit a declaration that failed to parse".into())
+            }
+        }
+
+        // aliases are resolved once every `type` declaration is known, in
+        // declaration order so the type section does not depend on map order
+        for name in aliases {
+            if let Ok(ty) = context.function_type(&ast::TypeExpression::Named(name.clone())) {
+                context.signatures.insert(name.clone(), (module.add_type(ty.clone()), ty));
+            }
+        }
+
+        let function_base = u32::try_from(context.functions.len())?;
+
+        for (index, (decl, export)) in functions.iter().enumerate() {
+            let index = function_base + u32::try_from(index)?;
+            let ty = context.func_type(&decl.signature)?;
+
+            context.functions.insert(decl.name.clone(), (index, ty));
+            module.export(export, ExportKind::Function, index);
+        }
+
+        for (decl, export) in tables {
+            let index = u32::try_from(context.tables.len())?;
+
+            let ty = TableType::from_type(&decl.ty)?;
+
+            context.tables.insert(decl.name.clone(), (index, ty.element));
+            module.tables.push(ty);
+            module.export(&export, ExportKind::Table, index);
+        }
+
+        for (decl, export) in memories {
+            let index = u32::try_from(context.memories.len())?;
+
+            let mut limits = Limits::from_type(&decl.ty)?;
+
+            context.reserve_scratch(&decl.name, &mut limits)?;
+            module.memories.push(limits);
+            context.memories.insert(decl.name.clone(), index);
+            module.export(&export, ExportKind::Memory, index);
+        }
+
+        for (decl, export) in globals {
+            // a destructured global takes one constant of the tuple per name
+            let (bindings, values) = match (&decl.pattern, &decl.value) {
+                (ast::Pattern::Single(x), value) => (std::slice::from_ref(x), std::slice::from_ref(value)),
+                (ast::Pattern::Tuple(list), ast::Expression::Tuple(values)) => (list.as_slice(), values.as_slice()),
+                (ast::Pattern::Tuple(_), _) => return Err("a destructured global must be initialized with a tuple of constants".into())
+            };
+
+            if bindings.len() != values.len() {
+                return Err(format!("cannot destructure {} values into {} names", values.len(), bindings.len()).into());
+            }
+
+            if bindings.len() > 1 && matches!(export, Some(Some(_))) {
+                return Err("an export alias cannot name a destructured global".into());
+            }
+
+            for (binding, value) in bindings.iter().zip(values.iter()) {
+                let index = u32::try_from(context.globals.len())?;
+                let ty = match decl.ty.as_ref().map(|x| return context.value_types(x)).transpose()?.as_deref() {
+                    Some([x]) => *x,
+                    Some(_) => return Err(format!("global `{}` must have a single value", binding.name).into()),
+                    None => literal_type(value, &context.defaults).unwrap_or_else(|| return ValType::from_token(&context.defaults.int))
+                };
+
+                module.globals.push(Global {
+                    name: binding.name.clone(),
+                    ty,
+                    is_mutable: binding.is_mutable,
+                    init: constant(value, &ty.to_token())?
+                });
+                context.globals.insert(binding.name.clone(), (index, ty, binding.is_mutable));
+
+                if let Some(alias) = &export {
+                    module.export(&Some(alias.clone().unwrap_or_else(|| return binding.name.clone())), ExportKind::Global, index);
+                }
+            }
+        }
+
+        for (name, alias) in aliased {
+            let (kind, index) = if let Some((x, _)) = context.functions.get(name) {
+                (ExportKind::Function, *x)
+            }
+            else if let Some((x, _)) = context.tables.get(name) {
+                (ExportKind::Table, *x)
+            }
+            else if let Some(x) = context.memories.get(name) {
+                (ExportKind::Memory, *x)
+            }
+            else if let Some((x, _, _)) = context.globals.get(name) {
+                (ExportKind::Global, *x)
+            }
+            else {
+                return Err(format!("cannot find `{}` to export", name).into());
+            };
+
+            module.export(&Some(alias), kind, index);
+        }
+
+        for (decl, _) in functions {
+            let (_, ty) = &context.functions[&decl.name];
+            let ty_index = module.add_type(ty.clone());
+            let function = FunctionBuilder::new(&context, decl, ty)?.build(&decl.body)?;
+
+            for instruction in function.body.iter() {
+                if let Instruction::RefFunc(x) = instruction {
+                    module.declared.push(*x);
+                }
+            }
+
+            module.functions.push(Function { ty: ty_index, ..function });
+        }
+
+        module.declared.sort_unstable();
+        module.declared.dedup();
+
+        return Ok(module);
+    }
+
+    fn import(&mut self, context: &mut Context, decl: &ast::ImportDeclaration) -> Result<(), Box<dyn Error>> {
+        let (name, kind) = match &decl.item {
+            ast::ImportItem::Function(name, signature) => {
+                let ty = context.func_type(signature)?;
+                let index = u32::try_from(context.functions.len())?;
+
+                context.functions.insert(name.clone(), (index, ty.clone()));
+                (name, ImportKind::Function(self.add_type(ty)))
+            },
+            ast::ImportItem::Table(name, ty) => {
+                let ty = TableType::from_type(ty)?;
+
+                context.tables.insert(name.clone(), (u32::try_from(context.tables.len())?, ty.element));
+                (name, ImportKind::Table(ty))
+            },
+            ast::ImportItem::Memory(name, ty) => {
+                let mut limits = Limits::from_type(ty)?;
+
+                context.reserve_scratch(name, &mut limits)?;
+                context.memories.insert(name.clone(), u32::try_from(context.memories.len())?);
+                (name, ImportKind::Memory(limits))
+            },
+            ast::ImportItem::Variable(binding, ty) => {
+                let ty = match context.value_types(ty)?.as_slice() {
+                    [x] => *x,
+                    _ => return Err(format!("imported variable `{}` must have a single value type", binding.name).into())
+                };
+
+                context.globals.insert(binding.name.clone(), (u32::try_from(context.globals.len())?, ty, binding.is_mutable));
+                (&binding.name, ImportKind::Global(ty, binding.is_mutable))
+            }
+        };
+
+        self.imports.push(Import { module: decl.module.clone(), name: name.clone(), kind });
+
+        return Ok(());
+    }
+
+    fn export(&mut self, name: &Option<String>, kind: ExportKind, index: u32) {
+        if let Some(name) = name {
+            self.exports.push(Export { name: name.clone(), kind, index });
+        }
+    }
+}
+
+// the parts of a built module, for embedders that inspect or change it
+// before `to_wasm`. the compiler itself only writes it out
+#[allow(dead_code)]
+impl Module {
+    pub fn types(&self) -> &[FuncType] {
+        return &self.types;
+    }
+
+    pub fn imports(&self) -> &[Import] {
+        return &self.imports;
+    }
+
+    // function indices count the imported functions first
+    pub fn functions(&self) -> &[Function] {
+        return &self.functions;
+    }
+
+    pub fn functions_mut(&mut self) -> &mut Vec<Function> {
+        return &mut self.functions;
+    }
+
+    pub fn tables(&self) -> &[TableType] {
+        return &self.tables;
+    }
+
+    pub fn memories(&self) -> &[Limits] {
+        return &self.memories;
+    }
+
+    pub fn globals(&self) -> &[Global] {
+        return &self.globals;
+    }
+
+    pub fn exports(&self) -> &[Export] {
+        return &self.exports;
+    }
+
+    pub fn exports_mut(&mut self) -> &mut Vec<Export> {
+        return &mut self.exports;
+    }
+}
+
+impl Module {
+    // identical signatures share the index of their first occurrence, so a
+    // function added by hand gets its `ty` from here
+    pub fn add_type(&mut self, ty: FuncType) -> u32 {
+        if let Some(index) = self.type_indices.get(&ty) {
+            return *index;
+        }
+
+        let index = u32::try_from(self.types.len()).expect("unexpected type count overflow");
+
+        self.types.push(ty.clone());
+        self.type_indices.insert(ty, index);
+
+        return index;
+    }
+
+    // the whole module in memory, for embedders and tests. the command line
+    // streams it with `write_wasm`
+    #[allow(dead_code)]
+    pub fn to_wasm(&self) -> Vec<u8> {
+        let mut buf = vec![];
+
+        self.write_wasm(&mut buf).expect("unexpected write failure");
+
+        return buf;
+    }
+
+    // streams the binary into `w` a section at a time, and the code section a
+    // function at a time, so the whole module is never held in memory. what is
+    // held is one section, or one function body, which `validator::ValidatingWriter`
+    // holds too while it validates it
+    pub fn write_wasm<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
+        w.write_all(&[0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00])?;
+
+        write_section(w, 1, &self.types, |buf, x| {
+            buf.push(0x60);
+            write_vec(buf, &x.params, |buf, x| return buf.push(x.code()));
+            write_vec(buf, &x.results, |buf, x| return buf.push(x.code()));
+        })?;
+        write_section(w, 2, &self.imports, |buf, x| {
+            write_name(buf, &x.module);
+            write_name(buf, &x.name);
+
+            match &x.kind {
+                ImportKind::Function(ty) => {
+                    buf.push(0x00);
+                    write_unsigned(buf, u64::from(*ty));
+                },
+                ImportKind::Table(ty) => {
+                    buf.push(0x01);
+                    ty.to_wasm(buf);
+                },
+                ImportKind::Memory(limits) => {
+                    buf.push(0x02);
+                    limits.to_wasm(buf);
+                },
+                ImportKind::Global(ty, is_mutable) => {
+                    buf.extend_from_slice(&[0x03, ty.code(), u8::from(*is_mutable)]);
+                }
+            }
+        })?;
+        write_section(w, 3, &self.functions, |buf, x| return write_unsigned(buf, u64::from(x.ty)))?;
+        write_section(w, 4, &self.tables, |buf, x| return x.to_wasm(buf))?;
+        write_section(w, 5, &self.memories, |buf, x| return x.to_wasm(buf))?;
+        write_section(w, 6, &self.globals, |buf, x| {
+            buf.extend_from_slice(&[x.ty.code(), u8::from(x.is_mutable)]);
+            x.init.to_wasm(buf);
+            Instruction::End.to_wasm(buf);
+        })?;
+        write_section(w, 7, &self.exports, |buf, x| {
+            write_name(buf, &x.name);
+            buf.push(match x.kind {
+                ExportKind::Function => 0x00,
+                ExportKind::Table => 0x01,
+                ExportKind::Memory => 0x02,
+                ExportKind::Global => 0x03
+            });
+            write_unsigned(buf, u64::from(x.index));
+        })?;
+        // a single declarative segment of function indices
+        write_section(w, 9, if self.declared.is_empty() { &[] } else { std::slice::from_ref(&self.declared) }, |buf, x| {
+            buf.extend_from_slice(&[0x03, 0x00]);
+            write_vec(buf, x, |buf, x| return write_unsigned(buf, u64::from(*x)));
+        })?;
+
+        if self.functions.is_empty() {
+            return Ok(());
+        }
+
+        // bodies are encoded twice, once to size the section and once to write it
+        let mut size = unsigned_size(self.functions.len());
+
+        for x in self.functions.iter() {
+            let mut body = vec![];
+
+            x.to_wasm(&mut body);
+            size += unsigned_size(body.len()) + body.len();
+        }
+
+        let mut header = vec![10];
+
+        write_unsigned(&mut header, u64::try_from(size).expect("unexpected section size overflow"));
+        write_unsigned(&mut header, u64::try_from(self.functions.len()).expect("unexpected vector size overflow"));
+        w.write_all(&header)?;
+
+        for x in self.functions.iter() {
+            let mut body = vec![];
+
+            x.to_wasm(&mut body);
+            body.splice(0..0, unsigned_bytes(body.len()));
+            w.write_all(&body)?;
+        }
+
+        return Ok(());
+    }
+
+    // the flat instruction list of every function, see `ir::dump_function`
+    pub fn to_ir(&self) -> String {
+        return self.functions.iter()
+            .map(|x| return ir::dump_function(&x.name, &self.types[usize::try_from(x.ty).expect("unexpected type index overflow")], &x.locals, &x.body))
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+
+    // a Graphviz digraph of the control flow of every function, see `cfg::dump_function`
+    pub fn to_cfg(&self) -> String {
+        return self.functions.iter()
+            .map(|x| return cfg::dump_function(&x.name, &x.body))
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+
+    pub fn to_wat(&self) -> String {
+        let mut out = String::from("(module\n");
+        let types = |x: &[ValType]| return x.iter().map(|x| return x.name()).collect::<Vec<_>>().join(" ");
+        let signature = |x: &FuncType| {
+            let mut text = String::new();
+
+            if !x.params.is_empty() {
+                text.push_str(&format!(" (param {})", types(&x.params)));
+            }
+
+            if !x.results.is_empty() {
+                text.push_str(&format!(" (result {})", types(&x.results)));
+            }
+
+            return text;
+        };
+
+        for (index, ty) in self.types.iter().enumerate() {
+            writeln!(out, "  (type (;{};) (func{}))", index, signature(ty)).expect("unexpected write failure");
+        }
+
+        for x in self.imports.iter() {
+            let desc = match &x.kind {
+                ImportKind::Function(ty) => format!("(func (type {}))", ty),
+                ImportKind::Table(ty) => format!("(table {})", ty.to_wat()),
+                ImportKind::Memory(limits) => format!("(memory {})", limits.to_wat()),
+                ImportKind::Global(ty, true) => format!("(global (mut {}))", ty.name()),
+                ImportKind::Global(ty, false) => format!("(global {})", ty.name())
+            };
+
+            writeln!(out, "  (import {} {} {})", wat_string(&x.module), wat_string(&x.name), desc).expect("unexpected write failure");
+        }
+
+        for x in self.functions.iter() {
+            writeln!(out, "  (func ${} (type {}){}", x.name, x.ty, signature(&self.types[usize::try_from(x.ty).expect("unexpected type index overflow")])).expect("unexpected write failure");
+
+            if !x.locals.is_empty() {
+                writeln!(out, "    (local {})", types(&x.locals)).expect("unexpected write failure");
+            }
+
+            let mut depth = 2;
+
+            // the last `end` closes the function itself
+            for instruction in x.body.iter().take(x.body.len().saturating_sub(1)) {
+                if matches!(instruction, Instruction::End | Instruction::Else) {
+                    depth -= 1;
+                }
+
+                writeln!(out, "{}{}", "  ".repeat(depth), instruction.to_wat()).expect("unexpected write failure");
+
+                if matches!(instruction, Instruction::Block(_) | Instruction::Loop(_) | Instruction::If(_) | Instruction::Else) {
+                    depth += 1;
+                }
+            }
+
+            out.push_str("  )\n");
+        }
+
+        for x in self.tables.iter() {
+            writeln!(out, "  (table {})", x.to_wat()).expect("unexpected write failure");
+        }
+
+        for x in self.memories.iter() {
+            writeln!(out, "  (memory {})", x.to_wat()).expect("unexpected write failure");
+        }
+
+        for x in self.globals.iter() {
+            let ty = if x.is_mutable { format!("(mut {})", x.ty.name()) } else { x.ty.name().to_string() };
+
+            writeln!(out, "  (global ${} {} ({}))", x.name, ty, x.init.to_wat()).expect("unexpected write failure");
+        }
+
+        for x in self.exports.iter() {
+            let kind = match x.kind {
+                ExportKind::Function => "func",
+                ExportKind::Table => "table",
+                ExportKind::Memory => "memory",
+                ExportKind::Global => "global"
+            };
+
+            writeln!(out, "  (export {} ({} {}))", wat_string(&x.name), kind, x.index).expect("unexpected write failure");
+        }
+
+        if !self.declared.is_empty() {
+            let indices = self.declared.iter().map(u32::to_string).collect::<Vec<_>>();
+
+            writeln!(out, "  (elem declare func {})", indices.join(" ")).expect("unexpected write failure");
+        }
+
+        out.push_str(")\n");
+
+        return out;
+    }
+
+    // a json summary of what the module imports and exports, for tools that
+    // would otherwise have to parse the wasm
+    pub fn to_metadata(&self, opt_level: &str) -> String {
+        let signature = |ty: u32| {
+            let ty = &self.types[usize::try_from(ty).expect("unexpected type index overflow")];
+            let types = |x: &[ValType]| return x.iter().map(|x| return json_string(x.name())).collect::<Vec<_>>().join(", ");
+
+            return format!("\"params\": [{}], \"results\": [{}]", types(&ty.params), types(&ty.results));
+        };
+        let table = |x: &TableType| return format!("\"kind\": \"table\", \"element\": {}, {}", json_string(x.element.name()), x.limits.to_json());
+        let memory = |x: &Limits| return format!("\"kind\": \"memory\", {}", x.to_json());
+        let global = |ty: ValType, is_mutable: bool| return format!("\"kind\": \"global\", \"type\": {}, \"mutable\": {}", json_string(ty.name()), is_mutable);
+        let list = |items: Vec<String>| {
+            if items.is_empty() {
+                return "[]".to_string();
+            }
+
+            return format!("[\n    {{ {} }}\n  ]", items.join(" },\n    { "));
+        };
+
+        // each index space counts imports first
+        let mut functions = vec![];
+        let mut tables = vec![];
+        let mut memories = vec![];
+        let mut globals = vec![];
+
+        let imports = self.imports.iter().map(|x| {
+            let desc = match &x.kind {
+                ImportKind::Function(ty) => {
+                    functions.push(*ty);
+                    format!("\"kind\": \"function\", {}", signature(*ty))
+                },
+                ImportKind::Table(ty) => {
+                    tables.push(ty);
+                    table(ty)
+                },
+                ImportKind::Memory(limits) => {
+                    memories.push(limits);
+                    memory(limits)
+                },
+                ImportKind::Global(ty, is_mutable) => {
+                    globals.push((*ty, *is_mutable));
+                    global(*ty, *is_mutable)
+                }
+            };
+
+            return format!("\"module\": {}, \"name\": {}, {}", json_string(&x.module), json_string(&x.name), desc);
+        }).collect();
+
+        functions.extend(self.functions.iter().map(|x| return x.ty));
+        tables.extend(self.tables.iter());
+        memories.extend(self.memories.iter());
+        globals.extend(self.globals.iter().map(|x| return (x.ty, x.is_mutable)));
+
+        let exports = self.exports.iter().map(|x| {
+            let index = usize::try_from(x.index).expect("unexpected index overflow");
+            let desc = match x.kind {
+                ExportKind::Function => format!("\"kind\": \"function\", {}", signature(functions[index])),
+                ExportKind::Table => table(tables[index]),
+                ExportKind::Memory => memory(memories[index]),
+                ExportKind::Global => global(globals[index].0, globals[index].1)
+            };
+
+            return format!("\"name\": {}, {}", json_string(&x.name), desc);
+        }).collect();
+
+        let mut out = String::from("{\n");
+
+        writeln!(out, "  \"target\": \"wasm32\",").expect("unexpected write failure");
+        writeln!(out, "  \"opt_level\": {},", json_string(opt_level)).expect("unexpected write failure");
+        writeln!(out, "  \"imports\": {},", list(imports)).expect("unexpected write failure");
+        writeln!(out, "  \"exports\": {},", list(exports)).expect("unexpected write failure");
+        writeln!(out, "  \"memories\": {},", list(memories.iter().map(|x| return x.to_json()).collect())).expect("unexpected write failure");
+        writeln!(out, "  \"tables\": {}", list(tables.iter().map(|x| return format!("\"element\": {}, {}", json_string(x.element.name()), x.limits.to_json())).collect())).expect("unexpected write failure");
+        out.push_str("}\n");
+
+        return out;
+    }
+}
+
+// function section
+struct FunctionBuilder<'c> {
+    context: &'c Context,
+    name: String,
+    results: Vec<ValType>,
+    // every local, with parameters first
+    locals: Vec<ValType>,
+    // names in scope, later bindings shadow earlier ones
+    scope: Vec<(String, u32)>,
+    body: Vec<Instruction>,
+    // open control labels, and the label of the enclosing `block` of each loop
+    labels: usize,
+    loops: Vec<usize>,
+    // deferred blocks so far, with the length of the scope they were declared in
+    defers: Vec<(ast::Block, usize)>
+}
+
+impl<'c> FunctionBuilder<'c> {
+    fn new(context: &'c Context, decl: &ast::FunctionDeclaration, ty: &FuncType) -> Result<Self, Box<dyn Error>> {
+        let mut builder = Self {
+            context,
+            name: decl.name.clone(),
+            results: ty.results.clone(),
+            locals: vec![],
+            scope: vec![],
+            body: vec![],
+            labels: 0,
+            loops: vec![],
+            defers: vec![]
+        };
+
+        for param in decl.signature.params.iter() {
+            match context.value_types(&param.ty)?.as_slice() {
+                [x] => {
+                    builder.declare(&param.name, *x)?;
+                },
+                _ => return Err(format!("parameter `{}` must have a single value type", param.name).into())
+            }
+        }
+
+        return Ok(builder);
+    }
+
+    fn build(mut self, block: &ast::Block) -> Result<Function, Box<dyn Error>> {
+        let params = self.scope.len();
+
+        self.statements(&block.statements)?;
+
+        if let Some(tail) = &block.tail {
+            let results = self.results.clone();
+
+            self.values(tail, &results)?;
+
+            if !self.tail_call(tail) {
+                self.defers()?;
+            }
+        }
+        else if !matches!(block.statements.last().map(|x| return &x.node), Some(ast::Statement::Return(_))) {
+            if self.results.is_empty() {
+                self.defers()?;
+            }
+            else {
+                // every path has returned already, which the checker ensures
+                self.body.push(Instruction::Unreachable);
+            }
+        }
+
+        self.body.push(Instruction::End);
+
+        return Ok(Function {
+            name: self.name,
+            ty: 0,
+            locals: self.locals.split_off(params),
+            body: self.body
+        });
+    }
+
+    fn declare(&mut self, name: &str, ty: ValType) -> Result<u32, Box<dyn Error>> {
+        let index = u32::try_from(self.locals.len())?;
+
+        self.locals.push(ty);
+        self.scope.push((name.to_string(), index));
+
+        return Ok(index);
+    }
+
+    // an extra argument of a variadic call, which must be of the type of the
+    // variadic parameter
+    fn variadic_argument(&mut self, arg: &ast::Expression, ty: ValType) -> Result<(), Box<dyn Error>> {
+        if let [found] = self.expression(arg, Some(ty))?.as_slice() {
+            if *found != ty {
+                return Err(format!("extra arguments must be {}, found {}", ty.name(), found.name()).into());
+            }
+        }
+
+        return Ok(());
+    }
+
+    // a local the program cannot name, such as the counter of a `repeat`
+    fn hidden(&mut self, ty: ValType) -> Result<u32, Box<dyn Error>> {
+        let index = u32::try_from(self.locals.len())?;
+
+        self.locals.push(ty);
+
+        return Ok(index);
+    }
+
+    // `op` on the two integers on the stack, trapping when the result
+    // overflows. a signed sum overflows when its sign differs from both
+    // operands, and a difference when the operands differ in sign and the
+    // result differs from the first. an unsigned sum carries when it is below
+    // an operand, and a difference borrows when the second operand is above
+    // the first. a product overflows when dividing it by one operand misses
+    // the other
+    fn checked(&mut self, ty: ValType, op: BinaryOp, is_unsigned: bool) -> Result<(), Box<dyn Error>> {
+        let (a, b, result) = (self.hidden(ty)?, self.hidden(ty)?, self.hidden(ty)?);
+        let zero = if ty == ValType::I64 { Instruction::I64Const(0) } else { Instruction::I32Const(0) };
+
+        self.body.extend(vec![
+            Instruction::LocalSet(b),
+            Instruction::LocalSet(a),
+            Instruction::LocalGet(a),
+            Instruction::LocalGet(b),
+            Instruction::Binary(ty, op),
+            Instruction::LocalSet(result)
+        ]);
+
+        if op == BinaryOp::Mul {
+            self.body.extend(vec![
+                Instruction::LocalGet(a),
+                zero,
+                Instruction::Binary(ty, BinaryOp::Ne),
+                Instruction::If(BlockType::Empty),
+                Instruction::LocalGet(result),
+                Instruction::LocalGet(a),
+                Instruction::Binary(ty, if is_unsigned { BinaryOp::DivU } else { BinaryOp::Div }),
+                Instruction::LocalGet(b),
+                Instruction::Binary(ty, BinaryOp::Ne),
+                Instruction::If(BlockType::Empty),
+                Instruction::Unreachable,
+                Instruction::End,
+                Instruction::End
+            ]);
+        }
+        else if is_unsigned {
+            let (x, y) = if op == BinaryOp::Add { (result, a) } else { (a, b) };
+
+            self.body.extend(vec![
+                Instruction::LocalGet(x),
+                Instruction::LocalGet(y),
+                Instruction::Binary(ty, BinaryOp::LtU),
+                Instruction::If(BlockType::Empty),
+                Instruction::Unreachable,
+                Instruction::End
+            ]);
+        }
+        else {
+            let (x, y) = if op == BinaryOp::Add { (b, result) } else { (b, a) };
+
+            self.body.extend(vec![
+                Instruction::LocalGet(a),
+                Instruction::LocalGet(result),
+                Instruction::Binary(ty, BinaryOp::Xor),
+                Instruction::LocalGet(x),
+                Instruction::LocalGet(y),
+                Instruction::Binary(ty, BinaryOp::Xor),
+                Instruction::Binary(ty, BinaryOp::And),
+                zero,
+                Instruction::Binary(ty, BinaryOp::Lt),
+                Instruction::If(BlockType::Empty),
+                Instruction::Unreachable,
+                Instruction::End
+            ]);
+        }
+
+        self.body.push(Instruction::LocalGet(result));
+
+        return Ok(());
+    }
+
+    // an integer whose type no context decides
+    fn default_int(&self) -> ValType {
+        return ValType::from_token(&self.context.defaults.int);
+    }
+
+    fn local(&self, name: &str) -> Option<(u32, ValType)> {
+        return self.scope.iter().rev()
+            .find(|x| return x.0 == name)
+            .map(|x| return (x.1, self.locals[usize::try_from(x.1).expect("unexpected local index overflow")]));
+    }
+
+    fn block(&mut self, block: &ast::Block) -> Result<(), Box<dyn Error>> {
+        let scope = self.scope.len();
+
+        self.statements(&block.statements)?;
+
+        // a nested tail is the value of the function, as the checker sees it
+        if let Some(tail) = &block.tail {
+            if self.results.is_empty() {
+                for _ in self.expression(tail, None)? {
+                    self.body.push(Instruction::Drop);
+                }
+            }
+            else {
+                self.ret(Some(tail))?;
+            }
+        }
+
+        self.scope.truncate(scope);
+
+        return Ok(());
+    }
+
+    fn ret(&mut self, value: Option<&ast::Expression>) -> Result<(), Box<dyn Error>> {
+        if let Some(value) = value {
+            let results = self.results.clone();
+
+            self.values(value, &results)?;
+
+            if self.tail_call(value) {
+                return Ok(());
+            }
+        }
+
+        self.defers()?;
+        self.body.push(Instruction::Return);
+
+        return Ok(());
+    }
+
+    // a returned call, whose results are those of the function once the values
+    // are checked, becomes a tail call when enabled. a deferred block would
+    // have to run after it, so then the frame is kept
+    fn tail_call(&mut self, value: &ast::Expression) -> bool {
+        if !self.context.features.tail_call || !self.defers.is_empty() {
+            return false;
+        }
+
+        let tail = match (value, self.body.last()) {
+            (ast::Expression::Call { .. }, Some(Instruction::Call(x))) => Instruction::ReturnCall(*x),
+            (ast::Expression::CallIndirect { .. }, Some(Instruction::CallIndirect(ty, table))) => Instruction::ReturnCallIndirect(*ty, *table),
+            _ => return false
+        };
+
+        self.body.pop();
+        self.body.push(tail);
+
+        return true;
+    }
+
+    // copies the deferred blocks on an exit of the function, the latest first.
+    // the returned values are already on the stack, so a deferred block cannot
+    // change them, and it only sees the names in scope where it was declared
+    fn defers(&mut self) -> Result<(), Box<dyn Error>> {
+        for (block, scope) in self.defers.clone().iter().rev() {
+            let shadowing = self.scope.split_off(*scope);
+
+            self.block(block)?;
+            self.scope.extend(shadowing);
+        }
+
+        return Ok(());
+    }
+
+    fn statements(&mut self, statements: &[Spanned<ast::Statement>]) -> Result<(), Box<dyn Error>> {
+        for statement in statements {
+            self.statement(statement)?;
+        }
+
+        return Ok(());
+    }
+
+    fn statement(&mut self, statement: &ast::Statement) -> Result<(), Box<dyn Error>> {
+        match statement {
+            ast::Statement::Variable(decl) => {
+                let declared = decl.ty.as_ref().map(|x| return self.context.value_types(x)).transpose()?;
+                let types = self.expression(&decl.value, declared.as_ref().and_then(|x| return x.first().copied()))?;
+
+                if let (Some(declared), ast::Pattern::Single(x)) = (&declared, &decl.pattern) {
+                    if declared != &types {
+                        return Err(format!("variable `{}` is declared {}, but its value is {}", x.name, value_list(declared), value_list(&types)).into());
+                    }
+                }
+
+                let bindings = match &decl.pattern {
+                    ast::Pattern::Single(x) if types.len() != 1 => {
+                        return Err(format!("variable `{}` must have a single value", x.name).into());
+                    },
+                    ast::Pattern::Single(x) => std::slice::from_ref(x),
+                    ast::Pattern::Tuple(list) if types.len() != list.len() => {
+                        return Err(format!("cannot destructure {} values into {} names", types.len(), list.len()).into());
+                    },
+                    ast::Pattern::Tuple(list) => list.as_slice()
+                };
+                let indices = bindings.iter()
+                    .zip(types)
+                    .map(|(x, ty)| return self.declare(&x.name, ty))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                // the last value is on top of the stack
+                for index in indices.into_iter().rev() {
+                    self.body.push(Instruction::LocalSet(index));
+                }
+            },
+            ast::Statement::Expression(expr) => {
+                for _ in self.expression(expr, None)? {
+                    self.body.push(Instruction::Drop);
+                }
+            },
+            ast::Statement::Assignment(ast::Expression::Offset { offset, signature, base, align }, value) => {
+                if let Some((index, element)) = self.table_entry(offset, signature.as_ref(), base)? {
+                    if let [ty] = self.expression(value, Some(element))?.as_slice() {
+                        if *ty != element {
+                            return Err(format!("cannot store {} in table `{}`, which holds {}", ty.name(), base, element.name()).into());
+                        }
+                    }
+
+                    self.body.push(Instruction::TableSet(index));
+                }
+                else {
+                    let (ty, memarg) = self.address(offset, signature.as_ref(), base, align.as_deref())?;
+
+                    self.expression(value, Some(ty))?;
+                    self.body.push(Instruction::Store(ty, memarg));
+                }
+            },
+            ast::Statement::Assignment(target, value) => {
+                let name = match target {
+                    ast::Expression::Identifier(x) => x,
+                    _ => return Err("assignment to this target is not supported yet".into())
+                };
+
+                if let Some((index, ty)) = self.local(name) {
+                    self.expression(value, Some(ty))?;
+                    self.body.push(Instruction::LocalSet(index));
+                }
+                else if let Some((index, ty, is_mutable)) = self.context.globals.get(name) {
+                    if !is_mutable {
+                        return Err(format!("cannot assign twice to immutable variable `{}`", name).into());
+                    }
+
+                    self.expression(value, Some(*ty))?;
+                    self.body.push(Instruction::GlobalSet(*index));
+                }
+                else {
+                    return Err(format!("cannot find `{}` in this scope", name).into());
+                }
+            },
+            ast::Statement::If(x) => {
+                let mut branches = 0;
+
+                for (condition, block) in std::iter::once((&x.condition, &x.block)).chain(x.else_ifs.iter().map(|x| return (&x.0, &x.1))) {
+                    if branches > 0 {
+                        self.body.push(Instruction::Else);
+                    }
+
+                    self.condition(condition)?;
+                    self.body.push(Instruction::If(BlockType::Empty));
+                    self.labels += 1;
+                    self.block(block)?;
+                    branches += 1;
+                }
+
+                if let Some(block) = &x.else_block {
+                    self.body.push(Instruction::Else);
+                    self.block(block)?;
+                }
+
+                for _ in 0..branches {
+                    self.body.push(Instruction::End);
+                }
+
+                self.labels -= branches;
+            },
+            ast::Statement::While(x) => {
+                self.body.push(Instruction::Block(BlockType::Empty));
+                self.body.push(Instruction::Loop(BlockType::Empty));
+                self.loops.push(self.labels);
+                self.labels += 2;
+
+                self.condition(&x.condition)?;
+                self.body.push(Instruction::Eqz(ValType::I32));
+                self.body.push(Instruction::BrIf(1));
+                self.block(&x.block)?;
+                self.body.push(Instruction::Br(0));
+
+                self.body.push(Instruction::End);
+                self.body.push(Instruction::End);
+                self.loops.pop();
+                self.labels -= 2;
+            },
+            // the counter is decremented before the body, so `cont` needs no
+            // extra label to reach the decrement
+            ast::Statement::Repeat(x) => {
+                let count = i32::try_from(checker::repeat_count(&x.count)?)?;
+                let counter = self.hidden(ValType::I32)?;
+
+                self.body.push(Instruction::I32Const(count));
+                self.body.push(Instruction::LocalSet(counter));
+                self.body.push(Instruction::Block(BlockType::Empty));
+                self.body.push(Instruction::Loop(BlockType::Empty));
+                self.loops.push(self.labels);
+                self.labels += 2;
+
+                self.body.push(Instruction::LocalGet(counter));
+                self.body.push(Instruction::Eqz(ValType::I32));
+                self.body.push(Instruction::BrIf(1));
+                self.body.push(Instruction::LocalGet(counter));
+                self.body.push(Instruction::I32Const(1));
+                self.body.push(Instruction::Binary(ValType::I32, BinaryOp::Sub));
+                self.body.push(Instruction::LocalSet(counter));
+                self.block(&x.block)?;
+                self.body.push(Instruction::Br(0));
+
+                self.body.push(Instruction::End);
+                self.body.push(Instruction::End);
+                self.loops.pop();
+                self.labels -= 2;
+            },
+            // `return` leaves every enclosing block and loop at once, so unlike
+            // `break` it needs no label depth
+            ast::Statement::Return(value) => self.ret(value.as_ref())?,
+            ast::Statement::Break | ast::Statement::Continue => {
+                let label = match self.loops.last() {
+                    Some(x) => *x,
+                    None => return Err("`break` and `continue` are only allowed inside a loop".into())
+                };
+
+                // the loop label sits right inside its block label
+                let target = if statement == &ast::Statement::Break { label } else { label + 1 };
+
+                self.body.push(Instruction::Br(u32::try_from(self.labels - 1 - target)?));
+            },
+            ast::Statement::Defer(x) => self.defers.push((x.clone(), self.scope.len())),
+            ast::Statement::StaticAssert(_) => {},
+            ast::Statement::Function(x) => return Err(format!("nested function `{}` was not lifted to module scope", x.name).into()),
+            ast::Statement::Block(x) => self.block(x)?
+        }
+
+        return Ok(());
+    }
+
+    // emits a value for each expected type, spreading tuples across them
+    fn values(&mut self, expr: &ast::Expression, expected: &[ValType]) -> Result<(), Box<dyn Error>> {
+        let types = match expr {
+            ast::Expression::Tuple(list) if list.len() == expected.len() => {
+                for (x, ty) in list.iter().zip(expected.iter()) {
+                    self.expression(x, Some(*ty))?;
+                }
+
+                expected.to_vec()
+            },
+            _ => self.expression(expr, expected.first().copied())?
+        };
+
+        if types != expected {
+            return Err(format!("function `{}` expects a result of {}, found {}", self.name, value_list(expected), value_list(&types)).into());
+        }
+
+        return Ok(());
+    }
+
+    // the type of an expression when it can be told without context
+    fn peek(&self, expr: &ast::Expression) -> Option<ValType> {
+        return match expr {
+            ast::Expression::Literal(_) => literal_type(expr, &self.context.defaults),
+            ast::Expression::Identifier(name) => match self.local(name) {
+                Some((_, ty)) => Some(ty),
+                None if self.context.functions.contains_key(name) => Some(ValType::FuncRef),
+                None => self.context.globals.get(name).map(|x| return x.1)
+            },
+            ast::Expression::Call { callee, args } if !self.context.functions.contains_key(callee) => match Intrinsic::from_name(callee) {
+                Some(Intrinsic::IsNull) => Some(ValType::I32),
+                Some(_) => args.iter().find_map(|x| return self.peek(x)),
+                None => None
+            },
+            ast::Expression::Call { callee, .. } => self.context.functions.get(callee).and_then(|x| return x.1.results.first().copied()),
+            ast::Expression::Unary { op: token::Symbol::LogicalNegation, .. } => Some(ValType::I32),
+            ast::Expression::Unary { operand, .. } => self.peek(operand),
+            ast::Expression::Binary { op, lhs, rhs } => match BinaryOp::from_symbol(op) {
+                Some(x) if x.is_comparison() => Some(ValType::I32),
+                _ => self.peek(lhs).or_else(|| return self.peek(rhs))
+            },
+            ast::Expression::Conditional { consequent, alternative, .. } => self.peek(consequent).or_else(|| return self.peek(alternative)),
+            ast::Expression::Cast { ty, .. } => Some(ValType::from_token(ty)),
+            ast::Expression::Offset { base, .. } if self.context.tables.contains_key(base) => Some(self.context.tables[base].1),
+            ast::Expression::Offset { signature: Some(x), .. } => match self.context.value_types(x) {
+                Ok(list) if list.len() == 1 => Some(list[0]),
+                _ => None
+            },
+            _ => None
+        };
+    }
+
+    // pushes the entry index of `@offset(table)` and returns the table, or
+    // nothing when `base` is not a table
+    fn table_entry(&mut self, offset: &ast::Expression, signature: Option<&ast::TypeExpression>, base: &str) -> Result<Option<(u32, ValType)>, Box<dyn Error>> {
+        let (index, element) = match self.context.tables.get(base) {
+            Some(x) => *x,
+            None => return Ok(None)
+        };
+
+        if let Some(ty) = signature {
+            if self.context.value_types(ty)? != [element] {
+                return Err(format!("table `{}` holds {}, not `{}`", base, element.name(), ty).into());
+            }
+        }
+
+        self.expression(offset, Some(ValType::I32))?;
+
+        return Ok(Some((index, element)));
+    }
+
+    // pushes the address of `@offset<ty>(memory)`, and returns the type and
+    // memarg the load or store through it uses
+    // accesses promise the natural alignment of their type unless a lower one
+    // is given. wasm rejects alignments above the natural one, so those are
+    // lowered to it, which the `over-aligned-access` lint reports
+    fn address(&mut self, offset: &ast::Expression, signature: Option<&ast::TypeExpression>, base: &str, align: Option<&str>) -> Result<(ValType, MemArg), Box<dyn Error>> {
+        match self.context.memories.get(base) {
+            Some(0) => {},
+            Some(x) => return Err(format!("cannot address memory `{}`; only the first memory can be loaded from or stored to, and this is memory {}", base, x).into()),
+            None => return Err(format!("cannot find memory `{}`", base).into())
+        }
+
+        let ty = match signature.map(|x| return self.context.value_types(x)).transpose()?.as_deref() {
+            Some([x]) => *x,
+            Some(_) => return Err(format!("memory `{}` can only be accessed one number at a time", base).into()),
+            None => return Err(format!("memory access through `{}` needs a value type, as in `@0<i32>({})`", base, base).into())
+        };
+
+        let align = match align {
+            Some(x) => alignment(x)?.min(ty.natural_alignment()),
+            None => ty.natural_alignment()
+        };
+
+        self.expression(offset, Some(ValType::I32))?;
+
+        return Ok((ty, MemArg { align, offset: 0 }));
+    }
+
+    // operands share the type of the first one that tells it
+    fn intrinsic(&mut self, name: &str, intrinsic: Intrinsic, args: &[ast::Expression], expected: Option<ValType>) -> Result<Vec<ValType>, Box<dyn Error>> {
+        if args.len() != intrinsic.arity() {
+            return Err(format!("intrinsic `{}` takes {} arguments, but {} were given", name, intrinsic.arity(), args.len()).into());
+        }
+
+        let ty = args.iter().find_map(|x| return self.peek(x))
+            .or_else(|| return expected.filter(|x| return intrinsic.accepts(*x)))
+            .unwrap_or(intrinsic.operand_types()[0]);
+
+        if !intrinsic.accepts(ty) {
+            return Err(format!("intrinsic `{}` takes {} operands, found {}", name, intrinsic.operands_name(), ty.name()).into());
+        }
+
+        for arg in args.iter() {
+            if self.expression(arg, Some(ty))? != [ty] {
+                return Err(format!("operands of intrinsic `{}` must all be {}", name, ty.name()).into());
+            }
+        }
+
+        self.body.push(intrinsic.instruction(ty));
+
+        return Ok(vec![intrinsic.result_type(ty)]);
+    }
+
+    // branches test an i32, so a wider integer is compared with zero first
+    // branches take an i32, so an i64 condition is compared with zero
+    fn condition(&mut self, expr: &ast::Expression) -> Result<(), Box<dyn Error>> {
+        return match self.expression(expr, Some(ValType::I32))?.as_slice() {
+            [ValType::I32] => Ok(()),
+            [ValType::I64] => {
+                self.body.push(Instruction::I64Const(0));
+                self.body.push(Instruction::Binary(ValType::I64, BinaryOp::Ne));
+                Ok(())
+            },
+            found => Err(format!("condition must be a scalar integer, found {}", value_list(found)).into())
+        };
+    }
+
+    fn expression(&mut self, expr: &ast::Expression, expected: Option<ValType>) -> Result<Vec<ValType>, Box<dyn Error>> {
+        return match expr {
+            ast::Expression::Literal(x) if ast::string_contents(x).is_some() => {
+                Err(format!("string literal {} has no runtime value; strings can only be concatenated at compile time", x).into())
+            },
+            ast::Expression::Literal(_) => {
+                let ty = literal_type(expr, &self.context.defaults).or(expected).unwrap_or_else(|| return self.default_int());
+
+                self.body.push(constant(expr, &ty.to_token())?);
+                Ok(vec![ty])
+            },
+            ast::Expression::Identifier(name) => {
+                if let Some((index, ty)) = self.local(name) {
+                    self.body.push(Instruction::LocalGet(index));
+                    Ok(vec![ty])
+                }
+                else if let Some((index, ty, _)) = self.context.globals.get(name) {
+                    self.body.push(Instruction::GlobalGet(*index));
+                    Ok(vec![*ty])
+                }
+                // a function named as a value is a reference to it
+                else if let Some((index, _)) = self.context.functions.get(name) {
+                    self.body.push(Instruction::RefFunc(*index));
+                    Ok(vec![ValType::FuncRef])
+                }
+                else {
+                    Err(format!("cannot find `{}` in this scope", name).into())
+                }
+            },
+            ast::Expression::Call { callee, args } => {
+                let (index, ty) = match (self.context.functions.get(callee), Intrinsic::from_name(callee)) {
+                    (Some(x), _) => x,
+                    (None, Some(intrinsic)) => return self.intrinsic(callee, intrinsic, args, expected),
+                    (None, None) => return Err(format!("cannot find function `{}`", callee).into())
+                };
+
+                // the extra arguments of a variadic call fill two parameters
+                let arity = args.iter().map(|x| return if matches!(x, ast::Expression::Variadic { .. }) { 2 } else { 1 }).sum::<usize>();
+
+                if arity != ty.params.len() {
+                    return Err(format!("function `{}` takes {} arguments, but {} were given", callee, ty.params.len(), arity).into());
+                }
+
+                let mut params = ty.params.iter();
+
+                for arg in args.iter() {
+                    let param = params.next().copied();
+
+                    if let ast::Expression::Variadic { .. } = arg {
+                        params.next();
+                    }
+
+                    self.expression(arg, param)?;
+                }
+
+                self.body.push(Instruction::Call(*index));
+                Ok(ty.results.clone())
+            },
+            ast::Expression::CallIndirect { target, signature, args } => {
+                let (ty_index, ty) = match signature {
+                    ast::TypeExpression::Named(name) => match self.context.signatures.get(name) {
+                        Some(x) => x,
+                        None => return Err(format!("`{}` is not a function type", name).into())
+                    },
+                    x => return Err(format!("call_indirect needs a `type` alias for its signature, found `{}`", x).into())
+                };
+
+                if args.len() != ty.params.len() {
+                    return Err(format!("signature `{}` takes {} arguments, but {} were given", signature, ty.params.len(), args.len()).into());
+                }
+
+                for (arg, param) in args.iter().zip(ty.params.iter()) {
+                    self.expression(arg, Some(*param))?;
+                }
+
+                // `@i(table)` names the table, a plain index refers to the first one
+                let table = match target.as_ref() {
+                    ast::Expression::Offset { offset, base, .. } => {
+                        self.expression(offset, Some(ValType::I32))?;
+
+                        match self.context.tables.get(base) {
+                            Some((x, ValType::FuncRef)) => *x,
+                            Some(_) => return Err(format!("cannot call through table `{}`, which does not hold fref", base).into()),
+                            None => return Err(format!("cannot find table `{}`", base).into())
+                        }
+                    },
+                    x => {
+                        self.expression(x, Some(ValType::I32))?;
+                        0
+                    }
+                };
+
+                self.body.push(Instruction::CallIndirect(*ty_index, table));
+                Ok(ty.results.clone())
+            },
+            ast::Expression::Unary { op: token::Symbol::Minus, operand } => {
+                let ty = self.peek(operand).or(expected).unwrap_or_else(|| return self.default_int());
+
+                if ty.is_reference() {
+                    return Err(format!("operator - is not defined for {}", ty.name()).into());
+                }
+
+                if let ast::Expression::Literal(_) = operand.as_ref() {
+                    self.body.push(constant(expr, &ty.to_token())?);
+                }
+                else if ty.is_float() {
+                    self.expression(operand, Some(ty))?;
+                    self.body.push(Instruction::Neg(ty));
+                }
+                else {
+                    self.body.push(if ty == ValType::I32 { Instruction::I32Const(0) } else { Instruction::I64Const(0) });
+                    self.expression(operand, Some(ty))?;
+                    self.body.push(Instruction::Binary(ty, BinaryOp::Sub));
+                }
+
+                Ok(vec![ty])
+            },
+            ast::Expression::Unary { op: token::Symbol::Plus, operand } => self.expression(operand, expected),
+            // there is no `not` instruction, so the bits are flipped by xor with all ones
+            ast::Expression::Unary { op: token::Symbol::BitwiseNot, operand } => {
+                let ty = self.peek(operand).or(expected).unwrap_or_else(|| return self.default_int());
+
+                if ty.is_float() || ty.is_reference() {
+                    return Err(format!("operator ~ is not defined for {}", ty.name()).into());
+                }
+
+                self.expression(operand, Some(ty))?;
+                self.body.push(if ty == ValType::I32 { Instruction::I32Const(-1) } else { Instruction::I64Const(-1) });
+                self.body.push(Instruction::Binary(ty, BinaryOp::Xor));
+
+                Ok(vec![ty])
+            },
+            ast::Expression::Unary { op: token::Symbol::LogicalNegation, operand } => {
+                let ty = self.peek(operand).unwrap_or_else(|| return self.default_int());
+
+                if ty.is_float() || ty.is_reference() {
+                    return Err(format!("operator ! is not defined for {}", ty.name()).into());
+                }
+
+                self.expression(operand, Some(ty))?;
+                self.body.push(Instruction::Eqz(ty));
+
+                Ok(vec![ValType::I32])
+            },
+            ast::Expression::Binary { op, lhs, rhs } => {
+                let op_code = match BinaryOp::from_symbol(op) {
+                    Some(x) => x,
+                    None => return Err(format!("operator `{}` is not supported yet", op.name()).into())
+                };
+                let hint = if op_code.is_comparison() { None } else { expected };
+                let ty = self.peek(lhs).or_else(|| return self.peek(rhs)).or(hint).unwrap_or_else(|| return self.default_int());
+
+                if op_code == BinaryOp::RemS && ty.is_float() {
+                    return Err(format!("operator % is not defined for {}; wasm has no float remainder instruction", ty.name()).into());
+                }
+
+                if op_code.encoding(ty).is_none() {
+                    return Err(format!("operator `{}` is not defined for {}", op.name(), ty.name()).into());
+                }
+
+                self.expression(lhs, Some(ty))?;
+                self.expression(rhs, Some(ty))?;
+
+                if self.context.features.overflow == feature::Overflow::Trap && !ty.is_float() && matches!(op_code, BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul) {
+                    let is_unsigned = matches!(op, token::Symbol::PlusUnsigned | token::Symbol::MinusUnsigned | token::Symbol::AsteriskUnsigned);
+
+                    self.checked(ty, op_code, is_unsigned)?;
+                }
+                else {
+                    self.body.push(Instruction::Binary(ty, op_code));
+                }
+
+                Ok(vec![if op_code.is_comparison() { ValType::I32 } else { ty }])
+            },
+            // signed and unsigned integers share a representation, so only a
+            // change of width emits an instruction. the checker keeps the
+            // signedness of a widened operand, so the target tells how to extend
+            ast::Expression::Cast { ty, operand } => {
+                let target = ValType::from_token(ty);
+                let source = self.peek(operand).unwrap_or(target);
+
+                self.expression(operand, Some(source))?;
+
+                match (source, target) {
+                    (ValType::I64, ValType::I32) => self.body.push(Instruction::I32WrapI64),
+                    (ValType::I32, ValType::I64) if ty == &token::Type::U64 => self.body.push(Instruction::I64ExtendI32U),
+                    (ValType::I32, ValType::I64) => self.body.push(Instruction::I64ExtendI32S),
+                    _ => {}
+                }
+
+                Ok(vec![target])
+            },
+            ast::Expression::Conditional { condition, consequent, alternative } => {
+                let ty = self.peek(consequent).or_else(|| return self.peek(alternative)).or(expected).unwrap_or_else(|| return self.default_int());
+
+                // `select` evaluates both arms, so only arms without effects
+                // and of a single number take it
+                let is_single = |x: &ast::Expression| return !matches!(x, ast::Expression::Tuple(_));
+
+                let traps = self.context.features.overflow == feature::Overflow::Trap;
+
+                if consequent.is_pure(traps) && alternative.is_pure(traps) && is_single(consequent) && is_single(alternative) && !ty.is_reference() {
+                    self.expression(consequent, Some(ty))?;
+                    self.expression(alternative, Some(ty))?;
+                    self.condition(condition)?;
+                    self.body.push(Instruction::Select);
+
+                    return Ok(vec![ty]);
+                }
+
+                self.condition(condition)?;
+                self.body.push(Instruction::If(BlockType::Value(ty)));
+                self.labels += 1;
+                self.expression(consequent, Some(ty))?;
+                self.body.push(Instruction::Else);
+                self.expression(alternative, Some(ty))?;
+                self.body.push(Instruction::End);
+                self.labels -= 1;
+
+                Ok(vec![ty])
+            },
+            ast::Expression::Offset { offset, signature, base, align } => {
+                if let Some((index, element)) = self.table_entry(offset, signature.as_ref(), base)? {
+                    self.body.push(Instruction::TableGet(index));
+                    return Ok(vec![element]);
+                }
+
+                let (ty, memarg) = self.address(offset, signature.as_ref(), base, align.as_deref())?;
+
+                self.body.push(Instruction::Load(ty, memarg));
+                Ok(vec![ty])
+            },
+            ast::Expression::Tuple(list) => {
+                let mut types = vec![];
+
+                for x in list {
+                    types.extend(self.expression(x, None)?);
+                }
+
+                Ok(types)
+            },
+            // the arguments are stored in the pages reserved for them in the
+            // first memory, where the host reads them during the call. every
+            // call shares those pages, so an argument that may make a call of
+            // its own is held in a local until all of them are evaluated
+            ast::Expression::Variadic { ty, args } => {
+                let ty = ValType::from_token(ty);
+                let size = 1 << ty.natural_alignment();
+                let base = match self.context.scratch_base {
+                    _ if args.is_empty() => 0,
+                    Some(base) => base,
+                    None => return Err("extra arguments of a variadic call are passed in memory, but there is no memory".into())
+                };
+                // an address is unsigned, while an i32 constant is read as signed
+                let address = i32::from_ne_bytes(base.to_ne_bytes());
+                let mut held = Vec::with_capacity(args.len());
+
+                for arg in args {
+                    if arg.is_constant() {
+                        held.push(None);
+                        continue;
+                    }
+
+                    self.variadic_argument(arg, ty)?;
+
+                    let index = self.hidden(ty)?;
+
+                    self.body.push(Instruction::LocalSet(index));
+                    held.push(Some(index));
+                }
+
+                for (index, (arg, local)) in args.iter().zip(held).enumerate() {
+                    self.body.push(Instruction::I32Const(address));
+
+                    match local {
+                        Some(local) => self.body.push(Instruction::LocalGet(local)),
+                        None => self.variadic_argument(arg, ty)?
+                    }
+
+                    self.body.push(Instruction::Store(ty, MemArg { align: ty.natural_alignment(), offset: u32::try_from(u64::try_from(index)? * size)? }));
+                }
+
+                self.body.push(Instruction::I32Const(address));
+                self.body.push(Instruction::I32Const(i32::try_from(args.len())?));
+
+                Ok(vec![ValType::I32, ValType::I32])
+            },
+            ast::Expression::Unary { op, .. } => Err(format!("operator {} is not a unary operator", op.name()).into()),
+            ast::Expression::TypeOf(name) => Err(format!("`typeof {}` names a type and has no runtime value", name).into()),
+            // replaced by `lowering::lower` before any code is emitted
+            ast::Expression::GenericCall { callee, .. } => Err(format!("generic call to `{}` was not instantiated", callee).into()),
+            ast::Expression::Type(ty) => Err(format!("type {} is not a value", ast::type_name(ty)).into())
+        };
+    }
+}
+
+// the bytes taken by the extra arguments of the largest variadic call, which
+// every call shares since the host reads them before the call returns
+fn scratch_size(program: &ast::Program) -> u64 {
+    struct Scratch(u64);
+
+    impl Visitor for Scratch {
+        fn visit_expr(&mut self, expr: &ast::Expression) {
+            if let ast::Expression::Variadic { ty, args } = expr {
+                let size = 1_u64 << ValType::from_token(ty).natural_alignment();
+
+                self.0 = self.0.max(u64::try_from(args.len()).unwrap_or(u64::MAX).saturating_mul(size));
+            }
+
+            visitor::walk_expr(self, expr);
+        }
+    }
+
+    let mut scratch = Scratch(0);
+
+    scratch.visit_program(program);
+
+    return scratch.0;
+}
+
+// the wasm types of the values an expression leaves
+fn value_list(types: &[ValType]) -> String {
+    if types.is_empty() {
+        return "no value".to_string();
+    }
+
+    return types.iter().map(|x| return x.name()).collect::<Vec<_>>().join(", ");
+}
+
+// log2 of an alignment hint, which is given in bytes
+fn alignment(literal: &str) -> Result<u32, Box<dyn Error>> {
+    return match literal.parse::<u32>() {
+        Ok(x) if x.is_power_of_two() => Ok(x.trailing_zeros()),
+        _ => Err(format!("alignment `{}` must be a power of two number of bytes", literal).into())
+    };
+}
+
+// the type a literal has on its own, plain integers take it from the context
+fn literal_type(expr: &ast::Expression, defaults: &checker::Defaults) -> Option<ValType> {
+    let literal = match expr {
+        ast::Expression::Literal(x) => x,
+        ast::Expression::Unary { op: token::Symbol::Minus, operand } => return literal_type(operand, defaults),
+        _ => return None
+    };
+
+    return match split_suffix(literal) {
+        (_, Some(x)) => Some(ValType::from_token(&x)),
+        (x, None) if x.contains('.') || x == "NaN" || x == "Inf" => Some(ValType::from_token(&defaults.float)),
+        _ => None
+    };
+}
+
+// an alias is the value of its string literal, identifiers are used as they are
+fn export_name(alias: &Option<String>, name: &str) -> Result<String, Box<dyn Error>> {
+    return match alias {
+        Some(x) => ast::unescape(x),
+        None => Ok(name.to_string())
+    };
+}
+
+// quotes and escapes a string as wat does, bytes outside printable ascii
+// are written as `\hh`
+fn wat_string(value: &str) -> String {
+    let mut out = String::from("\"");
+
+    for byte in value.bytes() {
+        match byte {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7e => out.push(char::from(byte)),
+            x => write!(out, "\\{:02x}", x).expect("unexpected write failure")
+        }
+    }
+
+    out.push('"');
+
+    return out;
+}
+
+// quotes and escapes a string as json does
+fn json_string(value: &str) -> String {
+    let mut out = String::from("\"");
+
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            x if x.is_control() => write!(out, "\\u{:04x}", u32::from(x)).expect("unexpected write failure"),
+            x => out.push(x)
+        }
+    }
+
+    out.push('"');
+
+    return out;
+}
+
+fn write_name(buf: &mut Vec<u8>, name: &str) {
+    write_unsigned(buf, u64::try_from(name.len()).expect("unexpected name size overflow"));
+    buf.extend_from_slice(name.as_bytes());
+}
+
+fn write_vec<T>(buf: &mut Vec<u8>, items: &[T], f: impl Fn(&mut Vec<u8>, &T)) {
+    write_unsigned(buf, u64::try_from(items.len()).expect("unexpected vector size overflow"));
+
+    for x in items {
+        f(buf, x);
+    }
+}
+
+fn unsigned_bytes(value: usize) -> Vec<u8> {
+    let mut buf = vec![];
+
+    write_unsigned(&mut buf, u64::try_from(value).expect("unexpected size overflow"));
+
+    return buf;
+}
+
+fn unsigned_size(value: usize) -> usize {
+    return unsigned_bytes(value).len();
+}
+
+// empty sections are left out, and only one section is buffered at a time
+fn write_section<T, W: std::io::Write>(w: &mut W, id: u8, items: &[T], f: impl Fn(&mut Vec<u8>, &T)) -> std::io::Result<()> {
+    if items.is_empty() {
+        return Ok(());
+    }
+
+    let mut content = vec![];
+
+    write_vec(&mut content, items, f);
+    content.splice(0..0, unsigned_bytes(content.len()));
+    content.insert(0, id);
+
+    return w.write_all(&content);
+}
+
+// constant section
+const F32_SIGN: u32 = 1 << 31;
+const F64_SIGN: u64 = 1 << 63;
+
+// encodes a constant expression of the given type, a literal suffix must
+// agree with the expected type
+pub fn constant(expr: &ast::Expression, ty: &token::Type) -> Result<Instruction, Box<dyn Error>> {
+    let (literal, is_negative) = match expr {
+        ast::Expression::Literal(x) => (x, false),
+        ast::Expression::Unary { op: token::Symbol::Minus, operand } => match operand.as_ref() {
+            ast::Expression::Literal(x) => (x, true),
+            _ => return Err("expected a constant expression".into())
+        },
+        _ => return Err("expected a constant expression".into())
+    };
+
+    if literal == "Null" {
+        return match ty {
+            token::Type::Fref | token::Type::Xref if !is_negative => Ok(Instruction::RefNull(ValType::from_token(ty))),
+            _ => Err(format!("`Null` is a reference, but {} is expected", ast::type_name(ty)).into())
+        };
+    }
+
+    let (digits, suffix) = split_suffix(literal);
+
+    if let Some(suffix) = suffix {
+        if &suffix != ty {
+            return Err(format!("literal `{}` does not have type {:?}", literal, ty).into());
+        }
+    }
+
+    return match ty {
+        token::Type::I32 => {
+            let value = integer(digits, is_negative)?;
+
+            match i32::try_from(value) {
+                Ok(x) => Ok(Instruction::I32Const(x)),
+                // unsigned values are stored by their bit pattern
+                Err(_) => match u32::try_from(value) {
+                    Ok(x) => Ok(Instruction::I32Const(i32::from_ne_bytes(x.to_ne_bytes()))),
+                    Err(_) => Err(format!("literal `{}` is out of range for i32", literal).into())
+                }
+            }
+        },
+        token::Type::I64 => {
+            let value = integer(digits, is_negative)?;
+
+            match i64::try_from(value) {
+                Ok(x) => Ok(Instruction::I64Const(x)),
+                Err(_) => match u64::try_from(value) {
+                    Ok(x) => Ok(Instruction::I64Const(i64::from_ne_bytes(x.to_ne_bytes()))),
+                    Err(_) => Err(format!("literal `{}` is out of range for i64", literal).into())
+                }
+            }
+        },
+        token::Type::F32 => {
+            let bits = match digits {
+                "NaN" => F32_CANONICAL_NAN,
+                "Inf" => f32::INFINITY.to_bits(),
+                _ => digits.parse::<f32>().map_err(|_| return format!("invalid f32 literal `{}`", literal))?.to_bits()
+            };
+
+            // negation only flips the sign bit, as `f32.neg` does
+            Ok(Instruction::F32Const(if is_negative { bits ^ F32_SIGN } else { bits }))
+        },
+        token::Type::F64 => {
+            let bits = match digits {
+                "NaN" => F64_CANONICAL_NAN,
+                "Inf" => f64::INFINITY.to_bits(),
+                _ => digits.parse::<f64>().map_err(|_| return format!("invalid f64 literal `{}`", literal))?.to_bits()
+            };
+
+            Ok(Instruction::F64Const(if is_negative { bits ^ F64_SIGN } else { bits }))
+        },
+        _ => Err(format!("type {:?} has no constants", ty).into())
+    };
+}
+
+fn split_suffix(literal: &str) -> (&str, Option<token::Type>) {
+    for (suffix, ty) in [("f32", token::Type::F32), ("f64", token::Type::F64)].iter() {
+        if let Some(digits) = literal.strip_suffix(suffix) {
+            return (digits, Some(ty.clone()));
+        }
+    }
+
+    return (literal, None);
+}
+
+fn integer(digits: &str, is_negative: bool) -> Result<i128, Box<dyn Error>> {
+    let value = if let Some(x) = digits.strip_prefix("0x") {
+        i128::from_str_radix(x, 16)
+    }
+    else if let Some(x) = digits.strip_prefix("0b") {
+        i128::from_str_radix(x, 2)
+    }
+    else if let Some(x) = digits.strip_prefix("0o") {
+        i128::from_str_radix(x, 8)
+    }
+    else {
+        digits.parse::<i128>()
+    };
+
+    let value = value.map_err(|_| return format!("invalid integer literal `{}`", digits))?;
+
+    return Ok(if is_negative { -value } else { value });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use proptest::prelude::*;
+    use proptest::sample::select;
+    use proptest::strategy::Union;
+    use crate::cli;
+
+    fn encode(text: &str, is_negative: bool, ty: token::Type) -> Vec<u8> {
+        let literal = ast::Expression::Literal(text.to_string());
+        let expr = match is_negative {
+            true => ast::Expression::Unary { op: token::Symbol::Minus, operand: Box::new(literal) },
+            false => literal
+        };
+        let mut buf = vec![];
+
+        constant(&expr, &ty).unwrap().to_wasm(&mut buf);
+
+        return buf;
+    }
+
+    #[test]
+    fn encode_nan() {
+        assert_eq!(encode("NaN", false, token::Type::F32), vec![0x43, 0x00, 0x00, 0xc0, 0x7f]);
+        assert_eq!(encode("NaN", false, token::Type::F64), vec![0x44, 0, 0, 0, 0, 0, 0, 0xf8, 0x7f]);
+    }
+
+    #[test]
+    fn encode_infinity() {
+        assert_eq!(encode("Inf", false, token::Type::F32), vec![0x43, 0x00, 0x00, 0x80, 0x7f]);
+        assert_eq!(encode("Inf", true, token::Type::F32), vec![0x43, 0x00, 0x00, 0x80, 0xff]);
+        assert_eq!(encode("Inf", true, token::Type::F64), vec![0x44, 0, 0, 0, 0, 0, 0, 0xf0, 0xff]);
+    }
+
+    #[test]
+    fn encode_nearest_fraction() {
+        // 1.1 rounds to 0x3f8ccccd as f32, not to the truncated f64 bits
+        assert_eq!(encode("1.1f32", false, token::Type::F32), vec![0x43, 0xcd, 0xcc, 0x8c, 0x3f]);
+        assert_eq!(encode("1.1", false, token::Type::F64), vec![0x44, 0x9a, 0x99, 0x99, 0x99, 0x99, 0x99, 0xf1, 0x3f]);
+    }
+
+    #[test]
+    fn encode_integers() {
+        assert_eq!(encode("1", true, token::Type::I32), vec![0x41, 0x7f]);
+        assert_eq!(encode("0xffffffff", false, token::Type::I32), vec![0x41, 0x7f]);
+        assert_eq!(encode("128", false, token::Type::I64), vec![0x42, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn tokenize_float_literals() {
+        let tokens = crate::tokenizer::tokenize("NaN Inf 1.1f32").unwrap();
+
+        assert_eq!(tokens.into_iter().map(crate::span::Spanned::into_node).collect::<Vec<_>>(), vec![
+            token::Token::Literal(token::Literal::Numeric("NaN")),
+            token::Token::Literal(token::Literal::Numeric("Inf")),
+            token::Token::Literal(token::Literal::Numeric("1.1f32"))
+        ]);
+    }
+
+    #[test]
+    fn reject_mismatched_suffix() {
+        let expr = ast::Expression::Literal("1.1f64".to_string());
+
+        assert!(constant(&expr, &token::Type::F32).is_err());
+    }
+
+    fn build(text: &str) -> Result<Module, Box<dyn Error>> {
+        let tokens = crate::tokenizer::tokenize(text)?;
+
+        return Module::build(&crate::parser::parse_syntax(&tokens, false)?, &checker::Defaults::default());
+    }
+
+    #[test]
+    fn emit_valid_module() {
+        let module = build("fn f(a: i32) -> i32 { let b <- 0; while (b < a) { b <- b + 1; } b } exp \"g\" fn g(x: f64) -> f64 { -x }").unwrap();
+
+        assert!(crate::validator::validate(&module.to_wasm()).is_ok());
+        assert!(module.to_wat().contains("(export \"g\" (func 1))"));
+        assert!(module.to_wat().contains("f64.neg"));
+    }
+
+    #[test]
+    fn emit_literals_and_identifiers() {
+        let module = build("let mut g <- 7; fn f(a: i32) -> i32 { g <- g + a; let b <- 2; a + b + g + 0x10 } fn h() -> f64 { 1.5 } fn k() -> i64 { 5 }").unwrap();
+
+        assert_eq!(module.functions[0].body, vec![
+            Instruction::GlobalGet(0),
+            Instruction::LocalGet(0),
+            Instruction::Binary(ValType::I32, BinaryOp::Add),
+            Instruction::GlobalSet(0),
+            Instruction::I32Const(2),
+            Instruction::LocalSet(1),
+            Instruction::LocalGet(0),
+            Instruction::LocalGet(1),
+            Instruction::Binary(ValType::I32, BinaryOp::Add),
+            Instruction::GlobalGet(0),
+            Instruction::Binary(ValType::I32, BinaryOp::Add),
+            Instruction::I32Const(16),
+            Instruction::Binary(ValType::I32, BinaryOp::Add),
+            Instruction::End
+        ]);
+        assert_eq!(module.functions[1].body, vec![Instruction::F64Const(1.5f64.to_bits()), Instruction::End]);
+        assert_eq!(module.functions[2].body, vec![Instruction::I64Const(5), Instruction::End]);
+    }
+
+    #[test]
+    fn emit_unary_operators() {
+        let module = build("fn f(a: i32) -> i32 { -a } fn g(a: i32) -> i32 { +a } fn h() -> i32 { -5 } fn k(a: f32) -> f32 { -a }").unwrap();
+
+        assert_eq!(module.functions[0].body, vec![Instruction::I32Const(0), Instruction::LocalGet(0), Instruction::Binary(ValType::I32, BinaryOp::Sub), Instruction::End]);
+        assert_eq!(module.functions[1].body, vec![Instruction::LocalGet(0), Instruction::End]);
+        assert_eq!(module.functions[2].body, vec![Instruction::I32Const(-5), Instruction::End]);
+        assert_eq!(module.functions[3].body, vec![Instruction::LocalGet(0), Instruction::Neg(ValType::F32), Instruction::End]);
+    }
+
+    #[test]
+    fn emit_binary_operators_at_each_type() {
+        for (ty, name) in [("i32", "i32"), ("i64", "i64"), ("f32", "f32"), ("f64", "f64")].iter() {
+            let wat = build(&format!("fn f(a: {0}, b: {0}) -> {0} {{ (a + b) * (a - b) / b }}", ty)).unwrap().to_wat();
+            let division = if name.starts_with('f') { "div" } else { "div_s" };
+
+            for op in ["add", "sub", "mul", division].iter() {
+                assert!(wat.contains(&format!("{}.{}", name, op)), "{}.{}", name, op);
+            }
+        }
+    }
+
+    #[test]
+    fn emit_calls_and_casts() {
+        let module = build("fn twice(a: i32) -> i32 { a * 2 } fn f(a: i32) -> i64 { i64(twice(a)) }").unwrap();
+
+        assert_eq!(module.functions[1].body, vec![
+            Instruction::LocalGet(0),
+            Instruction::Call(0),
+            Instruction::I64ExtendI32S,
+            Instruction::End
+        ]);
+    }
+
+    #[test]
+    fn run_while_with_break_and_continue() {
+        let text = "
+            exp \"odd\" fn odd(n: i32) -> i32 {
+                let i <- 0;
+                let total <- 0;
+
+                while (1) {
+                    i <- i + 1;
+
+                    if (i > n) {
+                        brk;
+                    }
+
+                    if (!(i % 2)) {
+                        cont;
+                    }
+
+                    total <- total + i;
+                }
+
+                total
+            }
+        ";
+
+        assert_eq!(run(text, "odd", 0).unwrap(), 0);
+        assert_eq!(run(text, "odd", 6).unwrap(), 9);
+        assert_eq!(run(text, "odd", 7).unwrap(), 16);
+    }
+
+    #[test]
+    fn reject_values_without_runtime_representation() {
+        let message = |text| return build(text).unwrap_err().to_string();
+
+        assert!(message("fn f() -> i32 { \"a\" }").contains("string literal \"a\" has no runtime value"));
+        assert_eq!(message("fn f(a: i32) -> i32 { let t <- typeof a; a }"), "`typeof a` names a type and has no runtime value");
+    }
+
+    #[test]
+    fn reject_mismatched_result() {
+        let message = |text| return build(text).unwrap_err().to_string();
+
+        assert_eq!(message("fn f() -> i32 { 1.5 }"), "function `f` expects a result of i32, found f64");
+        assert_eq!(message("fn g() -> (i32, f64) { (1, 1.5) } fn f() -> i64 { g() }"), "function `f` expects a result of i64, found i32, f64");
+    }
+
+    #[test]
+    fn compile_source_to_module() {
+        let module = compile_to_module("mem m = (1; page; 2); fn f(a: i32) -> i32 { a } exp \"twice\" fn g(x: i32) -> i32 { f(x) + f(x) }").unwrap();
+
+        assert_eq!(module.functions().iter().map(|x| return x.name.as_str()).collect::<Vec<_>>(), vec!["f", "g"]);
+        assert_eq!(module.types(), &[FuncType { params: vec![ValType::I32], results: vec![ValType::I32] }]);
+        assert_eq!(module.exports(), &[Export { name: "twice".to_string(), kind: ExportKind::Function, index: 1 }]);
+        assert_eq!(module.memories(), &[Limits { min: 1, max: Some(2) }]);
+        assert!(module.imports().is_empty());
+        assert!(compile_to_module("exp \"f\" fn f() -> i32 { x }").is_err());
+    }
+
+    #[test]
+    fn compile_at_recursion_limit() {
+        let depth = parser::DEFAULT_MAX_DEPTH;
+        let parens = format!("exp \"f\" fn f() -> i32 {{ {}1{} }}", "(".repeat(depth - 2), ")".repeat(depth - 2));
+        let chain = format!("exp \"f\" fn f(a: i32) -> i32 {{ a{} }}", " + a".repeat(depth - 2));
+        let blocks = format!("exp \"f\" fn f(a: i32) {{ {}{} }}", "if (a) { ".repeat(depth - 1), "}".repeat(depth - 1));
+
+        // every pass after the parser fits in the stack of a test thread
+        for text in [parens, chain, blocks] {
+            assert!(compile_to_module(&text).unwrap().to_wasm().len() > 8);
+        }
+    }
+
+    #[test]
+    fn export_attributed_functions() {
+        let module = compile_to_module("@export(\"twice\") fn f(x: i32) -> i32 { x * 2 } @export @inline fn g() {} fn h() {}").unwrap();
+
+        assert_eq!(module.functions().iter().map(|x| return x.name.as_str()).collect::<Vec<_>>(), vec!["f", "g"]);
+        assert_eq!(module.exports(), &[
+            Export { name: "twice".to_string(), kind: ExportKind::Function, index: 0 },
+            Export { name: "g".to_string(), kind: ExportKind::Function, index: 1 }
+        ]);
+        assert!(compile_to_module("@export fn f<T>(a: T) -> T { a }").unwrap_err().to_string().contains("generic function `f` cannot be exported"));
+    }
+
+    #[test]
+    fn serialize_changed_module() {
+        let mut module = compile_to_module("exp \"f\" fn f(a: i32) -> i32 { a }").unwrap();
+        let ty = module.add_type(FuncType { params: vec![], results: vec![ValType::I64] });
+
+        module.functions_mut().push(Function { name: "seven".to_string(), ty, locals: vec![], body: vec![Instruction::I64Const(7), Instruction::End] });
+        module.exports_mut().push(Export { name: "seven".to_string(), kind: ExportKind::Function, index: 1 });
+
+        let wasm = module.to_wasm();
+
+        assert!(crate::validator::validate(&wasm).is_ok());
+        assert!(module.to_wat().contains("(export \"seven\" (func 1))"));
+    }
+
+    #[test]
+    fn emit_empty_module() {
+        for text in ["", "  \n\t\n"].iter() {
+            let wasm = build(text).unwrap().to_wasm();
+
+            assert_eq!(wasm, vec![0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00]);
+            assert!(crate::validator::validate(&wasm).is_ok());
+        }
+    }
+
+    #[test]
+    fn emit_default_literal_types() {
+        let text = "fn f(a: i32) { let x <- 1; let y <- 1.5; let z <- 1.5f64; let w <- a + 1; }";
+        let program = crate::parser::parse_syntax(&crate::tokenizer::tokenize(text).unwrap(), false).unwrap();
+        let defaults = checker::Defaults { int: token::Type::I64, float: token::Type::F32 };
+        let module = Module::build(&program, &defaults).unwrap();
+
+        assert_eq!(module.functions[0].locals, vec![ValType::I64, ValType::F32, ValType::F64, ValType::I32]);
+        assert_eq!(build(text).unwrap().functions[0].locals, vec![ValType::I32, ValType::F64, ValType::F64, ValType::I32]);
+    }
+
+    #[test]
+    fn drop_unused_results() {
+        let module = build("fn foo() -> i32 { 1 } fn pair() -> (i32, i64) { (1, 2) } fn log() {} fn f() { foo() + 1; pair(); log(); }").unwrap();
+
+        assert_eq!(module.functions[3].body, vec![
+            Instruction::Call(0),
+            Instruction::I32Const(1),
+            Instruction::Binary(ValType::I32, BinaryOp::Add),
+            Instruction::Drop,
+            Instruction::Call(1),
+            Instruction::Drop,
+            Instruction::Drop,
+            Instruction::Call(2),
+            Instruction::End
+        ]);
+        assert!(crate::validator::validate(&module.to_wasm()).is_ok());
+    }
+
+    #[test]
+    fn destructure_multi_value_result() {
+        let module = build("fn pair() -> (i32, i32) { (1, 2) } fn f() -> i32 { let (a, b) <- pair(); a - b }").unwrap();
+
+        assert_eq!(module.functions[1].body, vec![
+            Instruction::Call(0),
+            Instruction::LocalSet(1),
+            Instruction::LocalSet(0),
+            Instruction::LocalGet(0),
+            Instruction::LocalGet(1),
+            Instruction::Binary(ValType::I32, BinaryOp::Sub),
+            Instruction::End
+        ]);
+        assert!(crate::validator::validate(&module.to_wasm()).is_ok());
+    }
+
+    #[test]
+    fn reject_destructure_arity_mismatch() {
+        let err = build("fn pair() -> (i32, i32) { (1, 2) } fn f() { let (a, b, c) <- pair(); }").err().unwrap();
+
+        assert_eq!(err.to_string(), "cannot destructure 2 values into 3 names");
+        assert!(build("let (a, b) <- (1, 2, 3);").is_err());
+    }
+
+    #[test]
+    fn emit_bitwise_not() {
+        let module = build("fn f(a: i32, b: i64) -> i64 { let c <- ~a; ~b }").unwrap();
+
+        assert_eq!(module.functions[0].body, vec![
+            Instruction::LocalGet(0),
+            Instruction::I32Const(-1),
+            Instruction::Binary(ValType::I32, BinaryOp::Xor),
+            Instruction::LocalSet(2),
+            Instruction::LocalGet(1),
+            Instruction::I64Const(-1),
+            Instruction::Binary(ValType::I64, BinaryOp::Xor),
+            Instruction::End
+        ]);
+        assert!(crate::validator::validate(&module.to_wasm()).is_ok());
+    }
+
+    #[test]
+    fn emit_logical_negation() {
+        let module = build("fn f(a: i64) -> i32 { !a }").unwrap();
+
+        assert_eq!(module.functions[0].body, vec![
+            Instruction::LocalGet(0),
+            Instruction::Eqz(ValType::I64),
+            Instruction::End
+        ]);
+        assert!(build("fn f(a: f32) -> i32 { !a }").is_err());
+    }
+
+    // instantiates the module and calls an exported `i32 -> i32` function
+    fn run(text: &str, name: &str, arg: i32) -> Result<i32, Box<dyn Error>> {
+        let engine = wasmi::Engine::default();
+        let module = wasmi::Module::new(&engine, &build(text)?.to_wasm()[..])?;
+        let mut store = wasmi::Store::new(&engine, ());
+        let instance = wasmi::Linker::<()>::new(&engine).instantiate(&mut store, &module)?.start(&mut store)?;
+
+        return Ok(instance.get_typed_func::<i32, i32>(&store, name)?.call(&mut store, arg)?);
+    }
+
+    fn build_tail_calls(text: &str) -> Result<Module, Box<dyn Error>> {
+        let tokens = crate::tokenizer::tokenize(text)?;
+
+        return Module::build_with_features(&crate::parser::parse_syntax(&tokens, false)?, &checker::Defaults::default(), &feature::Features { tail_call: true, ..feature::Features::default() });
+    }
+
+    #[test]
+    fn emit_tail_call() {
+        let text = "
+            fn down(n: i32, acc: i32) -> i32 {
+                if (n == 0) { ret acc; }
+                ret down(n - 1, acc + 1);
+            }
+            fn twice(n: i32) -> i32 { ret down(n, 0) * 2; }
+            fn last(n: i32) -> i32 { down(n, 0) }
+            fn deferred(n: i32) -> i32 { defer { down(0, 0); } ret down(n, 0); }
+        ";
+        let module = build_tail_calls(text).unwrap();
+        let wat = module.to_wat();
+
+        assert_eq!(module.functions[0].body.iter().rev().nth(1), Some(&Instruction::ReturnCall(0)));
+        assert!(!module.functions[0].body.contains(&Instruction::Call(0)));
+        assert!(module.functions[1].body.contains(&Instruction::Call(0)));
+        assert_eq!(module.functions[2].body, vec![Instruction::LocalGet(0), Instruction::I32Const(0), Instruction::ReturnCall(0), Instruction::End]);
+        assert!(!module.functions[3].body.contains(&Instruction::ReturnCall(0)));
+        assert!(wat.contains("return_call 0"));
+        assert!(crate::validator::validate(&module.to_wasm()).is_ok());
+
+        // plain calls unless the feature is enabled
+        assert!(!build(text).unwrap().functions.iter().any(|x| return x.body.contains(&Instruction::ReturnCall(0))));
+    }
+
+    #[test]
+    fn emit_tail_call_indirect() {
+        let module = build_tail_calls("tab table = (1; fref; 1); type Unary = fn (i32) -> i32; fn f(i: i32) -> i32 { ret *i(table)::<Unary>(i); }").unwrap();
+
+        assert_eq!(module.functions[0].body.iter().rev().nth(1), Some(&Instruction::ReturnCallIndirect(0, 0)));
+        assert!(crate::validator::validate(&module.to_wasm()).is_ok());
+    }
+
+    #[test]
+    fn recurse_deeply_in_tail_position() {
+        let text = "
+            exp \"count\" fn count(n: i32) -> i32 { ret down(n, 0); }
+            fn down(n: i32, acc: i32) -> i32 {
+                if (n == 0) { ret acc; }
+                ret down(n - 1, acc + 1);
+            }
+        ";
+        let engine = wasmi::Engine::default();
+        let module = wasmi::Module::new(&engine, &build_tail_calls(text).unwrap().to_wasm()[..]).unwrap();
+        let mut store = wasmi::Store::new(&engine, ());
+        let instance = wasmi::Linker::<()>::new(&engine).instantiate(&mut store, &module).unwrap().start(&mut store).unwrap();
+        let count = instance.get_typed_func::<i32, i32>(&store, "count").unwrap();
+
+        assert_eq!(count.call(&mut store, 1_000_000).unwrap(), 1_000_000);
+
+        // the same recursion overflows the stack with plain calls
+        assert!(run(text, "count", 1_000_000).is_err());
+    }
+
+    #[test]
+    fn return_from_nested_loops() {
+        let text = "
+            exp \"find\" fn find(n: i32) -> i32 {
+                let i <- 1;
+
+                while (i < n) {
+                    let j <- 1;
+
+                    while (j < n) {
+                        if (!(i * j - n)) {
+                            if (i > 1) {
+                                ret i * 100 + j;
+                            }
+                        }
+
+                        j <- j + 1;
+                    }
+
+                    i <- i + 1;
+                }
+
+                ret 0;
+            }
+        ";
+
+        assert_eq!(run(text, "find", 12).unwrap(), 206);
+        assert_eq!(run(text, "find", 7).unwrap(), 0);
+    }
+
+    #[test]
+    fn count_repeat_iterations() {
+        let text = "
+            exp \"sum\" fn sum(n: i32) -> i32 {
+                let total <- 0;
+                let i <- 0;
+
+                repeat 10 {
+                    i <- i + 1;
+
+                    if (!(i - n)) {
+                        cont;
+                    }
+
+                    repeat 2 {
+                        total <- total + i;
+                    }
+                }
+
+                repeat 0 {
+                    ret -1;
+                }
+
+                total
+            }
+        ";
+
+        assert_eq!(run(text, "sum", 0).unwrap(), 110);
+        assert_eq!(run(text, "sum", 4).unwrap(), 102);
+        assert_eq!(build(text).unwrap().functions[0].locals, vec![ValType::I32, ValType::I32, ValType::I32, ValType::I32, ValType::I32]);
+    }
+
+    #[test]
+    fn run_defer_on_early_return() {
+        let text = "
+            let mut log <- 0;
+
+            fn f(n: i32) -> i32 {
+                let a <- 1;
+
+                log <- 0;
+
+                defer {
+                    log <- log * 10 + a;
+                }
+
+                {
+                    let a <- 9;
+
+                    if (n) {
+                        ret a;
+                    }
+                }
+
+                a <- 2;
+                a
+            }
+
+            exp \"value\" fn value(n: i32) -> i32 { f(n) }
+            exp \"log\" fn logged(n: i32) -> i32 { f(n); log }
+        ";
+
+        assert_eq!(run(text, "value", 1).unwrap(), 9);
+        assert_eq!(run(text, "log", 1).unwrap(), 1);
+        assert_eq!(run(text, "value", 0).unwrap(), 2);
+        assert_eq!(run(text, "log", 0).unwrap(), 2);
+    }
+
+    #[test]
+    fn run_defers_last_first() {
+        let text = "
+            let mut log <- 0;
+
+            fn f(n: i32) {
+                defer { log <- log * 10 + 1; }
+                defer { log <- log * 10 + 2; }
+
+                if (n) {
+                    ret;
+                }
+
+                defer { log <- log * 10 + 3; }
+            }
+
+            exp \"log\" fn logged(n: i32) -> i32 { log <- 0; f(n); log }
+        ";
+
+        assert_eq!(run(text, "log", 0).unwrap(), 321);
+        assert_eq!(run(text, "log", 1).unwrap(), 21);
+    }
+
+    #[test]
+    fn emit_unsigned_operations() {
+        let text = "
+            exp \"half\" fn half(a: u32) -> u32 { a / 2 }
+            exp \"signed\" fn signed(a: i32) -> i32 { a / 2 }
+            exp \"mixed\" fn mixed(a: i32) -> i32 { i32(u32(a) % 3) + (u32(a) >= 2) }
+        ";
+        let mut program = crate::parser::parse_syntax(&crate::tokenizer::tokenize(text).unwrap(), false).unwrap();
+
+        checker::sign_operators(&mut program, &checker::Defaults::default()).unwrap();
+
+        let module = Module::build(&program, &checker::Defaults::default()).unwrap();
+        let engine = wasmi::Engine::default();
+        let wasm = wasmi::Module::new(&engine, &module.to_wasm()[..]).unwrap();
+        let mut store = wasmi::Store::new(&engine, ());
+        let instance = wasmi::Linker::<()>::new(&engine).instantiate(&mut store, &wasm).unwrap().start(&mut store).unwrap();
+        let mut call = |name: &str, arg: i32| return instance.get_typed_func::<i32, i32>(&store, name).unwrap().call(&mut store, arg).unwrap();
+
+        assert!(module.to_wat().contains("i32.div_u"));
+        assert!(module.to_wat().contains("i32.div_s"));
+        assert_eq!(call("half", -2), 0x7fff_ffff);
+        assert_eq!(call("signed", -2), -1);
+        assert_eq!(call("mixed", -1), 1);
+    }
+
+    #[test]
+    fn emit_unsigned_literal_operations() {
+        let text = "
+            exp \"div\" fn div(a: u32) -> u32 { 4000000000 / 2 }
+            exp \"rem\" fn rem(a: u32) -> u32 { 4000000000 % 7 }
+            exp \"shr\" fn shr(a: u32) -> u32 { 4000000000 >> 1 }
+            exp \"less\" fn less(a: u32) -> i32 { a < 4000000000 / 2 }
+        ";
+        let expected = [("div", 2_000_000_000), ("rem", 3), ("shr", 2_000_000_000), ("less", 0)];
+        let mut program = crate::parser::parse_syntax(&crate::tokenizer::tokenize(text).unwrap(), false).unwrap();
+
+        checker::sign_operators(&mut program, &checker::Defaults::default()).unwrap();
+
+        let module = Module::build(&program, &checker::Defaults::default()).unwrap();
+        let wat = module.to_wat();
+
+        assert!(wat.contains("i32.div_u") && wat.contains("i32.rem_u") && wat.contains("i32.shr_u"));
+        assert!(!wat.contains("i32.div_s") && !wat.contains("i32.rem_s") && !wat.contains("i32.shr_s"));
+
+        // the same values once constant folding has run
+        for wasm in [module.to_wasm(), compile_to_module(text).unwrap().to_wasm()].iter() {
+            let engine = wasmi::Engine::default();
+            let wasm = wasmi::Module::new(&engine, &wasm[..]).unwrap();
+            let mut store = wasmi::Store::new(&engine, ());
+            let instance = wasmi::Linker::<()>::new(&engine).instantiate(&mut store, &wasm).unwrap().start(&mut store).unwrap();
+
+            for (name, value) in expected.iter() {
+                assert_eq!(instance.get_typed_func::<i32, i32>(&store, name).unwrap().call(&mut store, -1).unwrap(), *value, "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn reject_out_of_range_literals() {
+        let message = |text| return compile_to_module(text).unwrap_err().to_string();
+
+        assert!(compile_to_module("fn f() -> u32 { 4294967295 } fn g() -> i32 { -2147483648 } fn h() -> i32 { 0xFFFFFFFF } fn k() -> i64 { 4294967296 }").is_ok());
+        assert_eq!(message("fn f() -> i32 { 4000000000 }"), "literal `4000000000` is out of range for i32");
+        assert_eq!(message("fn f() -> i32 { -2147483649 }"), "literal `-2147483649` is out of range for i32");
+        assert_eq!(message("fn f(a: u32) -> u32 { a + -1 }"), "literal `-1` is out of range for u32");
+        assert_eq!(message("fn f() -> u32 { 4294967296 }"), "literal `4294967296` is out of range for u32");
+        assert_eq!(message("fn f() -> i32 { 0x100000000 }"), "literal `0x100000000` is out of range for i32");
+        assert_eq!(message("fn f() -> u64 { -1 }"), "literal `-1` is out of range for u64");
+    }
+
+    #[test]
+    fn run_width_casts() {
+        let text = "
+            exp \"wrap\" fn wrap(a: i32) -> i32 { i32((i64(a) << 32) + 5) }
+            exp \"high\" fn high(a: i32) -> i32 { i32(i64(a) >> 32) }
+            exp \"high_u\" fn high_u(a: i32) -> i32 { i32(i64(u64(u32(a)) >>> 32)) }
+        ";
+        let wat = build(text).unwrap().to_wat();
+
+        assert!(wat.contains("i32.wrap_i64"));
+        assert!(wat.contains("i64.extend_i32_s"));
+        assert!(wat.contains("i64.extend_i32_u"));
+        assert_eq!(run(text, "wrap", 7).unwrap(), 5);
+        assert_eq!(run(text, "high", -1).unwrap(), -1);
+        assert_eq!(run(text, "high_u", -1).unwrap(), 0);
+    }
+
+    #[test]
+    fn return_from_every_branch() {
+        let text = "
+            exp \"sign\" fn sign(a: i32) -> i32 {
+                {
+                    if (a > 0) {
+                        ret 1;
+                    }
+                    elif (a < 0) {
+                        { ret -1; }
+                    }
+                    else {
+                        ret 0;
+                    }
+                }
+            }
+        ";
+
+        assert_eq!(run(text, "sign", 5).unwrap(), 1);
+        assert_eq!(run(text, "sign", -5).unwrap(), -1);
+        assert_eq!(run(text, "sign", 0).unwrap(), 0);
+    }
+
+    // `r` records the branch taken, so a chain that falls through keeps -1
+    fn classify(chain: &str) -> String {
+        return format!("exp \"f\" fn f(a: i32) -> i32 {{ let mut r <- -1; {} r }}", chain);
+    }
+
+    #[test]
+    fn run_if_without_else() {
+        let text = classify("if (a > 10) { r <- 1; }");
+
+        assert_eq!(run(&text, "f", 11).unwrap(), 1);
+        assert_eq!(run(&text, "f", 10).unwrap(), -1);
+    }
+
+    #[test]
+    fn run_elif_chain_without_else() {
+        let text = classify("if (a > 10) { r <- 1; } elif (a > 5) { r <- 2; } elif (a > 0) { r <- 3; }");
+
+        assert_eq!(run(&text, "f", 20).unwrap(), 1);
+        assert_eq!(run(&text, "f", 7).unwrap(), 2);
+        assert_eq!(run(&text, "f", 1).unwrap(), 3);
+        assert_eq!(run(&text, "f", 0).unwrap(), -1);
+    }
+
+    #[test]
+    fn run_i64_conditions() {
+        let text = "
+            exp \"f\" fn f(a: i32) -> i32 {
+                let x <- i64(a) << 32;
+                let n <- 0;
+
+                if (x) { n <- n + 1; } elif (x + 1) { n <- n + 10; }
+
+                let y <- x;
+
+                while (y) { y <- 0; n <- n + 100; }
+
+                n + (x ? 1000 : 2000)
+            }
+        ";
+
+        assert!(build(text).unwrap().to_wat().contains("i64.ne"));
+        assert_eq!(run(text, "f", 1).unwrap(), 1101);
+        assert_eq!(run(text, "f", 0).unwrap(), 2010);
+    }
+
+    #[test]
+    fn run_elif_chain_with_else() {
+        let text = classify("if (a > 10) { r <- 1; } elif (a > 5) { r <- 2; } else { r <- 0; }");
+
+        assert_eq!(run(&text, "f", 20).unwrap(), 1);
+        assert_eq!(run(&text, "f", 7).unwrap(), 2);
+        assert_eq!(run(&text, "f", 5).unwrap(), 0);
+    }
+
+    #[test]
+    fn emit_nested_if_for_elif() {
+        let module = build("fn f(a: i32) { if (a) {} elif (a) {} else {} }").unwrap();
+
+        assert_eq!(module.functions[0].body, vec![
+            Instruction::LocalGet(0),
+            Instruction::If(BlockType::Empty),
+            Instruction::Else,
+            Instruction::LocalGet(0),
+            Instruction::If(BlockType::Empty),
+            Instruction::Else,
+            Instruction::End,
+            Instruction::End,
+            Instruction::End
+        ]);
+    }
+
+    #[test]
+    fn emit_signed_remainder() {
+        let module = build("fn f(a: i32, b: i64) -> i64 { let c <- a % 3; b % 2 }").unwrap();
+
+        assert_eq!(module.functions[0].body, vec![
+            Instruction::LocalGet(0),
+            Instruction::I32Const(3),
+            Instruction::Binary(ValType::I32, BinaryOp::RemS),
+            Instruction::LocalSet(2),
+            Instruction::LocalGet(1),
+            Instruction::I64Const(2),
+            Instruction::Binary(ValType::I64, BinaryOp::RemS),
+            Instruction::End
+        ]);
+        assert!(module.to_wat().contains("i64.rem_s"));
+        assert!(crate::validator::validate(&module.to_wasm()).is_ok());
+    }
+
+    #[test]
+    fn emit_signed_and_unsigned_comparisons() {
+        let module = build("fn f(a: i32, b: i64) { let c <- a < 1; let d <- a <u 1; let e <- b >=u 2; let g <- b >= 2; }").unwrap();
+        let comparisons = module.functions[0].body.iter()
+            .filter_map(|x| return match x {
+                Instruction::Binary(ty, op) => op.encoding(*ty),
+                _ => None
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(comparisons, vec![(0x48, "lt_s"), (0x49, "lt_u"), (0x5a, "ge_u"), (0x59, "ge_s")]);
+        assert!(module.to_wat().contains("i32.lt_u"));
+        assert!(crate::validator::validate(&module.to_wasm()).is_ok());
+        assert!(build("fn f(a: f32) -> i32 { a <u 1.0 }").is_err());
+    }
+
+    #[test]
+    fn reject_float_remainder() {
+        let err = build("fn f(a: f64) -> f64 { a % 2.0 }").err().unwrap();
+
+        assert!(err.to_string().contains("operator % is not defined for f64"));
+    }
+
+    #[test]
+    fn reject_non_integer_condition() {
+        let message = |text| return build(text).err().unwrap().to_string();
+
+        assert_eq!(message("fn f(a: f64) -> i32 { a ? 1 : 2 }"), "condition must be a scalar integer, found f64");
+        assert_eq!(message("fn g() {} fn f() { while (g()) {} }"), "condition must be a scalar integer, found no value");
+        assert_eq!(message("fn g() -> (i32, i32) { (1, 2) } fn f() { if (g()) {} }"), "condition must be a scalar integer, found i32, i32");
+    }
+
+    #[test]
+    fn emit_memory_load_and_store() {
+        let module = build("mem memory = (1; page; 1); fn f(i: i32) -> f64 { @i<f32>(memory) <- 1.5f32; @(i + 8)<f64>(memory) }").unwrap();
+
+        assert_eq!(module.functions[0].body, vec![
+            Instruction::LocalGet(0),
+            Instruction::F32Const(1.5_f32.to_bits()),
+            Instruction::Store(ValType::F32, MemArg { align: 2, offset: 0 }),
+            Instruction::LocalGet(0),
+            Instruction::I32Const(8),
+            Instruction::Binary(ValType::I32, BinaryOp::Add),
+            Instruction::Load(ValType::F64, MemArg { align: 3, offset: 0 }),
+            Instruction::End
+        ]);
+        assert_eq!(Instruction::Load(ValType::I64, MemArg { align: 3, offset: 0 }).to_wat(), "i64.load");
+        assert_eq!(Instruction::Store(ValType::I32, MemArg { align: 0, offset: 4 }).to_wat(), "i32.store offset=4 align=1");
+        assert!(crate::validator::validate(&module.to_wasm()).is_ok());
+    }
+
+    #[test]
+    fn emit_variadic_call() {
+        let text = "mem memory = (1; page; 2); imp fn log(fmt: i32, ...args: f64) from \"env\"; fn f() { log(7, 1.5, 2.5); }";
+        let mut program = crate::parser::parse_syntax(&crate::tokenizer::tokenize(text).unwrap(), false).unwrap();
+
+        crate::lowering::lower(&mut program).unwrap();
+
+        let module = Module::build(&program, &checker::Defaults::default()).unwrap();
+
+        assert_eq!(module.types[0], FuncType { params: vec![ValType::I32, ValType::I32, ValType::I32], results: vec![] });
+        // the arguments go to a page reserved past the declared one
+        assert_eq!(module.memories[0], Limits { min: 2, max: Some(2) });
+        assert_eq!(module.functions[0].body, vec![
+            Instruction::I32Const(7),
+            Instruction::I32Const(65536),
+            Instruction::F64Const(1.5_f64.to_bits()),
+            Instruction::Store(ValType::F64, MemArg { align: 3, offset: 0 }),
+            Instruction::I32Const(65536),
+            Instruction::F64Const(2.5_f64.to_bits()),
+            Instruction::Store(ValType::F64, MemArg { align: 3, offset: 8 }),
+            Instruction::I32Const(65536),
+            Instruction::I32Const(2),
+            Instruction::Call(0),
+            Instruction::End
+        ]);
+        assert!(crate::validator::validate(&module.to_wasm()).is_ok());
+    }
+
+    #[test]
+    fn reserve_pages_for_variadic_calls() {
+        let build = |memory: &str, count: usize| {
+            let args = vec!["1"; count].join(", ");
+            let text = format!("{} imp fn log(...args: i64) from \"env\"; fn f() {{ log({}); }}", memory, args);
+            let mut program = crate::parser::parse_syntax(&crate::tokenizer::tokenize(&text)?, false)?;
+
+            crate::lowering::lower(&mut program)?;
+
+            return Module::build(&program, &checker::Defaults::default());
+        };
+
+        assert_eq!(build("mem memory = (3; page; 4);", 8).unwrap().memories[0], Limits { min: 4, max: Some(4) });
+        // 8193 i64 arguments take a byte more than a page
+        assert_eq!(build("mem memory = (0; page; 5);", 8193).unwrap().memories[0], Limits { min: 2, max: Some(5) });
+        assert_eq!(build("imp mem memory = (1; page; 2) from \"env\";", 1).unwrap().imports[0].kind, ImportKind::Memory(Limits { min: 2, max: Some(2) }));
+        // memories past the first, and modules without variadic calls, keep their limits
+        assert_eq!(build("mem a = (1; page; 1); mem b = (1; page; 1);", 0).unwrap().memories, vec![Limits { min: 1, max: Some(1) }; 2]);
+        assert!(build("mem memory = (1; page; 1);", 1).unwrap_err().to_string().contains("need 1 more page(s) of memory `memory`, past its maximum of 1"));
+        assert!(build("mem memory = (65536; page; 65536);", 1).is_err());
+    }
+
+    #[test]
+    fn run_variadic_call_with_memory() {
+        let text = "
+            exp mem memory = (1; page; 2);
+            imp fn sum(...args: i32) -> i32 from \"env\";
+            exp \"f\" fn f(a: i32) -> i32 {
+                @65532<i32>(memory) <- a;
+
+                sum(a, 2, 3) + @65532<i32>(memory)
+            }
+            fn g(a: i32) -> i32 { sum(0, a, a) }
+            exp \"h\" fn h(a: i32) -> i32 { sum(1, 2, g(a)) }
+        ";
+        let mut program = crate::parser::parse_syntax(&crate::tokenizer::tokenize(text).unwrap(), false).unwrap();
+
+        crate::lowering::lower(&mut program).unwrap();
+
+        let wasm = Module::build(&program, &checker::Defaults::default()).unwrap().to_wasm();
+        let engine = wasmi::Engine::default();
+        let module = wasmi::Module::new(&engine, &wasm[..]).unwrap();
+        let mut store = wasmi::Store::new(&engine, ());
+        let mut linker = wasmi::Linker::<()>::new(&engine);
+
+        // the host sums the arguments it is handed in memory
+        linker.func_wrap("env", "sum", |caller: wasmi::Caller<'_, ()>, address: i32, count: i32| -> i32 {
+            let memory = caller.get_export("memory").and_then(wasmi::Extern::into_memory).unwrap();
+            let mut bytes = vec![0; usize::try_from(count).unwrap() * 4];
+
+            memory.read(&caller, usize::try_from(address).unwrap(), &mut bytes).unwrap();
+
+            return bytes.chunks(4).map(|x| return i32::from_le_bytes([x[0], x[1], x[2], x[3]])).sum();
+        }).unwrap();
+
+        let instance = linker.instantiate(&mut store, &module).unwrap().start(&mut store).unwrap();
+        let f = instance.get_typed_func::<i32, i32>(&store, "f").unwrap();
+
+        // the word at the end of the declared page is left as it was stored
+        assert_eq!(f.call(&mut store, 10).unwrap(), 10 + 2 + 3 + 10);
+
+        // the inner call reuses the pages before the outer one stores its arguments
+        let h = instance.get_typed_func::<i32, i32>(&store, "h").unwrap();
+
+        assert_eq!(h.call(&mut store, 10).unwrap(), 1 + 2 + 20);
+    }
+
+    // the alignment immediate of every load and store, in order
+    fn alignments(text: &str) -> Vec<u32> {
+        return build(text).unwrap().functions[0].body.iter().filter_map(|x| return match x {
+            Instruction::Load(_, memarg) | Instruction::Store(_, memarg) => Some(memarg.align),
+            _ => None
+        }).collect();
+    }
+
+    #[test]
+    fn emit_alignment_hints() {
+        let i32s = "mem memory = (1; page; 1); fn f(i: i32) -> i32 { @i<i32>(memory; 1) <- @i<i32>(memory; 2); @i<i32>(memory) }";
+        let i64s = "mem memory = (1; page; 1); fn f(i: i32) -> i64 { @i<i64>(memory; 4) <- @i<i64>(memory; 8); @i<i64>(memory; 16) }";
+
+        assert_eq!(alignments(i32s), vec![1, 0, 2]);
+        assert_eq!(alignments(i64s), vec![3, 2, 3]);
+        assert!(build(i32s).unwrap().to_wat().contains("i32.store align=1"));
+        assert!(crate::validator::validate(&build(i64s).unwrap().to_wasm()).is_ok());
+        assert!(build("mem memory = (1; page; 1); fn f() -> i32 { @0<i32>(memory; 3) }").err().unwrap().to_string().contains("must be a power of two"));
+    }
+
+    #[test]
+    fn reject_untyped_memory_access() {
+        let err = build("mem memory = (1; page; 1); fn f() -> i32 { @0(memory) }").err().unwrap();
+
+        assert!(err.to_string().contains("needs a value type"));
+        assert!(build("fn f() -> i32 { @0<i32>(memory) }").is_err());
+    }
+
+    #[test]
+    fn emit_function_reference_into_table() {
+        let module = build("tab table = (2; fref; 2); fn g(a: i32) -> i32 { a } fn f(r: fref) { @0(table) <- g; @1<fref>(table) <- r; }").unwrap();
+
+        assert_eq!(module.functions[1].body, vec![
+            Instruction::I32Const(0),
+            Instruction::RefFunc(0),
+            Instruction::TableSet(0),
+            Instruction::I32Const(1),
+            Instruction::LocalGet(0),
+            Instruction::TableSet(0),
+            Instruction::End
+        ]);
+        assert!(module.to_wat().contains("(table 2 2 funcref)"));
+        assert!(module.to_wat().contains("(elem declare func 0)"));
+        assert!(crate::validator::validate(&module.to_wasm()).is_ok());
+    }
+
+    #[test]
+    fn emit_host_reference_table() {
+        let module = build("tab hosts = (1; xref; 1); fn f(x: xref) -> xref { @0(hosts) <- x; @0(hosts) }").unwrap();
+
+        assert!(module.to_wat().contains("(table 1 1 externref)"));
+        assert!(crate::validator::validate(&module.to_wasm()).is_ok());
+        assert!(build("tab hosts = (1; xref; 1); type F = fn () -> i32; fn f() -> i32 { @0(hosts)::<F>() }").is_err());
+        assert!(build("tab hosts = (1; xref; 1); fn g() {} fn f() { @0(hosts) <- g; }").is_err());
+    }
+
+    // (name, kind) of every entry in the export section
+    fn exports(buf: &[u8]) -> Vec<(String, wasmparser::ExternalKind)> {
+        let mut res = vec![];
+
+        for payload in wasmparser::Parser::new(0).parse_all(buf) {
+            if let wasmparser::Payload::ExportSection(reader) = payload.unwrap() {
+                for export in reader {
+                    let export = export.unwrap();
+
+                    res.push((export.name.to_string(), export.kind));
+                }
+            }
+        }
+
+        return res;
+    }
+
+    #[test]
+    fn emit_memory_and_table_exports() {
+        let module = build("exp \"mem\" mem m = (1; page; 2); exp tab t = (1; fref; 1); exp \"count\" let mut counter <- 0;").unwrap();
+        let buf = module.to_wasm();
+
+        assert_eq!(exports(&buf), vec![
+            ("t".to_string(), wasmparser::ExternalKind::Table),
+            ("mem".to_string(), wasmparser::ExternalKind::Memory),
+            ("count".to_string(), wasmparser::ExternalKind::Global)
+        ]);
+        assert!(module.to_wat().contains("(export \"mem\" (memory 0))"));
+        assert!(crate::validator::validate(&buf).is_ok());
+    }
+
+    #[test]
+    fn emit_escaped_export_alias() {
+        let module = build("exp \"say \\\"hi\\\"\\n\\u{e9}\\x41\" fn f() {}\nexp f as \"back\\\\slash\";").unwrap();
+        let buf = module.to_wasm();
+
+        assert_eq!(exports(&buf), vec![
+            ("say \"hi\"\n\u{e9}A".to_string(), wasmparser::ExternalKind::Func),
+            ("back\\slash".to_string(), wasmparser::ExternalKind::Func)
+        ]);
+        assert!(module.to_wat().contains("(export \"say \\\"hi\\\"\\0a\\c3\\a9A\" (func 0))"));
+        assert!(crate::validator::validate(&buf).is_ok());
+    }
+
+    #[test]
+    fn reject_malformed_export_alias() {
+        let message = |text| return build(text).unwrap_err().to_string();
+
+        assert_eq!(message("exp \"\\xff\" fn f() {}"), "string \"\\xff\" is not valid utf-8");
+        assert_eq!(message("exp \"a\\q\" fn f() {}"), "unknown escape `\\q` in string \"a\\q\"");
+        assert!(message("exp \"\\u{d800}\" fn f() {}").contains("invalid unicode escape"));
+        assert!(message("exp \"\\x4\" fn f() {}").contains("invalid escape `\\x4`"));
+        assert!(build("exp \"\\xc3\\xa9\" fn f() {}").is_ok());
+    }
+
+    #[test]
+    fn dump_function_ir() {
+        let module = build("fn f(a: i32) -> i32 { let b <- 2; if (a) { ret b; } a * b }").unwrap();
+
+        assert_eq!(module.to_ir(), "\
+fn f(i32) -> i32 {
+    locals i32
+    0000 i32.const 2
+    0001 local.set 1
+    0002 local.get 0
+    0003 if
+    0004   local.get 1
+    0005   return
+    0006 end
+    0007 local.get 0
+    0008 local.get 1
+    0009 i32.mul
+    0010 end
+}
+");
+    }
+
+    #[test]
+    fn emit_call_indirect_through_alias() {
+        let module = build("tab table = (1; fref; 1); type BinOp = fn (i32, i32) -> i32; fn f(i: i32) -> i32 { *i(table)::<BinOp>(1, 2) }").unwrap();
+
+        assert_eq!(module.functions[0].body, vec![
+            Instruction::I32Const(1),
+            Instruction::I32Const(2),
+            Instruction::LocalGet(0),
+            Instruction::CallIndirect(0, 0),
+            Instruction::End
+        ]);
+        assert!(crate::validator::validate(&module.to_wasm()).is_ok());
+        assert!(build("tab table = (1; fref; 1); type Pair = (i32, i32); fn f() { *0(table)::<Pair>(); }").is_err());
+    }
+
+    #[test]
+    fn emit_identical_bytes() {
+        let text = std::fs::read_to_string("tests/samples/reproducible.cwal").unwrap();
+        let module = build(&text).unwrap();
+
+        assert_eq!(module.to_wasm(), build(&text).unwrap().to_wasm());
+        // `apply` shares the type of `BinOp` rather than adding a duplicate
+        assert_eq!(module.types.len(), 5);
+    }
+
+    #[test]
+    fn stream_into_vec_sink() {
+        let text = std::fs::read_to_string("tests/samples/reproducible.cwal").unwrap();
+        let module = build(&text).unwrap();
+        let mut sink = vec![];
+
+        module.write_wasm(&mut sink).unwrap();
+
+        assert!(crate::validator::validate(&sink).is_ok());
+        assert_eq!(sink, module.to_wasm());
+        // a body over 127 bytes needs a two-byte size
+        let long = format!("fn f(a: i32) -> i32 {{ {} a }}", "a <- a + 1; ".repeat(40));
+        let mut sink = vec![];
+
+        build(&long).unwrap().write_wasm(&mut sink).unwrap();
+        assert!(crate::validator::validate(&sink).is_ok());
+    }
+
+    #[test]
+    fn stream_into_file() {
+        let text = std::fs::read_to_string("tests/samples/reproducible.cwal").unwrap();
+        let module = build(&text).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out").join("module.wasm").to_string_lossy().to_string();
+
+        crate::io::write_stream(&path, |w| return module.write_wasm(w)).unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), module.to_wasm());
+
+        crate::io::write_stream(&path, |w| {
+            let mut w = crate::validator::ValidatingWriter::new(w);
+
+            module.write_wasm(&mut w)?;
+
+            return w.finish().map(drop);
+        }).unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), module.to_wasm());
+        // a failed stream leaves no partial file behind
+        assert!(crate::io::write_stream(&path, |w| return std::io::Write::write_all(w, &[0x00, 0x61])
+            .and_then(|_| return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid")))).is_err());
+        assert!(!std::path::Path::new(&path).exists());
+    }
+
+    #[test]
+    fn share_function_types() {
+        let module = build("fn a(x: i32) -> i32 { x } fn b(y: i32) -> i32 { y + 1 } fn c(z: i32) -> i32 { a(z) + b(z) }").unwrap();
+
+        assert_eq!(module.types.len(), 1);
+        assert!(module.functions.iter().all(|x| return x.ty == 0));
+        assert!(crate::validator::validate(&module.to_wasm()).is_ok());
+    }
+
+    // found by the generated programs below, `==` and `!=` had no instruction
+    #[test]
+    fn emit_equality() {
+        let module = build("fn f(a: f64, b: i64) -> i32 { (a != 1.5) + (b == 2) }").unwrap();
+        let wat = module.to_wat();
+
+        assert!(module.functions[0].body.contains(&Instruction::Binary(ValType::F64, BinaryOp::Ne)));
+        assert!(module.functions[0].body.contains(&Instruction::Binary(ValType::I64, BinaryOp::Eq)));
+        assert!(wat.contains("f64.ne") && wat.contains("i64.eq"));
+        assert!(crate::validator::validate(&module.to_wasm()).is_ok());
+    }
+
+    #[test]
+    fn emit_equality_opcodes() {
+        let module = build("fn f(a: i32) -> i32 { a == 7 } fn g(a: f64) -> i32 { a != 0.5 } fn h() -> i64 { 5 } fn k() -> i32 { h() == 1 }").unwrap();
+        let buf = module.to_wasm();
+
+        assert_eq!(module.functions[0].body, vec![Instruction::LocalGet(0), Instruction::I32Const(7), Instruction::Binary(ValType::I32, BinaryOp::Eq), Instruction::End]);
+        assert!(buf.windows(4).any(|x| return x == [0x20, 0x00, 0x41, 0x07]));
+        assert!(buf.windows(2).any(|x| return x == [0x07, 0x46]));
+        assert_eq!(module.functions[1].body[2], Instruction::Binary(ValType::F64, BinaryOp::Ne));
+        assert!(buf.windows(10).any(|x| return x[0] == 0x44 && x[9] == 0x62));
+        assert_eq!(module.functions[3].body, vec![Instruction::Call(2), Instruction::I64Const(1), Instruction::Binary(ValType::I64, BinaryOp::Eq), Instruction::End]);
+        assert!(build("fn f(a: fref) -> i32 { a == a }").unwrap_err().to_string().contains("operator `==` is not defined for funcref"));
+    }
+
+    #[test]
+    fn emit_select_for_pure_arms() {
+        let module = build("fn f(a: i32, b: i32) -> i32 { a > b ? a : -b } fn g(a: i32) -> f64 { a ? 0.5 : 1.5 }").unwrap();
+
+        assert_eq!(module.functions[0].body, vec![
+            Instruction::LocalGet(0),
+            Instruction::I32Const(0),
+            Instruction::LocalGet(1),
+            Instruction::Binary(ValType::I32, BinaryOp::Sub),
+            Instruction::LocalGet(0),
+            Instruction::LocalGet(1),
+            Instruction::Binary(ValType::I32, BinaryOp::Gt),
+            Instruction::Select,
+            Instruction::End
+        ]);
+        assert_eq!(module.functions[1].body[2..], [Instruction::LocalGet(0), Instruction::Select, Instruction::End]);
+        assert!(module.to_wasm().windows(2).any(|x| return x == [0x1b, 0x0b]));
+        assert!(crate::validator::validate(&module.to_wasm()).is_ok());
+    }
+
+    #[test]
+    fn emit_if_for_effectful_arms() {
+        let module = build("fn h() -> i32 { 1 } fn f(a: i32) -> i32 { a ? h() : 0 } fn g(a: i32, b: i32) -> i32 { b ? a / b : 0 }").unwrap();
+
+        for function in module.functions[1..].iter() {
+            assert!(function.body.contains(&Instruction::If(BlockType::Value(ValType::I32))));
+            assert!(!function.body.contains(&Instruction::Select));
+        }
+
+        // the division is never evaluated when `b` is zero
+        assert_eq!(run("exp \"f\" fn f(b: i32) -> i32 { b ? 10 / b : -1 }", "f", 0).unwrap(), -1);
+        assert_eq!(run("exp \"f\" fn f(b: i32) -> i32 { b ? 10 : -1 }", "f", 2).unwrap(), 10);
+    }
+
+    // signed as the checker leaves them, so unsigned operands get unsigned checks
+    fn build_checked(text: &str) -> Result<Module, Box<dyn Error>> {
+        let tokens = crate::tokenizer::tokenize(text)?;
+        let defaults = checker::Defaults::default();
+        let features = feature::Features { overflow: feature::Overflow::Trap, ..feature::Features::default() };
+        let mut program = crate::parser::parse_syntax(&tokens, false)?;
+
+        checker::sign_operators(&mut program, &defaults)?;
+
+        return Module::build_with_features(&program, &defaults, &features);
+    }
+
+    #[test]
+    fn emit_overflow_checks() {
+        let text = "fn f(a: i32, b: i32) -> i32 { a + b } fn g(a: i64, b: i64) -> i64 { a * b } fn h(a: f64, b: f64) -> f64 { a - b }";
+        let wrapped = build(text).unwrap();
+        let checked = build_checked(text).unwrap();
+
+        assert_eq!(wrapped.functions[0].body, vec![Instruction::LocalGet(0), Instruction::LocalGet(1), Instruction::Binary(ValType::I32, BinaryOp::Add), Instruction::End]);
+        assert!(wrapped.functions.iter().all(|x| return !x.body.contains(&Instruction::Unreachable)));
+
+        assert!(checked.functions[0].body.contains(&Instruction::Unreachable));
+        assert_eq!(checked.functions[0].locals, vec![ValType::I32; 3]);
+        assert!(checked.functions[1].body.contains(&Instruction::Binary(ValType::I64, BinaryOp::Div)));
+        assert_eq!(checked.functions[2].body, wrapped.functions[2].body);
+        assert!(crate::validator::validate(&checked.to_wasm()).is_ok());
+    }
+
+    #[test]
+    fn trap_on_overflow() {
+        let text = "
+            exp \"add\" fn add(a: i32) -> i32 { a + 2147483600 }
+            exp \"sub\" fn sub(a: i32) -> i32 { -2147483600 - a }
+            exp \"mul\" fn mul(a: i32) -> i32 { a * 65536 }
+            exp \"mul64\" fn mul64(a: i64) -> i64 { a * a }
+        ";
+        let engine = wasmi::Engine::default();
+        let module = wasmi::Module::new(&engine, &build_checked(text).unwrap().to_wasm()[..]).unwrap();
+        let mut store = wasmi::Store::new(&engine, ());
+        let instance = wasmi::Linker::<()>::new(&engine).instantiate(&mut store, &module).unwrap().start(&mut store).unwrap();
+        let mut call = |name: &str, arg: i32| return instance.get_typed_func::<i32, i32>(&store, name).unwrap().call(&mut store, arg);
+
+        assert_eq!(call("add", 47).unwrap(), 2147483647);
+        assert!(call("add", 48).is_err());
+        assert_eq!(call("add", -2147483600).unwrap(), 0);
+        assert_eq!(call("sub", 48).unwrap(), -2147483648);
+        assert!(call("sub", 49).is_err());
+        assert_eq!(call("mul", -32768).unwrap(), -2147483648);
+        assert!(call("mul", 32768).is_err());
+        assert_eq!(call("mul", 0).unwrap(), 0);
+
+        let mul64 = instance.get_typed_func::<i64, i64>(&store, "mul64").unwrap();
+
+        assert_eq!(mul64.call(&mut store, 3037000499).unwrap(), 9223372030926249001);
+        assert!(mul64.call(&mut store, 3037000500).is_err());
+        assert!(run("exp \"f\" fn f(a: i32) -> i32 { a + 2147483600 }", "f", 48).is_ok());
+    }
+
+    #[test]
+    fn trap_on_unsigned_overflow() {
+        let text = "
+            exp \"add\" fn add(a: u32) -> u32 { a + 1 }
+            exp \"sub\" fn sub(a: u32) -> u32 { 1 - a }
+            exp \"mul\" fn mul(a: u32) -> u32 { a * 2 }
+            exp \"add64\" fn add64(a: u64) -> u64 { a + 1 }
+        ";
+        let module = build_checked(text).unwrap();
+
+        assert!(module.functions[0].body.contains(&Instruction::Binary(ValType::I32, BinaryOp::LtU)));
+        assert!(module.functions[2].body.contains(&Instruction::Binary(ValType::I32, BinaryOp::DivU)));
+
+        let engine = wasmi::Engine::default();
+        let module = wasmi::Module::new(&engine, &module.to_wasm()[..]).unwrap();
+        let mut store = wasmi::Store::new(&engine, ());
+        let instance = wasmi::Linker::<()>::new(&engine).instantiate(&mut store, &module).unwrap().start(&mut store).unwrap();
+        let mut call = |name: &str, arg: i32| return instance.get_typed_func::<i32, i32>(&store, name).unwrap().call(&mut store, arg);
+
+        // past the signed maximum is still in range
+        assert_eq!(call("add", 2147483647).unwrap(), -2147483648);
+        // 0xFFFFFFFF
+        assert!(call("add", -1).is_err());
+        assert_eq!(call("sub", 1).unwrap(), 0);
+        assert!(call("sub", 2).is_err());
+        assert_eq!(call("mul", 2147483647).unwrap(), -2);
+        assert!(call("mul", -2147483648).is_err());
+
+        let add64 = instance.get_typed_func::<i64, i64>(&store, "add64").unwrap();
+
+        assert_eq!(add64.call(&mut store, i64::MAX).unwrap(), i64::MIN);
+        assert!(add64.call(&mut store, -1).is_err());
+    }
+
+    #[test]
+    fn trap_only_on_taken_arm() {
+        let module = build_checked("exp \"pick\" fn pick(a: i32, b: i32) -> i32 { b < 10 ? a + b : 0 }").unwrap();
+
+        assert!(!module.functions[0].body.contains(&Instruction::Select));
+
+        let engine = wasmi::Engine::default();
+        let module = wasmi::Module::new(&engine, &module.to_wasm()[..]).unwrap();
+        let mut store = wasmi::Store::new(&engine, ());
+        let instance = wasmi::Linker::<()>::new(&engine).instantiate(&mut store, &module).unwrap().start(&mut store).unwrap();
+        let pick = instance.get_typed_func::<(i32, i32), i32>(&store, "pick").unwrap();
+
+        assert_eq!(pick.call(&mut store, (i32::MAX, 100)).unwrap(), 0);
+        assert_eq!(pick.call(&mut store, (1, 2)).unwrap(), 3);
+        assert!(pick.call(&mut store, (i32::MAX, 1)).is_err());
+    }
+
+    #[test]
+    fn emit_float_intrinsics() {
+        let module = build("fn f(a: f32) -> f32 { sqrt(a) } fn g(a: f64, b: f64) -> f64 { min(a, b) }").unwrap();
+        let buf = module.to_wasm();
+
+        assert_eq!(module.functions[0].body, vec![Instruction::LocalGet(0), Instruction::Unary(ValType::F32, ir::UnaryOp::Sqrt), Instruction::End]);
+        assert_eq!(module.functions[1].body[2], Instruction::Binary(ValType::F64, BinaryOp::Min));
+        assert!(buf.windows(3).any(|x| return x == [0x20, 0x00, 0x91]));
+        assert!(buf.windows(5).any(|x| return x == [0x20, 0x00, 0x20, 0x01, 0xa4]));
+        assert!(module.to_wat().contains("f32.sqrt"));
+        assert!(module.to_wat().contains("f64.min"));
+        assert!(crate::validator::validate(&buf).is_ok());
+    }
+
+    #[test]
+    fn run_float_intrinsics() {
+        let evaluate = |expr| {
+            let engine = wasmi::Engine::default();
+            let module = wasmi::Module::new(&engine, &build(&format!("exp \"f\" fn f() -> f64 {{ {} }}", expr)).unwrap().to_wasm()[..]).unwrap();
+            let mut store = wasmi::Store::new(&engine, ());
+            let instance = wasmi::Linker::<()>::new(&engine).instantiate(&mut store, &module).unwrap().start(&mut store).unwrap();
+
+            return instance.get_typed_func::<(), f64>(&store, "f").unwrap().call(&mut store, ()).unwrap();
+        };
+
+        assert_eq!(evaluate("sqrt(4.0)"), 2.0);
+        assert_eq!(evaluate("min(1.5, -2.5)"), -2.5);
+        assert_eq!(evaluate("copysign(3.0, -0.0)"), -3.0);
+    }
+
+    #[test]
+    fn prefer_declared_function_over_intrinsic() {
+        let module = build("fn sqrt(a: f64) -> f64 { a } fn f(a: f64) -> f64 { sqrt(a) }").unwrap();
+
+        assert_eq!(module.functions[1].body[1], Instruction::Call(0));
+    }
+
+    #[test]
+    fn emit_integer_intrinsics() {
+        let module = build("fn f(a: i32) -> i32 { clz(a) + ctz(a) } fn g(a: i64) -> i64 { popcnt(a) }").unwrap();
+        let buf = module.to_wasm();
+
+        assert_eq!(module.functions[0].body[..4], [
+            Instruction::LocalGet(0),
+            Instruction::Unary(ValType::I32, ir::UnaryOp::Clz),
+            Instruction::LocalGet(0),
+            Instruction::Unary(ValType::I32, ir::UnaryOp::Ctz)
+        ]);
+        assert!(buf.windows(6).any(|x| return x == [0x20, 0x00, 0x67, 0x20, 0x00, 0x68]));
+        assert!(buf.windows(3).any(|x| return x == [0x20, 0x00, 0x7b]));
+        assert!(module.to_wat().contains("i64.popcnt"));
+        assert!(crate::validator::validate(&buf).is_ok());
+    }
+
+    #[test]
+    fn run_integer_intrinsics() {
+        assert_eq!(run("exp \"f\" fn f(a: i32) -> i32 { popcnt(0b1011) }", "f", 0).unwrap(), 3);
+        assert_eq!(run("exp \"f\" fn f(a: i32) -> i32 { clz(a) }", "f", 1).unwrap(), 31);
+        assert_eq!(run("exp \"f\" fn f(a: i32) -> i32 { ctz(a) }", "f", 8).unwrap(), 3);
+    }
+
+    #[test]
+    fn emit_null_references() {
+        let module = build("fn f() -> fref { Null } fn g(r: xref) -> i32 { is_null(r) } tab t = (1; xref; 1); fn h() { @0(t) <- Null; }").unwrap();
+        let buf = module.to_wasm();
+
+        assert_eq!(module.functions[0].body, vec![Instruction::RefNull(ValType::FuncRef), Instruction::End]);
+        assert_eq!(module.functions[1].body, vec![Instruction::LocalGet(0), Instruction::RefIsNull, Instruction::End]);
+        assert!(module.functions[2].body.contains(&Instruction::RefNull(ValType::ExternRef)));
+        assert!(buf.windows(2).any(|x| return x == [0xd0, 0x70]));
+        assert!(buf.windows(3).any(|x| return x == [0x20, 0x00, 0xd1]));
+        assert!(module.to_wat().contains("ref.null func"));
+        assert!(module.to_wat().contains("ref.null extern"));
+        assert!(crate::validator::validate(&buf).is_ok());
+        assert!(build("fn f() -> i32 { Null }").unwrap_err().to_string().contains("`Null` is a reference, but i32 is expected"));
+        assert!(build("fn f(a: i32) -> i32 { is_null(a) }").unwrap_err().to_string().contains("takes fref or xref operands"));
+    }
+
+    #[test]
+    fn emit_declared_variable_types() {
+        let module = build("let g: xref <- Null; fn f() -> i32 { let r: xref <- Null; let n: i64 <- 5; is_null(r) }").unwrap();
+
+        assert_eq!(module.globals[0].ty, ValType::ExternRef);
+        assert_eq!(module.globals[0].init, Instruction::RefNull(ValType::ExternRef));
+        assert_eq!(module.functions[0].locals, vec![ValType::ExternRef, ValType::I64]);
+        assert_eq!(&module.functions[0].body[..4], &[
+            Instruction::RefNull(ValType::ExternRef),
+            Instruction::LocalSet(0),
+            Instruction::I64Const(5),
+            Instruction::LocalSet(1)
+        ]);
+        assert!(crate::validator::validate(&module.to_wasm()).is_ok());
+        assert_eq!(build("fn f() { let r: fref <- 1.5; }").unwrap_err().to_string(), "variable `r` is declared funcref, but its value is f64");
+    }
+
+    #[test]
+    fn run_null_checks() {
+        let text = "
+            tab table = (1; fref; 1);
+            type Unary = fn (i32) -> i32;
+
+            fn id(a: i32) -> i32 { a }
+
+            exp \"null\" fn null(a: i32) -> i32 { is_null(@0(table)) }
+            exp \"set\" fn set(a: i32) -> i32 { @0(table) <- id; is_null(@0(table)) }
+            exp \"call\" fn call(a: i32) -> i32 { @0(table) <- Null; *0(table)::<Unary>(a) }
+            exp \"call_id\" fn call_id(a: i32) -> i32 { @0(table) <- id; *0(table)::<Unary>(a) }
+        ";
+
+        assert_eq!(run(text, "null", 0).unwrap(), 1);
+        assert_eq!(run(text, "set", 0).unwrap(), 0);
+        assert_eq!(run(text, "call_id", 7).unwrap(), 7);
+        // calling through a null entry traps rather than being skipped
+        assert!(run(text, "call", 0).is_err());
+    }
+    // programs of a small typed subset of the language, which every stage
+    // accepts, so any module emitted for one that does not validate is a bug
+    #[derive(Debug, Clone)]
+    struct Shape {
+        params: Vec<token::Type>,
+        result: token::Type
+    }
+
+    #[derive(Clone)]
+    struct Scope {
+        shapes: Rc<Vec<Shape>>,
+        locals: Rc<Vec<(String, token::Type)>>
+    }
+
+    const NUMBERS: [token::Type; 4] = [token::Type::I32, token::Type::I64, token::Type::F32, token::Type::F64];
+
+    fn number() -> impl Strategy<Value = token::Type> {
+        return select(NUMBERS.to_vec());
+    }
+
+    fn is_integer(ty: &token::Type) -> bool {
+        return matches!(ty, token::Type::I32 | token::Type::I64);
+    }
+
+    fn literal(ty: &token::Type) -> BoxedStrategy<ast::Expression> {
+        return match ty {
+            token::Type::F32 => "[0-9]{1,3}\\.[0-9]{1,2}f32".prop_map(ast::Expression::Literal).boxed(),
+            token::Type::F64 => "[0-9]{1,3}\\.[0-9]{1,2}f64".prop_map(ast::Expression::Literal).boxed(),
+            _ => "[0-9]{1,5}".prop_map(ast::Expression::Literal).boxed()
+        };
+    }
+
+    fn binary(op: token::Symbol, lhs: ast::Expression, rhs: ast::Expression) -> ast::Expression {
+        return ast::Expression::Binary { op, lhs: Box::new(lhs), rhs: Box::new(rhs) };
+    }
+
+    fn call(callee: &str, args: Vec<ast::Expression>) -> ast::Expression {
+        return ast::Expression::Call { callee: callee.to_string(), args };
+    }
+
+    fn expression(ty: token::Type, scope: Scope, depth: u32) -> BoxedStrategy<ast::Expression> {
+        let locals = scope.locals.iter().filter(|x| return x.1 == ty).map(|x| return ast::Expression::Identifier(x.0.clone())).collect::<Vec<_>>();
+        let mut leaves = vec![literal(&ty)];
+
+        if !locals.is_empty() {
+            leaves.push(select(locals).boxed());
+        }
+
+        if depth == 0 {
+            return Union::new(leaves).boxed();
+        }
+
+        let sub = {
+            let scope = scope.clone();
+
+            move |ty: &token::Type| return expression(ty.clone(), scope.clone(), depth - 1)
+        };
+        let ops = match is_integer(&ty) {
+            true => vec![
+                token::Symbol::Plus, token::Symbol::Minus, token::Symbol::Asterisk, token::Symbol::Solidus, token::Symbol::Modulo,
+                token::Symbol::BitwiseAnd, token::Symbol::BitwiseOr, token::Symbol::BitwiseXor, token::Symbol::ShiftLeftLogical
+            ],
+            false => vec![token::Symbol::Plus, token::Symbol::Minus, token::Symbol::Asterisk, token::Symbol::Solidus]
+        };
+        let unary = match is_integer(&ty) {
+            true => vec!["clz", "ctz", "popcnt"],
+            false => vec!["abs", "ceil", "floor", "trunc", "nearest", "sqrt"]
+        };
+        let mut branches = vec![
+            Union::new(leaves).boxed(),
+            (sub(&ty), select(ops), sub(&ty)).prop_map(|(lhs, op, rhs)| return binary(op, lhs, rhs)).boxed(),
+            (select(unary), sub(&ty)).prop_map(|(name, x)| return call(name, vec![x])).boxed(),
+            (sub(&token::Type::I32), sub(&ty), sub(&ty)).prop_map(|(condition, consequent, alternative)| return ast::Expression::Conditional {
+                condition: Box::new(condition),
+                consequent: Box::new(consequent),
+                alternative: Box::new(alternative)
+            }).boxed()
+        ];
+
+        match ty {
+            token::Type::I32 => {
+                let compare = sub.clone();
+                let comparisons = vec![
+                    token::Symbol::Equal, token::Symbol::NotEqual, token::Symbol::LessThan,
+                    token::Symbol::GreaterThan, token::Symbol::LessThanOrEqual, token::Symbol::GreaterThanOrEqual
+                ];
+
+                branches.push(number().prop_flat_map(move |x| return (compare(&x), select(comparisons.clone()), compare(&x)))
+                    .prop_map(|(lhs, op, rhs)| return binary(op, lhs, rhs))
+                    .boxed());
+                branches.push(sub(&token::Type::I64).prop_map(|x| return ast::Expression::Cast { ty: token::Type::I32, operand: Box::new(x) }).boxed());
+            },
+            token::Type::I64 => {
+                branches.push(sub(&token::Type::I32).prop_map(|x| return ast::Expression::Cast { ty: token::Type::I64, operand: Box::new(x) }).boxed());
+            },
+            _ => {
+                branches.push((select(vec!["min", "max", "copysign"]), sub(&ty), sub(&ty)).prop_map(|(name, a, b)| return call(name, vec![a, b])).boxed());
+                branches.push(sub(&ty).prop_map(|x| return ast::Expression::Unary { op: token::Symbol::Minus, operand: Box::new(x) }).boxed());
+            }
+        }
+
+        for (index, shape) in scope.shapes.iter().enumerate().filter(|(_, x)| return x.result == ty) {
+            let args = shape.params.iter().map(&sub).collect::<Vec<_>>();
+
+            branches.push(args.prop_map(move |args| return call(&format!("f{}", index), args)).boxed());
+        }
+
+        return Union::new(branches).boxed();
+    }
+
+    fn block(statements: Vec<ast::Statement>) -> ast::Block {
+        return ast::Block { statements: statements.into_iter().map(|x| return Spanned::new(x, 0..0)).collect(), tail: None };
+    }
+
+    fn statement(scope: Scope, result: token::Type, depth: u32) -> BoxedStrategy<ast::Statement> {
+        let values = scope.clone();
+        let mut branches = vec![
+            number().prop_flat_map(move |x| return expression(x, values.clone(), 2)).prop_map(|value| return ast::Statement::Variable(ast::VariableDeclaration {
+                span: 0..0,
+                pattern: ast::Pattern::Single(ast::Binding { name: "v".to_string(), is_mutable: false }),
+                ty: None,
+                value
+            })).boxed(),
+            expression(result.clone(), scope.clone(), 2).prop_map(|x| return ast::Statement::Return(Some(x))).boxed()
+        ];
+
+        if depth > 0 {
+            let statements = || return proptest::collection::vec(statement(scope.clone(), result.clone(), depth - 1), 0..3);
+
+            branches.push((expression(token::Type::I32, scope.clone(), 2), statements(), statements()).prop_map(|(condition, then, otherwise)| {
+                return ast::Statement::If(ast::IfStatement { condition, block: block(then), else_ifs: vec![], else_block: Some(block(otherwise)) });
+            }).boxed());
+            branches.push((expression(token::Type::I32, scope.clone(), 2), statements()).prop_map(|(condition, statements)| {
+                return ast::Statement::While(ast::WhileStatement { condition, block: block(statements) });
+            }).boxed());
+        }
+
+        return Union::new(branches).boxed();
+    }
+
+    fn function(index: usize, shapes: Rc<Vec<Shape>>) -> BoxedStrategy<ast::Declaration> {
+        let shape = shapes[index].clone();
+        let scope = Scope {
+            locals: Rc::new(shape.params.iter().enumerate().map(|(i, x)| return (format!("p{}", i), x.clone())).collect()),
+            shapes
+        };
+
+        return (proptest::collection::vec(statement(scope.clone(), shape.result.clone(), 1), 0..4), expression(shape.result.clone(), scope.clone(), 3))
+            .prop_map(move |(statements, tail)| return ast::Declaration::Export(ast::ExportDeclaration {
+                alias: None,
+                item: ast::ExportItem::Function(ast::FunctionDeclaration {
+                    span: 0..0,
+                    name: format!("f{}", index),
+                    is_public: false,
+                    generics: vec![],
+                    constraints: vec![],
+                    signature: ast::Signature {
+                        params: scope.locals.iter().map(|(name, ty)| return ast::Param {
+                            span: 0..0,
+                            name: name.clone(),
+                            ty: ast::TypeExpression::Primitive(ty.clone()),
+                            default: None,
+                            is_variadic: false
+                        }).collect(),
+                        result: Some(ast::TypeExpression::Primitive(shape.result.clone()))
+                    },
+                    body: ast::Block { tail: Some(Spanned::new(tail, 0..0)), ..block(statements) },
+                    attributes: vec![]
+                })
+            }))
+            .boxed();
+    }
+
+    fn program() -> impl Strategy<Value = ast::Program> {
+        let shape = (proptest::collection::vec(number(), 0..3), number()).prop_map(|(params, result)| return Shape { params, result });
+
+        return proptest::collection::vec(shape, 1..4)
+            .prop_flat_map(|shapes| {
+                let shapes = Rc::new(shapes);
+
+                return (0..shapes.len()).map(|x| return function(x, shapes.clone())).collect::<Vec<_>>();
+            })
+            .prop_map(|declarations| return ast::Program { declarations });
+    }
+
+    // every stage the command line runs, from lowering to emitting
+    fn compile(mut program: ast::Program, level: &cli::OptLevel, features: &feature::Features) -> Result<Vec<u8>, Box<dyn Error>> {
+        let defaults = checker::Defaults::default();
+
+        crate::lowering::lower(&mut program)?;
+        checker::default_literals(&mut program, &defaults);
+        checker::infer_results(&mut program, &defaults)?;
+        checker::check(&program)?;
+        checker::sign_operators(&mut program, &defaults)?;
+
+        let symbols = crate::resolver::resolve(&program)?;
+
+        crate::optimizer::optimize(&mut program, &symbols, level, &defaults, &mut vec![]);
+
+        let mut module = Module::build_with_features(&program, &defaults, features)?;
+
+        crate::optimizer::optimize_module(&mut module, &program, level, &mut vec![]);
+
+        return Ok(module.to_wasm());
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn emit_valid_module_for_any_program(program in program(), level in select(vec![cli::OptLevel::O0, cli::OptLevel::O1, cli::OptLevel::O2, cli::OptLevel::O3]), tail_call in any::<bool>(), overflow in select(vec![feature::Overflow::Wrap, feature::Overflow::Trap])) {
+            let wasm = compile(program.clone(), &level, &feature::Features { tail_call, overflow }).map_err(|err| return TestCaseError::fail(format!("{} in {:?}", err, program)))?;
+
+            prop_assert!(crate::validator::validate(&wasm).is_ok(), "{:?}", crate::validator::validate(&wasm));
+        }
+    }
+}
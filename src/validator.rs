@@ -0,0 +1,232 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use wasmparser::{Chunk, FuncValidatorAllocations, Parser, ValidPayload, Validator};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    func_index: Option<u32>,
+    offset: usize,
+    reason: String
+}
+
+#[cfg(test)]
+impl ValidationError {
+    pub const fn func_index(&self) -> Option<u32> {
+        return self.func_index;
+    }
+
+    pub fn reason(&self) -> &str {
+        return &self.reason;
+    }
+}
+
+impl ValidationError {
+    fn new(func_index: Option<u32>, err: wasmparser::BinaryReaderError) -> Self {
+        return Self {
+            func_index,
+            offset: err.offset(),
+            reason: err.message().to_string()
+        };
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(index) = self.func_index {
+            return write!(f, "invalid emitted wasm in function #{}: {} (at offset 0x{:x})", index, self.reason, self.offset);
+        }
+
+        return write!(f, "invalid emitted wasm: {} (at offset 0x{:x})", self.reason, self.offset);
+    }
+}
+
+impl Error for ValidationError {}
+
+// main program section
+// a whole module at once, as the tests check what they emit. the command line
+// validates while it streams instead
+#[cfg(test)]
+pub fn validate(buf: &[u8]) -> Result<(), ValidationError> {
+    let mut writer = ValidatingWriter::new(io::sink());
+
+    writer.pending.extend_from_slice(buf);
+
+    return writer.validate_pending(true);
+}
+
+// validates the binary while it is written, passing each payload on to
+// `inner` once it is valid. the bytes of the payload being written are held
+// until it is complete, which is a whole section, or a single function body
+// within the code section. an invalid payload is never passed on
+pub struct ValidatingWriter<W> {
+    inner: W,
+    parser: Parser,
+    validator: Validator,
+    allocs: FuncValidatorAllocations,
+    pending: Vec<u8>,
+    // the bytes at the front of `pending` already validated
+    valid: usize
+}
+
+impl<W: Write> ValidatingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        return Self {
+            inner,
+            parser: Parser::new(0),
+            validator: Validator::new(),
+            allocs: FuncValidatorAllocations::default(),
+            pending: vec![],
+            valid: 0
+        };
+    }
+
+    // checks that the module written is complete, and hands back the sink
+    pub fn finish(mut self) -> io::Result<W> {
+        self.pass_on(true)?;
+
+        return Ok(self.inner);
+    }
+
+    // passes the valid bytes on to `inner`, even when a payload after them is invalid
+    fn pass_on(&mut self, eof: bool) -> io::Result<()> {
+        let result = self.validate_pending(eof);
+
+        self.inner.write_all(&self.pending[..self.valid])?;
+        self.pending.drain(..self.valid);
+        self.valid = 0;
+
+        return result.map_err(|x| return io::Error::new(io::ErrorKind::InvalidData, x));
+    }
+
+    // validates the complete payloads in the pending bytes, moving `valid` past each
+    fn validate_pending(&mut self, eof: bool) -> Result<(), ValidationError> {
+        loop {
+            let (consumed, payload) = match self.parser.parse(&self.pending[self.valid..], eof) {
+                Ok(Chunk::NeedMoreData(_)) => return Ok(()),
+                Ok(Chunk::Parsed { consumed, payload }) => (consumed, payload),
+                Err(err) => return Err(ValidationError::new(None, err))
+            };
+
+            match self.validator.payload(&payload) {
+                Ok(ValidPayload::Func(func, body)) => {
+                    let mut func_validator = func.into_validator(std::mem::take(&mut self.allocs));
+
+                    if let Err(err) = func_validator.validate(&body) {
+                        return Err(ValidationError::new(Some(func_validator.index()), err));
+                    }
+
+                    self.allocs = func_validator.into_allocations();
+                },
+                Ok(ValidPayload::End(_)) => {
+                    self.valid += consumed;
+
+                    return Ok(());
+                },
+                Ok(_) => {},
+                Err(err) => return Err(ValidationError::new(None, err))
+            }
+
+            self.valid += consumed;
+        }
+    }
+}
+
+impl<W: Write> Write for ValidatingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        self.pass_on(false)?;
+
+        return Ok(buf.len());
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return self.inner.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::*;
+
+    const HEADER: &[u8] = &[0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+
+    // (type (func (result i32))) (func (type 0) <body>)
+    fn module_with_body(body: &[u8]) -> Vec<u8> {
+        let mut buf = HEADER.to_vec();
+
+        buf.extend_from_slice(&[0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f]);
+        buf.extend_from_slice(&[0x03, 0x02, 0x01, 0x00]);
+
+        let code_len = u8::try_from(body.len() + 3).unwrap();
+        let body_len = u8::try_from(body.len() + 1).unwrap();
+        buf.extend_from_slice(&[0x0a, code_len, 0x01, body_len, 0x00]);
+        buf.extend_from_slice(body);
+
+        return buf;
+    }
+
+    #[test]
+    fn valid_module() {
+        assert_eq!(validate(HEADER), Ok(()));
+        assert_eq!(validate(&module_with_body(&[0x41, 0x2a, 0x0b])), Ok(()));
+    }
+
+    #[test]
+    fn malformed_function_body() {
+        // `i32`-returning function that leaves nothing on the stack
+        let err = validate(&module_with_body(&[0x0b])).unwrap_err();
+
+        assert_eq!(err.func_index(), Some(0));
+        assert!(err.reason().contains("type mismatch"), "{}", err);
+    }
+
+    #[test]
+    fn malformed_module() {
+        let err = validate(&[0x00, 0x61, 0x73, 0x6d]).unwrap_err();
+
+        assert_eq!(err.func_index(), None);
+    }
+
+    #[test]
+    fn pass_on_each_valid_section() {
+        let module = module_with_body(&[0x41, 0x2a, 0x0b]);
+        let mut writer = ValidatingWriter::new(vec![]);
+
+        // the type section is held until its last byte is written
+        writer.write_all(&module[..HEADER.len() + 6]).unwrap();
+        assert_eq!(writer.inner, HEADER);
+        writer.write_all(&module[HEADER.len() + 6..HEADER.len() + 7]).unwrap();
+        assert_eq!(writer.inner.len(), HEADER.len() + 7);
+
+        for x in module[HEADER.len() + 7..].iter() {
+            writer.write_all(&[*x]).unwrap();
+        }
+
+        assert_eq!(writer.finish().unwrap(), module);
+    }
+
+    #[test]
+    fn hold_back_invalid_function_body() {
+        let module = module_with_body(&[0x0b]);
+        let mut writer = ValidatingWriter::new(vec![]);
+        let err = writer.write_all(&module).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("in function #0"), "{}", err);
+        // the sections and code section header before the body were valid
+        assert_eq!(writer.inner, module[..module.len() - 3]);
+    }
+
+    #[test]
+    fn reject_incomplete_module() {
+        let module = module_with_body(&[0x41, 0x2a, 0x0b]);
+        let mut writer = ValidatingWriter::new(vec![]);
+
+        writer.write_all(&module[..module.len() - 1]).unwrap();
+
+        assert_eq!(writer.finish().unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,179 @@
+use crate::ast;
+
+// read-only traversal of the ast, every `visit_*` recurses through the
+// matching `walk_*` unless overridden
+pub trait Visitor {
+    fn visit_program(&mut self, program: &ast::Program) {
+        walk_program(self, program);
+    }
+
+    fn visit_decl(&mut self, decl: &ast::Declaration) {
+        walk_decl(self, decl);
+    }
+
+    fn visit_fn(&mut self, function: &ast::FunctionDeclaration) {
+        walk_fn(self, function);
+    }
+
+    fn visit_variable(&mut self, variable: &ast::VariableDeclaration) {
+        walk_variable(self, variable);
+    }
+
+    fn visit_block(&mut self, block: &ast::Block) {
+        walk_block(self, block);
+    }
+
+    fn visit_stmt(&mut self, stmt: &ast::Statement) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &ast::Expression) {
+        walk_expr(self, expr);
+    }
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &ast::Program) {
+    for decl in program.declarations.iter() {
+        visitor.visit_decl(decl);
+    }
+}
+
+// imports, types, tables and memories hold no expressions
+pub fn walk_decl<V: Visitor + ?Sized>(visitor: &mut V, decl: &ast::Declaration) {
+    match decl {
+        ast::Declaration::Function(x) => visitor.visit_fn(x),
+        ast::Declaration::Variable(x) => visitor.visit_variable(x),
+        ast::Declaration::Export(x) => match &x.item {
+            ast::ExportItem::Function(x) => visitor.visit_fn(x),
+            ast::ExportItem::Variable(x) => visitor.visit_variable(x),
+            _ => {}
+        },
+        ast::Declaration::StaticAssert(x) => visitor.visit_expr(&x.condition),
+        _ => {}
+    }
+}
+
+pub fn walk_fn<V: Visitor + ?Sized>(visitor: &mut V, function: &ast::FunctionDeclaration) {
+    for param in function.signature.params.iter() {
+        if let Some(x) = &param.default {
+            visitor.visit_expr(x);
+        }
+    }
+
+    visitor.visit_block(&function.body);
+}
+
+pub fn walk_variable<V: Visitor + ?Sized>(visitor: &mut V, variable: &ast::VariableDeclaration) {
+    visitor.visit_expr(&variable.value);
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, block: &ast::Block) {
+    for stmt in block.statements.iter() {
+        visitor.visit_stmt(stmt);
+    }
+
+    if let Some(x) = &block.tail {
+        visitor.visit_expr(x);
+    }
+}
+
+pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, stmt: &ast::Statement) {
+    match stmt {
+        ast::Statement::Variable(x) => visitor.visit_variable(x),
+        ast::Statement::Expression(x) => visitor.visit_expr(x),
+        ast::Statement::Assignment(target, value) => {
+            visitor.visit_expr(target);
+            visitor.visit_expr(value);
+        },
+        ast::Statement::If(x) => {
+            visitor.visit_expr(&x.condition);
+            visitor.visit_block(&x.block);
+
+            for (condition, block) in x.else_ifs.iter() {
+                visitor.visit_expr(condition);
+                visitor.visit_block(block);
+            }
+
+            if let Some(block) = &x.else_block {
+                visitor.visit_block(block);
+            }
+        },
+        ast::Statement::While(x) => {
+            visitor.visit_expr(&x.condition);
+            visitor.visit_block(&x.block);
+        },
+        ast::Statement::Repeat(x) => visitor.visit_block(&x.block),
+        ast::Statement::Defer(x) => visitor.visit_block(x),
+        ast::Statement::Return(Some(x)) => visitor.visit_expr(x),
+        ast::Statement::StaticAssert(x) => visitor.visit_expr(&x.condition),
+        ast::Statement::Function(x) => visitor.visit_fn(x),
+        ast::Statement::Block(x) => visitor.visit_block(x),
+        ast::Statement::Return(None) | ast::Statement::Break | ast::Statement::Continue => {}
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &ast::Expression) {
+    match expr {
+        ast::Expression::Call { args, .. } | ast::Expression::GenericCall { args, .. } | ast::Expression::Variadic { args, .. } => {
+            for arg in args.iter() {
+                visitor.visit_expr(arg);
+            }
+        },
+        ast::Expression::CallIndirect { target, args, .. } => {
+            visitor.visit_expr(target);
+
+            for arg in args.iter() {
+                visitor.visit_expr(arg);
+            }
+        },
+        ast::Expression::Unary { operand, .. } | ast::Expression::Cast { operand, .. } => visitor.visit_expr(operand),
+        ast::Expression::Binary { lhs, rhs, .. } => {
+            visitor.visit_expr(lhs);
+            visitor.visit_expr(rhs);
+        },
+        ast::Expression::Conditional { condition, consequent, alternative } => {
+            visitor.visit_expr(condition);
+            visitor.visit_expr(consequent);
+            visitor.visit_expr(alternative);
+        },
+        ast::Expression::Tuple(list) => {
+            for x in list.iter() {
+                visitor.visit_expr(x);
+            }
+        },
+        ast::Expression::Offset { offset, .. } => visitor.visit_expr(offset),
+        ast::Expression::Literal(_) | ast::Expression::Identifier(_) | ast::Expression::TypeOf(_) | ast::Expression::Type(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io;
+    use crate::parser;
+    use crate::tokenizer;
+
+    #[derive(Default)]
+    struct FunctionCounter {
+        count: usize
+    }
+
+    impl Visitor for FunctionCounter {
+        fn visit_fn(&mut self, function: &ast::FunctionDeclaration) {
+            self.count += 1;
+
+            walk_fn(self, function);
+        }
+    }
+
+    #[test]
+    fn count_functions() {
+        let text = io::read_file("tests/samples/emit.cwal").unwrap();
+        let program = parser::parse_syntax(&tokenizer::tokenize(&text).unwrap(), false).unwrap();
+        let mut counter = FunctionCounter::default();
+
+        counter.visit_program(&program);
+
+        assert_eq!(counter.count, 2);
+    }
+}
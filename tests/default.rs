@@ -1,11 +1,559 @@
+#![allow(clippy::needless_return)]
+
 use std::error::Error;
 use assert_cmd::Command;
 
 #[test]
 fn basic_syntax() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let outfile = dir.path().join("simple.wasm");
+    let mut cmd = Command::cargo_bin("c-webassembly")?;
+
+    cmd.arg("tests/samples/simple.cwal")
+        .args(["--emit", "wasm", "-o"])
+        .arg(&outfile)
+        .assert()
+        .success();
+
+    wasmparser::Validator::new().validate_all(&std::fs::read(&outfile)?)?;
+
+    return Ok(());
+}
+
+// the passes after the parser recurse once per operator of a chain
+#[test]
+fn compile_long_operator_chain() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let source = dir.path().join("chain.cwal");
+    let outfile = dir.path().join("chain.wasm");
+
+    std::fs::write(&source, format!("exp \"sum\" fn sum(a: i32) -> i32 {{ a{} }}", " + a".repeat(2000)))?;
+
+    Command::cargo_bin("c-webassembly")?
+        .arg(&source)
+        .args(["--emit", "wasm", "-o"])
+        .arg(&outfile)
+        .assert()
+        .success();
+
+    wasmparser::Validator::new().validate_all(&std::fs::read(&outfile)?)?;
+
+    return Ok(());
+}
+
+#[test]
+fn dump_symbols() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("c-webassembly")?;
+    let output = cmd.args(["tests/samples/simple.cwal", "--dump-symbols"]).output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+
+    assert!(output.status.success());
+    assert!(stdout.contains("function test"));
+    assert!(stdout.contains("type     BinaryFunction"));
+
+    return Ok(());
+}
+
+#[test]
+fn dump_cfg() -> Result<(), Box<dyn Error>> {
     let mut cmd = Command::cargo_bin("c-webassembly")?;
+    let output = cmd.args(["tests/samples/branch.cwal", "--dump-cfg"]).output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+
+    assert!(output.status.success());
+    assert!(stdout.starts_with("digraph \"f\" {"));
+    // the condition, the early return, the join after the `if` and the exit
+    assert_eq!(stdout.matches("[label=").count(), 4);
+    assert_eq!(stdout.matches(" -> ").count(), 4);
+
+    return Ok(());
+}
+
+#[test]
+fn emit_multiple_artifacts() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let outfile = dir.path().join("out.wasm");
+    let mut cmd = Command::cargo_bin("c-webassembly")?;
+
+    cmd.arg("tests/samples/emit.cwal")
+        .args(["--emit", "wasm,wat,ir,tokens", "-o"])
+        .arg(&outfile)
+        .assert()
+        .success();
+
+    assert!(dir.path().join("out.wasm").exists());
+    assert!(dir.path().join("out.wat").exists());
+    assert!(dir.path().join("out.ir").exists());
+    assert!(dir.path().join("out.tokens").exists());
+
+    return Ok(());
+}
+
+#[test]
+fn deny_unused_import() -> Result<(), Box<dyn Error>> {
+    let output = Command::cargo_bin("c-webassembly")?.arg("tests/samples/unused_import.cwal").output()?;
+
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("tests/samples/unused_import.cwal:2:1: warning: unused import: function `trace`"));
+
+    Command::cargo_bin("c-webassembly")?
+        .args(["tests/samples/unused_import.cwal", "--deny-warnings"])
+        .assert()
+        .failure();
+
+    return Ok(());
+}
+
+#[test]
+fn reproducible_output() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let mut outputs = vec![];
+
+    // separate processes, so every hash map is seeded differently
+    for name in ["first.wasm", "second.wasm"].iter() {
+        let outfile = dir.path().join(name);
+
+        Command::cargo_bin("c-webassembly")?
+            .args(["tests/samples/reproducible.cwal", "--emit", "wasm", "-o"])
+            .arg(&outfile)
+            .assert()
+            .success();
+
+        outputs.push(std::fs::read(&outfile)?);
+    }
+
+    assert_eq!(outputs[0], outputs[1]);
+
+    return Ok(());
+}
+
+#[test]
+fn explain_error_code() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("c-webassembly")?;
+    let output = cmd.args(["--explain", "E0001"]).output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+
+    assert!(output.status.success());
+    assert!(stdout.starts_with("E0001: unexpected token"));
+
+    Command::cargo_bin("c-webassembly")?
+        .args(["--explain", "E9999"])
+        .assert()
+        .failure();
+
+    return Ok(());
+}
+
+#[test]
+fn list_features() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("c-webassembly")?;
+    let output = cmd.arg("--list-features").output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+
+    assert!(output.status.success());
+
+    for name in ["multi-value", "reference-types", "bulk-memory", "simd"] {
+        let line = stdout.lines().find(|x| return x.starts_with(name)).unwrap();
+
+        assert!(line.contains("2.0"), "{}", line);
+    }
+
+    return Ok(());
+}
+
+#[test]
+fn enable_tail_calls() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let source = dir.path().join("count.cwal");
+    let out = dir.path().join("count.wat");
+
+    std::fs::write(&source, "exp \"count\" fn count(n: i32, acc: i32) -> i32 { if (n == 0) { ret acc; } ret count(n - 1, acc + 1); }")?;
+
+    Command::cargo_bin("c-webassembly")?
+        .args([source.to_str().unwrap(), "--enable", "tail-call", "-O0", "--emit", "wat", "-o", out.to_str().unwrap()])
+        .assert()
+        .success();
+
+    assert!(std::fs::read_to_string(&out)?.contains("return_call 0"));
+
+    Command::cargo_bin("c-webassembly")?
+        .args([source.to_str().unwrap(), "--enable", "threads"])
+        .assert()
+        .code(2);
+
+    return Ok(());
+}
+
+#[test]
+fn limit_token_length() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let source = dir.path().join("alias.cwal");
+
+    std::fs::write(&source, "exp \"a_rather_long_export_name\" fn f() {}")?;
+
+    let output = Command::cargo_bin("c-webassembly")?
+        .args([source.to_str().unwrap(), "--max-token-length", "16"])
+        .output()?;
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)?.contains("string literal exceeds maximum length of 16 bytes"));
+
+    Command::cargo_bin("c-webassembly")?
+        .args([source.to_str().unwrap(), "--max-token-length", "32"])
+        .assert()
+        .success();
+
+    return Ok(());
+}
+
+#[test]
+fn conditional_sections() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let enabled = dir.path().join("enabled.wat");
+    let disabled = dir.path().join("disabled.wat");
+
+    Command::cargo_bin("c-webassembly")?
+        .args(["tests/samples/defines.cwal", "-D", "DEBUG", "--emit", "wat", "-o"])
+        .arg(&enabled)
+        .assert()
+        .success();
+    Command::cargo_bin("c-webassembly")?
+        .args(["tests/samples/defines.cwal", "--emit", "wat", "-o"])
+        .arg(&disabled)
+        .assert()
+        .success();
+
+    assert!(std::fs::read_to_string(&enabled)?.contains("(export \"trace\""));
+    assert!(!std::fs::read_to_string(&disabled)?.contains("(export \"trace\""));
+
+    return Ok(());
+}
+
+#[test]
+fn generate_docs() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("c-webassembly")?;
+    let output = cmd.args(["doc", "tests/samples/documented.cwal"]).output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+
+    assert!(output.status.success());
+    assert!(stdout.contains("fn clamp(x: i32, max: i32) -> i32"));
+    assert!(stdout.contains("Clamps a value into `[0, max]`."));
+    assert!(!stdout.contains("twice"));
+
+    return Ok(());
+}
+
+#[test]
+fn link_multiple_files() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let outfile = dir.path().join("out.wasm");
+
+    Command::cargo_bin("c-webassembly")?
+        .args(["tests/samples/link_main.cwal", "tests/samples/link_lib.cwal", "--emit", "wasm,wat", "-o"])
+        .arg(&outfile)
+        .assert()
+        .success();
+
+    assert!(std::fs::read_to_string(dir.path().join("out.wat"))?.contains("call 1"));
+
+    let output = Command::cargo_bin("c-webassembly")?
+        .args(["tests/samples/link_main.cwal", "tests/samples/link_lib.cwal", "tests/samples/link_lib.cwal"])
+        .output()?;
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("`add` is defined multiple times"));
+
+    return Ok(());
+}
+
+#[test]
+fn strict_denies_lints() -> Result<(), Box<dyn Error>> {
+    let output = Command::cargo_bin("c-webassembly")?
+        .args(["tests/samples/unused_import.cwal", "--strict"])
+        .output()?;
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("tests/samples/unused_import.cwal:2:1: error: unused import: function `trace` [unused-imports]"));
+
+    Command::cargo_bin("c-webassembly")?
+        .args(["tests/samples/unused_import.cwal", "--strict", "-W", "unused-imports"])
+        .assert()
+        .success();
+
+    Command::cargo_bin("c-webassembly")?
+        .args(["tests/samples/unused_import.cwal", "--strict", "-A", "unused-imports", "--deny-warnings"])
+        .assert()
+        .success();
+
+    Command::cargo_bin("c-webassembly")?
+        .args(["tests/samples/unused_import.cwal", "-W", "unused-import"])
+        .assert()
+        .failure();
+
+    return Ok(());
+}
+
+#[test]
+fn color_diagnostics() -> Result<(), Box<dyn Error>> {
+    let stderr = |color: &str| -> Result<String, Box<dyn Error>> {
+        let output = Command::cargo_bin("c-webassembly")?
+            .args(["tests/samples/unused_import.cwal", "--strict", "--color", color])
+            .output()?;
+
+        return Ok(String::from_utf8(output.stderr)?);
+    };
+
+    let plain = stderr("never")?;
+    let colored = stderr("always")?;
+
+    assert!(!plain.contains('\u{1b}'));
+    assert!(plain.contains("tests/samples/unused_import.cwal:2:1: error: unused import"));
+    assert!(colored.contains("\u{1b}[31m"));
+    assert!(colored.contains("\u{1b}[36mtests/samples/unused_import.cwal:2:1\u{1b}[0m"));
+
+    // output captured by the test is not a terminal
+    assert!(!stderr("auto")?.contains('\u{1b}'));
+
+    Command::cargo_bin("c-webassembly")?
+        .args(["tests/samples/simple.cwal", "--color", "sometimes"])
+        .assert()
+        .code(2);
+
+    return Ok(());
+}
+
+// the whole help text is pinned, rewrite `tests/golden/help.txt` when an
+// option is meant to change
+#[test]
+fn print_help() -> Result<(), Box<dyn Error>> {
+    let output = Command::cargo_bin("c-webassembly")?.arg("--help").output()?;
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout)?, std::fs::read_to_string("tests/golden/help.txt")?);
+
+    return Ok(());
+}
+
+#[test]
+fn print_version() -> Result<(), Box<dyn Error>> {
+    Command::cargo_bin("c-webassembly")?
+        .arg("--version")
+        .assert()
+        .success()
+        .stdout(format!("c-webassembly {}\n", env!("CARGO_PKG_VERSION")));
+
+    return Ok(());
+}
+
+#[test]
+fn reject_bad_arguments() -> Result<(), Box<dyn Error>> {
+    Command::cargo_bin("c-webassembly")?
+        .args(["tests/samples/simple.cwal", "-O", "9"])
+        .assert()
+        .code(2)
+        .stdout("")
+        .stderr("error: Invalid value for '-O <opt-level>': cannot parse optimization level of: 9\n");
+
+    Command::cargo_bin("c-webassembly")?
+        .args(["tests/samples/simple.cwal", "--emit", "exe"])
+        .assert()
+        .code(2)
+        .stderr("error: Invalid value for '--emit <emit>...': cannot parse emit kind of: exe\n");
+
+    let output = Command::cargo_bin("c-webassembly")?.arg("tests/samples/missing.cwal").output()?;
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)?.starts_with("error: cannot read `tests/samples/missing.cwal`: "));
+
+    return Ok(());
+}
+
+#[test]
+fn exit_codes() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let broken = dir.path().join("broken.cwal");
+
+    std::fs::write(&broken, "fn f( {}")?;
+
+    let output = Command::cargo_bin("c-webassembly")?.arg(&broken).output()?;
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr)?.starts_with(&format!("{}:1:7: error[E0001]: unexpected token `{{`", broken.display())));
+
+    let output = Command::cargo_bin("c-webassembly")?.arg(dir.path().join("missing.cwal")).output()?;
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr)?.starts_with("error: cannot read `"));
+
+    Command::cargo_bin("c-webassembly")?.arg("--no-such-flag").assert().code(2);
+    Command::cargo_bin("c-webassembly")?.args(["--explain", "E9999"]).assert().code(2);
+    Command::cargo_bin("c-webassembly")?.args(["tests/samples/emit.cwal", "tests/samples/simple.cwal", "--format"]).assert().code(2);
+
+    return Ok(());
+}
+
+#[test]
+fn reexport_public_includes() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let outfile = dir.path().join("out.wasm");
+
+    Command::cargo_bin("c-webassembly")?
+        .args(["tests/samples/include/main.cwal", "--emit", "wat", "-o"])
+        .arg(&outfile)
+        .assert()
+        .success();
+
+    let wat = std::fs::read_to_string(dir.path().join("out.wat"))?;
+
+    assert!(wat.contains("(export \"run\""));
+    assert!(wat.contains("(export \"shown\""));
+    assert!(!wat.contains("(export \"hidden\""));
+
+    return Ok(());
+}
+
+#[test]
+fn print_optimizer_remarks() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let outfile = dir.path().join("out.wasm");
+    let output = Command::cargo_bin("c-webassembly")?
+        .args(["tests/samples/fold.cwal", "-O2", "--opt-remarks", "-o"])
+        .arg(&outfile)
+        .output()?;
+    let stderr = String::from_utf8(output.stderr)?;
+
+    assert!(output.status.success());
+    assert!(stderr.contains("tests/samples/fold.cwal:5:5: remark: folded a constant expression to `16` [constant-folding]"));
+    assert!(stderr.contains("tests/samples/fold.cwal:2:1: remark: removed unused function `unused` [dead-function-elimination]"));
+
+    let output = Command::cargo_bin("c-webassembly")?
+        .args(["tests/samples/fold.cwal", "-O2", "-o"])
+        .arg(&outfile)
+        .output()?;
+
+    assert!(!String::from_utf8(output.stderr)?.contains("remark:"));
+
+    return Ok(());
+}
+
+#[test]
+fn emit_metadata() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let outfile = dir.path().join("out.wasm");
+
+    Command::cargo_bin("c-webassembly")?
+        .args(["tests/samples/metadata.cwal", "--emit", "metadata", "-O1", "-o"])
+        .arg(&outfile)
+        .assert()
+        .success();
+
+    let metadata: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(dir.path().join("out.json"))?)?;
+
+    assert_eq!(metadata["opt_level"], "1");
+    assert_eq!(metadata["imports"], serde_json::json!([
+        { "module": "env", "name": "log", "kind": "function", "params": ["i32"], "results": [] }
+    ]));
+    assert_eq!(metadata["exports"], serde_json::json!([
+        { "name": "scale", "kind": "function", "params": ["i32", "f64"], "results": ["f64"] },
+        { "name": "memory", "kind": "memory", "min": 1, "max": 4 }
+    ]));
+    assert_eq!(metadata["tables"][0]["element"], "funcref");
+
+    return Ok(());
+}
+
+#[test]
+fn import_wasm_module() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let outfile = dir.path().join("out.wasm");
+
+    Command::cargo_bin("c-webassembly")?
+        .args(["tests/samples/import_wasm.cwal", "-o"])
+        .arg(&outfile)
+        .assert()
+        .success();
+
+    let imports = wasmparser::Parser::new(0).parse_all(&std::fs::read(&outfile)?)
+        .filter_map(|x| return match x {
+            Ok(wasmparser::Payload::ImportSection(reader)) => Some(reader),
+            _ => None
+        })
+        .flatten()
+        .map(|x| return x.map(|x| return (x.module.to_string(), x.name.to_string())))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    assert_eq!(imports, vec![("math".to_string(), "add".to_string())]);
+
+    // the prebuilt module provides `add` when the output is instantiated
+    let engine = wasmi::Engine::default();
+    let mut store = wasmi::Store::new(&engine, ());
+    let mut linker = wasmi::Linker::<()>::new(&engine);
+    let math = wasmi::Module::new(&engine, &std::fs::read("tests/samples/math.wasm")?[..])?;
+    let math = linker.instantiate(&mut store, &math)?.start(&mut store)?;
+    let add = math.get_func(&store, "add").ok_or("missing `add` export")?;
+
+    linker.define("math", "add", add)?;
+
+    let module = wasmi::Module::new(&engine, &std::fs::read(&outfile)?[..])?;
+    let instance = linker.instantiate(&mut store, &module)?.start(&mut store)?;
+
+    assert_eq!(instance.get_typed_func::<i32, i32>(&store, "twice")?.call(&mut store, 21)?, 42);
+
+    return Ok(());
+}
+
+#[test]
+fn default_numeric_types() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let file = dir.path().join("defaults.cwal");
+    let outfile = dir.path().join("defaults.wasm");
+
+    std::fs::write(&file, "exp fn f() {\n    let x <- 1;\n    let y <- 1.5;\n    let z <- 1.5f64;\n}\n")?;
+
+    Command::cargo_bin("c-webassembly")?
+        .arg(&file)
+        .args(["--default-int", "i64", "--default-float", "f32", "-A", "unused-variables", "--emit", "wat", "-o"])
+        .arg(&outfile)
+        .assert()
+        .success();
+
+    let wat = std::fs::read_to_string(dir.path().join("defaults.wat"))?;
+
+    assert!(wat.contains("(local i64 f32 f64)"), "{}", wat);
+    assert!(wat.contains("i64.const 1"), "{}", wat);
+
+    Command::cargo_bin("c-webassembly")?
+        .arg(&file)
+        .args(["--default-int", "u8"])
+        .assert()
+        .code(2)
+        .stderr("error: Invalid value for '--default-int <default-int>': cannot parse integer type of: u8\n");
+
+    return Ok(());
+}
+
+#[test]
+fn compile_empty_input() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+
+    for (name, text) in [("empty", ""), ("blank", "  \n\t\n"), ("comment", "// nothing yet\n")].iter() {
+        let file = dir.path().join(format!("{}.cwal", name));
+        let outfile = dir.path().join(format!("{}.wasm", name));
+
+        std::fs::write(&file, text)?;
+
+        Command::cargo_bin("c-webassembly")?
+            .arg(&file)
+            .arg("-o")
+            .arg(&outfile)
+            .assert()
+            .success();
 
-    cmd.arg("tests/samples/simple.cwal").assert().success();
+        // only the magic number and the version
+        assert_eq!(std::fs::read(&outfile)?, b"\0asm\x01\0\0\0");
+    }
 
     return Ok(());
-}
\ No newline at end of file
+}